@@ -0,0 +1,262 @@
+use crate::captures::bound_names;
+use alc_diagnostic::Spanned;
+use alc_parser::ast;
+
+/// A method called on a value whose type is a type parameter, as in `value.hash()` where `value: T`.
+pub struct BoundMethodCall<'ast> {
+    pub ty_param: &'ast ast::Ident,
+    pub method: &'ast Spanned<ast::Ident>,
+    pub arg_count: usize,
+}
+
+/// The methods a generic function calls on values whose type is one of its type parameters. These values are
+/// its parameters and the bindings annotated with a type parameter, along with the bindings they are copied to.
+pub fn bound_method_calls(decl: &ast::FnDecl) -> Vec<BoundMethodCall> {
+    let mut calls = BoundMethodCalls {
+        ty_params: decl.ty_params.iter().map(|ty_param| &**ty_param).collect(),
+        bound: vec![],
+        calls: vec![],
+    };
+    let params = calls.params(&decl.params);
+    calls.with_bound(params, |this| this.visit_term(&decl.body));
+    calls.calls
+}
+
+struct BoundMethodCalls<'ast> {
+    ty_params: Vec<&'ast ast::Ident>,
+    /// The names bound in the scopes that enclose the part of the function being visited, along with the type
+    /// parameter that is their type, if it is one.
+    bound: Vec<(&'ast ast::Ident, Option<&'ast ast::Ident>)>,
+    calls: Vec<BoundMethodCall<'ast>>,
+}
+
+impl<'ast> BoundMethodCalls<'ast> {
+    /// The type parameter that `ty` is, if it is written as a bare one.
+    fn ty_param(&self, ty: &ast::Ty) -> Option<&'ast ast::Ident> {
+        match ty {
+            ast::Ty::TyName(name, ty_args) if ty_args.is_empty() => {
+                self.ty_params.iter().find(|ty_param| **ty_param == name).copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn params(
+        &self,
+        params: &'ast [Spanned<ast::Binding>],
+    ) -> Vec<(&'ast ast::Ident, Option<&'ast ast::Ident>)> {
+        params
+            .iter()
+            .map(|param| (&*param.binder, self.ty_param(&param.ty)))
+            .collect()
+    }
+
+    /// The type parameter that is the type of what `expr` evaluates to, if it reads a binding of one.
+    fn ty_param_of(&self, expr: &ast::Expr) -> Option<&'ast ast::Ident> {
+        match expr {
+            ast::Expr::Var(stream) if stream.len() == 1 => self
+                .bound
+                .iter()
+                .rev()
+                .find(|(name, _)| **name == *stream[0])
+                .and_then(|(_, ty_param)| *ty_param),
+            _ => None,
+        }
+    }
+
+    /// Visits `visit` in a scope where the names in `names` are bound as well.
+    fn with_bound(
+        &mut self,
+        names: Vec<(&'ast ast::Ident, Option<&'ast ast::Ident>)>,
+        visit: impl FnOnce(&mut Self),
+    ) {
+        let depth = self.bound.len();
+        self.bound.extend(names);
+        visit(self);
+        self.bound.truncate(depth);
+    }
+
+    fn visit_term(&mut self, term: &'ast ast::Term) {
+        match term {
+            ast::Term::Let {
+                binder,
+                annotation,
+                expr,
+                body,
+                ..
+            } => {
+                self.visit_expr(expr);
+                let ty_param = match annotation {
+                    Some(ty) => self.ty_param(ty),
+                    None => self.ty_param_of(expr),
+                };
+                self.with_bound(vec![(&**binder, ty_param)], |this| this.visit_term(body));
+            }
+            ast::Term::LetPattern { expr, arm, .. } => {
+                self.visit_expr(expr);
+                self.visit_arm(arm);
+            }
+            ast::Term::Match { source, arms } => {
+                self.visit_expr(source);
+                for arm in arms.iter() {
+                    self.visit_arm(arm);
+                }
+            }
+            ast::Term::If {
+                source,
+                then,
+                otherwise,
+            } => {
+                self.visit_expr(source);
+                self.visit_term(then);
+                self.visit_term(otherwise);
+            }
+            ast::Term::Println { expr, body } | ast::Term::Expr { expr, body } => {
+                self.visit_expr(expr);
+                self.visit_term(body);
+            }
+            ast::Term::Assign {
+                index, expr, body, ..
+            } => {
+                if let Some(index) = index {
+                    self.visit_expr(index);
+                }
+                self.visit_expr(expr);
+                self.visit_term(body);
+            }
+            ast::Term::While { source, block, body } => {
+                self.visit_expr(source);
+                self.visit_term(block);
+                self.visit_term(body);
+            }
+            ast::Term::Loop { block, body } => {
+                self.visit_term(block);
+                self.visit_term(body);
+            }
+            ast::Term::Break | ast::Term::Continue | ast::Term::End => {}
+            ast::Term::Return(expr) | ast::Term::Value(expr) => self.visit_expr(expr),
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &'ast ast::Arm) {
+        let mut names = vec![];
+        bound_names(&arm.pattern, &mut names);
+        let names = names.into_iter().map(|name| (name, None)).collect();
+        self.with_bound(names, |this| {
+            if let Some(guard) = &arm.guard {
+                this.visit_expr(guard);
+            }
+            this.visit_term(&arm.body);
+        });
+    }
+
+    fn visit_exprs(&mut self, exprs: impl IntoIterator<Item = &'ast Spanned<ast::Expr>>) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        match expr {
+            ast::Expr::NumberLiteral(_)
+            | ast::Expr::FloatLiteral(_)
+            | ast::Expr::BoolLiteral(_)
+            | ast::Expr::StringLiteral(_)
+            | ast::Expr::Var(_) => {}
+            ast::Expr::ArrayLiteral(elements) | ast::Expr::Tuple(elements) => self.visit_exprs(elements),
+            ast::Expr::Index { target, index } => {
+                self.visit_expr(target);
+                self.visit_expr(index);
+            }
+            ast::Expr::Unop { operand, .. } => self.visit_expr(operand),
+            ast::Expr::Binop { left, right, .. } => {
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            ast::Expr::Call { args, .. } => self.visit_exprs(args),
+            ast::Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                if let Some(ty_param) = self.ty_param_of(receiver) {
+                    self.calls.push(BoundMethodCall {
+                        ty_param,
+                        method,
+                        arg_count: args.len(),
+                    });
+                }
+                self.visit_expr(receiver);
+                self.visit_exprs(args);
+            }
+            ast::Expr::Branch(term) => self.visit_term(term),
+            ast::Expr::Closure(closure) => {
+                let mut names = closure
+                    .name
+                    .iter()
+                    .map(|name| (&**name, None))
+                    .collect::<Vec<_>>();
+                names.extend(self.params(&closure.params));
+                self.with_bound(names, |this| this.visit_term(&closure.body));
+            }
+            ast::Expr::Variant { fields, .. } => match fields {
+                ast::Fields::Unit => {}
+                ast::Fields::Tuple(fields) => self.visit_exprs(fields),
+                ast::Fields::Named(fields) => self.visit_exprs(fields.iter().map(|(_, field)| field)),
+            },
+            ast::Expr::Record { fields, .. } => self.visit_exprs(fields.iter().map(|(_, field)| field)),
+            ast::Expr::Socket { domain, ty, protocol } => {
+                for expr in [domain, ty, protocol] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Bind {
+                socket_file_descriptor,
+                address,
+                address_length,
+            } => {
+                for expr in [socket_file_descriptor, address, address_length] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Listen {
+                socket_file_descriptor,
+                backlog,
+            } => {
+                self.visit_expr(socket_file_descriptor);
+                self.visit_expr(backlog);
+            }
+            ast::Expr::Accept {
+                socket_file_descriptor,
+            }
+            | ast::Expr::Close {
+                socket_file_descriptor,
+            } => self.visit_expr(socket_file_descriptor),
+            ast::Expr::Recv {
+                socket_file_descriptor,
+                buffer,
+                buffer_length,
+                flags,
+            } => {
+                for expr in [socket_file_descriptor, buffer, buffer_length, flags] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Send {
+                socket_file_descriptor,
+                buffer,
+                buffer_length,
+                content,
+                flags,
+            } => {
+                for expr in [socket_file_descriptor, buffer, buffer_length, content, flags] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::ListenAndServe { address, handler, .. } => {
+                self.visit_expr(address);
+                self.visit_expr(handler);
+            }
+        }
+    }
+}
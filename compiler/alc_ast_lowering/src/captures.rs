@@ -0,0 +1,240 @@
+use alc_diagnostic::Spanned;
+use alc_parser::ast;
+
+/// The names a closure uses without binding them itself, in the order in which they are first used. Some of
+/// them name bindings of the function it is written in, which the closure captures, and the rest name declared
+/// functions.
+pub fn free_names(closure: &ast::Closure) -> Vec<&Spanned<ast::Ident>> {
+    let mut names = FreeNames {
+        bound: closure.params.iter().map(|param| &*param.binder).collect(),
+        free: vec![],
+    };
+    names.visit_term(&closure.body);
+    names.free
+}
+
+struct FreeNames<'ast> {
+    /// The names bound in the scopes that enclose the part of the closure being visited.
+    bound: Vec<&'ast ast::Ident>,
+    free: Vec<&'ast Spanned<ast::Ident>>,
+}
+
+impl<'ast> FreeNames<'ast> {
+    fn use_name(&mut self, name: &'ast Spanned<ast::Ident>) {
+        if !self.bound.contains(&&**name) && !self.free.iter().any(|free| ***free == **name) {
+            self.free.push(name);
+        }
+    }
+
+    /// Visits `visit` in a scope where the names in `names` are bound as well.
+    fn with_bound(&mut self, names: Vec<&'ast ast::Ident>, visit: impl FnOnce(&mut Self)) {
+        let depth = self.bound.len();
+        self.bound.extend(names);
+        visit(self);
+        self.bound.truncate(depth);
+    }
+
+    fn visit_term(&mut self, term: &'ast ast::Term) {
+        match term {
+            ast::Term::Let {
+                binder, expr, body, ..
+            } => {
+                self.visit_expr(expr);
+                self.with_bound(vec![&**binder], |this| this.visit_term(body));
+            }
+            ast::Term::LetPattern { expr, arm, .. } => {
+                self.visit_expr(expr);
+                self.visit_arm(arm);
+            }
+            ast::Term::Match { source, arms } => {
+                self.visit_expr(source);
+                for arm in arms.iter() {
+                    self.visit_arm(arm);
+                }
+            }
+            ast::Term::If {
+                source,
+                then,
+                otherwise,
+            } => {
+                self.visit_expr(source);
+                self.visit_term(then);
+                self.visit_term(otherwise);
+            }
+            ast::Term::Println { expr, body } | ast::Term::Expr { expr, body } => {
+                self.visit_expr(expr);
+                self.visit_term(body);
+            }
+            ast::Term::Assign {
+                target,
+                index,
+                expr,
+                body,
+            } => {
+                self.use_name(target.first().unwrap());
+                if let Some(index) = index {
+                    self.visit_expr(index);
+                }
+                self.visit_expr(expr);
+                self.visit_term(body);
+            }
+            ast::Term::While { source, block, body } => {
+                self.visit_expr(source);
+                self.visit_term(block);
+                self.visit_term(body);
+            }
+            ast::Term::Loop { block, body } => {
+                self.visit_term(block);
+                self.visit_term(body);
+            }
+            ast::Term::Break | ast::Term::Continue | ast::Term::End => {}
+            ast::Term::Return(expr) | ast::Term::Value(expr) => self.visit_expr(expr),
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &'ast ast::Arm) {
+        let mut names = vec![];
+        bound_names(&arm.pattern, &mut names);
+        self.with_bound(names, |this| {
+            if let Some(guard) = &arm.guard {
+                this.visit_expr(guard);
+            }
+            this.visit_term(&arm.body);
+        });
+    }
+
+    fn visit_exprs(&mut self, exprs: impl IntoIterator<Item = &'ast Spanned<ast::Expr>>) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        match expr {
+            ast::Expr::NumberLiteral(_)
+            | ast::Expr::FloatLiteral(_)
+            | ast::Expr::BoolLiteral(_)
+            | ast::Expr::StringLiteral(_) => {}
+            ast::Expr::ArrayLiteral(elements) | ast::Expr::Tuple(elements) => self.visit_exprs(elements),
+            ast::Expr::Var(stream) => self.use_name(stream.first().unwrap()),
+            ast::Expr::Index { target, index } => {
+                self.visit_expr(target);
+                self.visit_expr(index);
+            }
+            ast::Expr::Unop { operand, .. } => self.visit_expr(operand),
+            ast::Expr::Binop { left, right, .. } => {
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            ast::Expr::Call { target, args, .. } => {
+                self.use_name(target);
+                self.visit_exprs(args);
+            }
+            ast::Expr::MethodCall { receiver, args, .. } => {
+                self.visit_expr(receiver);
+                self.visit_exprs(args);
+            }
+            ast::Expr::Branch(term) => self.visit_term(term),
+            ast::Expr::Closure(closure) => {
+                let params = closure.params.iter().map(|param| &*param.binder).collect();
+                self.with_bound(params, |this| this.visit_term(&closure.body));
+            }
+            ast::Expr::Variant { fields, .. } => match fields {
+                ast::Fields::Unit => {}
+                ast::Fields::Tuple(fields) => self.visit_exprs(fields),
+                ast::Fields::Named(fields) => self.visit_exprs(fields.iter().map(|(_, field)| field)),
+            },
+            ast::Expr::Record { fields, .. } => self.visit_exprs(fields.iter().map(|(_, field)| field)),
+            ast::Expr::Socket { domain, ty, protocol } => {
+                for expr in [domain, ty, protocol] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Bind {
+                socket_file_descriptor,
+                address,
+                address_length,
+            } => {
+                for expr in [socket_file_descriptor, address, address_length] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Listen {
+                socket_file_descriptor,
+                backlog,
+            } => {
+                self.visit_expr(socket_file_descriptor);
+                self.visit_expr(backlog);
+            }
+            ast::Expr::Accept {
+                socket_file_descriptor,
+            }
+            | ast::Expr::Close {
+                socket_file_descriptor,
+            } => self.visit_expr(socket_file_descriptor),
+            ast::Expr::Recv {
+                socket_file_descriptor,
+                buffer,
+                buffer_length,
+                flags,
+            } => {
+                for expr in [socket_file_descriptor, buffer, buffer_length, flags] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Send {
+                socket_file_descriptor,
+                buffer,
+                buffer_length,
+                content,
+                flags,
+            } => {
+                for expr in [socket_file_descriptor, buffer, buffer_length, content, flags] {
+                    self.visit_expr(expr);
+                }
+            }
+            // NOTE the other operands are filled in by the parser, so only the port and the handler are written
+            ast::Expr::ListenAndServe { address, handler, .. } => {
+                self.visit_expr(address);
+                self.visit_expr(handler);
+            }
+        }
+    }
+}
+
+/// Collects the names a pattern binds.
+pub fn bound_names<'ast>(pattern: &'ast ast::Pattern, names: &mut Vec<&'ast ast::Ident>) {
+    match pattern {
+        ast::Pattern::Ident(ident) => names.push(ident),
+        ast::Pattern::Tuple(fields) => {
+            for field in fields.iter() {
+                bound_names(field, names);
+            }
+        }
+        ast::Pattern::Variant { fields, .. } => match fields {
+            ast::Fields::Unit => {}
+            ast::Fields::Tuple(fields) => {
+                for field in fields.iter() {
+                    bound_names(field, names);
+                }
+            }
+            ast::Fields::Named(fields) => {
+                for (_, field) in fields.iter() {
+                    bound_names(field, names);
+                }
+            }
+        },
+        ast::Pattern::Record { fields, .. } => {
+            for (_, field) in fields.iter() {
+                bound_names(field, names);
+            }
+        }
+        // NOTE every alternative binds the same names
+        ast::Pattern::Or(alternatives) => bound_names(&alternatives[0], names),
+        ast::Pattern::NumberLiteral(_)
+        | ast::Pattern::BoolLiteral(_)
+        | ast::Pattern::ArrayLiteral(_)
+        | ast::Pattern::StringLiteral(_)
+        | ast::Pattern::Wildcard => {}
+    }
+}
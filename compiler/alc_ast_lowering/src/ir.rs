@@ -120,18 +120,51 @@ pub enum BinopKind {
     RShift,
 }
 
+impl BinopKind {
+    #[inline]
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinopKind::Less
+                | BinopKind::Leq
+                | BinopKind::Greater
+                | BinopKind::Geq
+                | BinopKind::Eq
+                | BinopKind::Neq
+        )
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ExprKind {
     I8Literal(i8),
     I16Literal(i16),
     I32Literal(i32),
     I64Literal(i64),
+    U8Literal(u8),
+    U16Literal(u16),
+    U32Literal(u32),
+    U64Literal(u64),
+    F32Literal(f32),
+    F64Literal(f64),
+    /// An integer literal without a suffix or an expected type, whose type is inferred from how it is used.
+    NumberLiteral(u64),
+    /// A float literal without a suffix or an expected type, whose type is inferred from how it is used.
+    FloatLiteral(f64),
+    BoolLiteral(bool),
     ArrayLiteral {
-        element_ty: Ty,
+        /// The type of the elements, unless it is inferred from how the array is used.
+        element_ty: Option<Ty>,
         elements: Vec<ExprKind>,
     },
     StringLiteral(String),
     Var(LocalIdx, Vec<FieldIdx>),
+    /// Reads an element of the array at `fields` of `target`, checking `index` against its length at runtime.
+    Index {
+        target: LocalIdx,
+        fields: Vec<FieldIdx>,
+        index: LocalIdx,
+    },
     Unop {
         kind: UnopKind,
         operand: LocalIdx,
@@ -145,15 +178,35 @@ pub enum ExprKind {
         target: DefIdx,
         args: IdxVec<ParamIdx, LocalIdx>,
     },
+    /// Calls the function value held by `target`.
+    CallValue {
+        target: LocalIdx,
+        args: IdxVec<ParamIdx, LocalIdx>,
+    },
+    /// Makes a function value of type `ty` that calls `target`, keeping the values of `captures` in its
+    /// environment. A declared function captures nothing, and is called through a thunk that ignores the
+    /// environment.
+    Closure {
+        ty: Ty,
+        target: DefIdx,
+        captures: Vec<LocalIdx>,
+    },
     Variant {
         ty: Ty,
         discriminant: VariantIdx,
-        body: LocalIdx,
+        fields: IdxVec<FieldIdx, LocalIdx>,
     },
     Record {
         ty: Ty,
         fields: IdxVec<FieldIdx, LocalIdx>,
     },
+    /// A tuple without an expected type, which is built like a record of the type inferred for it from its elements.
+    Tuple(IdxVec<FieldIdx, LocalIdx>),
+    /// Evaluates to the value yielded by the block of the first arm matching `source`.
+    Match {
+        source: LocalIdx,
+        arms: Vec<Arm>,
+    },
     Socket {
         domain: LocalIdx,
         ty: LocalIdx,
@@ -202,7 +255,7 @@ pub enum ExprKind {
         send_flags: LocalIdx,
         format_string: LocalIdx,
         http_header: LocalIdx,
-        call_handler: LocalIdx,
+        handler: LocalIdx,
     },
 }
 
@@ -219,6 +272,11 @@ pub enum PatternKind {
     I16Literal(i16),
     I32Literal(i32),
     I64Literal(i64),
+    U8Literal(u8),
+    U16Literal(u16),
+    U32Literal(u32),
+    U64Literal(u64),
+    BoolLiteral(bool),
     ArrayLiteral {
         element_ty: Ty,
         elements: Vec<ExprKind>,
@@ -228,7 +286,7 @@ pub enum PatternKind {
     Variant {
         ty: Ty,
         discriminant: VariantIdx,
-        binding: LocalIdx,
+        fields: IdxVec<FieldIdx, LocalIdx>,
     },
     Record {
         ty: Ty,
@@ -236,13 +294,53 @@ pub enum PatternKind {
     },
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Arm {
     pub span: Span,
     pub pattern: PatternKind,
     pub target: Block,
 }
 
+/// The body of a match arm, which takes the values its pattern bound as `params` from the leaf jumping to it.
+#[derive(Clone, Debug)]
+pub struct ArmBody {
+    pub params: Vec<LocalIdx>,
+    pub body: Block,
+}
+
+/// A pattern as it was written in a match arm, before the arms were compiled into `Terminator::Match` switches.
+#[derive(Clone, Debug)]
+pub enum WrittenPattern {
+    /// A wildcard or an identifier, either of which matches any value.
+    Wild,
+    Literal(PatternKind),
+    Variant {
+        ty: Ty,
+        discriminant: VariantIdx,
+        fields: IdxVec<FieldIdx, WrittenPattern>,
+    },
+    Record {
+        ty: Ty,
+        fields: IdxVec<FieldIdx, WrittenPattern>,
+    },
+    Or(Vec<WrittenPattern>),
+}
+
+#[derive(Clone, Debug)]
+pub struct WrittenArm {
+    pub span: Span,
+    pub pattern: WrittenPattern,
+    pub guarded: bool,
+}
+
+/// The arms of a match on `source` as they were written, kept so that they can be checked for exhaustiveness
+/// and reachability.
+#[derive(Clone, Debug)]
+pub struct WrittenMatch {
+    pub source: LocalIdx,
+    pub arms: Vec<WrittenArm>,
+}
+
 #[derive(Clone, Debug)]
 pub enum InstructionKind {
     Let {
@@ -250,11 +348,28 @@ pub enum InstructionKind {
         ty: Option<Ty>,
         expr: Expr,
     },
+    /// Declares a mutable binding, which lives in a stack slot initialised with `init`.
+    LetMut {
+        binding: LocalIdx,
+        ty: Option<Ty>,
+        init: LocalIdx,
+    },
+    /// Stores `value` into a binding, or into one of its fields when `fields` is not empty. With an `index`, the
+    /// place is an array and `value` is stored into its element instead.
+    /// `ty` is the type of the place written to when it is known while lowering.
+    Assign {
+        binding: LocalIdx,
+        fields: Vec<FieldIdx>,
+        index: Option<LocalIdx>,
+        ty: Option<Ty>,
+        value: LocalIdx,
+    },
     Println {
         idx: LocalIdx,
     },
     Mark(LocalIdx, Ty),
     Unmark(LocalIdx, Ty),
+    /// Drops a boxed value, releasing what its fields hold before freeing it.
     Free(LocalIdx, Ty),
     IncrementRc(LocalIdx, Ty),
     DecrementRc(LocalIdx, Ty),
@@ -266,13 +381,38 @@ pub struct Instruction {
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Terminator {
     Return(LocalIdx),
-    Match { source: LocalIdx, arms: Vec<Arm> },
+    Match {
+        source: LocalIdx,
+        arms: Vec<Arm>,
+    },
+    /// Runs `tree`, the switches compiled from the patterns of a match, whose leaves jump to `arms`. An arm reached
+    /// from several leaves is still lowered only once.
+    Decision {
+        tree: Box<Block>,
+        arms: Vec<ArmBody>,
+    },
+    /// Ends a leaf of the innermost decision by jumping to the arm body `target`, passing `args` as its params.
+    Jump {
+        target: BlockIdx,
+        args: Vec<LocalIdx>,
+    },
+    /// Ends a block belonging to an `ExprKind::Match`, giving the value of the whole expression.
+    Yield(LocalIdx),
+    /// Runs `body` repeatedly until it breaks, then continues with `next`.
+    Loop {
+        body: Box<Block>,
+        next: Box<Block>,
+    },
+    /// Leaves the innermost loop.
+    Break,
+    /// Jumps back to the start of the innermost loop.
+    Continue,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Block {
     pub owner: DefIdx,
     pub block_idx: BlockIdx,
@@ -285,6 +425,11 @@ pub struct Block {
 pub struct Entry {
     pub owner: DefIdx,
     pub param_bindings: IdxVec<ParamIdx, LocalIdx>,
+    /// The bindings of the values a closure captured, which are read from its environment. A declared function
+    /// has no environment.
+    pub capture_bindings: Option<Vec<LocalIdx>>,
+    /// The binding of `self`, the receiver of a method, which is borrowed from the caller.
+    pub receiver: Option<LocalIdx>,
     pub body: Block,
 }
 
@@ -296,6 +441,7 @@ pub struct Def {
     pub span: Span,
     pub entry: Entry,
     pub local_idxr: Idxr<LocalIdx>,
+    pub matches: Vec<WrittenMatch>,
 }
 
 #[derive(Debug)]
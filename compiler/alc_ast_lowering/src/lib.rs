@@ -1,3 +1,5 @@
+mod bounds;
+mod captures;
 pub mod idx;
 pub mod idx_vec;
 pub mod ir;
@@ -5,6 +7,7 @@ mod lowering;
 mod lowering_ctx;
 pub mod ty;
 mod ty_lowering;
+mod uses;
 
 use crate::ty_lowering::TyLowering;
 use alc_command_option::CommandOptions;
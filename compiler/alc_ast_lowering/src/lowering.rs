@@ -1,7 +1,78 @@
-use crate::{idx::Idxr, idx_vec::IdxVec, ir, lowering_ctx::LoweringCtx, ty, TyLowering, RESERVED_NAMES};
-use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
+use crate::{
+    bounds,
+    idx::{Idx, Idxr},
+    idx_vec::IdxVec,
+    ir,
+    lowering_ctx::LoweringCtx,
+    ty,
+    ty_lowering::{TyScope, INSTANCE_DEPTH_LIMIT},
+    TyLowering,
+    ENTRY_NAME,
+    RESERVED_NAMES,
+};
+use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span, Spanned};
 use alc_parser::ast;
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+};
+
+type TraitImpl<'ast> = (&'ast ast::Ident, &'ast ast::Ident);
+
+/// A method by the struct or enum it belongs to, the trait it is a method of if it is one, and its name.
+type MethodKey<'ast> = (&'ast ast::Ident, Option<&'ast ast::Ident>, &'ast ast::Ident);
+
+/// An instance of a generic function that has been referred to but not lowered yet.
+#[derive(Debug)]
+struct Instance<'ast> {
+    name: String,
+    decl: &'ast ast::FnDecl,
+    def_idx: ir::DefIdx,
+    ty_args: Vec<ty::Ty>,
+    depth: usize,
+    method: bool,
+}
+
+/// A closure that has been written in a function but not lowered yet, along with the bindings it captures from
+/// that function and the type parameters bound there.
+#[derive(Debug)]
+struct Closure<'ast> {
+    closure: &'ast ast::Closure,
+    def_idx: ir::DefIdx,
+    captures: Vec<Capture<'ast>>,
+    scope: TyScope<'ast>,
+    depth: usize,
+}
+
+/// A method declared in an impl block, which is bound to a definition unless it is generic.
+#[derive(Clone, Copy, Debug)]
+pub struct Method<'ast> {
+    pub decl: &'ast ast::FnDecl,
+    pub def_idx: Option<ir::DefIdx>,
+    owner: &'ast ast::Ident,
+    trait_name: Option<&'ast ast::Ident>,
+}
+
+impl<'ast> Method<'ast> {
+    /// The name of the method qualified by the struct or enum it belongs to, as in `Point.norm`, and by the trait
+    /// it is a method of, as in `Point.Hash.hash`.
+    fn qualified_name(&self) -> String {
+        match self.trait_name {
+            Some(trait_name) => format!("{}.{}.{}", self.owner, trait_name, &*self.decl.name),
+            None => format!("{}.{}", self.owner, &*self.decl.name),
+        }
+    }
+}
+
+/// A binding used by a closure from the function it is written in.
+pub type Capture<'ast> = (&'ast ast::Ident, Span, Option<ty::Ty>);
+
+/// A definition waiting to be lowered, in the order in which it was bound.
+#[derive(Debug)]
+enum Pending<'ast> {
+    Instance(Instance<'ast>),
+    Closure(Closure<'ast>),
+}
 
 #[derive(Debug)]
 pub struct Lowering<'ast> {
@@ -10,8 +81,19 @@ pub struct Lowering<'ast> {
     pub file_id: FileId,
     pub tys: TyLowering<'ast>,
     ir: ir::Ir,
-    bind_points: IdxVec<ir::DefIdx, Span>,
+    bind_points: RefCell<IdxVec<ir::DefIdx, Span>>,
+    fn_tys: RefCell<IdxVec<ir::DefIdx, ty::Ty>>,
     global_map: HashMap<&'ast ast::Ident, ir::DefIdx>,
+    generic_fns: HashMap<&'ast ast::Ident, &'ast ast::FnDecl>,
+    methods: HashMap<MethodKey<'ast>, Method<'ast>>,
+    traits: HashMap<&'ast ast::Ident, &'ast ast::Trait>,
+    /// The traits implemented by each struct or enum, along with where they are implemented.
+    trait_impls: HashMap<TraitImpl<'ast>, Span>,
+    /// The trait that declares each method called on a value of a type parameter, by where the method is named.
+    bound_methods: HashMap<Span, &'ast ast::Ident>,
+    instances: RefCell<HashMap<(String, Vec<ty::Ty>), ir::DefIdx>>,
+    pending: RefCell<VecDeque<Pending<'ast>>>,
+    instance_depth: Cell<usize>,
 }
 
 impl<'ast> Lowering<'ast> {
@@ -25,32 +107,118 @@ impl<'ast> Lowering<'ast> {
             file_id,
             tys,
             ir: ir::Ir { defs: IdxVec::new() },
-            bind_points: IdxVec::new(),
+            bind_points: RefCell::new(IdxVec::new()),
+            fn_tys: RefCell::new(IdxVec::new()),
             global_map: HashMap::new(),
+            generic_fns: HashMap::new(),
+            methods: HashMap::new(),
+            traits: HashMap::new(),
+            trait_impls: HashMap::new(),
+            bound_methods: HashMap::new(),
+            instances: RefCell::new(HashMap::new()),
+            pending: RefCell::new(VecDeque::new()),
+            instance_depth: Cell::new(0),
         }
     }
 
     pub fn register<T>(&mut self, items: T) -> Result<()>
-    where T: Iterator<Item = &'ast ast::Item> {
+    where T: Iterator<Item = &'ast ast::Item> + Clone {
+        // NOTE traits are bound first, since bounds and impl blocks may refer to ones declared after them
+        for item in items.clone() {
+            if let ast::Item::Trait(def) = item {
+                self.bind_trait(def)?;
+            }
+        }
         for item in items {
-            if let ast::Item::Fn(def) = item {
-                self.bind(&def.name, def.name.span())?;
+            match item {
+                ast::Item::Fn(decl) if decl.ty_params.is_empty() => {
+                    let fn_ty = self.lower_fn_ty(decl, &TyScope::new())?;
+                    self.bind(&decl.name, decl.name.span(), fn_ty)?;
+                }
+                ast::Item::Fn(decl) => self.bind_generic(decl)?,
+                ast::Item::Impl(def) => self.bind_impl(def)?,
+                _ => {}
             }
         }
         Ok(())
     }
 
+    /// Lowers the functions that are not generic, and then every instance of a generic function and every
+    /// closure they contain, directly or through other instances and closures.
     pub fn lower<T>(&mut self, items: T) -> Result<()>
     where T: Iterator<Item = &'ast ast::Item> {
+        let scope = TyScope::new();
         for item in items {
-            if let ast::Item::Fn(decl) = item {
-                let def = self.lower_decl(decl)?;
-                self.ir.defs.push(def);
+            match item {
+                ast::Item::Fn(decl) if decl.ty_params.is_empty() => {
+                    let def_idx = self.lookup(&decl.name, decl.name.span())?;
+                    let def = self.lower_decl(decl, def_idx, decl.name.to_string(), &scope, false)?;
+                    self.ir.defs.push(def);
+                }
+                ast::Item::Impl(def) => {
+                    let trait_name = def.trait_name.as_deref();
+                    for decl in def.methods.iter() {
+                        let method = self.methods[&(&*def.ty_name, trait_name, &*decl.name)];
+                        if let Some(def_idx) = method.def_idx {
+                            let name = method.qualified_name();
+                            let def = self.lower_decl(decl, def_idx, name, &scope, true)?;
+                            self.ir.defs.push(def);
+                        }
+                    }
+                }
+                _ => {}
             }
         }
+        loop {
+            let pending = self.pending.borrow_mut().pop_front();
+            let def = match pending {
+                Some(Pending::Instance(instance)) => self.lower_instance(instance)?,
+                Some(Pending::Closure(closure)) => self.lower_closure(closure)?,
+                None => break,
+            };
+            self.ir.defs.push(def);
+        }
         Ok(())
     }
 
+    fn lower_instance(&self, instance: Instance<'ast>) -> Result<ir::Def> {
+        let scope = instance
+            .decl
+            .ty_params
+            .iter()
+            .map(|ty_param| &**ty_param)
+            .zip(instance.ty_args.iter().copied())
+            .collect::<TyScope>();
+        let ty_sess = self.tys.ty_sess();
+        let ty_names = instance
+            .ty_args
+            .iter()
+            .map(|ty| ty_sess.ty_name(*ty))
+            .collect::<Vec<_>>();
+        let name = format!("{}<{}>", instance.name, ty_names.join(", "));
+        self.instance_depth.set(instance.depth);
+        self.lower_decl(instance.decl, instance.def_idx, name, &scope, instance.method)
+    }
+
+    fn lower_closure(&self, closure: Closure<'ast>) -> Result<ir::Def> {
+        let name = match &closure.closure.name {
+            Some(name) => format!("{}.{}", &**name, closure.def_idx.index()),
+            None => format!("closure.{}", closure.def_idx.index()),
+        };
+        let span = self.bind_points.borrow()[closure.def_idx];
+        self.instance_depth.set(closure.depth);
+        self.lower_fn(
+            closure.def_idx,
+            name,
+            span,
+            &closure.closure.params,
+            Some(&closure.captures),
+            &closure.closure.body,
+            &closure.scope,
+            false,
+        )
+    }
+
     pub fn complete(self) -> (ir::Ir, ty::TySess) {
         (self.ir, self.tys.into_ty_sess())
     }
@@ -70,7 +238,635 @@ impl<'ast> Lowering<'ast> {
         }
     }
 
-    fn bind(&mut self, ident: &'ast ast::Ident, span: Span) -> Result<ir::DefIdx> {
+    /// The generic function bound to `ident`, if there is one.
+    pub fn generic_fn(&self, ident: &ast::Ident) -> Option<&'ast ast::FnDecl> {
+        self.generic_fns.get(ident).copied()
+    }
+
+    /// The method named `method` of the struct or enum named `owner`, if there is one.
+    /// The method named `method` of the struct or enum `owner`. Called on a value of a type parameter, it is the
+    /// method of the trait that a bound of the type parameter declares it in. Otherwise it is the method declared
+    /// in an impl block of its own, or else the one method of that name among the traits `owner` implements.
+    pub fn method(&self, owner: &ast::Ident, method: &Spanned<ast::Ident>) -> Result<Option<Method<'ast>>> {
+        if let Some(trait_name) = self.bound_methods.get(&method.span()) {
+            return Ok(self.methods.get(&(owner, Some(*trait_name), &**method)).copied());
+        }
+        if let Some(found) = self.methods.get(&(owner, None, &**method)) {
+            return Ok(Some(*found));
+        }
+        let mut candidates = self
+            .methods
+            .iter()
+            .filter(|((ty_name, _, name), _)| *ty_name == owner && *name == &**method)
+            .map(|(_, found)| *found)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|found| found.decl.name.span().start());
+        match &candidates[..] {
+            [] => Ok(None),
+            [found] => Ok(Some(*found)),
+            [..] => Err(Box::from(
+                Diagnostic::new_error(
+                    "ambiguous method call",
+                    Label::new(
+                        self.file_id,
+                        method.span(),
+                        format!(
+                            "'{}' has a method named '{}' from more than one trait",
+                            owner, &**method
+                        ),
+                    ),
+                )
+                .with_secondary_labels(candidates.iter().map(|found| {
+                    Label::new(
+                        self.file_id,
+                        found.decl.name.span(),
+                        format!("declared for '{}' here", found.trait_name.unwrap()),
+                    )
+                }))
+                .with_notes(vec![String::from(
+                    "call it on a value of a type parameter that is bound to only one of these traits",
+                )]),
+            )),
+        }
+    }
+
+    /// The type of the function that is, or will be, defined at `def_idx`.
+    pub fn fn_ty(&self, def_idx: ir::DefIdx) -> ty::Ty {
+        self.fn_tys.borrow()[def_idx]
+    }
+
+    /// Lowers the signature of a function, where the type parameters in `scope` are bound.
+    pub fn lower_fn_ty(&self, decl: &'ast ast::FnDecl, scope: &TyScope<'ast>) -> Result<ty::Ty> {
+        self.lower_signature(&decl.params, &decl.return_ty, scope)
+    }
+
+    /// Lowers the signature of a closure, where the type parameters of the function it is written in are bound.
+    pub fn lower_closure_ty(&self, closure: &'ast ast::Closure, scope: &TyScope<'ast>) -> Result<ty::Ty> {
+        self.lower_signature(&closure.params, &closure.return_ty, scope)
+    }
+
+    fn lower_signature(
+        &self,
+        params: &'ast [Spanned<ast::Binding>],
+        return_ty: &'ast Spanned<ast::Ty>,
+        scope: &TyScope<'ast>,
+    ) -> Result<ty::Ty> {
+        let return_ty = self.tys.lookup_ty_in(return_ty, return_ty.span(), scope)?;
+        let param_tys = params
+            .iter()
+            .map(|binding| self.tys.lookup_binding_in(binding, scope))
+            .collect::<Result<_>>()?;
+        Ok(self.tys.ty_sess().make_fn(return_ty, param_tys))
+    }
+
+    /// The definition of a closure written at `span`, which is queued to be lowered with `captures` bound to the
+    /// values they hold when the closure is made.
+    pub fn bind_closure(
+        &self,
+        closure: &'ast ast::Closure,
+        captures: Vec<Capture<'ast>>,
+        scope: &TyScope<'ast>,
+        span: Span,
+    ) -> Result<(ir::DefIdx, ty::Ty)> {
+        let fn_ty = self.lower_closure_ty(closure, scope)?;
+        let def_idx = self.bind_points.borrow_mut().push(span);
+        self.fn_tys.borrow_mut().push(fn_ty);
+        self.pending.borrow_mut().push_back(Pending::Closure(Closure {
+            closure,
+            def_idx,
+            captures,
+            scope: scope.clone(),
+            depth: self.instance_depth.get(),
+        }));
+        Ok((def_idx, fn_ty))
+    }
+
+    /// The definition of the instance of a generic function for `ty_args`, which is queued to be lowered the
+    /// first time it is asked for.
+    pub fn instantiate_fn(
+        &self,
+        decl: &'ast ast::FnDecl,
+        ty_args: Vec<ty::Ty>,
+        span: Span,
+    ) -> Result<ir::DefIdx> {
+        self.instantiate(decl.name.to_string(), decl, ty_args, span, false)
+    }
+
+    /// The definition of `method` for `ty_args`, which are the type arguments of its impl block followed by its
+    /// own.
+    pub fn instantiate_method(
+        &self,
+        method: Method<'ast>,
+        ty_args: Vec<ty::Ty>,
+        span: Span,
+    ) -> Result<ir::DefIdx> {
+        match method.def_idx {
+            Some(def_idx) => Ok(def_idx),
+            None => self.instantiate(method.qualified_name(), method.decl, ty_args, span, true),
+        }
+    }
+
+    fn instantiate(
+        &self,
+        name: String,
+        decl: &'ast ast::FnDecl,
+        ty_args: Vec<ty::Ty>,
+        span: Span,
+        method: bool,
+    ) -> Result<ir::DefIdx> {
+        if ty_args.len() != decl.ty_params.len() {
+            return Err(Box::from(Diagnostic::new_error(
+                "wrong number of type arguments",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!(
+                        "'{}' takes {} type arguments, but {} were given",
+                        &*decl.name,
+                        decl.ty_params.len(),
+                        ty_args.len()
+                    ),
+                ),
+            )));
+        }
+        if let Some(def_idx) = self.instances.borrow().get(&(name.clone(), ty_args.clone())) {
+            return Ok(*def_idx);
+        }
+        self.check_bounds_satisfied(decl, &ty_args, span)?;
+        let depth = self.instance_depth.get() + 1;
+        if depth > INSTANCE_DEPTH_LIMIT {
+            return Err(Box::from(Diagnostic::new_error(
+                "recursion limit reached",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!(
+                        "'{}' is instantiated with ever larger type arguments",
+                        &*decl.name
+                    ),
+                ),
+            )));
+        }
+        let scope = decl
+            .ty_params
+            .iter()
+            .map(|ty_param| &**ty_param)
+            .zip(ty_args.iter().copied())
+            .collect::<TyScope>();
+        let fn_ty = self.lower_fn_ty(decl, &scope)?;
+        let def_idx = self.bind_points.borrow_mut().push(decl.name.span());
+        self.fn_tys.borrow_mut().push(fn_ty);
+        self.instances
+            .borrow_mut()
+            .insert((name.clone(), ty_args.clone()), def_idx);
+        self.pending.borrow_mut().push_back(Pending::Instance(Instance {
+            name,
+            decl,
+            def_idx,
+            ty_args,
+            depth,
+            method,
+        }));
+        Ok(def_idx)
+    }
+
+    fn rebind_error(&self, ident: &ast::Ident, span: Span, bind_point: Span) -> Box<Diagnostic> {
+        Box::from(
+            Diagnostic::new_error(
+                "attempt to rebind function name",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!("'{}' is already bound to a function", ident),
+                ),
+            )
+            .with_secondary_labels(vec![Label::new(
+                self.file_id,
+                bind_point,
+                "previously bound here",
+            )]),
+        )
+    }
+
+    fn bind_generic(&mut self, decl: &'ast ast::FnDecl) -> Result<()> {
+        if let Some(def_idx) = self.global_map.get(&*decl.name) {
+            return Err(self.rebind_error(&decl.name, decl.name.span(), self.bind_points.borrow()[*def_idx]));
+        }
+        if let Some(previous) = self.generic_fns.get(&*decl.name) {
+            return Err(self.rebind_error(&decl.name, decl.name.span(), previous.name.span()));
+        }
+        if *decl.name == ENTRY_NAME {
+            return Err(Box::from(Diagnostic::new_error(
+                "generic entry point",
+                Label::new(
+                    self.file_id,
+                    decl.name.span(),
+                    format!("'{}' can't have type parameters", ENTRY_NAME),
+                ),
+            )));
+        }
+        self.tys.check_ty_params(&decl.ty_params)?;
+        self.check_bounds(decl)?;
+        self.generic_fns.insert(&decl.name, decl);
+        Ok(())
+    }
+
+    /// Binds the methods of an impl block. The ones that are not generic get definitions of their own, and the
+    /// others are instantiated when they are called.
+    fn bind_impl(&mut self, def: &'ast ast::Impl) -> Result<()> {
+        let ty_param_count = if let Some(generic) = self.tys.generic_struct(&def.ty_name) {
+            generic.ty_params.len()
+        } else if let Some(generic) = self.tys.generic_enum(&def.ty_name) {
+            generic.ty_params.len()
+        } else {
+            self.tys.lookup(&def.ty_name, def.ty_name.span())?;
+            0
+        };
+        if def.ty_params.len() != ty_param_count {
+            return Err(Box::from(Diagnostic::new_error(
+                "wrong number of type parameters",
+                Label::new(
+                    self.file_id,
+                    def.ty_name.span(),
+                    format!(
+                        "'{}' takes {} type parameters, but {} were given",
+                        &*def.ty_name,
+                        ty_param_count,
+                        def.ty_params.len()
+                    ),
+                ),
+            )));
+        }
+        if let Some(trait_name) = &def.trait_name {
+            self.bind_trait_impl(trait_name, def)?;
+        }
+        let trait_name = def.trait_name.as_deref();
+        for decl in def.methods.iter() {
+            if decl.params.first().is_none_or(|param| *param.binder != "self") {
+                return Err(self.missing_receiver_error(&decl.name));
+            }
+            self.tys.check_ty_params(&decl.ty_params)?;
+            self.check_bounds(decl)?;
+            if let Some(previous) = self.methods.get(&(&*def.ty_name, trait_name, &*decl.name)) {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "attempt to rebind method name",
+                        Label::new(
+                            self.file_id,
+                            decl.name.span(),
+                            format!("'{}' already has a method named '{}'", &*def.ty_name, &*decl.name),
+                        ),
+                    )
+                    .with_secondary_labels(vec![Label::new(
+                        self.file_id,
+                        previous.decl.name.span(),
+                        "previously bound here",
+                    )]),
+                ));
+            }
+            let def_idx = if decl.ty_params.is_empty() {
+                let fn_ty = self.lower_fn_ty(decl, &TyScope::new())?;
+                let def_idx = self.bind_points.borrow_mut().push(decl.name.span());
+                self.fn_tys.borrow_mut().push(fn_ty);
+                Some(def_idx)
+            } else {
+                None
+            };
+            self.methods.insert(
+                (&def.ty_name, trait_name, &decl.name),
+                Method {
+                    decl,
+                    def_idx,
+                    owner: &def.ty_name,
+                    trait_name,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn missing_receiver_error(&self, name: &Spanned<ast::Ident>) -> Box<Diagnostic> {
+        Box::from(
+            Diagnostic::new_error(
+                "missing receiver",
+                Label::new(
+                    self.file_id,
+                    name.span(),
+                    format!("'{}' does not take 'self' as its first parameter", &**name),
+                ),
+            )
+            .with_notes(vec![String::from(
+                "functions without a receiver are declared outside of impl blocks",
+            )]),
+        )
+    }
+
+    fn bind_trait(&mut self, def: &'ast ast::Trait) -> Result<()> {
+        if let Some(previous) = self.traits.get(&*def.name) {
+            return Err(Box::from(
+                Diagnostic::new_error(
+                    "attempt to rebind trait name",
+                    Label::new(
+                        self.file_id,
+                        def.name.span(),
+                        format!("'{}' is already bound to a trait", &*def.name),
+                    ),
+                )
+                .with_secondary_labels(vec![Label::new(
+                    self.file_id,
+                    previous.name.span(),
+                    "previously bound here",
+                )]),
+            ));
+        }
+        for (i, sig) in def.methods.iter().enumerate() {
+            if sig.params.first().is_none_or(|param| *param.binder != "self") {
+                return Err(self.missing_receiver_error(&sig.name));
+            }
+            if let Some(previous) = def.methods[..i]
+                .iter()
+                .find(|previous| *previous.name == *sig.name)
+            {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "attempt to rebind method name",
+                        Label::new(
+                            self.file_id,
+                            sig.name.span(),
+                            format!("'{}' already has a method named '{}'", &*def.name, &*sig.name),
+                        ),
+                    )
+                    .with_secondary_labels(vec![Label::new(
+                        self.file_id,
+                        previous.name.span(),
+                        "previously bound here",
+                    )]),
+                ));
+            }
+        }
+        self.traits.insert(&def.name, def);
+        Ok(())
+    }
+
+    fn lookup_trait(&self, name: &Spanned<ast::Ident>) -> Result<&'ast ast::Trait> {
+        self.traits.get(&**name).copied().ok_or_else(|| {
+            Box::from(Diagnostic::new_error(
+                "reference to unbound trait",
+                Label::new(
+                    self.file_id,
+                    name.span(),
+                    format!("'{}' is not bound as a trait", &**name),
+                ),
+            ))
+        })
+    }
+
+    /// Checks that the bounds of a generic function refer to traits, and that the methods it calls on values of
+    /// its type parameters are declared by the traits these are bound to, with as many parameters as are given.
+    /// This holds for every instance of the function, so it is checked even if there are none.
+    fn check_bounds(&mut self, decl: &'ast ast::FnDecl) -> Result<()> {
+        for bound in decl.bounds.iter() {
+            for trait_name in bound.traits.iter() {
+                self.lookup_trait(trait_name)?;
+            }
+        }
+        for call in bounds::bound_method_calls(decl) {
+            let trait_names = decl
+                .bounds
+                .iter()
+                .filter(|bound| *bound.ty_param == *call.ty_param)
+                .flat_map(|bound| bound.traits.iter())
+                .collect::<Vec<_>>();
+            let declared = trait_names
+                .iter()
+                .filter_map(|trait_name| {
+                    let sig = self.traits[&***trait_name]
+                        .methods
+                        .iter()
+                        .find(|sig| *sig.name == **call.method)?;
+                    Some((*trait_name, sig))
+                })
+                .collect::<Vec<_>>();
+            let (trait_name, sig) =
+                match &declared[..] {
+                    [(trait_name, sig)] => (*trait_name, *sig),
+                    [] => {
+                        let bounded = match &trait_names[..] {
+                            [] => format!("'{}' is not bound to any trait", call.ty_param),
+                            _ => format!("none of the traits '{}' is bound to declare it", call.ty_param),
+                        };
+                        return Err(Box::from(
+                            Diagnostic::new_error(
+                                "reference to unbound method",
+                                Label::new(
+                                    self.file_id,
+                                    call.method.span(),
+                                    format!(
+                                        "no method named '{}' can be called on '{}', since {}",
+                                        &**call.method, call.ty_param, bounded
+                                    ),
+                                ),
+                            )
+                            .with_notes(vec![format!(
+                                "bind '{}' to a trait that declares the method, as in '{}: Trait'",
+                                call.ty_param, call.ty_param
+                            )]),
+                        ));
+                    }
+                    [..] => {
+                        return Err(Box::from(
+                            Diagnostic::new_error(
+                                "ambiguous method call",
+                                Label::new(
+                                    self.file_id,
+                                    call.method.span(),
+                                    format!(
+                                        "more than one trait '{}' is bound to declares a method named '{}'",
+                                        call.ty_param, &**call.method
+                                    ),
+                                ),
+                            )
+                            .with_secondary_labels(declared.iter().map(
+                                |(trait_name, _)| Label::new(self.file_id, trait_name.span(), "bound here"),
+                            )),
+                        ));
+                    }
+                };
+            if call.arg_count + 1 != sig.params.len() {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "argument count mismatch",
+                        Label::new(
+                            self.file_id,
+                            call.method.span(),
+                            format!(
+                                "method expected {} parameters but {} were found",
+                                sig.params.len() - 1,
+                                call.arg_count
+                            ),
+                        ),
+                    )
+                    .with_secondary_labels(vec![Label::new(
+                        self.file_id,
+                        sig.span(),
+                        format!("declared by '{}' here", &**trait_name),
+                    )]),
+                ));
+            }
+            self.bound_methods.insert(call.method.span(), trait_name);
+        }
+        Ok(())
+    }
+
+    /// Checks that an `impl Trait for Type` block provides every method of the trait, each with the signature the
+    /// trait declares for it once `Self` is replaced by the implementing type, and nothing else.
+    fn bind_trait_impl(&mut self, trait_name: &'ast Spanned<ast::Ident>, def: &'ast ast::Impl) -> Result<()> {
+        let trait_def = self.lookup_trait(trait_name)?;
+        if let Some(previous) = self.trait_impls.get(&(&**trait_name, &*def.ty_name)) {
+            return Err(Box::from(
+                Diagnostic::new_error(
+                    "conflicting implementations",
+                    Label::new(
+                        self.file_id,
+                        trait_name.span(),
+                        format!("'{}' already implements '{}'", &*def.ty_name, &**trait_name),
+                    ),
+                )
+                .with_secondary_labels(vec![Label::new(
+                    self.file_id,
+                    *previous,
+                    "previously implemented here",
+                )]),
+            ));
+        }
+        let self_ty = ast::Ty::TyName(
+            def.ty_name.to_string(),
+            def.ty_params
+                .iter()
+                .map(|ty_param| ast::Ty::TyName(ty_param.to_string(), vec![]))
+                .collect(),
+        );
+        for decl in def.methods.iter() {
+            let Some(sig) = trait_def.methods.iter().find(|sig| *sig.name == *decl.name) else {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "method is not a member of trait",
+                        Label::new(
+                            self.file_id,
+                            decl.name.span(),
+                            format!(
+                                "'{}' does not declare a method named '{}'",
+                                &**trait_name, &*decl.name
+                            ),
+                        ),
+                    )
+                    .with_notes(vec![String::from(
+                        "other methods are declared in an impl block of their own",
+                    )]),
+                ));
+            };
+            let matches = decl.ty_params.len() == def.ty_params.len()
+                && decl.params.len() == sig.params.len()
+                && decl
+                    .params
+                    .iter()
+                    .zip(sig.params.iter())
+                    .all(|(param, declared)| *param.ty == replace_self_ty(&declared.ty, &self_ty))
+                && *decl.return_ty == replace_self_ty(&sig.return_ty, &self_ty);
+            if !matches {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "incompatible method signature",
+                        Label::new(
+                            self.file_id,
+                            decl.name.span(),
+                            format!(
+                                "'{}' does not have the signature '{}' declares for it",
+                                &*decl.name, &**trait_name
+                            ),
+                        ),
+                    )
+                    .with_secondary_labels(vec![Label::new(
+                        self.file_id,
+                        sig.span(),
+                        "declared here",
+                    )]),
+                ));
+            }
+        }
+        let missing = trait_def
+            .methods
+            .iter()
+            .filter(|sig| def.methods.iter().all(|decl| *decl.name != *sig.name))
+            .map(|sig| format!("'{}'", &*sig.name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(Box::from(Diagnostic::new_error(
+                "missing trait methods",
+                Label::new(
+                    self.file_id,
+                    trait_name.span(),
+                    format!(
+                        "'{}' does not provide {} of '{}'",
+                        &*def.ty_name,
+                        missing.join(", "),
+                        &**trait_name
+                    ),
+                ),
+            )));
+        }
+        self.trait_impls
+            .insert((trait_name, &def.ty_name), trait_name.span());
+        Ok(())
+    }
+
+    /// Checks that the type arguments of an instance implement the traits their type parameters are bound to.
+    fn check_bounds_satisfied(&self, decl: &'ast ast::FnDecl, ty_args: &[ty::Ty], span: Span) -> Result<()> {
+        let ty_sess = self.tys.ty_sess();
+        for bound in decl.bounds.iter() {
+            let Some(ty_arg) = decl
+                .ty_params
+                .iter()
+                .position(|ty_param| **ty_param == *bound.ty_param)
+                .map(|i| ty_args[i])
+            else {
+                continue;
+            };
+            let owner = ty_sess.ty_kind(ty_arg).name().map(String::from);
+            for trait_name in bound.traits.iter() {
+                let implemented = owner
+                    .as_ref()
+                    .is_some_and(|owner| self.trait_impls.contains_key(&(&**trait_name, owner)));
+                if !implemented {
+                    return Err(Box::from(
+                        Diagnostic::new_error(
+                            "unsatisfied trait bound",
+                            Label::new(
+                                self.file_id,
+                                span,
+                                format!(
+                                    "'{}' does not implement '{}'",
+                                    ty_sess.ty_name(ty_arg),
+                                    &**trait_name
+                                ),
+                            ),
+                        )
+                        .with_secondary_labels(vec![Label::new(
+                            self.file_id,
+                            trait_name.span(),
+                            format!("required by this bound on '{}'", &*bound.ty_param),
+                        )]),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, ident: &'ast ast::Ident, span: Span, fn_ty: ty::Ty) -> Result<ir::DefIdx> {
+        if let Some(previous) = self.generic_fns.get(ident) {
+            return Err(self.rebind_error(ident, span, previous.name.span()));
+        }
         if let Some(def_idx) = self.global_map.get(ident) {
             Err(Box::from(
                 Diagnostic::new_error(
@@ -83,18 +879,27 @@ impl<'ast> Lowering<'ast> {
                 )
                 .with_secondary_labels(vec![Label::new(
                     self.file_id,
-                    self.bind_points[*def_idx],
+                    self.bind_points.borrow()[*def_idx],
                     "previously bound here",
                 )]),
             ))
         } else {
-            let def_idx = self.bind_points.push(span);
+            let def_idx = self.bind_points.borrow_mut().push(span);
+            self.fn_tys.borrow_mut().push(fn_ty);
             self.global_map.insert(ident, def_idx);
             Ok(def_idx)
         }
     }
 
-    fn lower_decl(&mut self, decl: &'ast ast::FnDecl) -> Result<ir::Def> {
+    /// Lowers a declared function, which is a method declared in an impl block if `method` is set.
+    fn lower_decl(
+        &self,
+        decl: &'ast ast::FnDecl,
+        def_idx: ir::DefIdx,
+        name: String,
+        scope: &TyScope<'ast>,
+        method: bool,
+    ) -> Result<ir::Def> {
         if RESERVED_NAMES.contains(&&**decl.name) {
             return Err(Box::from(Diagnostic::new_error(
                 "use of reserved name",
@@ -105,15 +910,65 @@ impl<'ast> Lowering<'ast> {
                 ),
             )));
         }
-        let def_idx = self.lookup(&decl.name, decl.name.span())?;
+        self.lower_fn(
+            def_idx,
+            name,
+            decl.name.span(),
+            &decl.params,
+            None,
+            &decl.body,
+            scope,
+            method,
+        )
+    }
+
+    /// Lowers the body of a function or a closure. The bindings a closure captures are bound before its
+    /// parameters, and the first parameter of a method is its receiver.
+    #[allow(clippy::too_many_arguments)]
+    fn lower_fn(
+        &self,
+        def_idx: ir::DefIdx,
+        name: String,
+        span: Span,
+        params: &'ast [Spanned<ast::Binding>],
+        captures: Option<&[Capture<'ast>]>,
+        body: &'ast Spanned<ast::Term>,
+        scope: &TyScope<'ast>,
+        method: bool,
+    ) -> Result<ir::Def> {
         let local_idxr = Idxr::new();
         let block_idxr = Idxr::new();
-        let mut lcx = LoweringCtx::new(self, &local_idxr, &block_idxr, def_idx);
-        let mut param_tys = IdxVec::new();
+        let matches = RefCell::new(vec![]);
+        let fn_ty = self.fn_ty(def_idx);
+        let return_ty = self.tys.ty_sess().ty_kind(fn_ty).return_ty().unwrap();
+        let mut lcx = LoweringCtx::new(
+            self,
+            &local_idxr,
+            &block_idxr,
+            &matches,
+            def_idx,
+            return_ty,
+            scope,
+        );
+        let capture_bindings = captures.map(|captures| {
+            captures
+                .iter()
+                .map(|(ident, span, ty)| {
+                    let local_idx = local_idxr.next().with_span(*span);
+                    lcx.bind(ident, local_idx, *ty);
+                    local_idx
+                })
+                .collect::<Vec<_>>()
+        });
         let mut param_bindings: IdxVec<ty::ParamIdx, ir::LocalIdx> = IdxVec::new();
-        for binding in decl.params.iter() {
+        for (param_idx, binding) in params.iter().enumerate() {
             let local_idx = local_idxr.next().with_span(binding.span());
-            if lcx.bind(&binding.binder, local_idx, None).is_some() {
+            let param_ty = self
+                .tys
+                .ty_sess()
+                .ty_kind(fn_ty)
+                .param_ty(ty::ParamIdx::new(param_idx));
+            if lcx.bind(&binding.binder, local_idx, param_ty).is_some() {
                 return Err(Box::from(Diagnostic::new_error(
                     "attempted to rebind formal parameter",
                     Label::new(
@@ -124,16 +979,38 @@ impl<'ast> Lowering<'ast> {
                 )));
             }
             param_bindings.push(local_idx.with_span(binding.span()));
-            param_tys.push(self.tys.lookup_ty(&binding.ty, binding.ty.span())?);
         }
-        let return_ty = self.tys.lookup_ty(&decl.return_ty, decl.return_ty.span())?;
+        // NOTE the receiver of a method is its first parameter, see `Lowering::bind_impl`
+        let receiver = method.then(|| param_bindings[ty::ParamIdx::new(0)]);
         Ok(ir::Def {
             def_idx,
-            name: decl.name.to_string(),
-            span: decl.name.span(),
-            ty: self.tys.ty_sess().make_fn(return_ty, param_tys),
-            entry: lcx.lower_entry(param_bindings, &decl.body, decl.body.span())?,
+            name,
+            span,
+            ty: fn_ty,
+            entry: lcx.lower_entry(param_bindings, capture_bindings, receiver, body, body.span())?,
             local_idxr,
+            matches: matches.into_inner(),
         })
     }
 }
+
+/// The type written in the signature of a trait with `Self` replaced by the implementing type.
+fn replace_self_ty(ty: &ast::Ty, self_ty: &ast::Ty) -> ast::Ty {
+    match ty {
+        ast::Ty::TyName(name, ty_args) if name == ast::SELF_TY && ty_args.is_empty() => self_ty.clone(),
+        ast::Ty::TyName(name, ty_args) => ast::Ty::TyName(
+            name.clone(),
+            ty_args
+                .iter()
+                .map(|ty_arg| replace_self_ty(ty_arg, self_ty))
+                .collect(),
+        ),
+        ast::Ty::Array(ty, len) => ast::Ty::Array(Box::new(replace_self_ty(ty, self_ty)), *len),
+        ast::Ty::Tuple(tys) => ast::Ty::Tuple(tys.iter().map(|ty| replace_self_ty(ty, self_ty)).collect()),
+        ast::Ty::Fn(param_tys, return_ty) => ast::Ty::Fn(
+            param_tys.iter().map(|ty| replace_self_ty(ty, self_ty)).collect(),
+            Box::new(replace_self_ty(return_ty, self_ty)),
+        ),
+        _ => ty.clone(),
+    }
+}
@@ -1,23 +1,202 @@
 use crate::{
-    idx::Idxr,
+    captures,
+    idx::{Idx, Idxr},
     idx_vec::{IdxVec, IntoIdxVec},
     ir,
-    lowering::Lowering,
+    lowering::{Lowering, Method},
     ty,
+    ty_lowering::TyScope,
+    uses::{self, Use},
 };
-use alc_diagnostic::{Diagnostic, Label, Result, Span};
+use alc_diagnostic::{Diagnostic, Label, Result, Span, Spanned};
 use alc_parser::ast;
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+/// A value being matched on: the source of a match, or a value held by a part of it that a pattern took apart.
+type Occurrence = (ir::LocalIdx, Option<ty::Ty>);
+
+/// An arm pattern with its names resolved, before the arms of its match are compiled into switches.
+#[derive(Clone, Debug)]
+enum Pattern<'ast> {
+    Wild,
+    Binding(&'ast ast::Ident),
+    Literal(ir::PatternKind),
+    Variant {
+        ty: ty::Ty,
+        discriminant: ty::VariantIdx,
+        fields: IdxVec<ty::FieldIdx, Spanned<Pattern<'ast>>>,
+    },
+    Record {
+        ty: ty::Ty,
+        fields: IdxVec<ty::FieldIdx, Spanned<Pattern<'ast>>>,
+    },
+    Or(Vec<Spanned<Pattern<'ast>>>),
+}
+
+/// The constructor a pattern tests for, which becomes an arm of a switch.
+#[derive(Clone, Debug, PartialEq)]
+enum Head {
+    Bool(bool),
+    Literal(String),
+    Variant(ty::Ty, ty::VariantIdx),
+    Record(ty::Ty),
+}
+
+impl<'ast> Pattern<'ast> {
+    fn head(&self) -> Option<Head> {
+        match self {
+            Pattern::Wild | Pattern::Binding(_) | Pattern::Or(_) => None,
+            Pattern::Literal(ir::PatternKind::BoolLiteral(value)) => Some(Head::Bool(*value)),
+            Pattern::Literal(kind) => Some(Head::Literal(format!("{:?}", kind))),
+            Pattern::Variant { ty, discriminant, .. } => Some(Head::Variant(*ty, *discriminant)),
+            Pattern::Record { ty, .. } => Some(Head::Record(*ty)),
+        }
+    }
+
+    /// The patterns for the values the constructor holds.
+    fn sub_patterns(&self) -> Vec<Spanned<Pattern<'ast>>> {
+        match self {
+            Pattern::Variant { fields, .. } | Pattern::Record { fields, .. } => {
+                fields.values().cloned().collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    fn bound_names(&self) -> Vec<&'ast ast::Ident> {
+        let mut names = match self {
+            Pattern::Binding(ident) => vec![*ident],
+            Pattern::Variant { fields, .. } | Pattern::Record { fields, .. } => {
+                fields.values().flat_map(|field| field.bound_names()).collect()
+            }
+            // NOTE every alternative binds the same names
+            Pattern::Or(alternatives) => alternatives[0].bound_names(),
+            Pattern::Wild | Pattern::Literal(_) => vec![],
+        };
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn written(&self) -> ir::WrittenPattern {
+        match self {
+            Pattern::Wild | Pattern::Binding(_) => ir::WrittenPattern::Wild,
+            Pattern::Literal(kind) => ir::WrittenPattern::Literal(kind.clone()),
+            Pattern::Variant {
+                ty,
+                discriminant,
+                fields,
+            } => ir::WrittenPattern::Variant {
+                ty: *ty,
+                discriminant: *discriminant,
+                fields: fields.values().map(|field| field.written()).collect(),
+            },
+            Pattern::Record { ty, fields } => ir::WrittenPattern::Record {
+                ty: *ty,
+                fields: fields.values().map(|field| field.written()).collect(),
+            },
+            Pattern::Or(alternatives) => ir::WrittenPattern::Or(
+                alternatives
+                    .iter()
+                    .map(|alternative| alternative.written())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// A name bound by a pattern, with the local holding its value and the type of the value if it is known.
+type Binding<'ast> = (&'ast ast::Ident, ir::LocalIdx, Option<ty::Ty>);
+
+/// A row of the pattern matrix: the patterns an arm has left to test, one for each occurrence, and the names its
+/// patterns have bound so far.
+#[derive(Clone, Debug)]
+struct Row<'ast> {
+    patterns: Vec<Spanned<Pattern<'ast>>>,
+    bindings: Vec<Binding<'ast>>,
+    arm: &'ast ast::Arm,
+    arm_idx: usize,
+}
+
+/// The arms that the leaves of a decision tree jump to, each with the block its body is lowered into and the
+/// bindings that the first leaf reaching it passed.
+type ReachedArms<'ast> = RefCell<Vec<Option<(ir::BlockIdx, Vec<Binding<'ast>>)>>>;
+
+impl<'ast> Row<'ast> {
+    /// Splits the row into one row for each alternative of its or-patterns, and turns its identifiers into
+    /// bindings of the occurrences they match.
+    fn expand(mut self, occurrences: &[Occurrence]) -> Vec<Row<'ast>> {
+        for column in 0..self.patterns.len() {
+            let span = self.patterns[column].span();
+            match &*self.patterns[column] {
+                Pattern::Binding(ident) => {
+                    let (local_idx, ty) = occurrences[column];
+                    self.bindings.push((*ident, local_idx.with_span(span), ty));
+                    self.patterns[column] = span.span(Pattern::Wild);
+                }
+                Pattern::Or(alternatives) => {
+                    return alternatives
+                        .iter()
+                        .flat_map(|alternative| {
+                            let mut row = self.clone();
+                            row.patterns[column] = alternative.clone();
+                            row.expand(occurrences)
+                        })
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        vec![self]
+    }
+
+    /// The row left for a value built with `head`, with the occurrence in `column` replaced by the `arity` values
+    /// it holds, or `None` when the row does not match such a value.
+    fn specialize(&self, column: usize, head: &Head, arity: usize) -> Option<Row<'ast>> {
+        let pattern = &self.patterns[column];
+        let sub_patterns = match pattern.head() {
+            None => vec![pattern.span().span(Pattern::Wild); arity],
+            Some(other) if other == *head => pattern.sub_patterns(),
+            Some(_) => return None,
+        };
+        let mut row = self.clone();
+        row.patterns.splice(column..=column, sub_patterns);
+        Some(row)
+    }
+}
+
+/// Where the value a term ends with goes.
+#[derive(Clone, Copy, Debug)]
+enum Tail {
+    /// It is returned from the function.
+    Return,
+    /// It is the value of the `if` or `match` expression the term is a branch of, which is expected to have the
+    /// type if it is known.
+    Yield(Option<ty::Ty>),
+    /// It is evaluated and discarded, since the `if` or `match` the term is a branch of is a statement.
+    Discard,
+    /// It is evaluated and discarded at the end of a loop body, which then starts its next iteration.
+    Continue,
+}
 
 #[derive(Debug)]
 pub(super) struct LoweringCtx<'lcx, 'ast> {
     sess: &'lcx Lowering<'ast>,
     local_idxr: &'lcx Idxr<ir::LocalIdx>,
     block_idxr: &'lcx Idxr<ir::BlockIdx>,
+    matches: &'lcx RefCell<Vec<ir::WrittenMatch>>,
     def_idx: ir::DefIdx,
+    return_ty: ty::Ty,
+    ty_scope: &'lcx TyScope<'ast>,
+    in_loop: bool,
+    tail: Tail,
     parent: Option<&'lcx LoweringCtx<'lcx, 'ast>>,
     local_map: HashMap<&'ast ast::Ident, (ir::LocalIdx, Option<ty::Ty>)>,
     field_map: HashMap<ir::LocalIdx, IdxVec<ty::FieldIdx, ir::LocalIdx>>,
+    slots: HashSet<ir::LocalIdx>,
     instructions: Vec<ir::Instruction>,
 }
 
@@ -26,16 +205,25 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
         sess: &'lcx Lowering<'ast>,
         local_idxr: &'lcx Idxr<ir::LocalIdx>,
         block_idxr: &'lcx Idxr<ir::BlockIdx>,
+        matches: &'lcx RefCell<Vec<ir::WrittenMatch>>,
         def_idx: ir::DefIdx,
+        return_ty: ty::Ty,
+        ty_scope: &'lcx TyScope<'ast>,
     ) -> LoweringCtx<'lcx, 'ast> {
         LoweringCtx {
             sess,
             local_idxr,
             block_idxr,
+            matches,
             def_idx,
+            return_ty,
+            ty_scope,
+            in_loop: false,
+            tail: Tail::Return,
             parent: None,
             local_map: HashMap::new(),
             field_map: HashMap::new(),
+            slots: HashSet::new(),
             instructions: vec![],
         }
     }
@@ -43,12 +231,16 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
     pub(super) fn lower_entry(
         self,
         param_bindings: IdxVec<ty::ParamIdx, ir::LocalIdx>,
+        capture_bindings: Option<Vec<ir::LocalIdx>>,
+        receiver: Option<ir::LocalIdx>,
         term: &'ast ast::Term,
         span: Span,
     ) -> Result<ir::Entry> {
         Ok(ir::Entry {
             owner: self.def_idx,
             param_bindings,
+            capture_bindings,
+            receiver,
             body: self.lower_term_to_block(term, span)?,
         })
     }
@@ -58,14 +250,35 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
             sess: self.sess,
             local_idxr: self.local_idxr,
             block_idxr: self.block_idxr,
+            matches: self.matches,
             def_idx: self.def_idx,
+            return_ty: self.return_ty,
+            ty_scope: self.ty_scope,
+            in_loop: self.in_loop,
+            tail: self.tail,
             parent: Some(self),
             local_map: HashMap::new(),
             field_map: HashMap::new(),
+            slots: HashSet::new(),
             instructions: vec![],
         }
     }
 
+    fn mk_loop_child(&'lcx self) -> LoweringCtx<'lcx, 'ast> {
+        LoweringCtx {
+            in_loop: true,
+            tail: Tail::Continue,
+            ..self.mk_child()
+        }
+    }
+
+    fn mk_branch_child(&'lcx self, tail: Tail) -> LoweringCtx<'lcx, 'ast> {
+        LoweringCtx {
+            tail,
+            ..self.mk_child()
+        }
+    }
+
     fn lookup(&self, ident: &'ast ast::Ident, span: Span) -> Result<(ir::LocalIdx, Option<ty::Ty>)> {
         if let Some(local_idx) = self.local_map.get(ident) {
             Ok(*local_idx)
@@ -83,52 +296,756 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
         }
     }
 
-    fn lookup_field(
+    /// Whether the local is a mutable binding, whose value has to be read from its slot.
+    fn is_slot(&self, local_idx: ir::LocalIdx) -> bool {
+        self.slots.contains(&local_idx) || self.parent.is_some_and(|parent| parent.is_slot(local_idx))
+    }
+
+    /// The declared function named by `stream` when it is used as a value, which it is when the name is not
+    /// bound here.
+    fn fn_value(&self, stream: &'ast [Spanned<ast::Ident>]) -> Option<ir::DefIdx> {
+        match stream {
+            [name] if self.lookup(name, name.span()).is_err() => self.sess.lookup(name, name.span()).ok(),
+            _ => None,
+        }
+    }
+
+    /// The value a binding holds at this point, which is copied out of the slot of a mutable binding.
+    fn read_binding(&mut self, local_idx: ir::LocalIdx, ty: Option<ty::Ty>, span: Span) -> ir::LocalIdx {
+        if !self.is_slot(local_idx) {
+            return local_idx.with_span(span);
+        }
+        let idx = self.local_idxr.next().with_span(span);
+        self.instructions.push(ir::Instruction {
+            span,
+            kind: ir::InstructionKind::Let {
+                binding: idx,
+                ty,
+                expr: ir::Expr {
+                    local_idx: idx,
+                    span,
+                    kind: ir::ExprKind::Var(local_idx, vec![]),
+                },
+            },
+        });
+        idx
+    }
+
+    /// Resolves the fields of `stream[1..]` starting from the type of the binding `stream[0]`.
+    fn lower_field_path(
         &self,
-        local_idx: ir::LocalIdx,
-        field_idx: ty::FieldIdx,
+        ty: Option<ty::Ty>,
+        stream: &'ast [Spanned<ast::Ident>],
         span: Span,
-    ) -> Result<ir::LocalIdx> {
-        if let Some(field_map) = self.field_map.get(&local_idx) {
-            if let Some(field_local_idx) = field_map.get(field_idx) {
-                Ok(field_local_idx.with_span(span))
-            } else {
-                Err(Box::from(Diagnostic::new_error(
-                    "reference to unbound field",
+    ) -> Result<(Vec<ty::FieldIdx>, Option<ty::Ty>)> {
+        let mut field_idxes = vec![];
+        let mut ty = ty;
+        for field in stream.iter().skip(1) {
+            let struct_ty = ty.ok_or_else(|| {
+                Diagnostic::new_error(
+                    "type annotations needed",
                     Label::new(
                         self.sess.file_id,
                         span,
-                        format!("'{:?}' is not bound here (while lowering)", field_idx),
+                        format!(
+                            "the type of '{}' must be annotated to access its fields",
+                            *stream[0]
+                        ),
                     ),
-                )))
+                )
+            })?;
+            let field_idx = self.sess.tys.lookup_field(struct_ty, field, field.span())?;
+            ty = self.sess.tys.ty_sess().ty_kind(struct_ty).field_ty(field_idx);
+            field_idxes.push(field_idx);
+        }
+        Ok((field_idxes, ty))
+    }
+
+    /// The type of an array element, when the type of the array is known while lowering.
+    fn element_ty(&self, ty: Option<ty::Ty>) -> Option<ty::Ty> {
+        ty.and_then(|ty| {
+            self.sess
+                .tys
+                .ty_sess()
+                .ty_kind(ty)
+                .as_array()
+                .map(|array| array.element_ty)
+        })
+    }
+
+    /// The type of a variable, a field or an element of either, when it is known while lowering.
+    fn place_ty(&self, expr: &'ast ast::Expr, span: Span) -> Result<Option<ty::Ty>> {
+        Ok(match expr {
+            ast::Expr::Var(stream) => {
+                let (_, ty) = self.lookup(stream.first().unwrap(), span)?;
+                self.lower_field_path(ty, stream, span)?.1
             }
-        } else if let Some(parent) = self.parent {
-            parent.lookup_field(local_idx, field_idx, span)
-        } else {
-            Err(Box::from(Diagnostic::new_error(
-                "reference to unbound field",
+            ast::Expr::Index { target, index: _ } => self.element_ty(self.place_ty(target, target.span())?),
+            _ => None,
+        })
+    }
+
+    /// Lowers the array being indexed, naming the binding and fields that hold it so the element is read in place.
+    fn lower_array_place(
+        &mut self,
+        array_ty: Option<ty::Ty>,
+        target: &'ast ast::Expr,
+        span: Span,
+    ) -> Result<(ir::LocalIdx, Vec<ty::FieldIdx>)> {
+        if let ast::Expr::Var(stream) = target {
+            if self.fn_value(stream).is_none() {
+                let (local_idx, ty) = self.lookup(stream.first().unwrap(), span)?;
+                let (field_idxes, _) = self.lower_field_path(ty, stream, span)?;
+                return Ok(
+                    match self
+                        .lookup_fields(local_idx, &field_idxes)
+                        .filter(|_| !self.is_slot(local_idx))
+                    {
+                        Some(field_local_idx) => (field_local_idx.with_span(span), vec![]),
+                        None => (local_idx.with_span(span), field_idxes),
+                    },
+                );
+            }
+        }
+        Ok((self.lower_expr(array_ty, target, span)?, vec![]))
+    }
+
+    /// Lowers an array index, rejecting a constant index that is known to be out of bounds.
+    fn lower_index(
+        &mut self,
+        array_ty: Option<ty::Ty>,
+        index: &'ast ast::Expr,
+        span: Span,
+    ) -> Result<ir::LocalIdx> {
+        let size = array_ty.and_then(|ty| {
+            self.sess
+                .tys
+                .ty_sess()
+                .ty_kind(ty)
+                .as_array()
+                .map(|array| array.size)
+        });
+        if let (Some(size), ast::Expr::NumberLiteral(literal)) = (size, index) {
+            if literal.value >= size as u64 {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "index out of bounds",
+                        Label::new(
+                            self.sess.file_id,
+                            span,
+                            format!("the length is {} but the index is {}", size, literal.value),
+                        ),
+                    )
+                    .with_notes(vec!["this would abort at runtime".to_owned()]),
+                ));
+            }
+        }
+        self.lower_expr(None, index, span)
+    }
+
+    fn lookup_field(&self, local_idx: ir::LocalIdx, field_idx: ty::FieldIdx) -> Option<ir::LocalIdx> {
+        match self.field_map.get(&local_idx) {
+            Some(field_map) => field_map.get(field_idx).copied(),
+            None => self
+                .parent
+                .and_then(|parent| parent.lookup_field(local_idx, field_idx)),
+        }
+    }
+
+    /// Finds the binding holding the field at the end of `field_idxes`, if the record it belongs to was built in
+    /// this function rather than passed in or returned from a call.
+    fn lookup_fields(&self, local_idx: ir::LocalIdx, field_idxes: &[ty::FieldIdx]) -> Option<ir::LocalIdx> {
+        field_idxes.iter().try_fold(local_idx, |local_idx, field_idx| {
+            self.lookup_field(local_idx, *field_idx)
+        })
+    }
+
+    /// Puts the fields given for a variant in the order they were declared in, checking that they are given the
+    /// way they were declared.
+    fn resolve_variant_fields<T>(
+        &self,
+        ty: ty::Ty,
+        discriminant: ty::VariantIdx,
+        fields: &'ast ast::Fields<T>,
+        span: Span,
+    ) -> Result<IdxVec<ty::FieldIdx, &'ast Spanned<T>>> {
+        let (named, count) = self
+            .sess
+            .tys
+            .ty_sess()
+            .ty_kind(ty)
+            .variant(discriminant)
+            .map_or((false, 0), |variant| (variant.is_named(), variant.fields.len()));
+        let malformed = |label: String| -> Box<Diagnostic> {
+            Box::from(Diagnostic::new_error(
+                "malformed variant",
+                Label::new(self.sess.file_id, span, label),
+            ))
+        };
+        match fields {
+            ast::Fields::Named(fields) => {
+                let mut resolved = HashMap::new();
+                for (field, value) in fields.iter() {
+                    let field_idx =
+                        self.sess
+                            .tys
+                            .lookup_variant_field(ty, discriminant, field, field.span())?;
+                    if resolved.insert(field_idx, value).is_some() {
+                        return Err(malformed(format!("'{}' is given more than once", &**field)));
+                    }
+                }
+                resolved
+                    .into_idx_vec()
+                    .filter(|fields| fields.len() == count)
+                    .ok_or_else(|| malformed("not all fields are given".to_owned()))
+            }
+            ast::Fields::Tuple(_) if named => Err(malformed(
+                "the fields of this variant must be given by name".to_owned(),
+            )),
+            ast::Fields::Tuple(values) if values.len() == count => Ok(values.iter().collect()),
+            ast::Fields::Tuple(values) => Err(malformed(format!(
+                "expected {} fields, found {}",
+                count,
+                values.len()
+            ))),
+            ast::Fields::Unit if count == 0 => Ok(IdxVec::new()),
+            ast::Fields::Unit => Err(malformed(format!(
+                "this variant has {} fields, but none are given",
+                count
+            ))),
+        }
+    }
+
+    /// Lowers a type written in the function being lowered, where its type parameters are bound.
+    #[inline]
+    fn lookup_ty(&self, ty: &'ast ast::Ty, span: Span) -> Result<ty::Ty> {
+        self.sess.tys.lookup_ty_in(ty, span, self.ty_scope)
+    }
+
+    /// The type of an expression, when it is known before the expression is lowered.
+    fn known_ty(&self, expr: &'ast ast::Expr, span: Span) -> Option<ty::Ty> {
+        let ty_sess = self.sess.tys.ty_sess();
+        match expr {
+            ast::Expr::NumberLiteral(ast::NumberLiteral {
+                suffix: Some(suffix), ..
+            })
+            | ast::Expr::FloatLiteral(ast::FloatLiteral {
+                suffix: Some(suffix), ..
+            }) => self.lookup_ty(suffix, span).ok(),
+            ast::Expr::NumberLiteral(_) | ast::Expr::FloatLiteral(_) => None,
+            ast::Expr::BoolLiteral(_) => Some(ty_sess.make_bool()),
+            ast::Expr::StringLiteral(_) => Some(ty_sess.make_string()),
+            ast::Expr::ArrayLiteral(elements) => {
+                let first = elements.first()?;
+                let element_ty = self.known_ty(first, first.span())?;
+                Some(ty_sess.make_array(element_ty, elements.len() as i32))
+            }
+            ast::Expr::Var(stream) => match self.fn_value(stream) {
+                Some(def_idx) => Some(self.sess.fn_ty(def_idx)),
+                None => self.place_ty(expr, span).ok().flatten(),
+            },
+            ast::Expr::Index { .. } => self.place_ty(expr, span).ok().flatten(),
+            ast::Expr::Unop { kind: _, operand } => self.known_ty(operand, operand.span()),
+            ast::Expr::Binop { kind, left, right } => match **kind {
+                ast::BinopKind::LogicalAnd | ast::BinopKind::LogicalOr => Some(ty_sess.make_bool()),
+                kind if self.lower_binop_kind(kind).is_comparison() => Some(ty_sess.make_bool()),
+                _ => self
+                    .known_ty(left, left.span())
+                    .or_else(|| self.known_ty(right, right.span())),
+            },
+            ast::Expr::Call { target, .. } if self.lookup(target, target.span()).is_ok() => {
+                let (_, fn_ty) = self.lookup(target, target.span()).ok()?;
+                let return_ty = ty_sess.ty_kind(fn_ty?).return_ty();
+                return_ty
+            }
+            ast::Expr::Call {
+                target,
+                ty_args,
+                args,
+            } => {
+                let fn_ty = match self.resolve_call(None, target, ty_args, args).ok()? {
+                    Some((decl, ty_args, _)) => {
+                        let scope = decl
+                            .ty_params
+                            .iter()
+                            .map(|ty_param| &**ty_param)
+                            .zip(ty_args)
+                            .collect::<TyScope>();
+                        self.sess.lower_fn_ty(decl, &scope).ok()?
+                    }
+                    None => self.sess.fn_ty(self.sess.lookup(target, target.span()).ok()?),
+                };
+                let return_ty = ty_sess.ty_kind(fn_ty).return_ty();
+                return_ty
+            }
+            ast::Expr::MethodCall { receiver, method, .. } if self.field_fn(receiver, method).is_some() => {
+                let (_, fn_ty) = self.field_fn(receiver, method)?;
+                let return_ty = ty_sess.ty_kind(fn_ty).return_ty();
+                return_ty
+            }
+            ast::Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                let (method, ty_args, _) = self.resolve_method(None, receiver, method, args).ok()?;
+                let fn_ty = match method.def_idx {
+                    Some(def_idx) => self.sess.fn_ty(def_idx),
+                    None => {
+                        let scope = method
+                            .decl
+                            .ty_params
+                            .iter()
+                            .map(|ty_param| &**ty_param)
+                            .zip(ty_args)
+                            .collect::<TyScope>();
+                        self.sess.lower_fn_ty(method.decl, &scope).ok()?
+                    }
+                };
+                let return_ty = ty_sess.ty_kind(fn_ty).return_ty();
+                return_ty
+            }
+            ast::Expr::Variant {
+                enum_name,
+                ty_args,
+                discriminant,
+                fields,
+            } => self
+                .resolve_variant_ty(None, enum_name, ty_args, discriminant, fields)
+                .ok(),
+            ast::Expr::Record {
+                struct_name,
+                ty_args,
+                fields,
+            } => self.resolve_record_ty(None, struct_name, ty_args, fields).ok(),
+            ast::Expr::Tuple(elements) => self.resolve_tuple_ty(elements).ok(),
+            ast::Expr::Closure(closure) => self.sess.lower_closure_ty(closure, self.ty_scope).ok(),
+            _ => None,
+        }
+    }
+
+    /// Infers type parameters from values given where types that mention them are declared, and then from the
+    /// type expected of the result. Only after both are unsuffixed literals given for a bare type parameter
+    /// taken to be `i32` or `f64`, and the type parameters inferred like this are returned.
+    fn infer_from_exprs(
+        &self,
+        ty_params: &'ast [Spanned<ast::Ident>],
+        given: &[(&'ast ast::Ty, &'ast Spanned<ast::Expr>)],
+        expected: Option<(&'ast ast::Ty, ty::Ty)>,
+        inferred: &mut TyScope<'ast>,
+    ) -> Vec<&'ast ast::Ident> {
+        for (declared, expr) in given.iter() {
+            if let Some(actual) = self.known_ty(expr, expr.span()) {
+                self.sess.tys.infer(ty_params, declared, actual, inferred);
+            }
+        }
+        if let Some((declared, actual)) = expected {
+            self.sess.tys.infer(ty_params, declared, actual, inferred);
+        }
+        let ty_sess = self.sess.tys.ty_sess();
+        let mut defaulted = vec![];
+        for (declared, expr) in given.iter() {
+            let default = match &***expr {
+                ast::Expr::NumberLiteral(ast::NumberLiteral { suffix: None, .. }) => ty_sess.make_i32(),
+                ast::Expr::FloatLiteral(ast::FloatLiteral { suffix: None, .. }) => ty_sess.make_f64(),
+                _ => continue,
+            };
+            let count = inferred.len();
+            self.sess.tys.infer(ty_params, declared, default, inferred);
+            match declared {
+                ast::Ty::TyName(ty_param, _) if inferred.len() > count => defaulted.push(ty_param),
+                _ => {}
+            }
+        }
+        defaulted
+    }
+
+    /// The type arguments of a generic item, which are the ones written if there are any, and are otherwise
+    /// inferred.
+    fn resolve_ty_args(
+        &self,
+        name: &'ast Spanned<ast::Ident>,
+        ty_params: &'ast [Spanned<ast::Ident>],
+        ty_args: &'ast [Spanned<ast::Ty>],
+        infer: impl FnOnce(&mut TyScope<'ast>),
+    ) -> Result<Vec<ty::Ty>> {
+        if !ty_args.is_empty() {
+            return ty_args
+                .iter()
+                .map(|ty_arg| self.lookup_ty(ty_arg, ty_arg.span()))
+                .collect();
+        }
+        let mut inferred = TyScope::new();
+        infer(&mut inferred);
+        ty_params
+            .iter()
+            .map(|ty_param| {
+                inferred.get(&**ty_param).copied().ok_or_else(|| {
+                    Box::from(
+                        Diagnostic::new_error(
+                            "type annotations needed",
+                            Label::new(
+                                self.sess.file_id,
+                                name.span(),
+                                format!(
+                                    "can't infer the type parameter '{}' of '{}'",
+                                    &**ty_param, &**name
+                                ),
+                            ),
+                        )
+                        .with_notes(vec![format!(
+                            "give the type arguments explicitly, as in '{}::<..>'",
+                            &**name
+                        )]),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// The enum or struct type that `name` refers to. When it is generic and no type arguments are written, it is
+    /// the expected type if that is an instance of it, and otherwise the instance `infer` points to.
+    fn resolve_named_ty(
+        &self,
+        ty: Option<ty::Ty>,
+        name: &'ast Spanned<ast::Ident>,
+        ty_params: Option<&'ast [Spanned<ast::Ident>]>,
+        ty_args: &'ast [Spanned<ast::Ty>],
+        infer: impl FnOnce(&mut TyScope<'ast>),
+    ) -> Result<ty::Ty> {
+        let ty_args = match ty_params {
+            Some(_) if ty_args.is_empty() && ty.is_some_and(|ty| self.is_instance_of(ty, name)) => {
+                return Ok(ty.unwrap());
+            }
+            Some(ty_params) => self.resolve_ty_args(name, ty_params, ty_args, infer)?,
+            None => ty_args
+                .iter()
+                .map(|ty_arg| self.lookup_ty(ty_arg, ty_arg.span()))
+                .collect::<Result<_>>()?,
+        };
+        self.sess.tys.lookup_named(name, ty_args, name.span())
+    }
+
+    #[inline]
+    fn is_instance_of(&self, ty: ty::Ty, name: &ast::Ident) -> bool {
+        self.sess.tys.ty_sess().ty_kind(ty).name() == Some(name.as_str())
+    }
+
+    fn resolve_variant_ty(
+        &self,
+        ty: Option<ty::Ty>,
+        enum_name: &'ast Spanned<ast::Ident>,
+        ty_args: &'ast [Spanned<ast::Ty>],
+        discriminant: &'ast ast::Ident,
+        fields: &'ast ast::Fields<ast::Expr>,
+    ) -> Result<ty::Ty> {
+        let generic = self.sess.tys.generic_enum(enum_name);
+        let ty_params = generic.map(|def| &def.ty_params[..]);
+        self.resolve_named_ty(ty, enum_name, ty_params, ty_args, |inferred| {
+            let Some(def) = generic else {
+                return;
+            };
+            let declared = def.variants.iter().find(|variant| *variant.name == *discriminant);
+            let given = match (declared.map(|variant| &variant.fields), fields) {
+                (Some(ast::Fields::Tuple(tys)), ast::Fields::Tuple(values)) => {
+                    tys.iter().map(|ty| &**ty).zip(values.iter()).collect()
+                }
+                (Some(ast::Fields::Named(tys)), ast::Fields::Named(values)) => values
+                    .iter()
+                    .filter_map(|(field, value)| {
+                        tys.iter()
+                            .find(|(name, _)| **name == **field)
+                            .map(|(_, ty)| (&**ty, value))
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            self.infer_from_exprs(&def.ty_params, &given, None, inferred);
+        })
+    }
+
+    fn resolve_record_ty(
+        &self,
+        ty: Option<ty::Ty>,
+        struct_name: &'ast Spanned<ast::Ident>,
+        ty_args: &'ast [Spanned<ast::Ty>],
+        fields: &'ast [(Spanned<ast::Ident>, Spanned<ast::Expr>)],
+    ) -> Result<ty::Ty> {
+        let generic = self.sess.tys.generic_struct(struct_name);
+        let ty_params = generic.map(|def| &def.ty_params[..]);
+        self.resolve_named_ty(ty, struct_name, ty_params, ty_args, |inferred| {
+            let Some(def) = generic else {
+                return;
+            };
+            let given = fields
+                .iter()
+                .filter_map(|(field, value)| {
+                    def.fields
+                        .iter()
+                        .find(|binding| *binding.binder == **field)
+                        .map(|binding| (&*binding.ty, value))
+                })
+                .collect::<Vec<_>>();
+            self.infer_from_exprs(&def.ty_params, &given, None, inferred);
+        })
+    }
+
+    /// The type expected of a tuple, if it is one with as many fields as there are elements.
+    fn expected_tuple_ty(&self, ty: Option<ty::Ty>, elements: &'ast [Spanned<ast::Expr>]) -> Option<ty::Ty> {
+        ty.filter(|ty| {
+            self.sess
+                .tys
+                .ty_sess()
+                .ty_kind(*ty)
+                .as_tuple()
+                .is_some_and(|tuple| tuple.fields.len() == elements.len())
+        })
+    }
+
+    /// The type of a tuple as far as lowering needs it, to take the tuple apart in patterns and read its fields.
+    /// Unsuffixed literals are taken to be `i32` or `f64` here, while the type checker infers the types of the
+    /// elements the tuple is built from.
+    fn resolve_tuple_ty(&self, elements: &'ast [Spanned<ast::Expr>]) -> Result<ty::Ty> {
+        let ty_sess = self.sess.tys.ty_sess();
+        let field_tys = elements
+            .iter()
+            .map(|element| {
+                let default = match &**element {
+                    ast::Expr::NumberLiteral(ast::NumberLiteral { suffix: None, .. }) => {
+                        Some(ty_sess.make_i32())
+                    }
+                    ast::Expr::FloatLiteral(ast::FloatLiteral { suffix: None, .. }) => {
+                        Some(ty_sess.make_f64())
+                    }
+                    _ => None,
+                };
+                self.known_ty(element, element.span()).or(default).ok_or_else(|| {
+                    Box::from(
+                        Diagnostic::new_error(
+                            "type annotations needed",
+                            Label::new(
+                                self.sess.file_id,
+                                element.span(),
+                                "the type of this element must be known to build the tuple",
+                            ),
+                        )
+                        .with_notes(vec![String::from(
+                            "give the tuple a type, as in 'let pair: (i32, string) = ..'",
+                        )]),
+                    )
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(ty_sess.make_tuple(field_tys))
+    }
+
+    /// The generic function a call refers to, along with the type arguments of the instance it calls and the type
+    /// parameters among them taken from the default type of literals. These are the ones written, or are otherwise
+    /// inferred from the arguments and the type expected of the result.
+    #[allow(clippy::type_complexity)]
+    fn resolve_call(
+        &self,
+        ty: Option<ty::Ty>,
+        target: &'ast Spanned<ast::Ident>,
+        ty_args: &'ast [Spanned<ast::Ty>],
+        args: &'ast [Spanned<ast::Expr>],
+    ) -> Result<Option<(&'ast ast::FnDecl, Vec<ty::Ty>, Vec<&'ast ast::Ident>)>> {
+        let Some(decl) = self.sess.generic_fn(target) else {
+            if let Some(ty_arg) = ty_args.first() {
+                return Err(Box::from(Diagnostic::new_error(
+                    "wrong number of type arguments",
+                    Label::new(
+                        self.sess.file_id,
+                        ty_arg.span(),
+                        format!("'{}' takes no type arguments", &**target),
+                    ),
+                )));
+            }
+            return Ok(None);
+        };
+        let mut defaulted = vec![];
+        let ty_args = self.resolve_ty_args(target, &decl.ty_params, ty_args, |inferred| {
+            let given = decl
+                .params
+                .iter()
+                .map(|param| &*param.ty)
+                .zip(args.iter())
+                .collect::<Vec<_>>();
+            let expected = ty.map(|ty| (&*decl.return_ty, ty));
+            defaulted = self.infer_from_exprs(&decl.ty_params, &given, expected, inferred);
+        })?;
+        Ok(Some((decl, ty_args, defaulted)))
+    }
+
+    /// The field named `method` of the record `receiver` evaluates to, along with its type, when the field holds a
+    /// function and the record has no method of that name. Then `receiver.method(..)` calls the function held.
+    fn field_fn(
+        &self,
+        receiver: &'ast ast::Expr,
+        method: &'ast Spanned<ast::Ident>,
+    ) -> Option<(ty::FieldIdx, ty::Ty)> {
+        let receiver_ty = self.known_ty(receiver, method.span())?;
+        let ty_sess = self.sess.tys.ty_sess();
+        if let Some(owner) = ty_sess.ty_kind(receiver_ty).name().map(String::from) {
+            if !matches!(self.sess.method(&owner, method), Ok(None)) {
+                return None;
+            }
+        }
+        let field_idx = self
+            .sess
+            .tys
+            .lookup_field(receiver_ty, method, method.span())
+            .ok()?;
+        let field_ty = ty_sess.ty_kind(receiver_ty).field_ty(field_idx)?;
+        ty_sess.ty_kind(field_ty).is_fn().then_some((field_idx, field_ty))
+    }
+
+    /// The method called on `receiver` with its type arguments, which are inferred from the receiver, the
+    /// arguments and the expected type of the result, along with the type parameters taken from the default type
+    /// of literals.
+    #[allow(clippy::type_complexity)]
+    fn resolve_method(
+        &self,
+        ty: Option<ty::Ty>,
+        receiver: &'ast ast::Expr,
+        method: &'ast Spanned<ast::Ident>,
+        args: &'ast [Spanned<ast::Expr>],
+    ) -> Result<(Method<'ast>, Vec<ty::Ty>, Vec<&'ast ast::Ident>)> {
+        let receiver_ty = self.known_ty(receiver, method.span()).ok_or_else(|| {
+            Diagnostic::new_error(
+                "type annotations needed",
                 Label::new(
                     self.sess.file_id,
-                    span,
-                    format!("'{:?}' is not bound here (while lowering)", field_idx),
+                    method.span(),
+                    "the type of the receiver must be known to call a method on it",
                 ),
-            )))
+            )
+        })?;
+        let ty_sess = self.sess.tys.ty_sess();
+        let owner = match &*ty_sess.ty_kind(receiver_ty) {
+            ty::TyKind::Struct(ty::Struct { name, .. }) | ty::TyKind::Enum(ty::Enum { name, .. }) => {
+                Some(name.clone())
+            }
+            _ => None,
+        };
+        let found = match owner {
+            Some(owner) => self.sess.method(&owner, method)?,
+            None => None,
+        };
+        let Some(found) = found else {
+            return Err(Box::from(Diagnostic::new_error(
+                "reference to unbound method",
+                Label::new(
+                    self.sess.file_id,
+                    method.span(),
+                    format!(
+                        "'{}' has no method named '{}'",
+                        ty_sess.ty_name(receiver_ty),
+                        &**method
+                    ),
+                ),
+            )));
+        };
+        let decl = found.decl;
+        if args.len() + 1 != decl.params.len() {
+            return Err(Box::from(Diagnostic::new_error(
+                "argument count mismatch",
+                Label::new(
+                    self.sess.file_id,
+                    method.span(),
+                    format!(
+                        "method expected {} parameters but {} were found",
+                        decl.params.len() - 1,
+                        args.len()
+                    ),
+                ),
+            )));
         }
+        let mut defaulted = vec![];
+        let ty_args = self.resolve_ty_args(method, &decl.ty_params, &[], |inferred| {
+            self.sess
+                .tys
+                .infer(&decl.ty_params, &decl.params[0].ty, receiver_ty, inferred);
+            let given = decl.params[1..]
+                .iter()
+                .map(|param| &*param.ty)
+                .zip(args.iter())
+                .collect::<Vec<_>>();
+            let expected = ty.map(|ty| (&*decl.return_ty, ty));
+            defaulted = self.infer_from_exprs(&decl.ty_params, &given, expected, inferred);
+        })?;
+        Ok((found, ty_args, defaulted))
     }
 
-    fn lookup_fields(
+    /// The type expected of the value bound to `binder` from how the rest of its scope uses it, when the value is
+    /// the result of a call whose type parameter would otherwise be taken from the default type of literals.
+    fn expected_from_uses(
         &self,
-        local_idx: ir::LocalIdx,
-        field_idxes: Vec<ty::FieldIdx>,
-        span: Span,
-    ) -> Result<ir::LocalIdx> {
-        let mut root_local_idx = self
-            .lookup_field(local_idx, *field_idxes.first().unwrap(), span)
-            .unwrap();
-        for field_idx in field_idxes.into_iter().skip(1) {
-            root_local_idx = self.lookup_field(root_local_idx, field_idx, span).unwrap();
+        binder: &'ast ast::Ident,
+        expr: &'ast ast::Expr,
+        body: &'ast ast::Term,
+    ) -> Option<ty::Ty> {
+        let (decl, defaulted) = match expr {
+            ast::Expr::Call {
+                target,
+                ty_args,
+                args,
+            } if self.lookup(target, target.span()).is_err() => {
+                let (decl, _, defaulted) = self.resolve_call(None, target, ty_args, args).ok()??;
+                (decl, defaulted)
+            }
+            ast::Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } if self.field_fn(receiver, method).is_none() => {
+                let (found, _, defaulted) = self.resolve_method(None, receiver, method, args).ok()?;
+                (found.decl, defaulted)
+            }
+            _ => return None,
+        };
+        if !defaulted
+            .iter()
+            .any(|ty_param| is_ty_param(&decl.return_ty, ty_param))
+        {
+            return None;
+        }
+        uses::uses(binder, body)
+            .iter()
+            .find_map(|binder_use| self.use_ty(binder_use))
+    }
+
+    /// The type a use of a binding gives it, if it is known before the rest of the scope is lowered.
+    fn use_ty(&self, binder_use: &Use<'ast>) -> Option<ty::Ty> {
+        match binder_use {
+            Use::Arg {
+                target,
+                ty_args,
+                position,
+            } => {
+                let fn_ty = match self.lookup(target, target.span()) {
+                    Ok((_, fn_ty)) => fn_ty?,
+                    Err(_) if ty_args.is_empty() && self.sess.generic_fn(target).is_none() => {
+                        self.sess.fn_ty(self.sess.lookup(target, target.span()).ok()?)
+                    }
+                    Err(_) => return None,
+                };
+                let param_ty = self
+                    .sess
+                    .tys
+                    .ty_sess()
+                    .ty_kind(fn_ty)
+                    .param_ty(ty::ParamIdx::new(*position));
+                param_ty
+            }
+            Use::Operand(other, span) => self.known_ty(other, *span),
+            Use::Annotated(ty) => self.lookup_ty(ty, ty.span()).ok(),
+            Use::Tail => match self.tail {
+                Tail::Return => Some(self.return_ty),
+                Tail::Yield(ty) => ty,
+                Tail::Discard | Tail::Continue => None,
+            },
         }
-        Ok(root_local_idx)
     }
 
     #[inline]
@@ -166,7 +1083,107 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
             ast::BinopKind::Xor => ir::BinopKind::Xor,
             ast::BinopKind::LShift => ir::BinopKind::LShift,
             ast::BinopKind::RShift => ir::BinopKind::RShift,
+            ast::BinopKind::LogicalAnd | ast::BinopKind::LogicalOr => {
+                unreachable!("short-circuit operators are lowered to matches")
+            }
+        }
+    }
+
+    /// Picks the literal type from its suffix, falling back to the expected type. Without either, the type is left
+    /// to be inferred by the type checker.
+    fn lower_number_literal(
+        &self,
+        ty: Option<ty::Ty>,
+        literal: &'ast ast::NumberLiteral,
+        span: Span,
+    ) -> Result<ir::ExprKind> {
+        let ty_sess = self.sess.tys.ty_sess();
+        let ty = match &literal.suffix {
+            Some(suffix) => self.lookup_ty(suffix, span)?,
+            None => match ty.filter(|ty| ty_sess.ty_kind(*ty).is_integer()) {
+                Some(ty) => ty,
+                None => return Ok(ir::ExprKind::NumberLiteral(literal.value)),
+            },
+        };
+        if ty_sess.ty_kind(ty).is_float() {
+            return self.lower_float_value(ty, literal.value as f64, span);
+        }
+        let out_of_range = |name: &str, range: String| {
+            Box::from(
+                Diagnostic::new_error(
+                    format!("literal out of range for '{}'", name),
+                    Label::new(
+                        self.sess.file_id,
+                        span,
+                        format!("'{}' does not fit into the type '{}'", literal.value, name),
+                    ),
+                )
+                .with_notes(vec![format!("the range of '{}' is {}", name, range)]),
+            )
+        };
+        macro_rules! narrow {
+            ( $kind:ident, $int:ty ) => {
+                ir::ExprKind::$kind(<$int>::try_from(literal.value).map_err(|_| {
+                    out_of_range(
+                        stringify!($int),
+                        format!("{}..={}", <$int>::MIN, <$int>::MAX),
+                    )
+                })?)
+            };
         }
+        Ok(match &*ty_sess.ty_kind(ty) {
+            ty::TyKind::I8 => narrow!(I8Literal, i8),
+            ty::TyKind::I16 => narrow!(I16Literal, i16),
+            ty::TyKind::I64 => narrow!(I64Literal, i64),
+            ty::TyKind::U8 => narrow!(U8Literal, u8),
+            ty::TyKind::U16 => narrow!(U16Literal, u16),
+            ty::TyKind::U32 => narrow!(U32Literal, u32),
+            ty::TyKind::U64 => narrow!(U64Literal, u64),
+            _ => narrow!(I32Literal, i32),
+        })
+    }
+
+    fn lower_float_literal(
+        &self,
+        ty: Option<ty::Ty>,
+        literal: &'ast ast::FloatLiteral,
+        span: Span,
+    ) -> Result<ir::ExprKind> {
+        let ty_sess = self.sess.tys.ty_sess();
+        let ty = match &literal.suffix {
+            Some(suffix) => self.lookup_ty(suffix, span)?,
+            None => match ty.filter(|ty| ty_sess.ty_kind(*ty).is_float()) {
+                Some(ty) => ty,
+                None => return Ok(ir::ExprKind::FloatLiteral(literal.value)),
+            },
+        };
+        self.lower_float_value(ty, literal.value, span)
+    }
+
+    fn lower_float_value(&self, ty: ty::Ty, value: f64, span: Span) -> Result<ir::ExprKind> {
+        Ok(if self.sess.tys.ty_sess().ty_kind(ty).is_f32() {
+            let narrowed = value as f32;
+            if narrowed.is_infinite() {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "literal out of range for 'f32'",
+                        Label::new(
+                            self.sess.file_id,
+                            span,
+                            format!("'{:e}' does not fit into the type 'f32'", value),
+                        ),
+                    )
+                    .with_notes(vec![format!(
+                        "the range of 'f32' is {:e}..={:e}",
+                        f32::MIN,
+                        f32::MAX
+                    )]),
+                ));
+            }
+            ir::ExprKind::F32Literal(narrowed)
+        } else {
+            ir::ExprKind::F64Literal(value)
+        })
     }
 
     fn lower_expr_kind(
@@ -176,38 +1193,14 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
         span: Span,
     ) -> Result<ir::ExprKind> {
         Ok(match expr {
-            ast::Expr::NumberLiteral(literal) => {
-                if let Some(ty) = ty {
-                    if self.sess.tys.ty_sess().make_i8() == ty {
-                        return Ok(ir::ExprKind::I8Literal(*literal as i8));
-                    } else if self.sess.tys.ty_sess().make_i16() == ty {
-                        return Ok(ir::ExprKind::I16Literal(*literal as i16));
-                    } else if self.sess.tys.ty_sess().make_i32() == ty {
-                        return Ok(ir::ExprKind::I32Literal(*literal as i32));
-                    } else if self.sess.tys.ty_sess().make_i64() == ty {
-                        return Ok(ir::ExprKind::I64Literal(*literal));
-                    }
-                }
-                ir::ExprKind::I32Literal(*literal as i32)
-            }
+            ast::Expr::NumberLiteral(literal) => self.lower_number_literal(ty, literal, span)?,
+            ast::Expr::FloatLiteral(literal) => self.lower_float_literal(ty, literal, span)?,
+            ast::Expr::BoolLiteral(literal) => ir::ExprKind::BoolLiteral(*literal),
             ast::Expr::ArrayLiteral(elements) => {
                 let mut lowered_elements = Vec::with_capacity(elements.len());
-                let element_ty = self
-                    .sess
-                    .tys
-                    .ty_sess()
-                    .ty_kind(ty.unwrap())
-                    .as_array()
-                    .unwrap()
-                    .element_ty;
-                if self.sess.tys.ty_sess().ty_kind(ty.unwrap()).is_array() {
-                    for element in elements {
-                        lowered_elements.push(self.lower_expr_kind(
-                            Some(element_ty),
-                            element,
-                            element.span(),
-                        )?);
-                    }
+                let element_ty = self.element_ty(ty);
+                for element in elements {
+                    lowered_elements.push(self.lower_expr_kind(element_ty, element, element.span())?);
                 }
                 ir::ExprKind::ArrayLiteral {
                     element_ty,
@@ -215,54 +1208,256 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
                 }
             }
             ast::Expr::StringLiteral(literal) => ir::ExprKind::StringLiteral(literal.clone()),
-            ast::Expr::Var(stream) => {
-                let (local_idx, ty) = self.lookup(stream.first().unwrap(), span)?;
-                if stream.len() == 1 {
-                    return Ok(ir::ExprKind::Var(local_idx, vec![]));
+            // NOTE a tuple is built like a struct whose fields are given in order
+            ast::Expr::Tuple(elements) => {
+                let tuple_ty = self.expected_tuple_ty(ty, elements);
+                let mut fields = IdxVec::new();
+                for (field_idx, element) in elements.iter().enumerate() {
+                    let field_ty = tuple_ty.and_then(|tuple_ty| {
+                        self.sess
+                            .tys
+                            .ty_sess()
+                            .ty_kind(tuple_ty)
+                            .field_ty(ty::FieldIdx::new(field_idx))
+                    });
+                    fields.push(self.lower_expr(field_ty, element, element.span())?);
+                }
+                match tuple_ty {
+                    Some(tuple_ty) => ir::ExprKind::Record { ty: tuple_ty, fields },
+                    None => ir::ExprKind::Tuple(fields),
+                }
+            }
+            ast::Expr::Var(stream) => match self.fn_value(stream) {
+                Some(target) => ir::ExprKind::Closure {
+                    ty: self.sess.fn_ty(target),
+                    target,
+                    captures: vec![],
+                },
+                None => {
+                    let (local_idx, ty) = self.lookup(stream.first().unwrap(), span)?;
+                    let (field_idxes, _) = self.lower_field_path(ty, stream, span)?;
+                    ir::ExprKind::Var(local_idx, field_idxes)
                 }
-                let mut field_idxes = vec![];
-                for field in stream.iter().skip(1) {
-                    let field_idx = self.sess.tys.lookup_field(ty.unwrap(), field, field.span())?;
-                    field_idxes.push(field_idx);
+            },
+            ast::Expr::Index { target, index } => {
+                let array_ty = self.place_ty(target, target.span())?;
+                let (target, fields) = self.lower_array_place(array_ty, target, target.span())?;
+                ir::ExprKind::Index {
+                    target,
+                    fields,
+                    index: self.lower_index(array_ty, index, index.span())?,
                 }
-                ir::ExprKind::Var(local_idx, field_idxes)
             }
             ast::Expr::Unop { kind, operand } => ir::ExprKind::Unop {
                 kind: self.lower_unop_kind(**kind),
-                operand: self.lower_expr(None, operand, operand.span())?,
-            },
-            ast::Expr::Binop { kind, left, right } => ir::ExprKind::Binop {
-                kind: self.lower_binop_kind(**kind),
-                left: self.lower_expr(None, left, left.span())?,
-                right: self.lower_expr(None, right, right.span())?,
+                operand: self.lower_expr(ty, operand, operand.span())?,
             },
-            ast::Expr::Call { target, args } => {
+            ast::Expr::Binop { kind, left, right }
+                if matches!(**kind, ast::BinopKind::LogicalAnd | ast::BinopKind::LogicalOr) =>
+            {
+                // NOTE `a && b` becomes `match a { false => false, true => b }` so `b` runs only when needed
+                let short_circuit = matches!(**kind, ast::BinopKind::LogicalOr);
+                let source = self.lower_expr(None, left, left.span())?;
+                ir::ExprKind::Match {
+                    source,
+                    arms: vec![
+                        ir::Arm {
+                            span: left.span(),
+                            pattern: ir::PatternKind::BoolLiteral(short_circuit),
+                            target: self.mk_child().lower_literal_to_block(
+                                ir::ExprKind::BoolLiteral(short_circuit),
+                                left.span(),
+                            ),
+                        },
+                        ir::Arm {
+                            span: right.span(),
+                            pattern: ir::PatternKind::BoolLiteral(!short_circuit),
+                            target: self.mk_child().lower_expr_to_block(None, right, right.span())?,
+                        },
+                    ],
+                }
+            }
+            ast::Expr::Binop { kind, left, right } => {
+                let kind = self.lower_binop_kind(**kind);
+                // NOTE operands of arithmetic share the type of the result, so literals can take it on
+                let operand_ty = if kind.is_comparison() { None } else { ty };
+                ir::ExprKind::Binop {
+                    kind,
+                    left: self.lower_expr(operand_ty, left, left.span())?,
+                    right: self.lower_expr(operand_ty, right, right.span())?,
+                }
+            }
+            ast::Expr::Call {
+                target,
+                ty_args,
+                args,
+            } if self.lookup(target, target.span()).is_ok() => {
+                let (local_idx, fn_ty) = self.lookup(target, target.span())?;
+                if let Some(ty_arg) = ty_args.first() {
+                    return Err(Box::from(Diagnostic::new_error(
+                        "wrong number of type arguments",
+                        Label::new(
+                            self.sess.file_id,
+                            ty_arg.span(),
+                            format!(
+                                "'{}' is a function value, which takes no type arguments",
+                                &**target
+                            ),
+                        ),
+                    )));
+                }
+                let target = self.read_binding(local_idx, fn_ty, target.span());
+                let mut lowered_args = IdxVec::new();
+                for (param_idx, arg) in args.iter().enumerate() {
+                    let param_ty = fn_ty.and_then(|fn_ty| {
+                        self.sess
+                            .tys
+                            .ty_sess()
+                            .ty_kind(fn_ty)
+                            .param_ty(ty::ParamIdx::new(param_idx))
+                    });
+                    lowered_args.push(self.lower_expr(param_ty, arg, arg.span())?);
+                }
+                ir::ExprKind::CallValue {
+                    target,
+                    args: lowered_args,
+                }
+            }
+            ast::Expr::Call {
+                target,
+                ty_args,
+                args,
+            } => {
+                let target = match self.resolve_call(ty, target, ty_args, args)? {
+                    Some((decl, ty_args, _)) => self.sess.instantiate_fn(decl, ty_args, target.span())?,
+                    None => self.sess.lookup(target, target.span())?,
+                };
+                let fn_ty = self.sess.fn_ty(target);
+                let mut lowered_args = IdxVec::new();
+                for (param_idx, arg) in args.iter().enumerate() {
+                    let param_ty = self
+                        .sess
+                        .tys
+                        .ty_sess()
+                        .ty_kind(fn_ty)
+                        .param_ty(ty::ParamIdx::new(param_idx));
+                    lowered_args.push(self.lower_expr(param_ty, arg, arg.span())?);
+                }
+                ir::ExprKind::Call {
+                    target,
+                    args: lowered_args,
+                }
+            }
+            ast::Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } if self.field_fn(receiver, method).is_some() => {
+                let (field_idx, fn_ty) = self.field_fn(receiver, method).unwrap();
+                let record = self.lower_expr(None, receiver, receiver.span())?;
+                let target = match self.lookup_field(record, field_idx) {
+                    Some(field_local_idx) => field_local_idx.with_span(method.span()),
+                    None => self.push_expr(
+                        Some(fn_ty),
+                        ir::ExprKind::Var(record, vec![field_idx]),
+                        method.span(),
+                    ),
+                };
+                let mut lowered_args = IdxVec::new();
+                for (param_idx, arg) in args.iter().enumerate() {
+                    let param_ty = self
+                        .sess
+                        .tys
+                        .ty_sess()
+                        .ty_kind(fn_ty)
+                        .param_ty(ty::ParamIdx::new(param_idx));
+                    lowered_args.push(self.lower_expr(param_ty, arg, arg.span())?);
+                }
+                ir::ExprKind::CallValue {
+                    target,
+                    args: lowered_args,
+                }
+            }
+            ast::Expr::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                let (found, ty_args, _) = self.resolve_method(ty, receiver, method, args)?;
+                let target = self.sess.instantiate_method(found, ty_args, method.span())?;
+                let fn_ty = self.sess.fn_ty(target);
+                // NOTE the receiver is passed as the first argument
+                let args = std::iter::once((&***receiver, receiver.span()))
+                    .chain(args.iter().map(|arg| (&**arg, arg.span())));
                 let mut lowered_args = IdxVec::new();
-                for arg in args.iter() {
-                    lowered_args.push(self.lower_expr(None, arg, arg.span())?);
+                for (param_idx, (arg, arg_span)) in args.enumerate() {
+                    let param_ty = self
+                        .sess
+                        .tys
+                        .ty_sess()
+                        .ty_kind(fn_ty)
+                        .param_ty(ty::ParamIdx::new(param_idx));
+                    lowered_args.push(self.lower_expr(param_ty, arg, arg_span)?);
                 }
                 ir::ExprKind::Call {
-                    target: self.sess.lookup(target, target.span())?,
+                    target,
                     args: lowered_args,
                 }
             }
+            ast::Expr::Branch(term) => self.lower_branch(Tail::Yield(ty), term, span)?,
+            ast::Expr::Closure(closure) => {
+                let mut captures = vec![];
+                let mut captured = vec![];
+                for name in captures::free_names(closure) {
+                    // NOTE a name that is not bound here names a function, or is reported as unbound in the closure
+                    if let Ok((local_idx, ty)) = self.lookup(name, name.span()) {
+                        captured.push(self.read_binding(local_idx, ty, name.span()));
+                        captures.push((&**name, name.span(), ty));
+                    }
+                }
+                let bind_point = closure.name.as_ref().map_or(span, |name| name.span());
+                let (target, closure_ty) =
+                    self.sess
+                        .bind_closure(closure, captures, self.ty_scope, bind_point)?;
+                ir::ExprKind::Closure {
+                    ty: closure_ty,
+                    target,
+                    captures: captured,
+                }
+            }
             ast::Expr::Variant {
                 enum_name,
+                ty_args,
                 discriminant,
-                body,
+                fields,
             } => {
-                let enum_ty = self.sess.tys.lookup(enum_name, enum_name.span())?;
-                ir::ExprKind::Variant {
-                    ty: enum_ty,
-                    discriminant: self
+                let enum_ty = self.resolve_variant_ty(ty, enum_name, ty_args, discriminant, fields)?;
+                let discriminant =
+                    self.sess
+                        .tys
+                        .lookup_variant(enum_ty, discriminant, discriminant.span())?;
+                let mut lowered_fields = IdxVec::new();
+                for (field_idx, field) in self.resolve_variant_fields(enum_ty, discriminant, fields, span)? {
+                    let field_ty = self
                         .sess
                         .tys
-                        .lookup_variant(enum_ty, discriminant, discriminant.span())?,
-                    body: self.lower_expr(None, body, body.span())?,
+                        .ty_sess()
+                        .ty_kind(enum_ty)
+                        .variant_field_ty(discriminant, field_idx);
+                    lowered_fields.push(self.lower_expr(field_ty, field, field.span())?);
+                }
+                ir::ExprKind::Variant {
+                    ty: enum_ty,
+                    discriminant,
+                    fields: lowered_fields,
                 }
             }
-            ast::Expr::Record { struct_name, fields } => {
-                let struct_ty = self.sess.tys.lookup(struct_name, struct_name.span())?;
+            ast::Expr::Record {
+                struct_name,
+                ty_args,
+                fields,
+            } => {
+                let struct_ty = self.resolve_record_ty(ty, struct_name, ty_args, fields)?;
                 let mut field_bindings = HashMap::new();
                 for (field, body) in fields.iter() {
                     let field_idx = self.sess.tys.lookup_field(struct_ty, field, field.span())?;
@@ -427,7 +1622,7 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
                 send_flags,
                 format_string,
                 http_header,
-                call_handler,
+                handler,
             } => {
                 let domain = self.lower_expr(None, domain, domain.span())?;
                 let ty = self.lower_expr(None, ty, ty.span())?;
@@ -477,7 +1672,9 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
                     http_header,
                     http_header.span(),
                 )?;
-                let call_handler = self.lower_expr(None, call_handler, call_handler.span())?;
+                let ty_sess = self.sess.tys.ty_sess();
+                let handler_ty = ty_sess.make_fn(ty_sess.make_string(), IdxVec::new());
+                let handler = self.lower_expr(Some(handler_ty), handler, handler.span())?;
                 ir::ExprKind::ListenAndServe {
                     domain,
                     ty,
@@ -493,7 +1690,7 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
                     send_flags,
                     format_string,
                     http_header,
-                    call_handler,
+                    handler,
                 }
             }
         })
@@ -501,145 +1698,639 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
 
     fn lower_expr(&mut self, ty: Option<ty::Ty>, expr: &'ast ast::Expr, span: Span) -> Result<ir::LocalIdx> {
         let kind = self.lower_expr_kind(ty, expr, span)?;
-        Ok(match kind {
-            ir::ExprKind::Var(local_idx, field_idxes) => {
-                if field_idxes.is_empty() {
-                    return Ok(local_idx);
-                }
-                self.lookup_fields(local_idx, field_idxes, span)?
+        // NOTE reads of mutable bindings, and of fields of records built elsewhere, fall through to a `Let`
+        if let ir::ExprKind::Var(local_idx, field_idxes) = &kind {
+            if let Some(field_local_idx) = self
+                .lookup_fields(*local_idx, field_idxes)
+                .filter(|_| !self.is_slot(*local_idx))
+            {
+                return Ok(field_local_idx.with_span(span));
             }
-            ir::ExprKind::Record {
-                ty: record_ty,
-                fields,
-            } => {
-                let idx = self.local_idxr.next();
-                self.field_map.insert(idx, fields.clone());
-                self.instructions.push(ir::Instruction {
+        }
+        let idx = self.local_idxr.next().with_span(span);
+        if let ir::ExprKind::Record { fields, .. } | ir::ExprKind::Tuple(fields) = &kind {
+            self.field_map.insert(idx, fields.clone());
+        }
+        self.instructions.push(ir::Instruction {
+            span,
+            kind: ir::InstructionKind::Let {
+                binding: idx,
+                ty,
+                expr: ir::Expr {
+                    local_idx: idx,
                     span,
-                    kind: ir::InstructionKind::Let {
-                        binding: idx,
-                        ty,
-                        expr: ir::Expr {
-                            local_idx: idx,
-                            span,
-                            kind: ir::ExprKind::Record {
-                                ty: record_ty,
-                                fields,
-                            },
-                        },
-                    },
-                });
-                idx
+                    kind,
+                },
+            },
+        });
+        Ok(idx)
+    }
+
+    /// Lowers an `if` or a `match` used as an expression, whose branches end in `tail`.
+    fn lower_branch(&mut self, tail: Tail, term: &'ast ast::Term, span: Span) -> Result<ir::ExprKind> {
+        let block = self.mk_branch_child(tail).lower_term_to_block(term, span)?;
+        Ok(match block.terminator {
+            ir::Terminator::Match { source, arms } => {
+                self.instructions.extend(block.instructions);
+                ir::ExprKind::Match { source, arms }
             }
-            _ => {
-                let idx = self.local_idxr.next();
-                self.instructions.push(ir::Instruction {
+            // NOTE a match whose first arm takes any value has nothing to test, so it gets a test that always holds
+            terminator => ir::ExprKind::Match {
+                source: self.push_expr(None, ir::ExprKind::BoolLiteral(true), span),
+                arms: vec![ir::Arm {
                     span,
-                    kind: ir::InstructionKind::Let {
-                        binding: idx,
-                        ty,
-                        expr: ir::Expr {
-                            local_idx: idx,
-                            span,
-                            kind,
-                        },
-                    },
-                });
-                idx
+                    pattern: ir::PatternKind::BoolLiteral(true),
+                    target: ir::Block { terminator, ..block },
+                }],
+            },
+        })
+    }
+
+    fn push_expr(&mut self, ty: Option<ty::Ty>, kind: ir::ExprKind, span: Span) -> ir::LocalIdx {
+        let local_idx = self.local_idxr.next().with_span(span);
+        self.instructions.push(ir::Instruction {
+            span,
+            kind: ir::InstructionKind::Let {
+                binding: local_idx,
+                ty,
+                expr: ir::Expr {
+                    local_idx,
+                    span,
+                    kind,
+                },
+            },
+        });
+        local_idx
+    }
+
+    /// Whether the expression names an immutable binding, which a new binding shares rather than copies.
+    fn is_var_of_immutable(&self, expr: &'ast ast::Expr) -> Result<bool> {
+        Ok(match expr {
+            ast::Expr::Var(stream) => {
+                let (local_idx, _) = self.lookup(stream.first().unwrap(), Span::dummy())?;
+                !self.is_slot(local_idx)
             }
-        }
-        .with_span(span))
+            _ => false,
+        })
     }
 
-    fn lower_arm(
+    /// Lowers a value stored into a mutable binding. A variable gets its own `Let` so that ownership passes
+    /// through it, like it does for a `let` binding.
+    fn lower_stored_expr(
         &mut self,
+        ty: Option<ty::Ty>,
+        expr: &'ast Spanned<ast::Expr>,
+    ) -> Result<ir::LocalIdx> {
+        let idx = self.lower_expr(ty, expr, expr.span())?;
+        if !self.is_var_of_immutable(expr)? {
+            return Ok(idx);
+        }
+        let local_idx = self.local_idxr.next().with_span(expr.span());
+        self.instructions.push(ir::Instruction {
+            span: expr.span(),
+            kind: ir::InstructionKind::Let {
+                binding: local_idx,
+                ty,
+                expr: ir::Expr {
+                    local_idx,
+                    span: expr.span(),
+                    kind: ir::ExprKind::Var(idx, vec![]),
+                },
+            },
+        });
+        Ok(local_idx)
+    }
+
+    /// Resolves the names in an arm pattern. `ty` is the type of the value it is matched against, when it is known.
+    fn lower_pattern(
+        &self,
+        ty: Option<ty::Ty>,
         pattern: &'ast ast::Pattern,
-        body: &'ast ast::Term,
-        pattern_span: Span,
-        body_span: Span,
-    ) -> Result<ir::Arm> {
-        let mut ctx = self.mk_child();
+        span: Span,
+    ) -> Result<Spanned<Pattern<'ast>>> {
+        let ty_sess = self.sess.tys.ty_sess();
         let pattern = match pattern {
             ast::Pattern::NumberLiteral(literal) => {
-                // TODO: 型のハンドリング
-                ir::PatternKind::I32Literal(*literal as i32)
-            }
-            ast::Pattern::ArrayLiteral(_) => {
-                unimplemented!();
-                // let mut lowered_elements = Vec::with_capacity(elements.len());
-                // for element in elements.iter() {
-                //     lowered_elements.push(ctx.lower_expr_kind(None, element, element.span())?);
-                // }
-                // ir::PatternKind::ArrayLiteral {
-                //     element_ty: None,
-                //     elements: lowered_elements,
-                // }
-            }
-            ast::Pattern::StringLiteral(literal) => ir::PatternKind::StringLiteral(literal.clone()),
-            ast::Pattern::Ident(ident) => {
-                let local_idx = self.local_idxr.next().with_span(pattern_span);
-                ctx.bind(ident, local_idx, None);
-                ir::PatternKind::Ident(local_idx)
+                // NOTE the constructors of a match are known while lowering it, so patterns take `i32` by default
+                let ty = ty
+                    .filter(|ty| ty_sess.ty_kind(*ty).is_integer())
+                    .unwrap_or_else(|| ty_sess.make_i32());
+                Pattern::Literal(match self.lower_number_literal(Some(ty), literal, span)? {
+                    ir::ExprKind::I8Literal(value) => ir::PatternKind::I8Literal(value),
+                    ir::ExprKind::I16Literal(value) => ir::PatternKind::I16Literal(value),
+                    ir::ExprKind::I32Literal(value) => ir::PatternKind::I32Literal(value),
+                    ir::ExprKind::I64Literal(value) => ir::PatternKind::I64Literal(value),
+                    ir::ExprKind::U8Literal(value) => ir::PatternKind::U8Literal(value),
+                    ir::ExprKind::U16Literal(value) => ir::PatternKind::U16Literal(value),
+                    ir::ExprKind::U32Literal(value) => ir::PatternKind::U32Literal(value),
+                    ir::ExprKind::U64Literal(value) => ir::PatternKind::U64Literal(value),
+                    ir::ExprKind::F32Literal(_) | ir::ExprKind::F64Literal(_) => {
+                        return Err(Box::from(Diagnostic::new_error(
+                            "float literals cannot be used in patterns",
+                            Label::new(self.sess.file_id, span, "floating point pattern"),
+                        )))
+                    }
+                    _ => unreachable!(),
+                })
+            }
+            ast::Pattern::ArrayLiteral(elements) => {
+                let mut literals = Vec::with_capacity(elements.len());
+                for element in elements.iter() {
+                    match &**element {
+                        ast::Expr::NumberLiteral(literal) => literals.push((literal, element.span())),
+                        _ => {
+                            return Err(Box::from(Diagnostic::new_error(
+                                "unsupported array pattern",
+                                Label::new(
+                                    self.sess.file_id,
+                                    element.span(),
+                                    "array patterns can only hold integer literals",
+                                ),
+                            )))
+                        }
+                    }
+                }
+                // NOTE without a known type, the elements take the type of the first one
+                let element_ty = match (self.element_ty(ty), literals.first()) {
+                    (Some(element_ty), _) => element_ty,
+                    (
+                        None,
+                        Some((
+                            ast::NumberLiteral {
+                                suffix: Some(suffix), ..
+                            },
+                            span,
+                        )),
+                    ) => self.lookup_ty(suffix, *span)?,
+                    (None, _) => ty_sess.make_i32(),
+                };
+                let mut lowered_elements = Vec::with_capacity(literals.len());
+                for (literal, span) in literals {
+                    lowered_elements.push(self.lower_number_literal(Some(element_ty), literal, span)?);
+                }
+                Pattern::Literal(ir::PatternKind::ArrayLiteral {
+                    element_ty,
+                    elements: lowered_elements,
+                })
             }
+            ast::Pattern::BoolLiteral(literal) => Pattern::Literal(ir::PatternKind::BoolLiteral(*literal)),
+            ast::Pattern::StringLiteral(literal) => {
+                Pattern::Literal(ir::PatternKind::StringLiteral(literal.clone()))
+            }
+            ast::Pattern::Wildcard => Pattern::Wild,
+            ast::Pattern::Ident(ident) => Pattern::Binding(ident),
             ast::Pattern::Variant {
                 enum_name,
+                ty_args,
                 discriminant,
-                bound,
+                fields,
             } => {
-                let local_idx = self.local_idxr.next().with_span(bound.span());
-                ctx.bind(bound, local_idx, None);
-                let ty = self.sess.tys.lookup(enum_name, enum_name.span())?;
+                let generic = self.sess.tys.generic_enum(enum_name);
+                let ty_params = generic.map(|def| &def.ty_params[..]);
+                let ty = self.resolve_named_ty(ty, enum_name, ty_params, ty_args, |_| {})?;
                 let discriminant = self
                     .sess
                     .tys
                     .lookup_variant(ty, discriminant, discriminant.span())?;
-                ir::PatternKind::Variant {
+                let mut field_patterns = IdxVec::new();
+                for (field_idx, field) in self.resolve_variant_fields(ty, discriminant, fields, span)? {
+                    let field_ty = ty_sess.ty_kind(ty).variant_field_ty(discriminant, field_idx);
+                    field_patterns.push(self.lower_pattern(field_ty, field, field.span())?);
+                }
+                Pattern::Variant {
                     ty,
                     discriminant,
-                    binding: local_idx,
+                    fields: field_patterns,
                 }
             }
-            ast::Pattern::Record { struct_name, fields } => {
-                let ty = self.sess.tys.lookup(struct_name, struct_name.span())?;
-                let mut field_bindings = HashMap::new();
-                for (field, bound) in fields {
-                    let local_idx = self.local_idxr.next().with_span(bound.span());
-                    ctx.bind(bound, local_idx, Some(ty));
+            ast::Pattern::Record {
+                struct_name,
+                ty_args,
+                fields,
+            } => {
+                let generic = self.sess.tys.generic_struct(struct_name);
+                let ty_params = generic.map(|def| &def.ty_params[..]);
+                let ty = self.resolve_named_ty(ty, struct_name, ty_params, ty_args, |_| {})?;
+                let mut field_patterns = HashMap::new();
+                for (field, pattern) in fields {
                     let field = self.sess.tys.lookup_field(ty, field, field.span())?;
-                    field_bindings.insert(field, local_idx);
+                    let field_ty = ty_sess.ty_kind(ty).field_ty(field);
+                    field_patterns.insert(field, self.lower_pattern(field_ty, pattern, pattern.span())?);
                 }
-                if let Some(fields) = field_bindings.into_idx_vec() {
-                    ir::PatternKind::Record { ty, fields }
-                } else {
+                match field_patterns
+                    .into_idx_vec()
+                    .filter(|fields| Some(fields.len()) == ty_sess.ty_kind(ty).field_count())
+                {
+                    Some(fields) => Pattern::Record { ty, fields },
+                    None => {
+                        return Err(Box::from(Diagnostic::new_error(
+                            "malformed match arm",
+                            Label::new(self.sess.file_id, span, "not all fields are matched"),
+                        )))
+                    }
+                }
+            }
+            ast::Pattern::Tuple(patterns) => {
+                let ty = ty.ok_or_else(|| {
+                    Diagnostic::new_error(
+                        "type annotations needed",
+                        Label::new(
+                            self.sess.file_id,
+                            span,
+                            "the type of the value must be known to take it apart",
+                        ),
+                    )
+                })?;
+                let field_count = ty_sess.ty_kind(ty).as_tuple().map(|tuple| tuple.fields.len());
+                if field_count != Some(patterns.len()) {
                     return Err(Box::from(Diagnostic::new_error(
                         "malformed match arm",
-                        Label::new(self.sess.file_id, pattern_span, "not all fields are matched"),
+                        Label::new(
+                            self.sess.file_id,
+                            span,
+                            format!(
+                                "expected '{}', found a tuple of {} fields",
+                                ty_sess.ty_name(ty),
+                                patterns.len()
+                            ),
+                        ),
                     )));
                 }
+                let mut fields = IdxVec::new();
+                for (field_idx, pattern) in patterns.iter().enumerate() {
+                    let field_ty = ty_sess.ty_kind(ty).field_ty(ty::FieldIdx::new(field_idx));
+                    fields.push(self.lower_pattern(field_ty, pattern, pattern.span())?);
+                }
+                Pattern::Record { ty, fields }
+            }
+            ast::Pattern::Or(alternatives) => {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|alternative| self.lower_pattern(ty, alternative, alternative.span()))
+                    .collect::<Result<Vec<_>>>()?;
+                let names = alternatives[0].bound_names();
+                for alternative in alternatives[1..].iter() {
+                    let other_names = alternative.bound_names();
+                    let unbound = names
+                        .iter()
+                        .chain(other_names.iter())
+                        .find(|name| !names.contains(name) || !other_names.contains(name));
+                    if let Some(name) = unbound {
+                        return Err(Box::from(
+                            Diagnostic::new_error(
+                                "inconsistent bindings in or-pattern",
+                                Label::new(
+                                    self.sess.file_id,
+                                    alternative.span(),
+                                    format!("'{}' is not bound in every alternative", name),
+                                ),
+                            )
+                            .with_notes(vec![String::from(
+                                "the arm can only use names that every alternative binds",
+                            )]),
+                        ));
+                    }
+                }
+                Pattern::Or(alternatives)
             }
         };
-        Ok(ir::Arm {
-            span: pattern_span.merge(body_span),
-            pattern,
-            target: ctx.lower_term_to_block(body, body_span)?,
+        Ok(span.span(pattern))
+    }
+
+    /// Compiles the arms of a match into a tree of `Terminator::Match` switches, each of which tests one value
+    /// against the constructors of its type, following "Compiling pattern matching to good decision trees"
+    /// (Maranget, 2008). The leaves of the tree jump to the bodies of the arms, which are lowered once each after it.
+    /// The arms are also kept as written for the type checker.
+    fn lower_match(
+        &mut self,
+        ty: Option<ty::Ty>,
+        source: &'ast Spanned<ast::Expr>,
+        arms: &'ast [ast::Arm],
+    ) -> Result<ir::Terminator> {
+        let source_ty = ty.or_else(|| self.known_ty(source, source.span()));
+        let source = self.lower_expr(ty, source, source.span())?;
+        let mut rows = Vec::with_capacity(arms.len());
+        let mut written_arms = Vec::with_capacity(arms.len());
+        for (arm_idx, arm) in arms.iter().enumerate() {
+            let pattern = self.lower_pattern(source_ty, &arm.pattern, arm.pattern.span())?;
+            written_arms.push(ir::WrittenArm {
+                span: arm.pattern.span().merge(arm.body.span()),
+                pattern: pattern.written(),
+                guarded: arm.guard.is_some(),
+            });
+            rows.push(Row {
+                patterns: vec![pattern],
+                bindings: vec![],
+                arm,
+                arm_idx,
+            });
+        }
+        self.matches.borrow_mut().push(ir::WrittenMatch {
+            source,
+            arms: written_arms,
+        });
+        if rows.is_empty() {
+            return Ok(ir::Terminator::Match { source, arms: vec![] });
+        }
+        let reached = RefCell::new(vec![None; arms.len()]);
+        let tree =
+            self.mk_child()
+                .lower_decision_to_block(&[(source, source_ty)], rows, source.span(), &reached)?;
+        let mut bodies = Vec::with_capacity(arms.len());
+        // NOTE an arm that no leaf reaches is reported by the type checker
+        for (arm, (block_idx, bindings)) in arms
+            .iter()
+            .zip(reached.into_inner())
+            .filter_map(|(arm, reached)| reached.map(|reached| (arm, reached)))
+        {
+            let mut ctx = self.mk_child();
+            let mut params = Vec::with_capacity(bindings.len());
+            for (ident, local_idx, ty) in bindings {
+                let param = self.local_idxr.next().with_span(local_idx.span());
+                ctx.bind(ident, param, ty);
+                params.push(param);
+            }
+            let terminator = ctx.lower_term(&arm.body, arm.body.span())?;
+            bodies.push(ir::ArmBody {
+                params,
+                body: ir::Block {
+                    owner: self.def_idx,
+                    block_idx,
+                    span: arm.body.span(),
+                    instructions: ctx.instructions,
+                    terminator,
+                },
+            });
+        }
+        Ok(ir::Terminator::Decision {
+            tree: Box::new(tree),
+            arms: bodies,
         })
     }
 
+    /// Lowers a pattern matrix with at least one row. The arm of the first row is taken when that row matches any
+    /// value, and otherwise the first occurrence it tests is switched on.
+    fn lower_decision(
+        &mut self,
+        occurrences: &[Occurrence],
+        rows: Vec<Row<'ast>>,
+        reached: &ReachedArms<'ast>,
+    ) -> Result<ir::Terminator> {
+        let rows = rows
+            .into_iter()
+            .flat_map(|row| row.expand(occurrences))
+            .collect::<Vec<_>>();
+        match rows[0]
+            .patterns
+            .iter()
+            .position(|pattern| pattern.head().is_some())
+        {
+            Some(column) => self.lower_switch(occurrences, &rows, column, reached),
+            None => self.lower_leaf(occurrences, &rows[0], &rows[1..], reached),
+        }
+    }
+
+    /// Jumps to the arm of a row that matches any value. When its guard fails, the rows after it are tried.
+    fn lower_leaf(
+        &mut self,
+        occurrences: &[Occurrence],
+        row: &Row<'ast>,
+        rest: &[Row<'ast>],
+        reached: &ReachedArms<'ast>,
+    ) -> Result<ir::Terminator> {
+        let arm = row.arm;
+        let jump = self.jump_to_arm(row, reached);
+        let guard = match &arm.guard {
+            Some(guard) => guard,
+            None => return Ok(jump),
+        };
+        let mut arms = Vec::with_capacity(2);
+        if !rest.is_empty() {
+            // NOTE lowered before the row binds its names, which must not be visible to the rows after it
+            arms.push(ir::Arm {
+                span: guard.span(),
+                pattern: ir::PatternKind::BoolLiteral(false),
+                target: self.mk_child().lower_decision_to_block(
+                    occurrences,
+                    rest.to_vec(),
+                    guard.span(),
+                    reached,
+                )?,
+            });
+        }
+        for (ident, local_idx, ty) in row.bindings.iter() {
+            self.bind(ident, *local_idx, *ty);
+        }
+        let source = self.lower_expr(None, guard, guard.span())?;
+        arms.push(ir::Arm {
+            span: arm.body.span(),
+            pattern: ir::PatternKind::BoolLiteral(true),
+            target: ir::Block {
+                owner: self.def_idx,
+                block_idx: self.block_idxr.next(),
+                span: arm.body.span(),
+                instructions: vec![],
+                terminator: jump,
+            },
+        });
+        Ok(ir::Terminator::Match { source, arms })
+    }
+
+    /// Passes what the row bound to the body of its arm, in the order of the names, which every leaf reaching the
+    /// arm binds.
+    fn jump_to_arm(&self, row: &Row<'ast>, reached: &ReachedArms<'ast>) -> ir::Terminator {
+        let mut bindings = row.bindings.clone();
+        bindings.sort_by_key(|(ident, ..)| *ident);
+        let args = bindings.iter().map(|(_, local_idx, _)| *local_idx).collect();
+        let mut reached = reached.borrow_mut();
+        let (target, _) = reached[row.arm_idx].get_or_insert_with(|| (self.block_idxr.next(), bindings));
+        ir::Terminator::Jump {
+            target: *target,
+            args,
+        }
+    }
+
+    /// Switches on the occurrence in `column`, with an arm for each constructor the column tests. The rows that
+    /// match any value there get a default arm, unless the constructors already cover every value of the type.
+    fn lower_switch(
+        &mut self,
+        occurrences: &[Occurrence],
+        rows: &[Row<'ast>],
+        column: usize,
+        reached: &ReachedArms<'ast>,
+    ) -> Result<ir::Terminator> {
+        let (source, _) = occurrences[column];
+        let mut heads: Vec<(Head, &Spanned<Pattern<'ast>>)> = vec![];
+        for row in rows.iter() {
+            let pattern = &row.patterns[column];
+            if let Some(head) = pattern.head() {
+                if heads.iter().all(|(other, _)| *other != head) {
+                    heads.push((head, pattern));
+                }
+            }
+        }
+        let mut arms = Vec::with_capacity(heads.len() + 1);
+        for (head, pattern) in heads.iter() {
+            let (kind, sub_occurrences) = self.lower_head(pattern);
+            let specialized = rows
+                .iter()
+                .filter_map(|row| row.specialize(column, head, sub_occurrences.len()))
+                .collect();
+            let occurrences = [
+                &occurrences[..column],
+                &sub_occurrences[..],
+                &occurrences[column + 1..],
+            ]
+            .concat();
+            arms.push(ir::Arm {
+                span: pattern.span(),
+                pattern: kind,
+                target: self.mk_child().lower_decision_to_block(
+                    &occurrences,
+                    specialized,
+                    pattern.span(),
+                    reached,
+                )?,
+            });
+        }
+        let default = rows
+            .iter()
+            .filter(|row| row.patterns[column].head().is_none())
+            .map(|row| {
+                let mut row = row.clone();
+                row.patterns.remove(column);
+                row
+            })
+            .collect::<Vec<_>>();
+        if !default.is_empty() && !self.is_complete(&heads) {
+            let binding = self.local_idxr.next().with_span(source.span());
+            let occurrences = [&occurrences[..column], &occurrences[column + 1..]].concat();
+            arms.push(ir::Arm {
+                span: source.span(),
+                pattern: ir::PatternKind::Ident(binding),
+                target: self.mk_child().lower_decision_to_block(
+                    &occurrences,
+                    default,
+                    source.span(),
+                    reached,
+                )?,
+            });
+        }
+        Ok(ir::Terminator::Match { source, arms })
+    }
+
+    /// The switch arm pattern for a constructor, which binds fresh locals to the values the constructor holds.
+    fn lower_head(&self, pattern: &Pattern<'ast>) -> (ir::PatternKind, Vec<Occurrence>) {
+        let ty_sess = self.sess.tys.ty_sess();
+        match pattern {
+            Pattern::Literal(kind) => (kind.clone(), vec![]),
+            Pattern::Variant {
+                ty,
+                discriminant,
+                fields,
+            } => {
+                let bindings = fields
+                    .values()
+                    .map(|field| self.local_idxr.next().with_span(field.span()))
+                    .collect::<IdxVec<ty::FieldIdx, _>>();
+                let occurrences = bindings
+                    .iter()
+                    .map(|(field_idx, binding)| {
+                        (
+                            *binding,
+                            ty_sess.ty_kind(*ty).variant_field_ty(*discriminant, field_idx),
+                        )
+                    })
+                    .collect();
+                (
+                    ir::PatternKind::Variant {
+                        ty: *ty,
+                        discriminant: *discriminant,
+                        fields: bindings,
+                    },
+                    occurrences,
+                )
+            }
+            Pattern::Record { ty, fields } => {
+                let bindings = fields
+                    .values()
+                    .map(|field| self.local_idxr.next().with_span(field.span()))
+                    .collect::<IdxVec<ty::FieldIdx, _>>();
+                let occurrences = bindings
+                    .iter()
+                    .map(|(field_idx, binding)| (*binding, ty_sess.ty_kind(*ty).field_ty(field_idx)))
+                    .collect();
+                (
+                    ir::PatternKind::Record {
+                        ty: *ty,
+                        fields: bindings,
+                    },
+                    occurrences,
+                )
+            }
+            Pattern::Wild | Pattern::Binding(_) | Pattern::Or(_) => {
+                unreachable!("only constructors are switched on")
+            }
+        }
+    }
+
+    /// Whether the constructors cover every value of their type.
+    fn is_complete(&self, heads: &[(Head, &Spanned<Pattern<'ast>>)]) -> bool {
+        match heads.first() {
+            Some((Head::Bool(_), _)) => heads.len() == 2,
+            Some((Head::Variant(ty, _), _)) => {
+                self.sess.tys.ty_sess().ty_kind(*ty).variant_count() == Some(heads.len())
+            }
+            Some((Head::Record(_), _)) => true,
+            _ => false,
+        }
+    }
+
     fn lower_term(&mut self, term: &'ast ast::Term, span: Span) -> Result<ir::Terminator> {
         match term {
             ast::Term::Let {
                 binder,
+                mutable: true,
+                annotation,
+                expr,
+                body,
+            } => {
+                let ty = match annotation {
+                    Some(ty) => Some(self.lookup_ty(ty, ty.span())?),
+                    _ => None,
+                };
+                let expected = ty.or_else(|| self.expected_from_uses(binder, expr, body));
+                let binder_ty = expected.or_else(|| self.known_ty(expr, expr.span()));
+                let init = self.lower_stored_expr(expected, expr)?;
+                let slot = self.local_idxr.next().with_span(binder.span());
+                self.instructions.push(ir::Instruction {
+                    span,
+                    kind: ir::InstructionKind::LetMut {
+                        binding: slot,
+                        ty,
+                        init,
+                    },
+                });
+                self.slots.insert(slot);
+                self.bind(binder, slot, binder_ty);
+                self.lower_term(body, body.span())
+            }
+            ast::Term::Let {
+                binder,
+                mutable: false,
                 annotation,
                 expr,
                 body,
             } => {
                 let ty = match annotation {
-                    Some(ty) => Some(self.sess.tys.lookup_ty(ty, ty.span())?),
+                    Some(ty) => Some(self.lookup_ty(ty, ty.span())?),
                     _ => None,
                 };
-                let idx = self.lower_expr(ty, expr, expr.span())?;
-                if let ast::Expr::Var(_) = expr.clone().into_raw() {
-                    let local_idx = self.local_idxr.next();
+                let expected = ty.or_else(|| self.expected_from_uses(binder, expr, body));
+                let binder_ty = expected.or_else(|| self.known_ty(expr, expr.span()));
+                let idx = self.lower_expr(expected, expr, expr.span())?;
+                if self.is_var_of_immutable(expr)? {
+                    let local_idx = self.local_idxr.next().with_span(binder.span());
                     self.instructions.push(ir::Instruction {
                         span,
                         kind: ir::InstructionKind::Let {
@@ -653,7 +2344,35 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
                         },
                     });
                 }
-                self.bind(binder, idx, ty);
+                self.bind(binder, idx, binder_ty);
+                self.lower_term(body, body.span())
+            }
+            ast::Term::Assign {
+                target,
+                index,
+                expr,
+                body,
+            } => {
+                let (local_idx, ty) = self.lookup(target.first().unwrap(), target.span())?;
+                let (fields, ty) = self.lower_field_path(ty, target, target.span())?;
+                let (index, ty) = match index {
+                    Some(index) => (
+                        Some(self.lower_index(ty, index, index.span())?),
+                        self.element_ty(ty),
+                    ),
+                    None => (None, ty),
+                };
+                let value = self.lower_stored_expr(ty, expr)?;
+                self.instructions.push(ir::Instruction {
+                    span,
+                    kind: ir::InstructionKind::Assign {
+                        binding: local_idx.with_span(target.span()),
+                        fields,
+                        index,
+                        ty,
+                        value,
+                    },
+                });
                 self.lower_term(body, body.span())
             }
             ast::Term::Println { expr, body } => {
@@ -664,41 +2383,204 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
                 });
                 self.lower_term(body, body.span())
             }
-            ast::Term::Match { source, arms } => {
-                let source = self.lower_expr(None, source, source.span())?;
-                let mut lowered_arms = vec![];
-                for (pattern, body) in arms.iter() {
-                    lowered_arms.push(self.lower_arm(pattern, body, pattern.span(), body.span())?);
+            ast::Term::Expr { expr, body } => {
+                if let ast::Expr::Branch(term) = &**expr {
+                    // NOTE the branches of a statement give no value, so they need not agree on its type
+                    let kind = self.lower_branch(Tail::Discard, term, expr.span())?;
+                    let bool_ty = self.sess.tys.ty_sess().make_bool();
+                    self.push_expr(Some(bool_ty), kind, expr.span());
+                } else {
+                    self.lower_expr(None, expr, expr.span())?;
                 }
-                Ok(ir::Terminator::Match {
-                    source,
-                    arms: lowered_arms,
-                })
+                self.lower_term(body, body.span())
+            }
+            ast::Term::LetPattern {
+                annotation,
+                expr,
+                arm,
+            } => {
+                let ty = match annotation {
+                    Some(ty) => Some(self.lookup_ty(ty, ty.span())?),
+                    _ => None,
+                };
+                self.lower_match(ty, expr, std::slice::from_ref(arm.as_ref()))
             }
+            ast::Term::Match { source, arms } => self.lower_match(None, source, arms),
             ast::Term::If {
                 source,
                 then,
                 otherwise,
             } => {
-                let source =
-                    self.lower_expr(Some(self.sess.tys.ty_sess().make_i32()), source, source.span())?;
+                let source = self.lower_expr(None, source, source.span())?;
                 Ok(ir::Terminator::Match {
                     source,
                     arms: vec![
                         ir::Arm {
                             span: otherwise.span(),
-                            pattern: ir::PatternKind::I32Literal(0),
+                            pattern: ir::PatternKind::BoolLiteral(false),
                             target: self.mk_child().lower_term_to_block(otherwise, otherwise.span())?,
                         },
                         ir::Arm {
                             span: then.span(),
-                            pattern: ir::PatternKind::Ident(self.local_idxr.next().with_span(source.span())),
+                            pattern: ir::PatternKind::BoolLiteral(true),
                             target: self.mk_child().lower_term_to_block(then, then.span())?,
                         },
                     ],
                 })
             }
-            ast::Term::Return(expr) => Ok(ir::Terminator::Return(self.lower_expr(None, expr, span)?)),
+            ast::Term::While { source, block, body } => Ok(ir::Terminator::Loop {
+                body: Box::new(self.mk_loop_child().lower_while_to_block(source, block, span)?),
+                next: Box::new(self.mk_child().lower_term_to_block(body, body.span())?),
+            }),
+            ast::Term::Loop { block, body } => Ok(ir::Terminator::Loop {
+                body: Box::new(self.mk_loop_child().lower_term_to_block(block, block.span())?),
+                next: Box::new(self.mk_child().lower_term_to_block(body, body.span())?),
+            }),
+            ast::Term::Break | ast::Term::Continue if !self.in_loop => {
+                let keyword = if let ast::Term::Break = term {
+                    "break"
+                } else {
+                    "continue"
+                };
+                Err(Box::from(Diagnostic::new_error(
+                    format!("'{}' outside of a loop", keyword),
+                    Label::new(
+                        self.sess.file_id,
+                        span,
+                        format!("cannot '{}' outside of a loop", keyword),
+                    ),
+                )))
+            }
+            ast::Term::Break => Ok(ir::Terminator::Break),
+            ast::Term::Continue => Ok(ir::Terminator::Continue),
+            ast::Term::Value(expr) => match self.tail {
+                Tail::Return => Ok(ir::Terminator::Return(self.lower_expr(
+                    Some(self.return_ty),
+                    expr,
+                    span,
+                )?)),
+                Tail::Yield(ty) => Ok(ir::Terminator::Yield(self.lower_expr(ty, expr, span)?)),
+                Tail::Discard => {
+                    self.lower_expr(None, expr, span)?;
+                    Ok(self.lower_discarded(span))
+                }
+                Tail::Continue => {
+                    self.lower_expr(None, expr, span)?;
+                    Ok(ir::Terminator::Continue)
+                }
+            },
+            ast::Term::End => match self.tail {
+                Tail::Return | Tail::Yield(_) => Err(Box::from(Diagnostic::new_error(
+                    "missing value at the end of a block",
+                    Label::new(self.sess.file_id, span, "expected a value before this"),
+                ))),
+                Tail::Discard => Ok(self.lower_discarded(span)),
+                Tail::Continue => Ok(ir::Terminator::Continue),
+            },
+            ast::Term::Return(expr) => Ok(ir::Terminator::Return(self.lower_expr(
+                Some(self.return_ty),
+                expr,
+                span,
+            )?)),
+        }
+    }
+
+    /// The branch of a statement yields a placeholder, since its value is never used.
+    fn lower_discarded(&mut self, span: Span) -> ir::Terminator {
+        let discarded = self.push_expr(None, ir::ExprKind::BoolLiteral(true), span);
+        ir::Terminator::Yield(discarded)
+    }
+
+    fn lower_expr_to_block(
+        mut self,
+        ty: Option<ty::Ty>,
+        expr: &'ast ast::Expr,
+        span: Span,
+    ) -> Result<ir::Block> {
+        let block_idx = self.block_idxr.next();
+        let local_idx = self.lower_expr(ty, expr, span)?;
+        Ok(ir::Block {
+            owner: self.def_idx,
+            block_idx,
+            span,
+            instructions: self.instructions,
+            terminator: ir::Terminator::Yield(local_idx),
+        })
+    }
+
+    /// `while source { block }` becomes a loop whose body is `match source { false => break, true => block }`.
+    fn lower_while_to_block(
+        mut self,
+        source: &'ast Spanned<ast::Expr>,
+        block: &'ast Spanned<ast::Term>,
+        span: Span,
+    ) -> Result<ir::Block> {
+        let block_idx = self.block_idxr.next();
+        let source = self.lower_expr(None, source, source.span())?;
+        let arms = vec![
+            ir::Arm {
+                span: source.span(),
+                pattern: ir::PatternKind::BoolLiteral(false),
+                target: ir::Block {
+                    owner: self.def_idx,
+                    block_idx: self.block_idxr.next(),
+                    span: source.span(),
+                    instructions: vec![],
+                    terminator: ir::Terminator::Break,
+                },
+            },
+            ir::Arm {
+                span: block.span(),
+                pattern: ir::PatternKind::BoolLiteral(true),
+                target: self.mk_child().lower_term_to_block(block, block.span())?,
+            },
+        ];
+        Ok(ir::Block {
+            owner: self.def_idx,
+            block_idx,
+            span,
+            instructions: self.instructions,
+            terminator: ir::Terminator::Match { source, arms },
+        })
+    }
+
+    fn lower_decision_to_block(
+        mut self,
+        occurrences: &[Occurrence],
+        rows: Vec<Row<'ast>>,
+        span: Span,
+        reached: &ReachedArms<'ast>,
+    ) -> Result<ir::Block> {
+        let block_idx = self.block_idxr.next();
+        let terminator = self.lower_decision(occurrences, rows, reached)?;
+        Ok(ir::Block {
+            owner: self.def_idx,
+            block_idx,
+            span,
+            instructions: self.instructions,
+            terminator,
+        })
+    }
+
+    fn lower_literal_to_block(self, kind: ir::ExprKind, span: Span) -> ir::Block {
+        let local_idx = self.local_idxr.next().with_span(span);
+        ir::Block {
+            owner: self.def_idx,
+            block_idx: self.block_idxr.next(),
+            span,
+            instructions: vec![ir::Instruction {
+                span,
+                kind: ir::InstructionKind::Let {
+                    binding: local_idx,
+                    ty: None,
+                    expr: ir::Expr {
+                        local_idx,
+                        span,
+                        kind,
+                    },
+                },
+            }],
+            terminator: ir::Terminator::Yield(local_idx),
         }
     }
 
@@ -714,3 +2596,8 @@ impl<'lcx, 'ast> LoweringCtx<'lcx, 'ast> {
         })
     }
 }
+
+/// Whether `ty` is written as the bare type parameter `ty_param`.
+fn is_ty_param(ty: &ast::Ty, ty_param: &ast::Ident) -> bool {
+    matches!(ty, ast::Ty::TyName(name, ty_args) if name == ty_param && ty_args.is_empty())
+}
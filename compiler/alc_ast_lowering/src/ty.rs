@@ -91,10 +91,18 @@ pub enum TyKind {
     I16,
     I32,
     I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Bool,
     String,
     Array(Array),
     Enum(Enum),
     Struct(Struct),
+    Tuple(Tuple),
     Fn(Prototype),
 }
 
@@ -139,6 +147,24 @@ impl TyKind {
         }
     }
 
+    #[inline]
+    pub fn as_tuple(&self) -> Option<&Tuple> {
+        match self {
+            TyKind::Tuple(ref desc) => Some(desc),
+            _ => None,
+        }
+    }
+
+    /// The fields of a struct or a tuple, which are both laid out as records.
+    #[inline]
+    pub fn record_fields(&self) -> Option<&IdxVec<FieldIdx, Ty>> {
+        match self {
+            TyKind::Struct(ref desc) => Some(&desc.fields),
+            TyKind::Tuple(ref desc) => Some(&desc.fields),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn as_prototype(&self) -> Option<&Prototype> {
         match self {
@@ -147,25 +173,57 @@ impl TyKind {
         }
     }
 
+    /// The name an enum or a struct was declared with.
+    #[inline]
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            TyKind::Enum(ref desc) => Some(&desc.name),
+            TyKind::Struct(ref desc) => Some(&desc.name),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn field_ty(&self, field_idx: FieldIdx) -> Option<Ty> {
-        self.as_struct()
-            .and_then(|desc| desc.fields.get(field_idx))
+        self.record_fields()
+            .and_then(|fields| fields.get(field_idx))
             .copied()
     }
 
+    #[inline]
+    pub fn field_name(&self, field_idx: FieldIdx) -> Option<&str> {
+        self.as_struct()
+            .and_then(|desc| desc.field_names.get(field_idx))
+            .map(String::as_str)
+    }
+
     #[inline]
     pub fn field_count(&self) -> Option<usize> {
-        self.as_struct().map(|desc| desc.fields.len())
+        self.record_fields().map(|fields| fields.len())
+    }
+
+    #[inline]
+    pub fn variant(&self, variant_idx: VariantIdx) -> Option<&Variant> {
+        self.as_enum().and_then(|desc| desc.variants.get(variant_idx))
     }
 
     #[inline]
-    pub fn variant_ty(&self, variant_idx: VariantIdx) -> Option<Ty> {
-        self.as_enum()
-            .and_then(|desc| desc.variants.get(variant_idx))
+    pub fn variant_field_ty(&self, variant_idx: VariantIdx, field_idx: FieldIdx) -> Option<Ty> {
+        self.variant(variant_idx)
+            .and_then(|variant| variant.fields.get(field_idx))
             .copied()
     }
 
+    #[inline]
+    pub fn variant_field_count(&self, variant_idx: VariantIdx) -> Option<usize> {
+        self.variant(variant_idx).map(|variant| variant.fields.len())
+    }
+
+    #[inline]
+    pub fn variant_name(&self, variant_idx: VariantIdx) -> Option<&str> {
+        self.variant(variant_idx).map(|variant| variant.name.as_str())
+    }
+
     #[inline]
     pub fn variant_count(&self) -> Option<usize> {
         self.as_enum().map(|desc| desc.variants.len())
@@ -208,6 +266,61 @@ impl TyKind {
         matches!(self, TyKind::I64)
     }
 
+    #[inline]
+    pub fn is_u8(&self) -> bool {
+        matches!(self, TyKind::U8)
+    }
+
+    #[inline]
+    pub fn is_u16(&self) -> bool {
+        matches!(self, TyKind::U16)
+    }
+
+    #[inline]
+    pub fn is_u32(&self) -> bool {
+        matches!(self, TyKind::U32)
+    }
+
+    #[inline]
+    pub fn is_u64(&self) -> bool {
+        matches!(self, TyKind::U64)
+    }
+
+    #[inline]
+    pub fn is_signed(&self) -> bool {
+        matches!(self, TyKind::I8 | TyKind::I16 | TyKind::I32 | TyKind::I64)
+    }
+
+    #[inline]
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, TyKind::U8 | TyKind::U16 | TyKind::U32 | TyKind::U64)
+    }
+
+    #[inline]
+    pub fn is_integer(&self) -> bool {
+        self.is_signed() || self.is_unsigned()
+    }
+
+    #[inline]
+    pub fn is_f32(&self) -> bool {
+        matches!(self, TyKind::F32)
+    }
+
+    #[inline]
+    pub fn is_f64(&self) -> bool {
+        matches!(self, TyKind::F64)
+    }
+
+    #[inline]
+    pub fn is_float(&self) -> bool {
+        self.is_f32() || self.is_f64()
+    }
+
+    #[inline]
+    pub fn is_bool(&self) -> bool {
+        matches!(self, TyKind::Bool)
+    }
+
     #[inline]
     pub fn is_string(&self) -> bool {
         matches!(self, TyKind::String)
@@ -227,6 +340,22 @@ impl TyKind {
     pub fn is_struct(&self) -> bool {
         matches!(self, TyKind::Struct(_))
     }
+
+    #[inline]
+    pub fn is_tuple(&self) -> bool {
+        matches!(self, TyKind::Tuple(_))
+    }
+
+    #[inline]
+    pub fn is_fn(&self) -> bool {
+        matches!(self, TyKind::Fn(_))
+    }
+
+    /// Whether values of the type are records of fields, as those of structs and tuples are.
+    #[inline]
+    pub fn is_record(&self) -> bool {
+        self.is_struct() || self.is_tuple()
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -237,11 +366,39 @@ pub struct Array {
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Enum {
-    pub variants: IdxVec<VariantIdx, Ty>,
+    pub name: String,
+    /// The type arguments of an instance of a generic enum.
+    pub ty_args: Vec<Ty>,
+    pub variants: IdxVec<VariantIdx, Variant>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: IdxVec<FieldIdx, Ty>,
+    /// Empty unless the variant was declared with named fields.
+    pub field_names: IdxVec<FieldIdx, String>,
+}
+
+impl Variant {
+    #[inline]
+    pub fn is_named(&self) -> bool {
+        !self.field_names.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Struct {
+    pub name: String,
+    /// The type arguments of an instance of a generic struct.
+    pub ty_args: Vec<Ty>,
+    pub fields: IdxVec<FieldIdx, Ty>,
+    pub field_names: IdxVec<FieldIdx, String>,
+}
+
+/// An anonymous struct, whose fields are named by their position.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Tuple {
     pub fields: IdxVec<FieldIdx, Ty>,
 }
 
@@ -255,6 +412,7 @@ pub struct Prototype {
 pub struct TySess {
     tys: RefCell<IdxVec<Ty, TyKind>>,
     uniqued: RefCell<HashMap<TyKind, Ty>>,
+    instances: RefCell<HashMap<(String, Vec<Ty>), Ty>>,
 }
 
 impl TySess {
@@ -263,7 +421,7 @@ impl TySess {
         TySess::default()
     }
 
-    fn bind(&mut self, kind: TyKind) -> Ty {
+    fn bind(&self, kind: TyKind) -> Ty {
         self.tys.borrow_mut().push(kind)
     }
 
@@ -293,6 +451,34 @@ impl TySess {
         self.make_unique(TyKind::I64)
     }
 
+    pub fn make_u8(&self) -> Ty {
+        self.make_unique(TyKind::U8)
+    }
+
+    pub fn make_u16(&self) -> Ty {
+        self.make_unique(TyKind::U16)
+    }
+
+    pub fn make_u32(&self) -> Ty {
+        self.make_unique(TyKind::U32)
+    }
+
+    pub fn make_u64(&self) -> Ty {
+        self.make_unique(TyKind::U64)
+    }
+
+    pub fn make_f32(&self) -> Ty {
+        self.make_unique(TyKind::F32)
+    }
+
+    pub fn make_f64(&self) -> Ty {
+        self.make_unique(TyKind::F64)
+    }
+
+    pub fn make_bool(&self) -> Ty {
+        self.make_unique(TyKind::Bool)
+    }
+
     pub fn make_string(&self) -> Ty {
         self.make_unique(TyKind::String)
     }
@@ -301,20 +487,98 @@ impl TySess {
         self.make_unique(TyKind::Array(Array { element_ty, size }))
     }
 
+    pub fn make_tuple(&self, fields: IdxVec<FieldIdx, Ty>) -> Ty {
+        self.make_unique(TyKind::Tuple(Tuple { fields }))
+    }
+
     pub fn make_fn(&self, return_ty: Ty, params: IdxVec<ParamIdx, Ty>) -> Ty {
         self.make_unique(TyKind::Fn(Prototype { return_ty, params }))
     }
 
-    pub fn make_enum(&mut self) -> Ty {
-        self.bind(TyKind::Enum(Enum {
+    /// Binds an enum without variants, which are set once it is bound so that they can refer to it. `ty_args` is
+    /// empty unless it is an instance of a generic enum.
+    pub fn make_enum(&self, name: &str, ty_args: Vec<Ty>) -> Ty {
+        let ty = self.bind(TyKind::Enum(Enum {
+            name: name.to_owned(),
+            ty_args: ty_args.clone(),
             variants: IdxVec::new(),
-        }))
+        }));
+        self.bind_instance(name, ty_args, ty);
+        ty
     }
 
-    pub fn make_struct(&mut self) -> Ty {
-        self.bind(TyKind::Struct(Struct {
+    /// Binds a struct without fields, which are set once it is bound so that they can refer to it. `ty_args` is
+    /// empty unless it is an instance of a generic struct.
+    pub fn make_struct(&self, name: &str, ty_args: Vec<Ty>) -> Ty {
+        let ty = self.bind(TyKind::Struct(Struct {
+            name: name.to_owned(),
+            ty_args: ty_args.clone(),
             fields: IdxVec::new(),
-        }))
+            field_names: IdxVec::new(),
+        }));
+        self.bind_instance(name, ty_args, ty);
+        ty
+    }
+
+    fn bind_instance(&self, name: &str, ty_args: Vec<Ty>, ty: Ty) {
+        if !ty_args.is_empty() {
+            self.instances.borrow_mut().insert((name.to_owned(), ty_args), ty);
+        }
+    }
+
+    /// The instance of the generic enum or struct `name` with the given type arguments, if it has been made.
+    pub fn instance(&self, name: &str, ty_args: &[Ty]) -> Option<Ty> {
+        self.instances
+            .borrow()
+            .get(&(name.to_owned(), ty_args.to_vec()))
+            .copied()
+    }
+
+    /// The type as it would be written in a program.
+    pub fn ty_name(&self, ty: Ty) -> String {
+        let ty_args = |ty_args: &[Ty]| {
+            if ty_args.is_empty() {
+                String::new()
+            } else {
+                let names = ty_args.iter().map(|ty| self.ty_name(*ty)).collect::<Vec<_>>();
+                format!("<{}>", names.join(", "))
+            }
+        };
+        match &*self.ty_kind(ty) {
+            TyKind::I8 => String::from("i8"),
+            TyKind::I16 => String::from("i16"),
+            TyKind::I32 => String::from("i32"),
+            TyKind::I64 => String::from("i64"),
+            TyKind::U8 => String::from("u8"),
+            TyKind::U16 => String::from("u16"),
+            TyKind::U32 => String::from("u32"),
+            TyKind::U64 => String::from("u64"),
+            TyKind::F32 => String::from("f32"),
+            TyKind::F64 => String::from("f64"),
+            TyKind::Bool => String::from("bool"),
+            TyKind::String => String::from("string"),
+            TyKind::Array(Array { element_ty, size }) => format!("[{}; {}]", self.ty_name(*element_ty), size),
+            TyKind::Enum(Enum {
+                name, ty_args: args, ..
+            })
+            | TyKind::Struct(Struct {
+                name, ty_args: args, ..
+            }) => {
+                format!("{}{}", name, ty_args(args))
+            }
+            // NOTE a tuple of one element keeps its comma, as it is written
+            TyKind::Tuple(Tuple { fields }) if fields.len() == 1 => {
+                format!("({},)", self.ty_name(*fields.values().next().unwrap()))
+            }
+            TyKind::Tuple(Tuple { fields }) => {
+                let fields = fields.values().map(|ty| self.ty_name(*ty)).collect::<Vec<_>>();
+                format!("({})", fields.join(", "))
+            }
+            TyKind::Fn(Prototype { return_ty, params }) => {
+                let params = params.values().map(|ty| self.ty_name(*ty)).collect::<Vec<_>>();
+                format!("func({}) {}", params.join(", "), self.ty_name(*return_ty))
+            }
+        }
     }
 
     pub fn ty_kind(&self, ty: Ty) -> TyKindRef {
@@ -324,7 +588,7 @@ impl TySess {
         }
     }
 
-    pub fn ty_kind_mut(&mut self, ty: Ty) -> TyKindRefMut {
+    pub fn ty_kind_mut(&self, ty: Ty) -> TyKindRefMut {
         TyKindRefMut {
             ty,
             guard: self.tys.borrow_mut(),
@@ -1,13 +1,32 @@
 use crate::{
-    idx_vec::{Indexable, IndexableIndexMap},
+    idx::Idx,
+    idx_vec::{IdxVec, IndexableIndexMap},
     ty,
 };
 use alc_command_option::CommandOptions;
-use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
+use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span, Spanned};
 use alc_parser::ast;
 use indexmap::IndexMap;
 use log::debug;
-use std::collections::HashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+
+/// How deeply instances of generic items can be nested in each other before they are assumed to never end.
+pub const INSTANCE_DEPTH_LIMIT: usize = 64;
+
+/// The type parameters in scope while lowering an instance of a generic item, and the types they stand for.
+pub type TyScope<'ast> = HashMap<&'ast ast::Ident, ty::Ty>;
+
+/// The fields of a struct or of an enum variant, by the names they are declared with.
+type FieldNames<'ast> = IndexMap<&'ast ast::Ident, ty::FieldIdx>;
+
+#[derive(Debug, Copy, Clone)]
+enum Generic<'ast> {
+    Enum(&'ast ast::Enum),
+    Struct(&'ast ast::Struct),
+}
 
 #[derive(Debug)]
 pub struct TyLowering<'ast> {
@@ -19,10 +38,20 @@ pub struct TyLowering<'ast> {
     i16_ty: ty::Ty,
     i32_ty: ty::Ty,
     i64_ty: ty::Ty,
+    u8_ty: ty::Ty,
+    u16_ty: ty::Ty,
+    u32_ty: ty::Ty,
+    u64_ty: ty::Ty,
+    f32_ty: ty::Ty,
+    f64_ty: ty::Ty,
+    bool_ty: ty::Ty,
     string_ty: ty::Ty,
     tys: HashMap<&'ast ast::Ident, ty::Ty>,
-    variants: HashMap<ty::Ty, HashMap<&'ast ast::Ident, ty::VariantIdx>>,
-    fields: HashMap<ty::Ty, IndexMap<&'ast ast::Ident, ty::FieldIdx>>,
+    generics: HashMap<&'ast ast::Ident, Generic<'ast>>,
+    instance_depth: Cell<usize>,
+    variants: RefCell<HashMap<ty::Ty, IndexMap<&'ast ast::Ident, ty::VariantIdx>>>,
+    fields: RefCell<HashMap<ty::Ty, FieldNames<'ast>>>,
+    variant_fields: RefCell<HashMap<(ty::Ty, ty::VariantIdx), FieldNames<'ast>>>,
 }
 
 impl<'ast> TyLowering<'ast> {
@@ -32,6 +61,13 @@ impl<'ast> TyLowering<'ast> {
         let i16_ty = ty_sess.make_i16();
         let i32_ty = ty_sess.make_i32();
         let i64_ty = ty_sess.make_i64();
+        let u8_ty = ty_sess.make_u8();
+        let u16_ty = ty_sess.make_u16();
+        let u32_ty = ty_sess.make_u32();
+        let u64_ty = ty_sess.make_u64();
+        let f32_ty = ty_sess.make_f32();
+        let f64_ty = ty_sess.make_f64();
+        let bool_ty = ty_sess.make_bool();
         let string_ty = ty_sess.make_string();
         TyLowering {
             command_options,
@@ -41,10 +77,20 @@ impl<'ast> TyLowering<'ast> {
             i16_ty,
             i32_ty,
             i64_ty,
+            u8_ty,
+            u16_ty,
+            u32_ty,
+            u64_ty,
+            f32_ty,
+            f64_ty,
+            bool_ty,
             string_ty,
             tys: HashMap::new(),
-            variants: HashMap::new(),
-            fields: HashMap::new(),
+            generics: HashMap::new(),
+            instance_depth: Cell::new(0),
+            variants: RefCell::new(HashMap::new()),
+            fields: RefCell::new(HashMap::new()),
+            variant_fields: RefCell::new(HashMap::new()),
         }
     }
 
@@ -52,12 +98,20 @@ impl<'ast> TyLowering<'ast> {
     where T: Iterator<Item = &'ast ast::Item> {
         for item in items {
             match item {
+                ast::Item::Enum(def) if !def.ty_params.is_empty() => {
+                    self.check_ty_params(&def.ty_params)?;
+                    self.bind_generic(&def.name, def.name.span(), Generic::Enum(def))?;
+                }
                 ast::Item::Enum(def) => {
-                    let ty = self.ty_sess.make_enum();
+                    let ty = self.ty_sess.make_enum(&def.name, vec![]);
                     self.bind(&def.name, def.name.span(), ty)?;
                 }
+                ast::Item::Struct(def) if !def.ty_params.is_empty() => {
+                    self.check_ty_params(&def.ty_params)?;
+                    self.bind_generic(&def.name, def.name.span(), Generic::Struct(def))?;
+                }
                 ast::Item::Struct(def) => {
-                    let ty = self.ty_sess.make_struct();
+                    let ty = self.ty_sess.make_struct(&def.name, vec![]);
                     self.bind(&def.name, def.name.span(), ty)?;
                 }
                 _ => {}
@@ -66,80 +120,20 @@ impl<'ast> TyLowering<'ast> {
         Ok(())
     }
 
+    /// Lowers the enums and structs that are not generic. Instances of generic ones are lowered when they are
+    /// first referred to.
     pub fn lower<T>(&mut self, items: T) -> Result<()>
     where T: Iterator<Item = &'ast ast::Item> {
-        let file_id = self.file_id;
+        let scope = TyScope::new();
         for item in items {
             match item {
-                ast::Item::Enum(def) => {
-                    let mut variant_tys = HashMap::new();
-                    for binding in def.variants.iter() {
-                        if variant_tys
-                            .insert(&*binding.binder, self.lookup_binding(binding)?)
-                            .is_some()
-                        {
-                            return Err(Box::from(Diagnostic::new_error(
-                                "attempted to rebind enum variant",
-                                Label::new(
-                                    file_id,
-                                    binding.span(),
-                                    "a variant with this name already exists",
-                                ),
-                            )));
-                        }
-                    }
-                    let (variants, index) = variant_tys.reindex::<ty::VariantIdx>();
+                ast::Item::Enum(def) if def.ty_params.is_empty() => {
                     let ty = self.lookup(&def.name, def.name.span())?;
-                    self.variants.insert(ty, variants);
-                    self.ty_sess
-                        .ty_kind_mut(ty)
-                        .as_enum_mut()
-                        .map(|desc| {
-                            desc.variants = index;
-                        })
-                        .ok_or_else(|| {
-                            Diagnostic::new_bug(
-                                "attempted to set variants for non-enum type",
-                                Label::new(
-                                    file_id,
-                                    def.name.span(),
-                                    "lowered type for this definition is not an enum",
-                                ),
-                            )
-                        })?;
+                    self.lower_enum(def, ty, &scope)?;
                 }
-                ast::Item::Struct(def) => {
-                    let mut field_tys = IndexMap::new();
-                    for binding in def.fields.iter() {
-                        if field_tys
-                            .insert(&*binding.binder, self.lookup_binding(binding)?)
-                            .is_some()
-                        {
-                            return Err(Box::from(Diagnostic::new_error(
-                                "attempted to rebind field type",
-                                Label::new(file_id, binding.span(), "a field with this name already exists"),
-                            )));
-                        }
-                    }
-                    let (fields, index) = field_tys.reindex::<ty::FieldIdx>();
+                ast::Item::Struct(def) if def.ty_params.is_empty() => {
                     let ty = self.lookup(&def.name, def.name.span())?;
-                    self.fields.insert(ty, fields);
-                    self.ty_sess
-                        .ty_kind_mut(ty)
-                        .as_struct_mut()
-                        .map(|desc| {
-                            desc.fields = index;
-                        })
-                        .ok_or_else(|| {
-                            Diagnostic::new_bug(
-                                "attempted to set fields for non-struct type",
-                                Label::new(
-                                    file_id,
-                                    def.name.span(),
-                                    "lowered type for this definition is not a struct",
-                                ),
-                            )
-                        })?;
+                    self.lower_struct(def, ty, &scope)?;
                 }
                 _ => {}
             }
@@ -147,8 +141,170 @@ impl<'ast> TyLowering<'ast> {
         Ok(())
     }
 
+    fn lower_enum(&self, def: &'ast ast::Enum, ty: ty::Ty, scope: &TyScope<'ast>) -> Result<()> {
+        let file_id = self.file_id;
+        let mut variant_descs = IndexMap::new();
+        let mut variant_fields = vec![];
+        for variant in def.variants.iter() {
+            let (desc, fields) = self.lower_variant(variant, scope)?;
+            variant_fields.push(fields);
+            if variant_descs.insert(&*variant.name, desc).is_some() {
+                return Err(Box::from(Diagnostic::new_error(
+                    "attempted to rebind enum variant",
+                    Label::new(file_id, variant.span(), "a variant with this name already exists"),
+                )));
+            }
+        }
+        let (variants, index) = variant_descs.reindex::<ty::VariantIdx>();
+        for (variant_idx, fields) in variants.values().zip(variant_fields) {
+            self.variant_fields
+                .borrow_mut()
+                .insert((ty, *variant_idx), fields);
+        }
+        self.variants.borrow_mut().insert(ty, variants);
+        self.ty_sess
+            .ty_kind_mut(ty)
+            .as_enum_mut()
+            .map(|desc| desc.variants = index)
+            .ok_or_else(|| {
+                Diagnostic::new_bug(
+                    "attempted to set variants for non-enum type",
+                    Label::new(
+                        file_id,
+                        def.name.span(),
+                        "lowered type for this definition is not an enum",
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    fn lower_struct(&self, def: &'ast ast::Struct, ty: ty::Ty, scope: &TyScope<'ast>) -> Result<()> {
+        let file_id = self.file_id;
+        let mut field_tys = IndexMap::new();
+        for binding in def.fields.iter() {
+            if field_tys
+                .insert(&*binding.binder, self.lookup_binding_in(binding, scope)?)
+                .is_some()
+            {
+                return Err(Box::from(Diagnostic::new_error(
+                    "attempted to rebind field type",
+                    Label::new(file_id, binding.span(), "a field with this name already exists"),
+                )));
+            }
+        }
+        let (fields, index) = field_tys.reindex::<ty::FieldIdx>();
+        let names = fields.keys().map(|name| name.to_string()).collect();
+        self.fields.borrow_mut().insert(ty, fields);
+        self.ty_sess
+            .ty_kind_mut(ty)
+            .as_struct_mut()
+            .map(|desc| {
+                desc.fields = index;
+                desc.field_names = names;
+            })
+            .ok_or_else(|| {
+                Diagnostic::new_bug(
+                    "attempted to set fields for non-struct type",
+                    Label::new(
+                        file_id,
+                        def.name.span(),
+                        "lowered type for this definition is not a struct",
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Lowers the fields of a variant, along with the index of its named fields.
+    fn lower_variant(
+        &self,
+        variant: &'ast ast::Variant,
+        scope: &TyScope<'ast>,
+    ) -> Result<(ty::Variant, FieldNames<'ast>)> {
+        let name = variant.name.to_string();
+        match &variant.fields {
+            ast::Fields::Unit => Ok((
+                ty::Variant {
+                    name,
+                    fields: IdxVec::new(),
+                    field_names: IdxVec::new(),
+                },
+                IndexMap::new(),
+            )),
+            ast::Fields::Tuple(tys) => Ok((
+                ty::Variant {
+                    name,
+                    fields: tys
+                        .iter()
+                        .map(|ty| self.lookup_ty_in(ty, ty.span(), scope))
+                        .collect::<Result<_>>()?,
+                    field_names: IdxVec::new(),
+                },
+                IndexMap::new(),
+            )),
+            ast::Fields::Named(bindings) => {
+                let mut field_tys = IndexMap::new();
+                for (field, ty) in bindings.iter() {
+                    if field_tys
+                        .insert(&**field, self.lookup_ty_in(ty, ty.span(), scope)?)
+                        .is_some()
+                    {
+                        return Err(Box::from(Diagnostic::new_error(
+                            "attempted to rebind field type",
+                            Label::new(
+                                self.file_id,
+                                field.span().merge(ty.span()),
+                                "a field with this name already exists",
+                            ),
+                        )));
+                    }
+                }
+                let (fields, index) = field_tys.reindex::<ty::FieldIdx>();
+                let field_names = fields.keys().map(|name| name.to_string()).collect();
+                Ok((
+                    ty::Variant {
+                        name,
+                        fields: index,
+                        field_names,
+                    },
+                    fields,
+                ))
+            }
+        }
+    }
+
+    /// Rejects a list of type parameters that names the same one twice.
+    pub fn check_ty_params(&self, ty_params: &'ast [Spanned<ast::Ident>]) -> Result<()> {
+        for (i, ty_param) in ty_params.iter().enumerate() {
+            if ty_params[..i].iter().any(|previous| **previous == **ty_param) {
+                return Err(Box::from(Diagnostic::new_error(
+                    "attempted to rebind type parameter",
+                    Label::new(
+                        self.file_id,
+                        ty_param.span(),
+                        "a type parameter with this name already exists",
+                    ),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn bind_generic(&mut self, ident: &'ast ast::Ident, span: Span, generic: Generic<'ast>) -> Result<()> {
+        if self.tys.contains_key(ident) || self.generics.contains_key(ident) {
+            return Err(Box::from(Diagnostic::new_error(
+                "previously bound type name",
+                Label::new(self.file_id, span, format!("attempt to rebind '{}' here", ident)),
+            )));
+        }
+        debug!("bind '{}' as generic", ident);
+        self.generics.insert(ident, generic);
+        Ok(())
+    }
+
     pub(super) fn bind(&mut self, ident: &'ast ast::Ident, span: Span, ty: ty::Ty) -> Result<()> {
-        if self.tys.get(ident).is_some() {
+        if self.tys.contains_key(ident) || self.generics.contains_key(ident) {
             return Err(Box::from(Diagnostic::new_error(
                 "previously bound type name",
                 Label::new(self.file_id, span, format!("attempt to rebind '{}' here", ident)),
@@ -169,24 +325,202 @@ impl<'ast> TyLowering<'ast> {
         )))
     }
 
-    #[inline]
-    pub fn lookup_ty(&self, ty: &'ast ast::Ty, span: Span) -> Result<ty::Ty> {
+    /// Lowers a type written where the type parameters in `scope` are bound.
+    pub fn lookup_ty_in(&self, ty: &'ast ast::Ty, span: Span, scope: &TyScope<'ast>) -> Result<ty::Ty> {
         match ty {
             ast::Ty::I8 => Ok(self.i8_ty),
             ast::Ty::I16 => Ok(self.i16_ty),
             ast::Ty::I32 => Ok(self.i32_ty),
             ast::Ty::I64 => Ok(self.i64_ty),
+            ast::Ty::U8 => Ok(self.u8_ty),
+            ast::Ty::U16 => Ok(self.u16_ty),
+            ast::Ty::U32 => Ok(self.u32_ty),
+            ast::Ty::U64 => Ok(self.u64_ty),
+            ast::Ty::F32 => Ok(self.f32_ty),
+            ast::Ty::F64 => Ok(self.f64_ty),
+            ast::Ty::Bool => Ok(self.bool_ty),
             ast::Ty::String => Ok(self.string_ty),
-            ast::Ty::Array(element_ty, size) => {
-                Ok(self.ty_sess.make_array(self.lookup_ty(element_ty, span)?, *size))
+            ast::Ty::Array(element_ty, size) => Ok(self
+                .ty_sess
+                .make_array(self.lookup_ty_in(element_ty, span, scope)?, *size)),
+            ast::Ty::Tuple(field_tys) => {
+                let field_tys = field_tys
+                    .iter()
+                    .map(|field_ty| self.lookup_ty_in(field_ty, span, scope))
+                    .collect::<Result<_>>()?;
+                Ok(self.ty_sess.make_tuple(field_tys))
+            }
+            ast::Ty::Fn(param_tys, return_ty) => {
+                let param_tys = param_tys
+                    .iter()
+                    .map(|param_ty| self.lookup_ty_in(param_ty, span, scope))
+                    .collect::<Result<_>>()?;
+                let return_ty = self.lookup_ty_in(return_ty, span, scope)?;
+                Ok(self.ty_sess.make_fn(return_ty, param_tys))
+            }
+            ast::Ty::TyName(ident, ty_args) => match scope.get(ident) {
+                Some(ty) if ty_args.is_empty() => Ok(*ty),
+                _ => {
+                    let ty_args = ty_args
+                        .iter()
+                        .map(|ty_arg| self.lookup_ty_in(ty_arg, span, scope))
+                        .collect::<Result<Vec<_>>>()?;
+                    self.lookup_named(ident, ty_args, span)
+                }
+            },
+        }
+    }
+
+    #[inline]
+    pub fn lookup_binding_in(&self, binding: &'ast ast::Binding, scope: &TyScope<'ast>) -> Result<ty::Ty> {
+        self.lookup_ty_in(&binding.ty, binding.ty.span(), scope)
+    }
+
+    /// Looks up a named type, making the instance of it for `ty_args` if it is generic.
+    pub fn lookup_named(&self, ident: &'ast ast::Ident, ty_args: Vec<ty::Ty>, span: Span) -> Result<ty::Ty> {
+        if let Some(generic) = self.generics.get(ident) {
+            return self.instantiate(*generic, ty_args, span);
+        }
+        let ty = self.lookup(ident, span)?;
+        if !ty_args.is_empty() {
+            return Err(Box::from(Diagnostic::new_error(
+                "wrong number of type arguments",
+                Label::new(self.file_id, span, format!("'{}' takes no type arguments", ident)),
+            )));
+        }
+        Ok(ty)
+    }
+
+    fn instantiate(&self, generic: Generic<'ast>, ty_args: Vec<ty::Ty>, span: Span) -> Result<ty::Ty> {
+        let (name, ty_params) = match generic {
+            Generic::Enum(def) => (&*def.name, &def.ty_params),
+            Generic::Struct(def) => (&*def.name, &def.ty_params),
+        };
+        if ty_args.len() != ty_params.len() {
+            return Err(Box::from(Diagnostic::new_error(
+                "wrong number of type arguments",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!(
+                        "'{}' takes {} type arguments, but {} were given",
+                        name,
+                        ty_params.len(),
+                        ty_args.len()
+                    ),
+                ),
+            )));
+        }
+        if let Some(ty) = self.ty_sess.instance(name, &ty_args) {
+            return Ok(ty);
+        }
+        if self.instance_depth.get() >= INSTANCE_DEPTH_LIMIT {
+            return Err(Box::from(Diagnostic::new_error(
+                "recursion limit reached",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!("'{}' is instantiated with ever larger type arguments", name),
+                ),
+            )));
+        }
+        let scope = ty_params
+            .iter()
+            .map(|ty_param| &**ty_param)
+            .zip(ty_args.iter().copied())
+            .collect::<TyScope>();
+        debug!("instantiate '{}' with {:?}", name, ty_args);
+        self.instance_depth.set(self.instance_depth.get() + 1);
+        let result = match generic {
+            Generic::Enum(def) => {
+                let ty = self.ty_sess.make_enum(name, ty_args);
+                self.lower_enum(def, ty, &scope).map(|_| ty)
+            }
+            Generic::Struct(def) => {
+                let ty = self.ty_sess.make_struct(name, ty_args);
+                self.lower_struct(def, ty, &scope).map(|_| ty)
+            }
+        };
+        self.instance_depth.set(self.instance_depth.get() - 1);
+        result
+    }
+
+    /// Infers the type parameters among `ty_params` that `declared` mentions from `actual`, the type of a value
+    /// given where `declared` is expected. Parameters that are already inferred keep their type.
+    pub fn infer(
+        &self,
+        ty_params: &'ast [Spanned<ast::Ident>],
+        declared: &'ast ast::Ty,
+        actual: ty::Ty,
+        inferred: &mut TyScope<'ast>,
+    ) {
+        match declared {
+            ast::Ty::TyName(ident, ty_args)
+                if ty_args.is_empty() && ty_params.iter().any(|ty_param| **ty_param == *ident) =>
+            {
+                inferred.entry(ident).or_insert(actual);
+            }
+            ast::Ty::TyName(ident, ty_args) => {
+                let actual_ty_args = match &*self.ty_sess.ty_kind(actual) {
+                    ty::TyKind::Enum(ty::Enum { name, ty_args, .. })
+                    | ty::TyKind::Struct(ty::Struct { name, ty_args, .. })
+                        if **name == **ident =>
+                    {
+                        ty_args.clone()
+                    }
+                    _ => return,
+                };
+                for (ty_arg, actual) in ty_args.iter().zip(actual_ty_args) {
+                    self.infer(ty_params, ty_arg, actual, inferred);
+                }
             }
-            ast::Ty::TyName(ident) => self.lookup(ident, span),
+            ast::Ty::Array(element_ty, _) => {
+                let actual_element_ty = self
+                    .ty_sess
+                    .ty_kind(actual)
+                    .as_array()
+                    .map(|array| array.element_ty);
+                if let Some(actual_element_ty) = actual_element_ty {
+                    self.infer(ty_params, element_ty, actual_element_ty, inferred);
+                }
+            }
+            ast::Ty::Tuple(field_tys) => {
+                let actual_field_tys = match self.ty_sess.ty_kind(actual).as_tuple() {
+                    Some(tuple) if tuple.fields.len() == field_tys.len() => tuple.fields.clone(),
+                    _ => return,
+                };
+                for (field_ty, actual) in field_tys.iter().zip(actual_field_tys.values()) {
+                    self.infer(ty_params, field_ty, *actual, inferred);
+                }
+            }
+            ast::Ty::Fn(param_tys, return_ty) => {
+                let actual_prototype = match self.ty_sess.ty_kind(actual).as_prototype() {
+                    Some(prototype) if prototype.params.len() == param_tys.len() => prototype.clone(),
+                    _ => return,
+                };
+                for (param_ty, actual) in param_tys.iter().zip(actual_prototype.params.values()) {
+                    self.infer(ty_params, param_ty, *actual, inferred);
+                }
+                self.infer(ty_params, return_ty, actual_prototype.return_ty, inferred);
+            }
+            _ => {}
+        }
+    }
+
+    #[inline]
+    pub fn generic_enum(&self, ident: &ast::Ident) -> Option<&'ast ast::Enum> {
+        match self.generics.get(ident) {
+            Some(Generic::Enum(def)) => Some(def),
+            _ => None,
         }
     }
 
     #[inline]
-    pub fn lookup_binding(&self, binding: &'ast ast::Binding) -> Result<ty::Ty> {
-        self.lookup_ty(&binding.ty, binding.ty.span())
+    pub fn generic_struct(&self, ident: &ast::Ident) -> Option<&'ast ast::Struct> {
+        match self.generics.get(ident) {
+            Some(Generic::Struct(def)) => Some(def),
+            _ => None,
+        }
     }
 
     pub fn lookup_variant(
@@ -195,7 +529,7 @@ impl<'ast> TyLowering<'ast> {
         variant_name: &'ast ast::Ident,
         span: Span,
     ) -> Result<ty::VariantIdx> {
-        if let Some(variants) = self.variants.get(&ty) {
+        if let Some(variants) = self.variants.borrow().get(&ty) {
             if let Some(variant) = variants.get(variant_name) {
                 Ok(*variant)
             } else {
@@ -221,7 +555,25 @@ impl<'ast> TyLowering<'ast> {
     }
 
     pub fn lookup_field(&self, ty: ty::Ty, field_name: &'ast ast::Ident, span: Span) -> Result<ty::FieldIdx> {
-        if let Some(fields) = self.fields.get(&ty) {
+        if let Some(tuple) = self.ty_sess.ty_kind(ty).as_tuple() {
+            // NOTE the fields of a tuple are named by their position
+            return match field_name.parse::<usize>() {
+                Ok(position) if position < tuple.fields.len() => Ok(ty::FieldIdx::new(position)),
+                _ => Err(Box::from(Diagnostic::new_error(
+                    "type usage error",
+                    Label::new(
+                        self.file_id,
+                        span,
+                        format!(
+                            "'{}' is not a field of the tuple given, which has {} fields",
+                            field_name,
+                            tuple.fields.len()
+                        ),
+                    ),
+                ))),
+            };
+        }
+        if let Some(fields) = self.fields.borrow().get(&ty) {
             if let Some(field) = fields.get(field_name) {
                 Ok(*field)
             } else {
@@ -240,7 +592,33 @@ impl<'ast> TyLowering<'ast> {
                 Label::new(
                     self.file_id,
                     span,
-                    "can't reference fields of a type that is not a struct",
+                    "can't reference fields of a type that is not a struct or a tuple",
+                ),
+            )))
+        }
+    }
+
+    pub fn lookup_variant_field(
+        &self,
+        ty: ty::Ty,
+        variant_idx: ty::VariantIdx,
+        field_name: &'ast ast::Ident,
+        span: Span,
+    ) -> Result<ty::FieldIdx> {
+        if let Some(field) = self
+            .variant_fields
+            .borrow()
+            .get(&(ty, variant_idx))
+            .and_then(|fields| fields.get(field_name))
+        {
+            Ok(*field)
+        } else {
+            Err(Box::from(Diagnostic::new_error(
+                "type usage error",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!("'{}' is not a field of the variant given", field_name),
                 ),
             )))
         }
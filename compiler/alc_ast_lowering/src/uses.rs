@@ -0,0 +1,240 @@
+use crate::captures::bound_names;
+use alc_diagnostic::{Span, Spanned};
+use alc_parser::ast;
+
+/// A use of a binding that can give the type it has, as in `clamp(limit)` where `clamp` takes an `i64`.
+pub enum Use<'ast> {
+    /// It is the argument at `position` of a call to `target`.
+    Arg {
+        target: &'ast Spanned<ast::Ident>,
+        ty_args: &'ast [Spanned<ast::Ty>],
+        position: usize,
+    },
+    /// It is an operand of a binop whose other operand is the expression given.
+    Operand(&'ast ast::Expr, Span),
+    /// It is the value of a binding annotated with the type given.
+    Annotated(&'ast Spanned<ast::Ty>),
+    /// It is the value of the term it is used in, which is given to what that term ends in.
+    Tail,
+}
+
+/// The uses of `binder` in `term`, the rest of the scope it is bound in, in the order they are written.
+pub fn uses<'ast>(binder: &ast::Ident, term: &'ast ast::Term) -> Vec<Use<'ast>> {
+    let mut uses = Uses { binder, uses: vec![] };
+    uses.visit_term(term, true);
+    uses.uses
+}
+
+struct Uses<'b, 'ast> {
+    binder: &'b ast::Ident,
+    uses: Vec<Use<'ast>>,
+}
+
+impl<'b, 'ast> Uses<'b, 'ast> {
+    fn is_binder(&self, expr: &ast::Expr) -> bool {
+        matches!(expr, ast::Expr::Var(stream) if stream.len() == 1 && *stream[0] == *self.binder)
+    }
+
+    fn binds(&self, pattern: &ast::Pattern) -> bool {
+        let mut names = vec![];
+        bound_names(pattern, &mut names);
+        names.into_iter().any(|name| *name == *self.binder)
+    }
+
+    /// Visits `term`, where `tail` is whether its value is the value of the term the binder is used in.
+    fn visit_term(&mut self, term: &'ast ast::Term, tail: bool) {
+        match term {
+            ast::Term::Let {
+                binder,
+                annotation,
+                expr,
+                body,
+                ..
+            } => {
+                match annotation {
+                    Some(annotation) if self.is_binder(expr) => self.uses.push(Use::Annotated(annotation)),
+                    _ => self.visit_expr(expr),
+                }
+                // NOTE the rest of the term refers to another binding of the same name
+                if **binder != *self.binder {
+                    self.visit_term(body, tail);
+                }
+            }
+            ast::Term::LetPattern { expr, arm, .. } => {
+                self.visit_expr(expr);
+                self.visit_arm(arm, tail);
+            }
+            ast::Term::Match { source, arms } => {
+                self.visit_expr(source);
+                for arm in arms.iter() {
+                    self.visit_arm(arm, tail);
+                }
+            }
+            ast::Term::If {
+                source,
+                then,
+                otherwise,
+            } => {
+                self.visit_expr(source);
+                self.visit_term(then, tail);
+                self.visit_term(otherwise, tail);
+            }
+            ast::Term::Println { expr, body } | ast::Term::Expr { expr, body } => {
+                self.visit_expr(expr);
+                self.visit_term(body, tail);
+            }
+            ast::Term::Assign {
+                index, expr, body, ..
+            } => {
+                if let Some(index) = index {
+                    self.visit_expr(index);
+                }
+                self.visit_expr(expr);
+                self.visit_term(body, tail);
+            }
+            ast::Term::While { source, block, body } => {
+                self.visit_expr(source);
+                self.visit_term(block, false);
+                self.visit_term(body, tail);
+            }
+            ast::Term::Loop { block, body } => {
+                self.visit_term(block, false);
+                self.visit_term(body, tail);
+            }
+            ast::Term::Break | ast::Term::Continue | ast::Term::End => {}
+            ast::Term::Return(expr) | ast::Term::Value(expr) if tail && self.is_binder(expr) => {
+                self.uses.push(Use::Tail)
+            }
+            ast::Term::Return(expr) | ast::Term::Value(expr) => self.visit_expr(expr),
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &'ast ast::Arm, tail: bool) {
+        if self.binds(&arm.pattern) {
+            return;
+        }
+        if let Some(guard) = &arm.guard {
+            self.visit_expr(guard);
+        }
+        self.visit_term(&arm.body, tail);
+    }
+
+    fn visit_exprs(&mut self, exprs: impl IntoIterator<Item = &'ast Spanned<ast::Expr>>) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast ast::Expr) {
+        match expr {
+            ast::Expr::NumberLiteral(_)
+            | ast::Expr::FloatLiteral(_)
+            | ast::Expr::BoolLiteral(_)
+            | ast::Expr::StringLiteral(_)
+            | ast::Expr::Var(_) => {}
+            ast::Expr::ArrayLiteral(elements) | ast::Expr::Tuple(elements) => self.visit_exprs(elements),
+            ast::Expr::Index { target, index } => {
+                self.visit_expr(target);
+                self.visit_expr(index);
+            }
+            ast::Expr::Unop { operand, .. } => self.visit_expr(operand),
+            ast::Expr::Binop { left, right, .. } => {
+                if self.is_binder(left) {
+                    self.uses.push(Use::Operand(right, right.span()));
+                }
+                if self.is_binder(right) {
+                    self.uses.push(Use::Operand(left, left.span()));
+                }
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            ast::Expr::Call {
+                target,
+                ty_args,
+                args,
+            } => {
+                for (position, arg) in args.iter().enumerate() {
+                    if self.is_binder(arg) {
+                        self.uses.push(Use::Arg {
+                            target,
+                            ty_args,
+                            position,
+                        });
+                    }
+                }
+                self.visit_exprs(args);
+            }
+            ast::Expr::MethodCall { receiver, args, .. } => {
+                self.visit_expr(receiver);
+                self.visit_exprs(args);
+            }
+            // NOTE the value of a branch used as an expression is not the value of the term it is written in
+            ast::Expr::Branch(term) => self.visit_term(term, false),
+            ast::Expr::Closure(closure) => {
+                let shadowed = closure.name.iter().any(|name| **name == *self.binder)
+                    || closure.params.iter().any(|param| *param.binder == *self.binder);
+                if !shadowed {
+                    self.visit_term(&closure.body, false);
+                }
+            }
+            ast::Expr::Variant { fields, .. } => match fields {
+                ast::Fields::Unit => {}
+                ast::Fields::Tuple(fields) => self.visit_exprs(fields),
+                ast::Fields::Named(fields) => self.visit_exprs(fields.iter().map(|(_, field)| field)),
+            },
+            ast::Expr::Record { fields, .. } => self.visit_exprs(fields.iter().map(|(_, field)| field)),
+            ast::Expr::Socket { domain, ty, protocol } => {
+                for expr in [domain, ty, protocol] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Bind {
+                socket_file_descriptor,
+                address,
+                address_length,
+            } => {
+                for expr in [socket_file_descriptor, address, address_length] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Listen {
+                socket_file_descriptor,
+                backlog,
+            } => {
+                self.visit_expr(socket_file_descriptor);
+                self.visit_expr(backlog);
+            }
+            ast::Expr::Accept {
+                socket_file_descriptor,
+            }
+            | ast::Expr::Close {
+                socket_file_descriptor,
+            } => self.visit_expr(socket_file_descriptor),
+            ast::Expr::Recv {
+                socket_file_descriptor,
+                buffer,
+                buffer_length,
+                flags,
+            } => {
+                for expr in [socket_file_descriptor, buffer, buffer_length, flags] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::Send {
+                socket_file_descriptor,
+                buffer,
+                buffer_length,
+                content,
+                flags,
+            } => {
+                for expr in [socket_file_descriptor, buffer, buffer_length, content, flags] {
+                    self.visit_expr(expr);
+                }
+            }
+            ast::Expr::ListenAndServe { address, handler, .. } => {
+                self.visit_expr(address);
+                self.visit_expr(handler);
+            }
+        }
+    }
+}
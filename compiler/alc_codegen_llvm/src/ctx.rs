@@ -1,11 +1,30 @@
-use crate::{CodegenLLVM, ACCEPT, BIND, CLOSE, HTONS, LISTEN, PRINTF, RECV, SEND, SNPRINTF, SOCKET, STRLEN};
-use alc_ast_lowering::{ir, ty};
+use crate::{
+    CodegenLLVM,
+    ABORT,
+    ACCEPT,
+    BIND,
+    CLOSE,
+    DPRINTF,
+    HTONS,
+    LISTEN,
+    PRINTF,
+    RECV,
+    SEND,
+    SNPRINTF,
+    SOCKET,
+    STDERR_FILENO,
+    STRCMP,
+    STRLEN,
+};
+use alc_ast_lowering::{idx::Idx, idx_vec::IdxVec, ir, ty};
 use alc_command_option::Gc;
-use alc_diagnostic::{Diagnostic, Label, Result};
+use alc_diagnostic::{Diagnostic, Label, Result, Span};
 use inkwell::{
-    types::BasicType,
-    values::{ArrayValue, BasicValue, BasicValueEnum, FunctionValue, IntValue, VectorValue},
+    basic_block::BasicBlock,
+    types::{BasicType, BasicTypeEnum},
+    values::{ArrayValue, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue},
     AddressSpace,
+    FloatPredicate,
     IntPredicate,
 };
 use std::{collections::HashMap, ops::Deref};
@@ -21,7 +40,7 @@ macro_rules! local {
 enum MatchCase<'ctx> {
     Wild,
     Record,
-    StringLiteral(VectorValue<'ctx>),
+    StringLiteral(PointerValue<'ctx>),
     ArrayLiteral(ArrayValue<'ctx>),
     Literal(IntValue<'ctx>),
     Variant(ty::Ty, IntValue<'ctx>),
@@ -31,7 +50,30 @@ pub struct CodegenLLVMCtx<'gen, 'ctx> {
     sess: &'gen CodegenLLVM<'gen, 'ctx>,
     ir: &'gen ir::Def,
     llvm: FunctionValue<'ctx>,
+    tys: &'gen IdxVec<ir::LocalIdx, ty::Ty>,
     bindings: HashMap<ir::LocalIdx, BasicValueEnum<'ctx>>,
+    slots: HashMap<ir::LocalIdx, PointerValue<'ctx>>,
+    yield_targets: Vec<YieldTarget<'ctx>>,
+    jump_targets: Vec<HashMap<ir::BlockIdx, JumpTarget<'ctx>>>,
+    loop_targets: Vec<LoopTarget<'ctx>>,
+}
+
+/// The join point of a match expression and the values yielded into it so far.
+struct YieldTarget<'ctx> {
+    block: BasicBlock<'ctx>,
+    incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)>,
+}
+
+/// The block of an arm body that the leaves of a decision jump to, and the arguments passed to it so far.
+struct JumpTarget<'ctx> {
+    block: BasicBlock<'ctx>,
+    incoming: Vec<(Vec<BasicValueEnum<'ctx>>, BasicBlock<'ctx>)>,
+}
+
+/// The blocks that `continue` and `break` jump to from inside a loop.
+struct LoopTarget<'ctx> {
+    header: BasicBlock<'ctx>,
+    exit: BasicBlock<'ctx>,
 }
 
 impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
@@ -40,7 +82,9 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
     }
 
     fn lookup(&self, idx: ir::LocalIdx) -> Result<BasicValueEnum<'ctx>> {
-        if let Some(value) = self.bindings.get(&idx) {
+        if let Some(slot) = self.slots.get(&idx) {
+            Ok(self.builder.build_load(*slot, local!(idx)))
+        } else if let Some(value) = self.bindings.get(&idx) {
             Ok(*value)
         } else {
             Err(Box::from(Diagnostic::new_bug(
@@ -54,6 +98,148 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
         }
     }
 
+    /// The value of `idx` as it is kept past the end of this function or in a mutable binding, which moves a string
+    /// literal off the stack.
+    fn lookup_escaping(&self, idx: ir::LocalIdx) -> Result<BasicValueEnum<'ctx>> {
+        let value = self.lookup(idx)?;
+        if !value.is_vector_value() {
+            return Ok(value);
+        }
+        let value = value.into_vector_value();
+        if self.command_options.gc == Gc::OwnRc {
+            return Ok(self.build_counted_string(value, local!(idx), idx.span())?.into());
+        }
+        let const_ref = self
+            .builder
+            .build_malloc(value.get_type(), local!(idx))
+            .map_err(|err| {
+                Box::from(Diagnostic::new_bug(
+                    "failed to build malloc call",
+                    Label::new(self.file_id, idx.span(), err),
+                ))
+            })?;
+        self.builder.build_store(const_ref, value);
+        Ok(unsafe { self.sess.gep(const_ref, 0, local!(idx)) }.into())
+    }
+
+    /// Allocates the slot of a mutable binding.
+    fn build_slot(&self, ty: BasicTypeEnum<'ctx>, idx: ir::LocalIdx) -> PointerValue<'ctx> {
+        self.build_entry_alloca(ty, local!(idx))
+    }
+
+    /// Allocates stack memory in the entry block, so that loops don't grow the stack.
+    fn build_entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let builder = self.context.create_builder();
+        let entry = self.llvm.get_first_basic_block().unwrap();
+        match entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name)
+    }
+
+    /// Walks `field_idxes` from the struct held by `idx`, returning the innermost struct and its type.
+    fn compile_field_path(
+        &self,
+        idx: ir::LocalIdx,
+        field_idxes: &[ty::FieldIdx],
+    ) -> Result<(PointerValue<'ctx>, ty::Ty)> {
+        let mut ptr = self.lookup(idx)?.into_pointer_value();
+        let mut ty = self.tys[idx];
+        for field_idx in field_idxes.iter() {
+            ptr = self.read_struct_field(ptr, ty, *field_idx).into_pointer_value();
+            ty = self.ty_sess.ty_kind(ty).field_ty(*field_idx).unwrap();
+        }
+        Ok((ptr, ty))
+    }
+
+    /// The pointer to the field at `field_idxes` of `idx`, or to the slot of `idx` when there are no fields, along with
+    /// the type it points to. A binding without fields or a slot is held as a value and has no pointer.
+    fn compile_place_ptr(
+        &self,
+        idx: ir::LocalIdx,
+        field_idxes: &[ty::FieldIdx],
+    ) -> Result<Option<(PointerValue<'ctx>, ty::Ty)>> {
+        Ok(match field_idxes.split_last() {
+            Some((field_idx, parents)) => {
+                let (ptr, ty) = self.compile_field_path(idx, parents)?;
+                Some((
+                    self.struct_field_ptr(ptr, ty, *field_idx),
+                    self.ty_sess.ty_kind(ty).field_ty(*field_idx).unwrap(),
+                ))
+            }
+            None => self.slots.get(&idx).map(|slot| (*slot, self.tys[idx])),
+        })
+    }
+
+    /// Aborts the program with a message pointing at `span` unless `index` is less than `size`.
+    fn build_bounds_check(&self, index: IntValue<'ctx>, size: i32, span: Span) -> Result<()> {
+        let in_bounds_block = self.context.append_basic_block(self.llvm, "in_bounds");
+        let out_of_bounds_block = self.context.append_basic_block(self.llvm, "out_of_bounds");
+        // NOTE the index is extended to 64 bits beforehand, so a negative index compares as a large one
+        self.builder.build_conditional_branch(
+            self.builder.build_int_compare(
+                IntPredicate::ULT,
+                index,
+                self.context.i64_type().const_int(size as u64, false),
+                "is_in_bounds",
+            ),
+            in_bounds_block,
+            out_of_bounds_block,
+        );
+        self.builder.position_at_end(out_of_bounds_block);
+        let message = format!(
+            "{}: index out of bounds: the length is {} but the index is %lld\n",
+            self.source_location(span)?.replace('%', "%%"),
+            size
+        );
+        let format = self
+            .builder
+            .build_global_string_ptr(&message, "bounds_check_format");
+        self.builder.build_call(
+            self.module.get_function(DPRINTF).unwrap(),
+            &[
+                self.context.i32_type().const_int(STDERR_FILENO, false).into(),
+                format.as_pointer_value().into(),
+                index.into(),
+            ],
+            "dprintf",
+        );
+        self.builder
+            .build_call(self.module.get_function(ABORT).unwrap(), &[], "abort");
+        self.builder.build_unreachable();
+        self.builder.position_at_end(in_bounds_block);
+        Ok(())
+    }
+
+    /// Points at the element `index` of the array behind `ptr`, after checking it against the array's length.
+    fn compile_element_ptr(
+        &self,
+        ptr: PointerValue<'ctx>,
+        array_ty: ty::Ty,
+        index: ir::LocalIdx,
+        span: Span,
+    ) -> Result<PointerValue<'ctx>> {
+        let size = self.ty_sess.ty_kind(array_ty).as_array().unwrap().size;
+        let signed = self.is_signed(index);
+        let index = self.lookup(index)?.into_int_value();
+        let index = if signed {
+            self.builder
+                .build_int_s_extend_or_bit_cast(index, self.context.i64_type(), "index")
+        } else {
+            self.builder
+                .build_int_z_extend_or_bit_cast(index, self.context.i64_type(), "index")
+        };
+        self.build_bounds_check(index, size, span)?;
+        Ok(unsafe {
+            self.builder.build_in_bounds_gep(
+                ptr,
+                &[self.context.i64_type().const_int(0, false), index],
+                "element_ptr",
+            )
+        })
+    }
+
     fn compile_unop(
         &self,
         idx: ir::LocalIdx,
@@ -66,18 +252,69 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
         })
     }
 
-    fn compile_int_predicate(&self, kind: ir::BinopKind) -> Option<IntPredicate> {
+    fn is_signed(&self, idx: ir::LocalIdx) -> bool {
+        self.ty_sess.ty_kind(self.tys[idx]).is_signed()
+    }
+
+    fn compile_int_predicate(&self, kind: ir::BinopKind, signed: bool) -> Option<IntPredicate> {
+        Some(match (kind, signed) {
+            (ir::BinopKind::Less, true) => IntPredicate::SLT,
+            (ir::BinopKind::Less, false) => IntPredicate::ULT,
+            (ir::BinopKind::Leq, true) => IntPredicate::SLE,
+            (ir::BinopKind::Leq, false) => IntPredicate::ULE,
+            (ir::BinopKind::Greater, true) => IntPredicate::SGT,
+            (ir::BinopKind::Greater, false) => IntPredicate::UGT,
+            (ir::BinopKind::Geq, true) => IntPredicate::SGE,
+            (ir::BinopKind::Geq, false) => IntPredicate::UGE,
+            (ir::BinopKind::Eq, _) => IntPredicate::EQ,
+            (ir::BinopKind::Neq, _) => IntPredicate::NE,
+            _ => return None,
+        })
+    }
+
+    fn is_float(&self, idx: ir::LocalIdx) -> bool {
+        self.ty_sess.ty_kind(self.tys[idx]).is_float()
+    }
+
+    fn compile_float_predicate(&self, kind: ir::BinopKind) -> Option<FloatPredicate> {
         Some(match kind {
-            ir::BinopKind::Less => IntPredicate::ULT,
-            ir::BinopKind::Leq => IntPredicate::ULE,
-            ir::BinopKind::Greater => IntPredicate::UGT,
-            ir::BinopKind::Geq => IntPredicate::UGE,
-            ir::BinopKind::Eq => IntPredicate::EQ,
-            ir::BinopKind::Neq => IntPredicate::NE,
+            ir::BinopKind::Less => FloatPredicate::OLT,
+            ir::BinopKind::Leq => FloatPredicate::OLE,
+            ir::BinopKind::Greater => FloatPredicate::OGT,
+            ir::BinopKind::Geq => FloatPredicate::OGE,
+            ir::BinopKind::Eq => FloatPredicate::OEQ,
+            // NOTE unordered so that NaN != NaN holds
+            ir::BinopKind::Neq => FloatPredicate::UNE,
             _ => return None,
         })
     }
 
+    fn compile_float_binop(
+        &self,
+        idx: ir::LocalIdx,
+        kind: ir::BinopKind,
+        left: ir::LocalIdx,
+        right: ir::LocalIdx,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let left = self.lookup(left)?.into_float_value();
+        let right = self.lookup(right)?.into_float_value();
+        Ok(match kind {
+            ir::BinopKind::Plus => self.builder.build_float_add(left, right, local!(idx)).into(),
+            ir::BinopKind::Minus => self.builder.build_float_sub(left, right, local!(idx)).into(),
+            ir::BinopKind::Mul => self.builder.build_float_mul(left, right, local!(idx)).into(),
+            ir::BinopKind::Div => self.builder.build_float_div(left, right, local!(idx)).into(),
+            comparison => self
+                .builder
+                .build_float_compare(
+                    self.compile_float_predicate(comparison).unwrap(),
+                    left,
+                    right,
+                    local!(idx),
+                )
+                .into(),
+        })
+    }
+
     fn compile_binop(
         &self,
         idx: ir::LocalIdx,
@@ -85,12 +322,19 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
         left: ir::LocalIdx,
         right: ir::LocalIdx,
     ) -> Result<BasicValueEnum<'ctx>> {
+        if self.is_float(left) {
+            return self.compile_float_binop(idx, kind, left, right);
+        }
+        let signed = self.is_signed(left);
         let left = self.lookup(left)?.into_int_value();
         let right = self.lookup(right)?.into_int_value();
         Ok(match kind {
             ir::BinopKind::Plus => self.builder.build_int_add(left, right, local!(idx)).into(),
             ir::BinopKind::Minus => self.builder.build_int_sub(left, right, local!(idx)).into(),
             ir::BinopKind::Mul => self.builder.build_int_mul(left, right, local!(idx)).into(),
+            ir::BinopKind::Div if signed => {
+                self.builder.build_int_signed_div(left, right, local!(idx)).into()
+            }
             ir::BinopKind::Div => self
                 .builder
                 .build_int_unsigned_div(left, right, local!(idx))
@@ -101,19 +345,17 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             ir::BinopKind::LShift => self.builder.build_left_shift(left, right, local!(idx)).into(),
             ir::BinopKind::RShift => self
                 .builder
-                .build_right_shift(left, right, false, local!(idx))
+                .build_right_shift(left, right, signed, local!(idx))
                 .into(),
-            comparison => {
-                let comparison = self.builder.build_int_compare(
-                    self.compile_int_predicate(comparison).unwrap(),
+            comparison => self
+                .builder
+                .build_int_compare(
+                    self.compile_int_predicate(comparison, signed).unwrap(),
                     left,
                     right,
                     local!(idx),
-                );
-                self.builder
-                    .build_int_z_extend::<IntValue>(comparison, self.context.i32_type(), "cast_tmp")
-                    .into()
-            }
+                )
+                .into(),
         })
     }
 
@@ -123,11 +365,54 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             ir::ExprKind::I16Literal(literal) => Ok(self.compile_i16_literal(*literal).into()),
             ir::ExprKind::I32Literal(literal) => Ok(self.compile_i32_literal(*literal).into()),
             ir::ExprKind::I64Literal(literal) => Ok(self.compile_i64_literal(*literal).into()),
-            ir::ExprKind::ArrayLiteral { element_ty, elements } => {
-                Ok(self.compile_array_literal(*element_ty, elements.to_vec()).into())
+            ir::ExprKind::U8Literal(literal) => Ok(self.compile_u8_literal(*literal).into()),
+            ir::ExprKind::U16Literal(literal) => Ok(self.compile_u16_literal(*literal).into()),
+            ir::ExprKind::U32Literal(literal) => Ok(self.compile_u32_literal(*literal).into()),
+            ir::ExprKind::U64Literal(literal) => Ok(self.compile_u64_literal(*literal).into()),
+            ir::ExprKind::F32Literal(literal) => Ok(self.compile_f32_literal(*literal).into()),
+            ir::ExprKind::F64Literal(literal) => Ok(self.compile_f64_literal(*literal).into()),
+            ir::ExprKind::NumberLiteral(literal) => {
+                Ok(self.compile_number_literal(self.tys[expr.local_idx], *literal))
+            }
+            ir::ExprKind::FloatLiteral(literal) => Ok(self
+                .compile_float_literal(self.tys[expr.local_idx], *literal)
+                .into()),
+            ir::ExprKind::BoolLiteral(literal) => Ok(self.compile_bool_literal(*literal).into()),
+            ir::ExprKind::ArrayLiteral { elements, .. } => {
+                let element_ty = self
+                    .ty_sess
+                    .ty_kind(self.tys[expr.local_idx])
+                    .as_array()
+                    .unwrap()
+                    .element_ty;
+                Ok(self.compile_array_literal(element_ty, elements.to_vec()).into())
             }
             ir::ExprKind::StringLiteral(literal) => Ok(self.compile_string_literal(literal).into()),
-            ir::ExprKind::Var(local_idx, _) => self.lookup(*local_idx),
+            ir::ExprKind::Var(local_idx, field_idxes) => match field_idxes.split_last() {
+                Some((field_idx, parents)) => {
+                    let (ptr, ty) = self.compile_field_path(*local_idx, parents)?;
+                    Ok(self.read_struct_field(ptr, ty, *field_idx))
+                }
+                None => self.lookup(*local_idx),
+            },
+            ir::ExprKind::Index {
+                target,
+                fields,
+                index,
+            } => {
+                let (array_ptr, array_ty) = match self.compile_place_ptr(*target, fields)? {
+                    Some(place) => place,
+                    // NOTE an array held by an immutable binding is a value, so it is read through a copy in a slot
+                    None => {
+                        let array = self.lookup(*target)?;
+                        let array_ptr = self.build_slot(array.get_type(), expr.local_idx);
+                        self.builder.build_store(array_ptr, array);
+                        (array_ptr, self.tys[*target])
+                    }
+                };
+                let element_ptr = self.compile_element_ptr(array_ptr, array_ty, *index, expr.span)?;
+                Ok(self.builder.build_load(element_ptr, local!(expr.local_idx)))
+            }
             ir::ExprKind::Unop { kind, operand } => self.compile_unop(expr.local_idx, *kind, *operand),
             ir::ExprKind::Binop { kind, left, right } => {
                 self.compile_binop(expr.local_idx, *kind, *left, *right)
@@ -149,25 +434,71 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
                         ))
                     })
             }
+            ir::ExprKind::CallValue { target, args } => {
+                let closure = self.lookup(*target)?.into_pointer_value();
+                let args = args
+                    .values()
+                    .map(|local_idx| self.lookup(*local_idx))
+                    .collect::<Result<Vec<_>>>()?;
+                self.build_closure_call(
+                    closure,
+                    self.tys[*target],
+                    args.as_slice(),
+                    local!(expr.local_idx),
+                    expr.span,
+                )
+            }
+            ir::ExprKind::Closure { ty, target, captures } => {
+                let captures = captures
+                    .iter()
+                    .map(|local_idx| self.lookup_escaping(*local_idx))
+                    .collect::<Result<Vec<_>>>()?;
+                let closure = self.build_alloc(*ty, local!(expr.local_idx), expr.span)?;
+                self.write_closure(closure, *target, captures.as_slice(), expr.span)?;
+                if self.command_options.gc == Gc::OwnRc {
+                    self.builder.build_store(
+                        self.mark_ptr(closure, *ty),
+                        self.context.i32_type().const_int(1, false),
+                    );
+                }
+                Ok(closure.into())
+            }
             ir::ExprKind::Variant {
                 ty,
                 discriminant,
-                body,
+                fields,
             } => {
+                let fields = fields
+                    .values()
+                    .map(|local_idx| self.lookup(*local_idx))
+                    .collect::<Result<Vec<_>>>()?;
                 let variant = self.build_alloc(*ty, local!(expr.local_idx), expr.span)?;
                 self.write_enum_discriminant(variant, *ty, *discriminant);
-                self.write_enum_body(variant, *ty, *discriminant, self.lookup(*body)?);
-                // TODO: GC
+                self.write_enum_body(variant, *ty, *discriminant, &fields, expr.span)?;
+                if self.command_options.gc == Gc::OwnRc {
+                    self.builder.build_store(
+                        self.mark_ptr(variant, *ty),
+                        self.context.i32_type().const_int(1, false),
+                    );
+                }
                 Ok(variant.into())
             }
-            ir::ExprKind::Record { ty, fields } => {
-                let record = self.build_alloc(*ty, local!(expr.local_idx), expr.span)?;
+            ir::ExprKind::Record { ty: _, fields } | ir::ExprKind::Tuple(fields) => {
+                let ty = self.tys[expr.local_idx];
+                let record = self.build_alloc(ty, local!(expr.local_idx), expr.span)?;
                 for (field_idx, local_idx) in fields.iter() {
-                    self.write_struct_field(record, *ty, field_idx, self.lookup(*local_idx)?);
+                    self.write_struct_field(record, ty, field_idx, self.lookup(*local_idx)?);
+                }
+                if self.command_options.gc == Gc::OwnRc {
+                    // NOTE a new record starts out with a single owner
+                    self.builder.build_store(
+                        self.mark_ptr(record, ty),
+                        self.context.i32_type().const_int(1, false),
+                    );
                 }
-                // TODO: GC
                 Ok(record.into())
             }
+            ir::ExprKind::Match { source, arms } => self.compile_match_expr(expr.local_idx, *source, arms),
             ir::ExprKind::Socket { domain, ty, protocol } => {
                 let domain = self.lookup(*domain)?.into_int_value();
                 let ty = self.lookup(*ty)?.into_int_value();
@@ -425,7 +756,7 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
                 send_flags,
                 format_string,
                 http_header,
-                call_handler,
+                handler,
             } => {
                 let domain = self.lookup(*domain)?.into_int_value();
                 let ty = self.lookup(*ty)?.into_int_value();
@@ -519,7 +850,8 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
                     .builder
                     .build_alloca(recv_buffer.get_type(), "allocated_buffer");
                 let recv_buffer_ptr = unsafe { self.gep(allocated_recv_buffer, 0, "buffer_ptr") };
-                let send_content_ptr = self.lookup(*call_handler)?.into_pointer_value();
+                let handler_ty = self.tys[*handler];
+                let handler = self.lookup(*handler)?.into_pointer_value();
                 let send_buffer = self.lookup(*send_buffer)?.into_array_value();
                 let send_buffer_length = self.lookup(*send_buffer_length)?.into_int_value();
                 let http_header = self.lookup(*http_header)?.into_vector_value();
@@ -539,34 +871,6 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
                 let send_buffer_ptr = unsafe { self.gep(allocated_send_buffer, 0, "buffer_ptr") };
                 let http_header_ptr = unsafe { self.gep(allocated_http_header, 0, "content_ptr") };
                 let format_string_ptr = unsafe { self.gep(allocated_format_string, 0, "content_ptr") };
-                self.builder.build_call(
-                    self.module.get_function(SNPRINTF).unwrap(),
-                    &[
-                        send_buffer_ptr.into(),
-                        send_buffer_length.into(),
-                        format_string_ptr.into(),
-                        http_header_ptr.into(),
-                        send_content_ptr.into(),
-                    ],
-                    "snprintf",
-                );
-                let send_buffer_ptr_with_content =
-                    unsafe { self.gep(allocated_send_buffer, 0, "buffer_ptr") };
-                let send_content_length = self
-                    .builder
-                    .build_call(
-                        self.module.get_function(STRLEN).unwrap(),
-                        &[send_buffer_ptr_with_content.into()],
-                        "content_length",
-                    )
-                    .try_as_basic_value()
-                    .left()
-                    .ok_or_else(|| {
-                        Box::from(Diagnostic::new_bug(
-                            "attempted to return non-basic value from function call",
-                            Label::new(self.file_id, expr.span, "this call returns a non-basic value"),
-                        ))
-                    })?;
                 self.builder.build_unconditional_branch(loop_block);
                 self.builder.position_at_end(loop_block);
                 let accept_file_descriptor = self
@@ -623,6 +927,37 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
                             Label::new(self.file_id, expr.span, "this call returns a non-basic value"),
                         ))
                     })?;
+                // NOTE the handler gives the body of the response to every request
+                let send_content_ptr =
+                    self.build_closure_call(handler, handler_ty, &[], "content", expr.span)?;
+                self.builder.build_call(
+                    self.module.get_function(SNPRINTF).unwrap(),
+                    &[
+                        send_buffer_ptr.into(),
+                        send_buffer_length.into(),
+                        format_string_ptr.into(),
+                        http_header_ptr.into(),
+                        send_content_ptr.into(),
+                    ],
+                    "snprintf",
+                );
+                let send_buffer_ptr_with_content =
+                    unsafe { self.gep(allocated_send_buffer, 0, "buffer_ptr") };
+                let send_content_length = self
+                    .builder
+                    .build_call(
+                        self.module.get_function(STRLEN).unwrap(),
+                        &[send_buffer_ptr_with_content.into()],
+                        "content_length",
+                    )
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| {
+                        Box::from(Diagnostic::new_bug(
+                            "attempted to return non-basic value from function call",
+                            Label::new(self.file_id, expr.span, "this call returns a non-basic value"),
+                        ))
+                    })?;
                 let _send = self
                     .builder
                     .build_call(
@@ -687,11 +1022,59 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
                 let compiled_expr = self.compile_expr(expr)?;
                 self.bind(*binding, compiled_expr);
             }
+            ir::InstructionKind::LetMut { binding, ty: _, init } => {
+                // NOTE the slot of a string holds a pointer, since strings of any length are assigned to it
+                let value = self.lookup_escaping(*init)?;
+                let slot = self.build_slot(self.compile_basic_ty(self.tys[*binding]), *binding);
+                self.builder.build_store(slot, value);
+                self.slots.insert(*binding, slot);
+            }
+            ir::InstructionKind::Assign {
+                binding,
+                fields,
+                index,
+                ty: _,
+                value,
+            } => {
+                let value = self.lookup_escaping(*value)?;
+                let (ptr, ty) = self.compile_place_ptr(*binding, fields)?.ok_or_else(|| {
+                    Box::from(Diagnostic::new_bug(
+                        "assignment to a binding without a slot",
+                        Label::new(self.file_id, binding.span(), "binding is not mutable"),
+                    ))
+                })?;
+                let ptr = match index {
+                    Some(index) => self.compile_element_ptr(ptr, ty, *index, instruction.span)?,
+                    None => ptr,
+                };
+                self.builder.build_store(ptr, value);
+            }
+            ir::InstructionKind::Println { idx } if self.is_float(*idx) => {
+                let value = self.lookup(*idx)?.into_float_value();
+                // NOTE variadic arguments are promoted to double
+                let value = self
+                    .builder
+                    .build_float_cast(value, self.context.f64_type(), "printf_arg");
+                let format = self
+                    .builder
+                    .build_global_string_ptr("%g\n", "printf_float_format");
+                self.builder.build_call(
+                    self.module.get_function(PRINTF).unwrap(),
+                    &[format.as_pointer_value().into(), value.into()],
+                    "printf",
+                );
+            }
             ir::InstructionKind::Println { idx } => {
-                let value = self.lookup(*idx)?.into_vector_value();
-                let const_ref = self.builder.build_alloca(value.get_type(), "printf_tmp");
-                self.builder.build_store(const_ref, value);
-                let ptr = unsafe { self.sess.gep(const_ref, 0, "printf_tmp") };
+                let value = self.lookup(*idx)?;
+                // NOTE a string on the heap is passed as it is, while a string literal is put on the stack first
+                let ptr = if value.is_pointer_value() {
+                    value.into_pointer_value()
+                } else {
+                    let value = value.into_vector_value();
+                    let const_ref = self.build_entry_alloca(value.get_type().into(), "printf_tmp");
+                    self.builder.build_store(const_ref, value);
+                    unsafe { self.sess.gep(const_ref, 0, "printf_tmp") }
+                };
                 self.builder.build_call(
                     self.module.get_function(PRINTF).unwrap(),
                     &[ptr.as_basic_value_enum().into()],
@@ -704,10 +1087,10 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             ir::InstructionKind::Unmark(idx, ty) => {
                 self.write_mark(self.lookup(*idx)?.into_pointer_value(), *ty, false);
             }
-            ir::InstructionKind::Free(idx, _) => match self.command_options.gc {
+            ir::InstructionKind::Free(idx, ty) => match self.command_options.gc {
                 Gc::OwnRc => {
                     let ptr = self.lookup(*idx)?.into_pointer_value();
-                    self.build_free(ptr);
+                    self.build_drop(ptr, *ty);
                 }
                 Gc::None => {}
             },
@@ -724,30 +1107,7 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             }
             ir::InstructionKind::DecrementRc(idx, ty) => {
                 let ptr = self.lookup(*idx)?.into_pointer_value();
-                let rc_ptr = self.mark_ptr(ptr, *ty);
-                let current_rc = self.builder.build_load(rc_ptr, "rc").into_int_value();
-                let new_rc = self.builder.build_int_sub(
-                    current_rc,
-                    self.context.i32_type().const_int(1, false),
-                    "decrement_rc",
-                );
-                self.builder.build_store(rc_ptr, new_rc);
-                let free_block = self.context.append_basic_block(self.llvm, "free");
-                let else_block = self.context.append_basic_block(self.llvm, "else");
-                self.builder.build_conditional_branch(
-                    self.builder.build_int_compare(
-                        IntPredicate::SLE,
-                        new_rc,
-                        self.context.i32_type().const_int(0, false),
-                        "is_zero",
-                    ),
-                    free_block,
-                    else_block,
-                );
-                self.builder.position_at_end(free_block);
-                self.build_free(ptr);
-                self.builder.build_unconditional_branch(else_block);
-                self.builder.position_at_end(else_block);
+                self.build_release(ptr, *ty);
             }
         }
         Ok(())
@@ -770,12 +1130,19 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             ir::PatternKind::I16Literal(literal) => MatchCase::Literal(self.compile_i16_literal(*literal)),
             ir::PatternKind::I32Literal(literal) => MatchCase::Literal(self.compile_i32_literal(*literal)),
             ir::PatternKind::I64Literal(literal) => MatchCase::Literal(self.compile_i64_literal(*literal)),
+            ir::PatternKind::U8Literal(literal) => MatchCase::Literal(self.compile_u8_literal(*literal)),
+            ir::PatternKind::U16Literal(literal) => MatchCase::Literal(self.compile_u16_literal(*literal)),
+            ir::PatternKind::U32Literal(literal) => MatchCase::Literal(self.compile_u32_literal(*literal)),
+            ir::PatternKind::U64Literal(literal) => MatchCase::Literal(self.compile_u64_literal(*literal)),
+            ir::PatternKind::BoolLiteral(literal) => MatchCase::Literal(self.compile_bool_literal(*literal)),
             ir::PatternKind::ArrayLiteral { element_ty, elements } => {
                 MatchCase::ArrayLiteral(self.compile_array_literal(*element_ty, elements.to_owned()))
             }
-            ir::PatternKind::StringLiteral(literal) => {
-                MatchCase::StringLiteral(self.compile_string_literal(literal))
-            }
+            ir::PatternKind::StringLiteral(literal) => MatchCase::StringLiteral(
+                self.builder
+                    .build_global_string_ptr(literal, "string_pattern")
+                    .as_pointer_value(),
+            ),
             ir::PatternKind::Ident(binding) => {
                 self.bind(*binding, source);
                 MatchCase::Wild
@@ -783,12 +1150,15 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             ir::PatternKind::Variant {
                 ty,
                 discriminant,
-                binding,
+                fields,
             } => {
-                self.bind(
-                    *binding,
-                    self.read_enum_body(source.into_pointer_value(), *ty, *discriminant),
-                );
+                let source = source.into_pointer_value();
+                for (field_idx, binding) in fields.iter() {
+                    self.bind(
+                        *binding,
+                        self.read_enum_field(source, *ty, *discriminant, field_idx),
+                    );
+                }
                 MatchCase::Variant(*ty, self.compile_variant_idx(*discriminant))
             }
             ir::PatternKind::Record { fields, ty } => {
@@ -801,101 +1171,319 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
         }
     }
 
-    fn compile_terminator(&mut self, terminator: &ir::Terminator) -> Result<()> {
-        match terminator {
-            ir::Terminator::Return(local_idx) => {
-                if self.lookup(*local_idx)?.is_vector_value() {
-                    let value = self.lookup(*local_idx)?.into_vector_value();
-                    // TODO: 回収と修正
-                    // let const_ref = self.builder.build_alloca(value.get_type(), "return_tmp");
-                    let const_ref = self
-                        .builder
-                        .build_malloc(value.get_type(), "return_tmp")
-                        .map_err(|err| {
-                            Box::from(Diagnostic::new_bug(
-                                "failed to build malloc call",
-                                Label::new(self.file_id, local_idx.span(), err),
-                            ))
-                        })?;
-                    self.builder.build_store(const_ref, value);
-                    let ptr = unsafe { self.sess.gep(const_ref, 0, "return_tmp") };
-                    self.builder.build_return(Some(&ptr));
-                } else {
-                    self.builder.build_return(Some(&self.lookup(*local_idx)?));
+    fn compile_match(&mut self, source: ir::LocalIdx, arms: &[ir::Arm]) -> Result<()> {
+        let source = self.lookup(source)?;
+        let origin = self.builder.get_insert_block().unwrap();
+        let mut source_ty = None;
+        let mut else_block = None;
+        let mut cases = vec![];
+        let mut comparisons = vec![];
+        for (i, arm) in arms.iter().enumerate() {
+            let block = if let ir::PatternKind::Record { .. } = &arm.pattern {
+                origin
+            } else {
+                self.context.append_basic_block(self.llvm, &format!("arm_{}", i))
+            };
+            self.builder.position_at_end(block);
+            match self.compile_pattern(source, &arm.pattern) {
+                MatchCase::Wild => {
+                    else_block = Some(block);
+                    self.compile_block(&arm.target)?;
+                    break;
+                }
+                MatchCase::Record => {
+                    self.compile_block(&arm.target)?;
+                    return Ok(());
+                }
+                MatchCase::Literal(case) => {
+                    cases.push((case, block));
+                    self.compile_block(&arm.target)?;
+                }
+                case @ (MatchCase::StringLiteral(_) | MatchCase::ArrayLiteral(_)) => {
+                    comparisons.push((case, block));
+                    self.compile_block(&arm.target)?;
+                }
+                MatchCase::Variant(ty, case) => {
+                    source_ty = Some(ty);
+                    cases.push((case, block));
+                    self.compile_block(&arm.target)?;
                 }
             }
-            ir::Terminator::Match { source, arms } => {
-                let source = self.lookup(*source)?;
-                let origin = self.builder.get_insert_block().unwrap();
-                let mut source_ty = None;
-                let mut else_block = None;
-                let mut cases = vec![];
-                for (i, arm) in arms.iter().enumerate() {
-                    let block = if let ir::PatternKind::Record { .. } = &arm.pattern {
-                        origin
-                    } else {
-                        self.context.append_basic_block(self.llvm, &format!("arm_{}", i))
-                    };
-                    self.builder.position_at_end(block);
-                    match self.compile_pattern(source, &arm.pattern) {
-                        MatchCase::Wild => {
-                            else_block = Some(block);
-                            self.compile_block(&arm.target)?;
-                            break;
-                        }
-                        MatchCase::Record => {
-                            self.compile_block(&arm.target)?;
-                            return Ok(());
-                        }
-                        MatchCase::Literal(case) => {
-                            cases.push((case, block));
-                            self.compile_block(&arm.target)?;
-                        }
-                        MatchCase::StringLiteral(_) => {
-                            // TODO: support
-                            // cases.push((case, block));
-                            self.compile_block(&arm.target)?;
-                        }
-                        MatchCase::ArrayLiteral(_) => {
-                            // TODO: support
-                            // cases.push((case, block));
-                            self.compile_block(&arm.target)?;
-                        }
-                        MatchCase::Variant(ty, case) => {
-                            source_ty = Some(ty);
-                            cases.push((case, block));
-                            self.compile_block(&arm.target)?;
-                        }
-                    }
+        }
+        let else_block = match else_block {
+            Some(block) => block,
+            _ => {
+                // NOTE the type checker has proven the arms exhaustive, so no value reaches this block
+                let block = self.context.append_basic_block(self.llvm, "unreachable_else");
+                self.builder.position_at_end(block);
+                self.builder.build_unreachable();
+                block
+            }
+        };
+        self.builder.position_at_end(origin);
+        if !comparisons.is_empty() {
+            self.build_comparison_chain(source, comparisons, else_block);
+            return Ok(());
+        }
+        let source = if let Some(ty) = source_ty {
+            self.read_enum_discriminant(source.into_pointer_value(), ty)?
+        } else {
+            source
+        }
+        .into_int_value();
+        self.builder.build_switch(source, else_block, cases.as_slice());
+        Ok(())
+    }
+
+    /// Tests the arms one after another, as strings and arrays are compared by content rather than switched on.
+    fn build_comparison_chain(
+        &self,
+        source: BasicValueEnum<'ctx>,
+        comparisons: Vec<(MatchCase<'ctx>, BasicBlock<'ctx>)>,
+        else_block: BasicBlock<'ctx>,
+    ) {
+        for (case, block) in comparisons {
+            let is_eq = match case {
+                MatchCase::StringLiteral(pattern) => self.build_string_eq(source, pattern),
+                MatchCase::ArrayLiteral(pattern) => self.build_array_eq(source.into_array_value(), pattern),
+                _ => unreachable!("only strings and arrays are compared by content"),
+            };
+            let next_block = self.context.append_basic_block(self.llvm, "next_comparison");
+            self.builder.build_conditional_branch(is_eq, block, next_block);
+            self.builder.position_at_end(next_block);
+        }
+        self.builder.build_unconditional_branch(else_block);
+    }
+
+    fn build_string_eq(&self, value: BasicValueEnum<'ctx>, pattern: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let ptr = if value.is_vector_value() {
+            let value = value.into_vector_value();
+            let const_ref = self.build_entry_alloca(value.get_type().into(), "strcmp_tmp");
+            self.builder.build_store(const_ref, value);
+            unsafe { self.sess.gep(const_ref, 0, "strcmp_tmp") }
+        } else {
+            value.into_pointer_value()
+        };
+        let ordering = self
+            .builder
+            .build_call(
+                self.module.get_function(STRCMP).unwrap(),
+                &[ptr.into(), pattern.into()],
+                "strcmp",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        self.builder.build_int_compare(
+            IntPredicate::EQ,
+            ordering,
+            self.context.i32_type().const_zero(),
+            "is_string_eq",
+        )
+    }
+
+    fn build_array_eq(&self, value: ArrayValue<'ctx>, pattern: ArrayValue<'ctx>) -> IntValue<'ctx> {
+        let mut is_eq = self.context.bool_type().const_int(1, false);
+        for i in 0..pattern.get_type().len() {
+            let element = self.builder.build_extract_value(value, i, "element").unwrap();
+            let expected = self.builder.build_extract_value(pattern, i, "expected").unwrap();
+            let is_element_eq = if element.is_float_value() {
+                self.builder.build_float_compare(
+                    FloatPredicate::OEQ,
+                    element.into_float_value(),
+                    expected.into_float_value(),
+                    "is_element_eq",
+                )
+            } else {
+                self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    element.into_int_value(),
+                    expected.into_int_value(),
+                    "is_element_eq",
+                )
+            };
+            is_eq = self.builder.build_and(is_eq, is_element_eq, "is_array_eq");
+        }
+        is_eq
+    }
+
+    fn compile_match_expr(
+        &mut self,
+        idx: ir::LocalIdx,
+        source: ir::LocalIdx,
+        arms: &[ir::Arm],
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let block = self.context.append_basic_block(self.llvm, "match_end");
+        self.yield_targets.push(YieldTarget {
+            block,
+            incoming: vec![],
+        });
+        self.compile_match(source, arms)?;
+        let target = self.yield_targets.pop().unwrap();
+        self.builder.position_at_end(target.block);
+        let phi = self
+            .builder
+            .build_phi(self.compile_basic_ty(self.tys[idx]), local!(idx));
+        for (value, block) in target.incoming.iter() {
+            phi.add_incoming(&[(value, *block)]);
+        }
+        Ok(phi.as_basic_value())
+    }
+
+    /// Compiles the switches of a match, then the body of each arm after all the leaves jumping to it, so that its
+    /// params can take the values those leaves passed.
+    fn compile_decision(&mut self, tree: &ir::Block, arms: &[ir::ArmBody]) -> Result<()> {
+        let targets = arms
+            .iter()
+            .map(|arm| {
+                let block = self.context.append_basic_block(self.llvm, "arm_body");
+                (
+                    arm.body.block_idx,
+                    JumpTarget {
+                        block,
+                        incoming: vec![],
+                    },
+                )
+            })
+            .collect();
+        self.jump_targets.push(targets);
+        self.compile_block(tree)?;
+        let mut targets = self.jump_targets.pop().unwrap();
+        for arm in arms.iter() {
+            let target = targets.remove(&arm.body.block_idx).unwrap();
+            self.builder.position_at_end(target.block);
+            for (i, param) in arm.params.iter().enumerate() {
+                let incoming = target
+                    .incoming
+                    .iter()
+                    .map(|(args, block)| (args[i], *block))
+                    .collect::<Vec<_>>();
+                let (first, _) = incoming[0];
+                // NOTE a value that every leaf passes is already available here, such as the matched value itself
+                if incoming.iter().all(|(value, _)| *value == first) {
+                    self.bind(*param, first);
+                    continue;
                 }
-                let else_block = match else_block {
-                    Some(block) => block,
-                    _ => {
-                        let block = self.context.append_basic_block(self.llvm, "unreachable_else");
-                        self.builder.position_at_end(block);
-                        self.builder.build_unreachable();
-                        block
-                    }
-                };
-                self.builder.position_at_end(origin);
-                let source = if let Some(ty) = source_ty {
-                    self.read_enum_discriminant(source.into_pointer_value(), ty)?
-                } else {
-                    source
+                let phi = self
+                    .builder
+                    .build_phi(self.compile_basic_ty(self.tys[*param]), local!(param));
+                for (value, block) in incoming.iter() {
+                    phi.add_incoming(&[(value, *block)]);
                 }
-                .into_int_value();
-                self.builder.build_switch(source, else_block, cases.as_slice());
+                self.bind(*param, phi.as_basic_value());
+            }
+            self.compile_block(&arm.body)?;
+        }
+        Ok(())
+    }
+
+    fn compile_terminator(&mut self, terminator: &ir::Terminator) -> Result<()> {
+        match terminator {
+            ir::Terminator::Return(local_idx) => {
+                let value = self.lookup_escaping(*local_idx)?;
+                self.builder.build_return(Some(&value));
+            }
+            ir::Terminator::Match { source, arms } => self.compile_match(*source, arms)?,
+            ir::Terminator::Yield(local_idx) => {
+                let value = self.lookup(*local_idx)?;
+                let block = self.builder.get_insert_block().unwrap();
+                let target = self.yield_targets.last_mut().ok_or_else(|| {
+                    Box::from(Diagnostic::new_bug(
+                        "yield outside of match expression",
+                        Label::new(self.sess.file_id, local_idx.span(), "value is yielded here"),
+                    ))
+                })?;
+                target.incoming.push((value, block));
+                let target_block = target.block;
+                self.builder.build_unconditional_branch(target_block);
+            }
+            ir::Terminator::Decision { tree, arms } => self.compile_decision(tree, arms)?,
+            ir::Terminator::Jump { target, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.lookup(*arg))
+                    .collect::<Result<Vec<_>>>()?;
+                let block = self.builder.get_insert_block().unwrap();
+                let target = self
+                    .jump_targets
+                    .last_mut()
+                    .and_then(|targets| targets.get_mut(target))
+                    .ok_or_else(|| {
+                        Box::from(Diagnostic::new_bug(
+                            "jump outside of decision",
+                            Label::new(
+                                self.sess.file_id,
+                                self.ir.span,
+                                "arm body is not in this decision",
+                            ),
+                        ))
+                    })?;
+                target.incoming.push((args, block));
+                let target_block = target.block;
+                self.builder.build_unconditional_branch(target_block);
+            }
+            ir::Terminator::Loop { body, next } => {
+                let header = self.context.append_basic_block(self.llvm, "loop_header");
+                let exit = self.context.append_basic_block(self.llvm, "loop_exit");
+                self.builder.build_unconditional_branch(header);
+                self.builder.position_at_end(header);
+                self.loop_targets.push(LoopTarget { header, exit });
+                self.compile_block(body)?;
+                self.loop_targets.pop();
+                self.builder.position_at_end(exit);
+                self.compile_block(next)?;
+            }
+            ir::Terminator::Break => {
+                let exit = self.loop_target()?.exit;
+                self.builder.build_unconditional_branch(exit);
+            }
+            ir::Terminator::Continue => {
+                let header = self.loop_target()?.header;
+                self.builder.build_unconditional_branch(header);
             }
         }
         Ok(())
     }
 
+    fn loop_target(&self) -> Result<&LoopTarget<'ctx>> {
+        self.loop_targets.last().ok_or_else(|| {
+            Box::from(Diagnostic::new_bug(
+                "jump outside of loop",
+                Label::new(
+                    self.file_id,
+                    self.ir.span,
+                    "break or continue is not inside a loop",
+                ),
+            ))
+        })
+    }
+
     fn compile_entry(&mut self, entry: &ir::Entry) -> Result<()> {
         let entry_block = self.context.append_basic_block(self.llvm, "entry");
         self.builder.position_at_end(entry_block);
+        // NOTE a closure takes its environment before its parameters
+        let offset = usize::from(entry.capture_bindings.is_some());
         for (param_idx, binding) in entry.param_bindings.iter() {
+            let param_idx = ty::ParamIdx::new(param_idx.index() + offset);
             self.bind(*binding, self.read_param(self.llvm, param_idx));
         }
+        if let Some(capture_bindings) = entry
+            .capture_bindings
+            .as_ref()
+            .filter(|bindings| !bindings.is_empty())
+        {
+            let env = self.builder.build_pointer_cast(
+                self.read_param(self.llvm, ty::ParamIdx::new(0))
+                    .into_pointer_value(),
+                self.compile_env_ty(entry.owner).ptr_type(AddressSpace::Generic),
+                "env",
+            );
+            for (i, binding) in capture_bindings.iter().enumerate() {
+                let capture_ptr = unsafe { self.gep(env, i as u64 + 1, &format!("capture_{}_ptr", i)) };
+                self.bind(*binding, self.builder.build_load(capture_ptr, local!(binding)));
+            }
+        }
         self.compile_block(&entry.body)
     }
 
@@ -912,7 +1500,12 @@ impl<'gen, 'ctx> CodegenLLVMCtx<'gen, 'ctx> {
             sess,
             ir: def,
             llvm: sess.lookup_def(def.def_idx, def.span)?,
+            tys: &sess.ty_env[def.def_idx],
             bindings: HashMap::new(),
+            slots: HashMap::new(),
+            yield_targets: vec![],
+            jump_targets: vec![],
+            loop_targets: vec![],
         };
         ctx.compile()
     }
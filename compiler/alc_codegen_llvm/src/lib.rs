@@ -5,15 +5,28 @@ extern crate core;
 use crate::ctx::CodegenLLVMCtx;
 use alc_ast_lowering::{idx::Idx, ir, ir::ExprKind, ty, ty::Array};
 use alc_command_option::{CommandOptions, Gc};
-use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
+use alc_diagnostic::{Diagnostic, FileId, Files, Label, Result, Span};
+use alc_type_checker::TyEnv;
 use inkwell::{
     builder::Builder,
     context::Context,
     module::Module,
     targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple},
-    types::{AnyType, AnyTypeEnum, BasicType, BasicTypeEnum, FunctionType},
-    values::{ArrayValue, BasicValue, BasicValueEnum, FunctionValue, IntValue, PointerValue, VectorValue},
+    types::{AnyType, AnyTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{
+        ArrayValue,
+        BasicMetadataValueEnum,
+        BasicValue,
+        BasicValueEnum,
+        CallableValue,
+        FloatValue,
+        FunctionValue,
+        IntValue,
+        PointerValue,
+        VectorValue,
+    },
     AddressSpace,
+    IntPredicate,
     OptimizationLevel,
 };
 use log::debug;
@@ -29,34 +42,47 @@ const SEND: &str = "send";
 const CLOSE: &str = "close";
 const SNPRINTF: &str = "snprintf";
 const STRLEN: &str = "strlen";
+const STRCMP: &str = "strcmp";
 const HTONS: &str = "htons";
 const MALLOC: &str = "malloc";
 const FREE: &str = "free";
+const DPRINTF: &str = "dprintf";
+const ABORT: &str = "abort";
+
+const STDERR_FILENO: u64 = 2;
+/// The bytes in front of the characters of a string on the heap that keep its reference count.
+const STRING_RC_SIZE: u64 = 4;
 
 pub fn generate<'a>(
     command_options: &'a CommandOptions,
+    files: &'a Files,
     file_id: FileId,
     ty_sess: &'a ty::TySess,
+    ty_env: &'a TyEnv,
     ir: &'a ir::Ir,
 ) -> Result<()> {
-    CodegenLLVM::generate(command_options, file_id, ty_sess, ir)
+    CodegenLLVM::generate(command_options, files, file_id, ty_sess, ty_env, ir)
 }
 
 pub struct CodegenLLVM<'gen, 'ctx> {
     command_options: &'gen CommandOptions,
+    files: &'gen Files,
     file_id: FileId,
     context: &'ctx Context,
     builder: &'gen Builder<'ctx>,
     module: &'gen Module<'ctx>,
     ty_sess: &'gen ty::TySess,
+    ty_env: &'gen TyEnv,
     ir: &'gen ir::Ir,
 }
 
 impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
     fn generate(
         command_options: &'gen CommandOptions,
+        files: &'gen Files,
         file_id: FileId,
         ty_sess: &'gen ty::TySess,
+        ty_env: &'gen TyEnv,
         ir: &'gen ir::Ir,
     ) -> Result<()> {
         let context = Context::create();
@@ -65,11 +91,13 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
         module.set_source_file_name(command_options.src_file_name());
         let ctx = CodegenLLVM {
             command_options,
+            files,
             file_id,
             context: &context,
             builder: &builder,
             module: &module,
             ty_sess,
+            ty_env,
             ir,
         };
         ctx.bind_reserved_functions();
@@ -97,7 +125,11 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
     }
 
     fn bind_def(&self, def: &ir::Def) -> FunctionValue<'ctx> {
-        let fn_ty = self.compile_ty(def.ty).into_function_type();
+        let fn_ty = match def.entry.capture_bindings {
+            // NOTE a closure is only called as a function value, so it takes its environment
+            Some(_) => self.compile_closure_fn_ty(self.ty_sess.ty_kind(def.ty).as_prototype().unwrap()),
+            None => self.compile_ty(def.ty).into_function_type(),
+        };
         self.module.add_function(&def.name, fn_ty, None)
     }
 
@@ -111,7 +143,7 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
                     .ptr_type(AddressSpace::Generic)
                     .as_basic_type_enum()
                     .into()],
-                false,
+                true,
             ),
             None,
         );
@@ -260,6 +292,25 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
             ),
             None,
         );
+        self.module.add_function(
+            STRCMP,
+            self.context.i32_type().fn_type(
+                &[
+                    self.context
+                        .i8_type()
+                        .ptr_type(AddressSpace::Generic)
+                        .as_basic_type_enum()
+                        .into(),
+                    self.context
+                        .i8_type()
+                        .ptr_type(AddressSpace::Generic)
+                        .as_basic_type_enum()
+                        .into(),
+                ],
+                false,
+            ),
+            None,
+        );
         self.module.add_function(
             HTONS,
             self.context
@@ -283,6 +334,39 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
             ),
             None,
         );
+        self.module.add_function(
+            DPRINTF,
+            self.context.i32_type().fn_type(
+                &[
+                    self.context.i32_type().as_basic_type_enum().into(),
+                    self.context
+                        .i8_type()
+                        .ptr_type(AddressSpace::Generic)
+                        .as_basic_type_enum()
+                        .into(),
+                ],
+                true,
+            ),
+            None,
+        );
+        self.module
+            .add_function(ABORT, self.context.void_type().fn_type(&[], false), None);
+    }
+
+    /// Formats the start of `span` as `file:line:column`, for messages reported by the compiled program.
+    fn source_location(&self, span: Span) -> Result<String> {
+        let location = self.files.location(self.file_id, span.start()).map_err(|err| {
+            Box::from(Diagnostic::new_bug(
+                "failed to locate span in source file",
+                Label::new(self.file_id, span, format!("{:?}", err)),
+            ))
+        })?;
+        Ok(format!(
+            "{}:{}:{}",
+            self.command_options.src_file_name(),
+            location.line.number(),
+            location.column.number()
+        ))
     }
 
     fn lookup_def(&self, def: ir::DefIdx, span: Span) -> Result<FunctionValue<'ctx>> {
@@ -299,6 +383,193 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
         })
     }
 
+    /// The code called through a function value made from `def`. A declared function does not take an environment,
+    /// so it is called through a thunk that drops it.
+    fn closure_code(&self, def: ir::DefIdx, span: Span) -> Result<FunctionValue<'ctx>> {
+        let target = self.lookup_def(def, span)?;
+        let def = self.ir.defs.get(def).unwrap();
+        if def.entry.capture_bindings.is_some() {
+            return Ok(target);
+        }
+        let name = format!("{}.thunk", def.name);
+        if let Some(thunk) = self.module.get_function(&name) {
+            return Ok(thunk);
+        }
+        let fn_ty = self.compile_closure_fn_ty(self.ty_sess.ty_kind(def.ty).as_prototype().unwrap());
+        let thunk = self.module.add_function(&name, fn_ty, None);
+        let builder = self.context.create_builder();
+        builder.position_at_end(self.context.append_basic_block(thunk, "entry"));
+        let args = thunk
+            .get_param_iter()
+            .skip(1)
+            .map(|param| param.into())
+            .collect::<Vec<BasicMetadataValueEnum>>();
+        let result = builder
+            .build_call(target, args.as_slice(), "result")
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| {
+                Box::from(Diagnostic::new_bug(
+                    "attempted to return non-basic value from function call",
+                    Label::new(self.file_id, span, "this function returns a non-basic value"),
+                ))
+            })?;
+        builder.build_return(Some(&result));
+        Ok(thunk)
+    }
+
+    /// The struct holding the values captured by the closure `def`, which the environment of a function value
+    /// points to. It starts with the routine that drops it, as the type of the function value doesn't tell what
+    /// was captured.
+    fn compile_env_ty(&self, def: ir::DefIdx) -> StructType<'ctx> {
+        let mut field_tys = vec![self.env_drop_fn_ty().ptr_type(AddressSpace::Generic).into()];
+        field_tys.extend(
+            self.capture_tys(def)
+                .into_iter()
+                .map(|ty| self.compile_basic_ty(ty)),
+        );
+        self.context.struct_type(field_tys.as_slice(), false)
+    }
+
+    fn capture_tys(&self, def: ir::DefIdx) -> Vec<ty::Ty> {
+        self.ir
+            .defs
+            .get(def)
+            .unwrap()
+            .entry
+            .capture_bindings
+            .iter()
+            .flatten()
+            .map(|binding| self.ty_env[def][*binding])
+            .collect()
+    }
+
+    fn env_drop_fn_ty(&self) -> FunctionType<'ctx> {
+        self.context.void_type().fn_type(
+            &[self.context.i8_type().ptr_type(AddressSpace::Generic).into()],
+            false,
+        )
+    }
+
+    /// The routine that drops an environment of the closure `def`, releasing the captured values that the
+    /// garbage collector retained for it before freeing it.
+    fn env_drop_fn(&self, def: ir::DefIdx) -> FunctionValue<'ctx> {
+        let name = format!("drop.env.{}", def.index());
+        if let Some(drop_fn) = self.module.get_function(&name) {
+            return drop_fn;
+        }
+        let drop_fn = self.module.add_function(&name, self.env_drop_fn_ty(), None);
+        let origin = self.builder.get_insert_block();
+        self.builder
+            .position_at_end(self.context.append_basic_block(drop_fn, "entry"));
+        let env = self.builder.build_pointer_cast(
+            drop_fn.get_nth_param(0).unwrap().into_pointer_value(),
+            self.compile_env_ty(def).ptr_type(AddressSpace::Generic),
+            "env",
+        );
+        for (i, ty) in self.capture_tys(def).into_iter().enumerate() {
+            if self.is_counted_field(ty) {
+                let capture_ptr = unsafe { self.gep(env, i as u64 + 1, &format!("capture_{}_ptr", i)) };
+                let capture = self.builder.build_load(capture_ptr, "capture");
+                self.build_release(capture.into_pointer_value(), ty);
+            }
+        }
+        self.build_free(env);
+        self.builder.build_return(None);
+        if let Some(origin) = origin {
+            self.builder.position_at_end(origin);
+        }
+        drop_fn
+    }
+
+    /// Points a function value at the code of `def` and at a new environment holding `captures`, or at null when
+    /// it captures nothing.
+    fn write_closure(
+        &self,
+        ptr: PointerValue<'ctx>,
+        def: ir::DefIdx,
+        captures: &[BasicValueEnum<'ctx>],
+        span: Span,
+    ) -> Result<()> {
+        let raw_ty = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let code = self.closure_code(def, span)?.as_global_value().as_pointer_value();
+        self.builder.build_store(
+            unsafe { self.gep(ptr, 0, "code_ptr") },
+            self.builder.build_pointer_cast(code, raw_ty, "code"),
+        );
+        let env = if captures.is_empty() {
+            raw_ty.const_null()
+        } else {
+            let env = self
+                .builder
+                .build_malloc(self.compile_env_ty(def), "env")
+                .map_err(|err| {
+                    Box::from(Diagnostic::new_bug(
+                        "failed to build malloc call",
+                        Label::new(self.file_id, span, err),
+                    ))
+                })?;
+            let env_drop_fn_ty = self.env_drop_fn_ty().ptr_type(AddressSpace::Generic);
+            let env_drop_fn = match self.command_options.gc {
+                Gc::OwnRc => self.env_drop_fn(def).as_global_value().as_pointer_value(),
+                Gc::None => env_drop_fn_ty.const_null(),
+            };
+            self.builder
+                .build_store(unsafe { self.gep(env, 0, "drop_ptr") }, env_drop_fn);
+            for (i, capture) in captures.iter().enumerate() {
+                self.builder.build_store(
+                    unsafe { self.gep(env, i as u64 + 1, &format!("capture_{}_ptr", i)) },
+                    *capture,
+                );
+            }
+            self.builder.build_pointer_cast(env, raw_ty, "env")
+        };
+        self.builder
+            .build_store(unsafe { self.gep(ptr, 1, "env_ptr") }, env);
+        Ok(())
+    }
+
+    /// Calls the code of a function value of type `ty` with its environment and `args`.
+    fn build_closure_call(
+        &self,
+        ptr: PointerValue<'ctx>,
+        ty: ty::Ty,
+        args: &[BasicValueEnum<'ctx>],
+        name: &str,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>> {
+        let code_ty = self
+            .compile_closure_fn_ty(self.ty_sess.ty_kind(ty).as_prototype().unwrap())
+            .ptr_type(AddressSpace::Generic);
+        let code = self
+            .builder
+            .build_load(unsafe { self.gep(ptr, 0, "code_ptr") }, "code")
+            .into_pointer_value();
+        let code = CallableValue::try_from(self.builder.build_pointer_cast(code, code_ty, "code")).map_err(
+            |_| {
+                Box::from(Diagnostic::new_bug(
+                    "failed to call function value",
+                    Label::new(self.file_id, span, "the code of this value is not a function"),
+                ))
+            },
+        )?;
+        let env = self
+            .builder
+            .build_load(unsafe { self.gep(ptr, 1, "env_ptr") }, "env");
+        let mut compiled_args: Vec<BasicMetadataValueEnum> = vec![env.into()];
+        compiled_args.extend(args.iter().map(|arg| BasicMetadataValueEnum::from(*arg)));
+        self.builder
+            .build_call(code, compiled_args.as_slice(), name)
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| {
+                Box::from(Diagnostic::new_bug(
+                    "attempted to return non-basic value from function call",
+                    Label::new(self.file_id, span, "this call returns a non-basic value"),
+                ))
+            })
+    }
+
     fn build_free(&self, ptr: PointerValue<'ctx>) {
         let ptr = self.builder.build_pointer_cast(
             ptr,
@@ -312,6 +583,246 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
         );
     }
 
+    /// Frees a boxed value, along with the payload of its variant when it is an enum and its environment when it
+    /// is a function value.
+    fn build_free_boxed(&self, ptr: PointerValue<'ctx>, ty: ty::Ty) {
+        if self.ty_sess.ty_kind(ty).is_enum() {
+            let body = self.builder.build_load(self.enum_body_ptr(ptr, ty), "body");
+            self.build_free(body.into_pointer_value());
+        } else if self.ty_sess.ty_kind(ty).is_fn() {
+            self.build_drop_env(ptr);
+        } else if self.ty_sess.ty_kind(ty).is_string() {
+            // NOTE the allocation starts at the reference count in front of the characters
+            self.build_free(self.string_header_ptr(ptr));
+            return;
+        }
+        self.build_free(ptr);
+    }
+
+    /// Calls the drop routine at the head of the environment of a function value, unless it captured nothing.
+    fn build_drop_env(&self, ptr: PointerValue<'ctx>) {
+        let env = self
+            .builder
+            .build_load(unsafe { self.gep(ptr, 1, "env_ptr") }, "env")
+            .into_pointer_value();
+        let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let drop_block = self.context.append_basic_block(function, "drop_env");
+        let dropped_block = self.context.append_basic_block(function, "dropped_env");
+        self.builder.build_conditional_branch(
+            self.builder.build_is_null(env, "is_null"),
+            dropped_block,
+            drop_block,
+        );
+        self.builder.position_at_end(drop_block);
+        let drop_ptr = self.builder.build_pointer_cast(
+            env,
+            self.env_drop_fn_ty()
+                .ptr_type(AddressSpace::Generic)
+                .ptr_type(AddressSpace::Generic),
+            "drop_ptr",
+        );
+        let drop_fn = self.builder.build_load(drop_ptr, "drop").into_pointer_value();
+        self.builder.build_call(
+            CallableValue::try_from(drop_fn).unwrap(),
+            &[env.into()],
+            "drop_env",
+        );
+        self.builder.build_unconditional_branch(dropped_block);
+        self.builder.position_at_end(dropped_block);
+    }
+
+    /// Whether a boxed value keeps a reference to the values of the type in its fields, as the garbage collector
+    /// retains them when they are stored.
+    fn is_counted_field(&self, ty: ty::Ty) -> bool {
+        let ty_kind = self.ty_sess.ty_kind(ty);
+        ty_kind.is_record() || ty_kind.is_enum() || ty_kind.is_fn() || ty_kind.is_string()
+    }
+
+    /// Gives up a reference to a boxed value, dropping it when it was the last one.
+    fn build_release(&self, ptr: PointerValue<'ctx>, ty: ty::Ty) {
+        let rc_ptr = self.mark_ptr(ptr, ty);
+        let current_rc = self.builder.build_load(rc_ptr, "rc").into_int_value();
+        let new_rc = self.builder.build_int_sub(
+            current_rc,
+            self.context.i32_type().const_int(1, false),
+            "decrement_rc",
+        );
+        self.builder.build_store(rc_ptr, new_rc);
+        let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let free_block = self.context.append_basic_block(function, "free");
+        let else_block = self.context.append_basic_block(function, "else");
+        self.builder.build_conditional_branch(
+            self.builder.build_int_compare(
+                IntPredicate::SLE,
+                new_rc,
+                self.context.i32_type().const_int(0, false),
+                "is_zero",
+            ),
+            free_block,
+            else_block,
+        );
+        self.builder.position_at_end(free_block);
+        self.build_drop(ptr, ty);
+        self.builder.build_unconditional_branch(else_block);
+        self.builder.position_at_end(else_block);
+    }
+
+    #[inline]
+    fn build_drop(&self, ptr: PointerValue<'ctx>, ty: ty::Ty) {
+        self.builder
+            .build_call(self.drop_fn(ty), &[ptr.as_basic_value_enum().into()], "drop");
+    }
+
+    /// The drop glue of `ty`, which releases what the fields of a boxed value hold before freeing it. It is
+    /// generated the first time a value of the type is dropped.
+    fn drop_fn(&self, ty: ty::Ty) -> FunctionValue<'ctx> {
+        let name = format!("drop.{}", ty.index());
+        if let Some(drop_fn) = self.module.get_function(&name) {
+            return drop_fn;
+        }
+        let drop_fn = self.module.add_function(
+            &name,
+            self.context
+                .void_type()
+                .fn_type(&[self.compile_basic_ty(ty).into()], false),
+            None,
+        );
+        let origin = self.builder.get_insert_block();
+        self.builder
+            .position_at_end(self.context.append_basic_block(drop_fn, "entry"));
+        let ptr = drop_fn.get_nth_param(0).unwrap().into_pointer_value();
+        let record_fields = self.ty_sess.ty_kind(ty).record_fields().cloned();
+        if let Some(field_tys) = record_fields {
+            for (field_idx, field_ty) in field_tys.iter() {
+                if self.is_counted_field(*field_ty) {
+                    let field = self.read_struct_field(ptr, ty, field_idx).into_pointer_value();
+                    self.build_release(field, *field_ty);
+                }
+            }
+        } else if self.ty_sess.ty_kind(ty).is_enum() {
+            self.build_release_variant_fields(drop_fn, ptr, ty);
+        }
+        self.build_free_boxed(ptr, ty);
+        self.builder.build_return(None);
+        if let Some(origin) = origin {
+            self.builder.position_at_end(origin);
+        }
+        drop_fn
+    }
+
+    /// Switches on the discriminant of an enum to release the fields of whichever variant it holds.
+    fn build_release_variant_fields(
+        &self,
+        drop_fn: FunctionValue<'ctx>,
+        ptr: PointerValue<'ctx>,
+        ty: ty::Ty,
+    ) {
+        let variant_count = self.ty_sess.ty_kind(ty).variant_count().unwrap();
+        let switch_block = self.builder.get_insert_block().unwrap();
+        let released_block = self.context.append_basic_block(drop_fn, "released");
+        let mut cases = vec![];
+        for variant_idx in (0..variant_count).map(ty::VariantIdx::new) {
+            let field_tys = self
+                .ty_sess
+                .ty_kind(ty)
+                .variant(variant_idx)
+                .map(|variant| variant.fields.clone())
+                .unwrap();
+            if !field_tys
+                .values()
+                .any(|field_ty| self.is_counted_field(*field_ty))
+            {
+                continue;
+            }
+            let variant_block = self.context.append_basic_block(drop_fn, "variant");
+            self.builder.position_at_end(variant_block);
+            for (field_idx, field_ty) in field_tys.iter() {
+                if self.is_counted_field(*field_ty) {
+                    let field = self
+                        .read_enum_field(ptr, ty, variant_idx, field_idx)
+                        .into_pointer_value();
+                    self.build_release(field, *field_ty);
+                }
+            }
+            self.builder.build_unconditional_branch(released_block);
+            cases.push((self.compile_variant_idx(variant_idx), variant_block));
+        }
+        self.builder.position_at_end(switch_block);
+        let discriminant = self
+            .builder
+            .build_load(self.enum_discriminant_ptr(ptr, ty), "discriminant")
+            .into_int_value();
+        self.builder
+            .build_switch(discriminant, released_block, cases.as_slice());
+        self.builder.position_at_end(released_block);
+    }
+
+    /// Copies a string onto the heap, with its reference count in front of the characters so that it is still
+    /// passed around as a pointer to them.
+    fn build_counted_string(
+        &self,
+        value: VectorValue<'ctx>,
+        name: &str,
+        span: Span,
+    ) -> Result<PointerValue<'ctx>> {
+        let header = self
+            .builder
+            .build_array_malloc(
+                self.context.i8_type(),
+                self.context
+                    .i64_type()
+                    .const_int(STRING_RC_SIZE + u64::from(value.get_type().get_size()), false),
+                name,
+            )
+            .map_err(|err| {
+                Box::from(Diagnostic::new_bug(
+                    "failed to build malloc call",
+                    Label::new(self.file_id, span, err),
+                ))
+            })?;
+        self.builder.build_store(
+            self.builder.build_pointer_cast(
+                header,
+                self.context.i32_type().ptr_type(AddressSpace::Generic),
+                "rc_ptr",
+            ),
+            self.context.i32_type().const_int(1, false),
+        );
+        let ptr = unsafe {
+            self.builder.build_in_bounds_gep(
+                header,
+                &[self.context.i64_type().const_int(STRING_RC_SIZE, false)],
+                name,
+            )
+        };
+        self.builder
+            .build_store(
+                self.builder.build_pointer_cast(
+                    ptr,
+                    value.get_type().ptr_type(AddressSpace::Generic),
+                    "chars",
+                ),
+                value,
+            )
+            .set_alignment(1)
+            .unwrap();
+        Ok(ptr)
+    }
+
+    fn string_header_ptr(&self, ptr: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        unsafe {
+            self.builder.build_in_bounds_gep(
+                ptr,
+                &[self
+                    .context
+                    .i64_type()
+                    .const_int(STRING_RC_SIZE, false)
+                    .const_neg()],
+                "header",
+            )
+        }
+    }
+
     #[allow(clippy::match_single_binding)]
     fn build_alloc(&self, ty: ty::Ty, name: &str, span: Span) -> Result<PointerValue<'ctx>> {
         let ty = self.compile_basic_ty_unboxed(ty);
@@ -328,70 +839,127 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
 
     #[inline]
     fn compile_i8_literal(&self, literal: i8) -> IntValue<'ctx> {
-        self.context
-            .i8_type()
-            .const_int(literal as u64, false)
-            .const_neg()
+        self.context.i8_type().const_int(literal as u64, true)
     }
 
     #[inline]
     fn compile_i16_literal(&self, literal: i16) -> IntValue<'ctx> {
-        self.context.i16_type().const_int(literal as u64, false)
+        self.context.i16_type().const_int(literal as u64, true)
     }
 
     #[inline]
     fn compile_i32_literal(&self, literal: i32) -> IntValue<'ctx> {
-        self.context.i32_type().const_int(literal as u64, false)
+        self.context.i32_type().const_int(literal as u64, true)
     }
 
     #[inline]
     fn compile_i64_literal(&self, literal: i64) -> IntValue<'ctx> {
-        self.context.i64_type().const_int(literal as u64, false)
+        self.context.i64_type().const_int(literal as u64, true)
+    }
+
+    #[inline]
+    fn compile_u8_literal(&self, literal: u8) -> IntValue<'ctx> {
+        self.context.i8_type().const_int(literal as u64, false)
+    }
+
+    #[inline]
+    fn compile_u16_literal(&self, literal: u16) -> IntValue<'ctx> {
+        self.context.i16_type().const_int(literal as u64, false)
+    }
+
+    #[inline]
+    fn compile_u32_literal(&self, literal: u32) -> IntValue<'ctx> {
+        self.context.i32_type().const_int(literal as u64, false)
+    }
+
+    #[inline]
+    fn compile_u64_literal(&self, literal: u64) -> IntValue<'ctx> {
+        self.context.i64_type().const_int(literal, false)
+    }
+
+    #[inline]
+    fn compile_f32_literal(&self, literal: f32) -> FloatValue<'ctx> {
+        self.context.f32_type().const_float(literal as f64)
+    }
+
+    #[inline]
+    fn compile_f64_literal(&self, literal: f64) -> FloatValue<'ctx> {
+        self.context.f64_type().const_float(literal)
+    }
+
+    /// Compiles an integer literal whose type was inferred as `ty`, which can also be a float type.
+    #[inline]
+    fn compile_number_literal(&self, ty: ty::Ty, literal: u64) -> BasicValueEnum<'ctx> {
+        if self.ty_sess.ty_kind(ty).is_float() {
+            self.compile_float_literal(ty, literal as f64).into()
+        } else {
+            self.compile_basic_ty_unboxed(ty)
+                .into_int_type()
+                .const_int(literal, false)
+                .into()
+        }
+    }
+
+    /// Compiles a float literal whose type was inferred as `ty`.
+    #[inline]
+    fn compile_float_literal(&self, ty: ty::Ty, literal: f64) -> FloatValue<'ctx> {
+        self.compile_basic_ty_unboxed(ty)
+            .into_float_type()
+            .const_float(literal)
+    }
+
+    #[inline]
+    fn compile_bool_literal(&self, literal: bool) -> IntValue<'ctx> {
+        self.context.bool_type().const_int(literal as u64, false)
+    }
+
+    fn compile_int_literal(&self, ty: ty::Ty, literal: &ExprKind) -> Option<IntValue<'ctx>> {
+        Some(match literal {
+            ExprKind::NumberLiteral(literal) => self
+                .compile_basic_ty_unboxed(ty)
+                .into_int_type()
+                .const_int(*literal, false),
+            ExprKind::I8Literal(literal) => self.compile_i8_literal(*literal),
+            ExprKind::I16Literal(literal) => self.compile_i16_literal(*literal),
+            ExprKind::I32Literal(literal) => self.compile_i32_literal(*literal),
+            ExprKind::I64Literal(literal) => self.compile_i64_literal(*literal),
+            ExprKind::U8Literal(literal) => self.compile_u8_literal(*literal),
+            ExprKind::U16Literal(literal) => self.compile_u16_literal(*literal),
+            ExprKind::U32Literal(literal) => self.compile_u32_literal(*literal),
+            ExprKind::U64Literal(literal) => self.compile_u64_literal(*literal),
+            _ => return None,
+        })
     }
 
     #[inline]
     fn compile_array_literal(&self, element_ty: ty::Ty, elements: Vec<ExprKind>) -> ArrayValue<'ctx> {
-        match &*self.ty_sess.ty_kind(element_ty) {
-            ty::TyKind::I8 => {
-                let mut values = Vec::with_capacity(elements.len());
-                for element in elements {
-                    if let ExprKind::I8Literal(literal) = element {
-                        values.push(self.compile_i8_literal(literal))
-                    }
-                }
-                self.context.i8_type().const_array(&values)
-            }
-            ty::TyKind::I16 => {
-                let mut values = Vec::with_capacity(elements.len());
-                for element in elements {
-                    if let ExprKind::I16Literal(literal) = element {
-                        values.push(self.compile_i16_literal(literal))
-                    }
-                }
-                self.context.i16_type().const_array(&values)
-            }
-            ty::TyKind::I32 => {
-                let mut values = Vec::with_capacity(elements.len());
-                for element in elements {
-                    if let ExprKind::I32Literal(literal) = element {
-                        values.push(self.compile_i32_literal(literal))
-                    }
-                }
-                self.context.i32_type().const_array(&values)
-            }
-            ty::TyKind::I64 => {
-                let mut values = Vec::with_capacity(elements.len());
-                for element in elements {
-                    if let ExprKind::I64Literal(literal) = element {
-                        values.push(self.compile_i64_literal(literal))
-                    }
-                }
-                self.context.i64_type().const_array(&values)
-            }
-            _ => {
-                panic!("unimplemented array literal type");
-            }
+        let element_llvm_ty = self.compile_basic_ty_unboxed(element_ty);
+        if self.ty_sess.ty_kind(element_ty).is_float() {
+            let values = elements
+                .iter()
+                .filter_map(|element| self.compile_float_element(element_ty, element))
+                .collect::<Vec<_>>();
+            return element_llvm_ty.into_float_type().const_array(&values);
         }
+        let values = elements
+            .iter()
+            .filter_map(|element| match element {
+                ExprKind::BoolLiteral(literal) => Some(self.compile_bool_literal(*literal)),
+                _ => self.compile_int_literal(element_ty, element),
+            })
+            .collect::<Vec<_>>();
+        element_llvm_ty.into_int_type().const_array(&values)
+    }
+
+    fn compile_float_element(&self, ty: ty::Ty, literal: &ExprKind) -> Option<FloatValue<'ctx>> {
+        Some(match literal {
+            ExprKind::NumberLiteral(literal) => self.compile_float_literal(ty, *literal as f64),
+            ExprKind::FloatLiteral(literal) | ExprKind::F64Literal(literal) => {
+                self.compile_float_literal(ty, *literal)
+            }
+            ExprKind::F32Literal(literal) => self.compile_float_literal(ty, *literal as f64),
+            _ => return None,
+        })
     }
 
     #[inline]
@@ -429,9 +997,9 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
     }
 
     fn mark_ptr(&self, ptr: PointerValue<'ctx>, ty: ty::Ty) -> PointerValue<'ctx> {
-        if self.ty_sess.ty_kind(ty).is_enum() {
+        if self.ty_sess.ty_kind(ty).is_enum() || self.ty_sess.ty_kind(ty).is_fn() {
             unsafe { self.gep(ptr, 2, "rc_ptr") }
-        } else if self.ty_sess.ty_kind(ty).is_struct() {
+        } else if self.ty_sess.ty_kind(ty).is_record() {
             unsafe {
                 self.gep(
                     ptr,
@@ -439,8 +1007,14 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
                     "rc_ptr",
                 )
             }
+        } else if self.ty_sess.ty_kind(ty).is_string() {
+            self.builder.build_pointer_cast(
+                self.string_header_ptr(ptr),
+                self.context.i32_type().ptr_type(AddressSpace::Generic),
+                "rc_ptr",
+            )
         } else {
-            panic!("atttempted to read mark of a type that wasn't a struct or an enum")
+            panic!("atttempted to read mark of a type that wasn't a record, an enum, a function or a string")
         }
     }
 
@@ -476,26 +1050,48 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
             .build_load(self.enum_discriminant_ptr(ptr, ty), "discriminant"))
     }
 
-    fn read_enum_body(
+    /// The struct holding the fields of a variant, which the body of an enum points to.
+    fn compile_variant_payload_ty(&self, ty: ty::Ty, idx: ty::VariantIdx) -> StructType<'ctx> {
+        let field_tys = self
+            .ty_sess
+            .ty_kind(ty)
+            .variant(idx)
+            .map(|variant| variant.fields.values().copied().collect::<Vec<_>>())
+            .unwrap();
+        let field_tys = field_tys
+            .into_iter()
+            .map(|field_ty| self.compile_basic_ty(field_ty))
+            .collect::<Vec<_>>();
+        self.context.struct_type(field_tys.as_slice(), false)
+    }
+
+    fn read_enum_field(
         &self,
         ptr: PointerValue<'ctx>,
         ty: ty::Ty,
         idx: ty::VariantIdx,
+        field_idx: ty::FieldIdx,
     ) -> BasicValueEnum<'ctx> {
-        let variant_ty = self.ty_sess.ty_kind(ty).variant_ty(idx).unwrap();
-        let body_ptr = self.enum_body_ptr(ptr, ty);
-        if variant_ty != self.ty_sess.make_i32() {
-            let uncast_body = self.builder.build_load(body_ptr, "body");
-            self.builder
-                .build_int_to_ptr(
-                    uncast_body.into_int_value(),
-                    self.compile_basic_ty(variant_ty).into_pointer_type(),
-                    "body",
+        let body = self
+            .builder
+            .build_load(self.enum_body_ptr(ptr, ty), "body")
+            .into_pointer_value();
+        let payload = self.builder.build_pointer_cast(
+            body,
+            self.compile_variant_payload_ty(ty, idx)
+                .ptr_type(AddressSpace::Generic),
+            "payload",
+        );
+        self.builder.build_load(
+            unsafe {
+                self.gep(
+                    payload,
+                    field_idx.index() as u64,
+                    &format!("field_{}_ptr", field_idx.index()),
                 )
-                .into()
-        } else {
-            self.builder.build_load(body_ptr, "body")
-        }
+            },
+            &format!("field_{}", field_idx.index()),
+        )
     }
 
     #[inline]
@@ -506,23 +1102,39 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
         );
     }
 
+    /// Points the body of an enum at a new payload holding the fields of the variant, or at null when the
+    /// variant has none.
     fn write_enum_body(
         &self,
         ptr: PointerValue<'ctx>,
         ty: ty::Ty,
         idx: ty::VariantIdx,
-        val: BasicValueEnum<'ctx>,
-    ) {
-        let variant_ty = self.ty_sess.ty_kind(ty).variant_ty(idx).unwrap();
-        let body_ptr = self.enum_body_ptr(ptr, ty);
-        let cast_body = if variant_ty != self.ty_sess.make_i32() {
-            self.builder
-                .build_ptr_to_int(val.into_pointer_value(), self.context.i64_type(), "cast_body")
-                .into()
+        fields: &[BasicValueEnum<'ctx>],
+        span: Span,
+    ) -> Result<()> {
+        let body_ty = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let body = if fields.is_empty() {
+            body_ty.const_null()
         } else {
-            val
+            let payload = self
+                .builder
+                .build_malloc(self.compile_variant_payload_ty(ty, idx), "payload")
+                .map_err(|err| {
+                    Box::from(Diagnostic::new_bug(
+                        "failed to build malloc call",
+                        Label::new(self.file_id, span, err),
+                    ))
+                })?;
+            for (i, field) in fields.iter().enumerate() {
+                self.builder.build_store(
+                    unsafe { self.gep(payload, i as u64, &format!("field_{}_ptr", i)) },
+                    *field,
+                );
+            }
+            self.builder.build_pointer_cast(payload, body_ty, "body")
         };
-        self.builder.build_store(body_ptr, cast_body);
+        self.builder.build_store(self.enum_body_ptr(ptr, ty), body);
+        Ok(())
     }
 
     fn struct_field_ptr(&self, ptr: PointerValue<'ctx>, ty: ty::Ty, idx: ty::FieldIdx) -> PointerValue<'ctx> {
@@ -566,18 +1178,29 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
             ty::TyKind::I16 => self.context.i16_type().into(),
             ty::TyKind::I32 => self.context.i32_type().into(),
             ty::TyKind::I64 => self.context.i64_type().into(),
+            ty::TyKind::U8 => self.context.i8_type().into(),
+            ty::TyKind::U16 => self.context.i16_type().into(),
+            ty::TyKind::U32 => self.context.i32_type().into(),
+            ty::TyKind::U64 => self.context.i64_type().into(),
+            ty::TyKind::F32 => self.context.f32_type().into(),
+            ty::TyKind::F64 => self.context.f64_type().into(),
+            ty::TyKind::Bool => self.context.bool_type().into(),
             ty::TyKind::String => self.context.i8_type().ptr_type(AddressSpace::Generic).into(),
-            ty::TyKind::Array(Array { element_ty: _, size }) => {
-                self.context.i8_type().array_type(*size as u32).into()
+            ty::TyKind::Array(Array { element_ty, size }) => {
+                self.compile_basic_ty(*element_ty).array_type(*size as u32).into()
             }
             ty::TyKind::Enum(_) => {
-                let mut field_tys = vec![self.context.i32_type().into(), self.context.i32_type().into()];
+                // NOTE the body points to the fields of the variant, see `write_enum_body`
+                let mut field_tys = vec![
+                    self.context.i32_type().into(),
+                    self.context.i8_type().ptr_type(AddressSpace::Generic).into(),
+                ];
                 if self.command_options.gc == Gc::OwnRc {
                     field_tys.push(self.context.i32_type().into());
                 }
                 self.context.struct_type(field_tys.as_slice(), false).into()
             }
-            ty::TyKind::Struct(ty::Struct { fields }) => {
+            ty::TyKind::Struct(ty::Struct { fields, .. }) | ty::TyKind::Tuple(ty::Tuple { fields }) => {
                 let mut field_tys = fields
                     .values()
                     .map(|ty| self.compile_basic_ty(*ty))
@@ -587,16 +1210,25 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
                 }
                 self.context.struct_type(field_tys.as_slice(), false).into()
             }
-            _ => panic!("attempted to compile function type as basic type"),
+            ty::TyKind::Fn(_) => {
+                // NOTE the code takes the environment, see `write_closure`
+                let mut field_tys = vec![
+                    self.context.i8_type().ptr_type(AddressSpace::Generic).into(),
+                    self.context.i8_type().ptr_type(AddressSpace::Generic).into(),
+                ];
+                if self.command_options.gc == Gc::OwnRc {
+                    field_tys.push(self.context.i32_type().into());
+                }
+                self.context.struct_type(field_tys.as_slice(), false).into()
+            }
         }
     }
 
     fn compile_basic_ty(&self, ty: ty::Ty) -> BasicTypeEnum<'ctx> {
         let compiled_ty_unboxed = self.compile_basic_ty_unboxed(ty);
-        if self.ty_sess.ty_kind(ty).is_i8()
-            || self.ty_sess.ty_kind(ty).is_i16()
-            || self.ty_sess.ty_kind(ty).is_i32()
-            || self.ty_sess.ty_kind(ty).is_i64()
+        if self.ty_sess.ty_kind(ty).is_integer()
+            || self.ty_sess.ty_kind(ty).is_float()
+            || self.ty_sess.ty_kind(ty).is_bool()
             || self.ty_sess.ty_kind(ty).is_string()
             || self.ty_sess.ty_kind(ty).is_array()
         {
@@ -617,6 +1249,17 @@ impl<'gen, 'ctx> CodegenLLVM<'gen, 'ctx> {
             .fn_type(param_tys.as_slice(), false)
     }
 
+    /// The type of the code of a function value, which takes the environment before the parameters.
+    fn compile_closure_fn_ty(&self, prototype: &ty::Prototype) -> FunctionType<'ctx> {
+        let mut param_tys = Vec::with_capacity(prototype.params.len() + 1);
+        param_tys.push(self.context.i8_type().ptr_type(AddressSpace::Generic).into());
+        for ty in prototype.params.values() {
+            param_tys.push(self.compile_basic_ty(*ty).into());
+        }
+        self.compile_basic_ty(prototype.return_ty)
+            .fn_type(param_tys.as_slice(), false)
+    }
+
     fn compile_ty(&self, ty: ty::Ty) -> AnyTypeEnum<'ctx> {
         match &*self.ty_sess.ty_kind(ty) {
             ty::TyKind::Fn(prototype) => self.compile_fn_ty(prototype).into(),
@@ -13,6 +13,14 @@ arg_enum! {
     }
 }
 
+arg_enum! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Ownership {
+        Rc,
+        Strict,
+    }
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "alc")]
 pub struct CommandOptions {
@@ -22,6 +30,8 @@ pub struct CommandOptions {
     pub out: PathBuf,
     #[structopt(long = "gc", default_value = "none")]
     pub gc: Gc,
+    #[structopt(long = "ownership", default_value = "rc")]
+    pub ownership: Ownership,
     #[structopt(long = "triple")]
     pub triple: Option<String>,
     #[structopt(long = "cpu-name")]
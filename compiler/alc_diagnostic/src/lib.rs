@@ -4,7 +4,7 @@ use codespan_reporting::term;
 use log::debug;
 use std::ops::Deref;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Span(codespan::Span);
 
 #[derive(Clone, Debug)]
@@ -94,6 +94,8 @@ impl<T> Deref for Spanned<T> {
 
 pub type Result<T> = std::result::Result<T, Box<Diagnostic>>;
 
+pub type MultiResult<T> = std::result::Result<T, Vec<Diagnostic>>;
+
 pub fn emit(files: &Files, diagnostic: &Diagnostic) {
     let writer = term::termcolor::StandardStream::stderr(term::termcolor::ColorChoice::Always);
     let config = term::Config::default();
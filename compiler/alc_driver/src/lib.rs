@@ -1,5 +1,6 @@
 use alc_command_option::CommandOptions;
-use alc_diagnostic::{emit, Diagnostic, FileId, Files, Label, Result, Span};
+use alc_diagnostic::{emit, Diagnostic, FileId, Files, Label, MultiResult, Result, Span};
+use alc_parser::ast;
 use log::debug;
 use std::{env, fs::File, io::Read, process, time::Instant};
 
@@ -14,8 +15,10 @@ pub fn drive() {
     let mut files = Files::new();
     let exit_code = match run_compiler(&command_options, &mut files) {
         Ok(_) => EXIT_SUCCESS,
-        Err(diagnostic) => {
-            emit(&files, &diagnostic);
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                emit(&files, diagnostic);
+            }
             EXIT_FAILURE
         }
     };
@@ -34,15 +37,24 @@ fn init_env_logger(is_debug: bool) {
     env_logger::init();
 }
 
-fn run_compiler(command_options: &CommandOptions, files: &mut Files) -> Result<()> {
-    let file_id = open_file(command_options, files)?;
+fn run_compiler(command_options: &CommandOptions, files: &mut Files) -> MultiResult<()> {
+    let file_id = open_file(command_options, files).map_err(|diagnostic| vec![*diagnostic])?;
     let ast = alc_parser::parse(command_options, files, file_id)?;
-    let (ir, ty_sess) = alc_ast_lowering::lower(command_options, file_id, &ast)?;
-    let ir = alc_garbage_collector::collect(command_options, file_id, &ty_sess, ir)?;
+    run_backend(command_options, files, file_id, &ast).map_err(|diagnostic| vec![*diagnostic])
+}
+
+fn run_backend(
+    command_options: &CommandOptions,
+    files: &Files,
+    file_id: FileId,
+    ast: &ast::Ast,
+) -> Result<()> {
+    let (ir, ty_sess) = alc_ast_lowering::lower(command_options, file_id, ast)?;
+    let mut ty_env = alc_type_checker::check(command_options, file_id, &ty_sess, &ir)?;
+    let ir = alc_garbage_collector::collect(command_options, file_id, &ty_sess, &mut ty_env, ir)?;
     debug!("{:#?}", ir);
     debug!("{:#?}", ty_sess);
-    let _ty_env = alc_type_checker::check(command_options, file_id, &ty_sess, &ir)?;
-    alc_codegen_llvm::generate(command_options, file_id, &ty_sess, &ir)
+    alc_codegen_llvm::generate(command_options, files, file_id, &ty_sess, &ty_env, &ir)
 }
 
 fn open_file(command_options: &CommandOptions, files: &mut Files) -> Result<FileId> {
@@ -0,0 +1,186 @@
+//! The parameters whose values a function can keep past the call, by storing them in a record, an enum, an array or
+//! the environment of a closure, by returning them, or by passing them on to a function that keeps them.
+//!
+//! A function retains what it keeps, so the caller has to count a reference to such an argument and can only free it
+//! once the count drops to zero. Any other argument is still owned by the caller alone after the call.
+
+use alc_ast_lowering::{idx_vec::IdxVec, ir, ty};
+use std::collections::{HashMap, HashSet};
+
+/// Whether each function can keep the value given for each of its parameters.
+pub(crate) type KeptParams = IdxVec<ir::DefIdx, IdxVec<ty::ParamIdx, bool>>;
+
+pub(crate) fn kept_params(ir: &ir::Ir) -> KeptParams {
+    let mut kept = ir
+        .defs
+        .values()
+        .map(|def| def.entry.param_bindings.values().map(|_| false).collect())
+        .collect::<KeptParams>();
+    // NOTE a parameter is kept when it is passed on to one that is, so this runs until no more are found
+    loop {
+        let mut changed = false;
+        for (def_idx, def) in ir.defs.iter() {
+            for (param_idx, binding) in def.entry.param_bindings.iter() {
+                if !kept[def_idx][param_idx] && Escape::escapes(&kept, *binding, &def.entry.body) {
+                    kept[def_idx][param_idx] = true;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return kept;
+        }
+    }
+}
+
+/// Follows a value through the locals that name it, such as a binding of it or the value of a match yielding it.
+struct Escape<'a> {
+    kept: &'a KeptParams,
+    names: HashSet<ir::LocalIdx>,
+    /// The params of the arm bodies that the leaves of a decision jump to.
+    arm_params: HashMap<ir::BlockIdx, Vec<ir::LocalIdx>>,
+    /// The locals taking the values of the match expressions being walked.
+    yield_targets: Vec<ir::LocalIdx>,
+    escaped: bool,
+}
+
+impl<'a> Escape<'a> {
+    fn escapes(kept: &'a KeptParams, binding: ir::LocalIdx, body: &ir::Block) -> bool {
+        let mut escape = Escape {
+            kept,
+            names: HashSet::from([binding]),
+            arm_params: HashMap::new(),
+            yield_targets: vec![],
+            escaped: false,
+        };
+        // NOTE a loop can name the value before the binding it is read from, so the body is walked until the names
+        // stop growing
+        loop {
+            let known = escape.names.len();
+            escape.visit_block(body);
+            if escape.escaped || escape.names.len() == known {
+                return escape.escaped;
+            }
+        }
+    }
+
+    fn is_named(&self, local_idx: ir::LocalIdx) -> bool {
+        self.names.contains(&local_idx)
+    }
+
+    fn name(&mut self, local_idx: ir::LocalIdx, value: ir::LocalIdx) {
+        if self.is_named(value) {
+            self.names.insert(local_idx);
+        }
+    }
+
+    fn keep(&mut self, local_idx: ir::LocalIdx) {
+        self.escaped |= self.is_named(local_idx);
+    }
+
+    fn visit_block(&mut self, block: &ir::Block) {
+        for instruction in block.instructions.iter() {
+            self.visit_instruction(&instruction.kind);
+        }
+        self.visit_terminator(&block.terminator);
+    }
+
+    fn visit_instruction(&mut self, instruction: &ir::InstructionKind) {
+        match instruction {
+            ir::InstructionKind::Let { binding, expr, .. } => self.visit_expr(*binding, &expr.kind),
+            ir::InstructionKind::LetMut { binding, init, .. } => self.name(*binding, *init),
+            ir::InstructionKind::Assign {
+                binding,
+                fields,
+                index,
+                value,
+                ..
+            } => {
+                if fields.is_empty() && index.is_none() {
+                    self.name(*binding, *value);
+                } else {
+                    self.keep(*value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expr(&mut self, binding: ir::LocalIdx, expr: &ir::ExprKind) {
+        match expr {
+            ir::ExprKind::Var(local_idx, fields) if fields.is_empty() => self.name(binding, *local_idx),
+            ir::ExprKind::Call { target, args } => {
+                for (param_idx, arg) in args.iter() {
+                    if self.kept[*target].get(param_idx).copied().unwrap_or(true) {
+                        self.keep(*arg);
+                    }
+                }
+            }
+            // NOTE the function called is not known, so it may keep any of its arguments
+            ir::ExprKind::CallValue { args, .. } => {
+                for arg in args.values() {
+                    self.keep(*arg);
+                }
+            }
+            ir::ExprKind::Closure { captures, .. } => {
+                for capture in captures.iter() {
+                    self.keep(*capture);
+                }
+            }
+            ir::ExprKind::Record { fields, .. }
+            | ir::ExprKind::Tuple(fields)
+            | ir::ExprKind::Variant { fields, .. } => {
+                for field in fields.values() {
+                    self.keep(*field);
+                }
+            }
+            ir::ExprKind::Match { source, arms } => {
+                self.yield_targets.push(binding);
+                self.visit_arms(*source, arms);
+                self.yield_targets.pop();
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_arms(&mut self, source: ir::LocalIdx, arms: &[ir::Arm]) {
+        for arm in arms.iter() {
+            if let ir::PatternKind::Ident(binding) = arm.pattern {
+                self.name(binding, source);
+            }
+            self.visit_block(&arm.target);
+        }
+    }
+
+    fn visit_terminator(&mut self, terminator: &ir::Terminator) {
+        match terminator {
+            ir::Terminator::Return(local_idx) => self.keep(*local_idx),
+            ir::Terminator::Match { source, arms } => self.visit_arms(*source, arms),
+            ir::Terminator::Decision { tree, arms } => {
+                for arm in arms.iter() {
+                    self.arm_params.insert(arm.body.block_idx, arm.params.clone());
+                }
+                self.visit_block(tree);
+                for arm in arms.iter() {
+                    self.visit_block(&arm.body);
+                }
+            }
+            ir::Terminator::Jump { target, args } => {
+                let params = self.arm_params[target].clone();
+                for (param, arg) in params.into_iter().zip(args.iter()) {
+                    self.name(param, *arg);
+                }
+            }
+            ir::Terminator::Yield(local_idx) => {
+                if let Some(target) = self.yield_targets.last().copied() {
+                    self.name(target, *local_idx);
+                }
+            }
+            ir::Terminator::Loop { body, next } => {
+                self.visit_block(body);
+                self.visit_block(next);
+            }
+            ir::Terminator::Break | ir::Terminator::Continue => {}
+        }
+    }
+}
@@ -1,123 +1,361 @@
+mod escape;
+
+use crate::escape::KeptParams;
 use alc_ast_lowering::{idx_vec::IdxVec, ir, ir::LocalIdx, ty};
-use alc_command_option::{CommandOptions, Gc};
-use alc_diagnostic::{FileId, Result};
-use std::collections::HashMap;
+use alc_command_option::{CommandOptions, Gc, Ownership};
+use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
+use alc_type_checker::TyEnv;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+};
 
 pub fn collect(
     command_options: &CommandOptions,
-    _file_id: FileId,
+    file_id: FileId,
     ty_sess: &ty::TySess,
+    ty_env: &mut TyEnv,
     ir: ir::Ir,
 ) -> Result<ir::Ir> {
     match command_options.gc {
         Gc::OwnRc => {
-            let mut ctx = OwnRcCtx::new(ty_sess);
-            ctx.collect_ir(&ir)?;
+            let mut ctx = OwnRcCtx::new(command_options, file_id, ty_sess, std::mem::take(ty_env), &ir);
+            ctx.collect_ir()?;
+            *ty_env = ctx.ty_env.into_inner();
             Ok(ctx.ir)
         }
+        Gc::None if command_options.ownership == Ownership::Strict => Err(Box::from(
+            Diagnostic::new_error(
+                "'--ownership=strict' requires reference counting",
+                Label::new(
+                    file_id,
+                    Span::dummy(),
+                    "no reference counts are kept under '--gc=none'",
+                ),
+            )
+            .with_notes(vec![String::from(
+                "use '--gc=ownrc' to check the ownership of values",
+            )]),
+        )),
         Gc::None => Ok(ir),
     }
 }
 
 struct OwnRcCtx<'gc> {
+    command_options: &'gc CommandOptions,
+    file_id: FileId,
     ir: ir::Ir,
+    source: &'gc ir::Ir,
     ty_sess: &'gc ty::TySess,
+    ty_env: RefCell<TyEnv>,
+    kept_params: KeptParams,
 }
 
 impl<'gc> OwnRcCtx<'gc> {
-    fn new(ty_sess: &'gc ty::TySess) -> OwnRcCtx {
+    fn new(
+        command_options: &'gc CommandOptions,
+        file_id: FileId,
+        ty_sess: &'gc ty::TySess,
+        ty_env: TyEnv,
+        source: &'gc ir::Ir,
+    ) -> OwnRcCtx<'gc> {
         OwnRcCtx {
+            command_options,
+            file_id,
             ir: ir::Ir { defs: IdxVec::new() },
+            source,
             ty_sess,
+            ty_env: RefCell::new(ty_env),
+            kept_params: escape::kept_params(source),
         }
     }
 
-    fn collect_ir(&mut self, ir: &'gc ir::Ir) -> Result<()> {
-        for def in ir.defs.values() {
+    fn collect_ir(&mut self) -> Result<()> {
+        for def in self.source.defs.values() {
             self.ir
                 .defs
-                .push(LocalOwnRcCtx::new(self, None).collect_def(def)?);
+                .push(LocalOwnRcCtx::new(self, def, None, Scope::Def).collect_def(def)?);
         }
         Ok(())
     }
+
+    fn return_ty(&self, def_idx: ir::DefIdx) -> Option<ty::Ty> {
+        self.ty_sess.ty_kind(self.source.defs[def_idx].ty).return_ty()
+    }
 }
 
 pub type RefCount = i32;
 
+/// The kind of block a scope was made for, which decides how far each way of leaving the block reaches.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    /// The body of a function, which only `return` leaves.
+    Def,
+    /// The body of a loop, which `break` and `continue` leave as well.
+    Loop,
+    /// An arm of a match expression, which yielding the value of the match leaves as well.
+    YieldingArm,
+    /// An arm of a match that doesn't yield a value.
+    Arm,
+    /// The switches of a match, which each leaf leaves by jumping to the body of its arm.
+    Decision,
+}
+
 struct LocalOwnRcCtx<'gc> {
     global_ctx: &'gc OwnRcCtx<'gc>,
     instructions: Vec<ir::Instruction>,
-    malloc_map: HashMap<LocalIdx, (ty::Ty, RefCount)>,
-    _parent: Option<&'gc LocalOwnRcCtx<'gc>>,
+    /// The values this scope holds a reference to, with the number of references to each that are known here. A
+    /// value is freed right away when this scope holds the only one.
+    malloc_map: RefCell<HashMap<LocalIdx, (ty::Ty, RefCount)>>,
+    /// Mutable bindings declared in this scope, which own whatever they hold at the end of it.
+    slots: HashSet<LocalIdx>,
+    /// Locals holding a copy of what a mutable binding held when it was read.
+    snapshots: HashMap<LocalIdx, LocalIdx>,
+    /// Locals holding a string literal, which is copied onto the heap when a mutable binding is given it.
+    string_literals: HashSet<LocalIdx>,
+    /// Locals holding the receiver of a method, a field of a record or what a pattern bound, which the caller,
+    /// the record or the matched value keeps owning.
+    borrowed: HashSet<LocalIdx>,
+    /// The type of the value that the arms of a match expression moved or retained when yielding it, with the most
+    /// references known to it.
+    yielded: Cell<Option<(ty::Ty, RefCount)>>,
+    /// Where the arms of a match expression yield a value they don't own, which is retained there once the type of
+    /// the value is known.
+    unowned_yields: RefCell<Vec<(ir::BlockIdx, usize, LocalIdx)>>,
+    /// The type of the value that the function returns to its caller, which owns it.
+    return_ty: Option<ty::Ty>,
+    def: &'gc ir::Def,
+    parent: Option<&'gc LocalOwnRcCtx<'gc>>,
+    scope: Scope,
 }
 
 impl<'gc> LocalOwnRcCtx<'gc> {
-    fn new(global_ctx: &'gc OwnRcCtx, parent: Option<&'gc LocalOwnRcCtx<'gc>>) -> LocalOwnRcCtx<'gc> {
+    fn new(
+        global_ctx: &'gc OwnRcCtx,
+        def: &'gc ir::Def,
+        parent: Option<&'gc LocalOwnRcCtx<'gc>>,
+        scope: Scope,
+    ) -> LocalOwnRcCtx<'gc> {
         LocalOwnRcCtx {
             global_ctx,
             instructions: vec![],
-            malloc_map: HashMap::new(),
-            _parent: parent,
+            malloc_map: RefCell::new(HashMap::new()),
+            slots: HashSet::new(),
+            snapshots: HashMap::new(),
+            string_literals: HashSet::new(),
+            borrowed: HashSet::new(),
+            yielded: Cell::new(None),
+            unowned_yields: RefCell::new(vec![]),
+            return_ty: parent.and_then(|parent| parent.return_ty),
+            def,
+            parent,
+            scope,
         }
     }
 
     fn collect_def(&mut self, def: &ir::Def) -> Result<ir::Def> {
+        self.return_ty = self.global_ctx.ty_sess.ty_kind(def.ty).return_ty();
         Ok(ir::Def {
             def_idx: def.def_idx,
             name: def.name.clone(),
             ty: def.ty,
             span: def.span,
-            entry: self.collect_entry(&def.entry),
+            entry: self.collect_entry(&def.entry)?,
             local_idxr: def.local_idxr.clone(),
+            matches: def.matches.clone(),
         })
     }
 
-    fn collect_entry(&mut self, entry: &ir::Entry) -> ir::Entry {
-        ir::Entry {
+    fn collect_entry(&mut self, entry: &ir::Entry) -> Result<ir::Entry> {
+        // NOTE the caller owns the arguments, so the function retains what it stores or returns of them
+        self.borrowed.extend(entry.receiver);
+        self.borrowed.extend(entry.param_bindings.values().copied());
+        self.borrowed
+            .extend(entry.capture_bindings.iter().flatten().copied());
+        Ok(ir::Entry {
             owner: entry.owner,
-            // TODO: 対応必要か確認
             param_bindings: entry.param_bindings.clone(),
-            body: self.collect_block(&entry.body),
-        }
+            capture_bindings: entry.capture_bindings.clone(),
+            receiver: entry.receiver,
+            body: self.collect_block(&entry.body)?,
+        })
     }
 
-    fn collect_block(&mut self, block: &ir::Block) -> ir::Block {
-        self.push_instructions(&block.instructions);
-        let terminator = self.collect_terminator(&block.terminator);
-        ir::Block {
+    fn collect_block(&mut self, block: &ir::Block) -> Result<ir::Block> {
+        self.push_instructions(&block.instructions)?;
+        let terminator = self.collect_terminator(&block.terminator, block.block_idx)?;
+        Ok(ir::Block {
             owner: block.owner,
             block_idx: block.block_idx,
             span: block.span,
             instructions: self.instructions.clone(),
             terminator,
-        }
+        })
+    }
+
+    /// Collects the block after a loop, which shares the ownership state but not the instructions of this one.
+    fn collect_next_block(&mut self, block: &ir::Block) -> Result<ir::Block> {
+        let instructions = std::mem::take(&mut self.instructions);
+        let block = self.collect_block(block);
+        self.instructions = instructions;
+        block
     }
 
-    fn push_instructions(&mut self, instructions: &[ir::Instruction]) {
+    fn push_instructions(&mut self, instructions: &[ir::Instruction]) -> Result<()> {
         for instruction in instructions {
-            let instruction = self.collect_instruction(instruction);
+            let instruction = self.collect_instruction(instruction)?;
             self.instructions.push(instruction);
         }
+        Ok(())
     }
 
-    fn collect_terminator(&mut self, terminator: &ir::Terminator) -> ir::Terminator {
-        match terminator {
+    fn collect_terminator(
+        &mut self,
+        terminator: &ir::Terminator,
+        block_idx: ir::BlockIdx,
+    ) -> Result<ir::Terminator> {
+        Ok(match terminator {
             ir::Terminator::Return(local_idx) => {
-                self.release_malloc_map();
+                // NOTE the caller owns the returned value, and a string literal is copied onto the heap for it
+                let returned = self.resolve(*local_idx);
+                if self.owner(returned).is_some() {
+                    self.release_scopes(Scope::Def, Some(returned));
+                } else {
+                    if let Some(ty) = self.return_ty.filter(|ty| self.holds_counted(*local_idx, *ty)) {
+                        self.forbid_increment(*local_idx, local_idx.span(), "returning the value here")?;
+                        self.instructions.push(ir::Instruction {
+                            kind: ir::InstructionKind::IncrementRc(*local_idx, ty),
+                            span: local_idx.span(),
+                        });
+                    }
+                    self.release_scopes(Scope::Def, None);
+                }
                 ir::Terminator::Return(*local_idx)
             }
-            ir::Terminator::Match { source, arms } => {
-                // TODO: 対応必要か確認
-                ir::Terminator::Match {
-                    source: *source,
-                    arms: arms.iter().map(|arm| self.collect_arm(arm)).collect(),
+            ir::Terminator::Match { source, arms } => ir::Terminator::Match {
+                source: *source,
+                arms: arms
+                    .iter()
+                    .map(|arm| self.collect_arm(arm))
+                    .collect::<Result<_>>()?,
+            },
+            ir::Terminator::Decision { tree, arms } => ir::Terminator::Decision {
+                tree: Box::new(
+                    LocalOwnRcCtx::new(self.global_ctx, self.def, Some(self), Scope::Decision)
+                        .collect_block(tree)?,
+                ),
+                arms: arms
+                    .iter()
+                    .map(|arm| self.collect_arm_body(arm))
+                    .collect::<Result<_>>()?,
+            },
+            ir::Terminator::Jump { target, args } => {
+                self.release_scopes(Scope::Decision, None);
+                ir::Terminator::Jump {
+                    target: *target,
+                    args: args.clone(),
                 }
             }
-        }
+            ir::Terminator::Yield(local_idx) => {
+                self.yield_value(*local_idx, block_idx)?;
+                ir::Terminator::Yield(*local_idx)
+            }
+            ir::Terminator::Loop { body, next } => ir::Terminator::Loop {
+                body: Box::new(
+                    LocalOwnRcCtx::new(self.global_ctx, self.def, Some(self), Scope::Loop)
+                        .collect_block(body)?,
+                ),
+                next: Box::new(self.collect_next_block(next)?),
+            },
+            ir::Terminator::Break => {
+                self.release_scopes(Scope::Loop, None);
+                ir::Terminator::Break
+            }
+            ir::Terminator::Continue => {
+                self.release_scopes(Scope::Loop, None);
+                ir::Terminator::Continue
+            }
+        })
     }
 
-    fn collect_instruction(&mut self, instruction: &ir::Instruction) -> ir::Instruction {
+    fn collect_instruction(&mut self, instruction: &ir::Instruction) -> Result<ir::Instruction> {
+        if let ir::InstructionKind::Let {
+            binding,
+            ty: _,
+            expr:
+                ir::Expr {
+                    local_idx: _,
+                    span: _,
+                    kind: ir::ExprKind::Var(local_idx_in_var, field_idxes),
+                },
+        } = &instruction.kind
+        {
+            if !field_idxes.is_empty() {
+                self.borrowed.insert(*binding);
+            } else if let Some(slot) = self.resolve_slot(*local_idx_in_var) {
+                self.snapshots.insert(*binding, slot);
+            } else if self.is_borrowed(*local_idx_in_var) {
+                self.borrowed.insert(*binding);
+            }
+        }
+        if let ir::InstructionKind::Let {
+            binding: _,
+            ty: _,
+            expr:
+                ir::Expr {
+                    local_idx: _,
+                    span: _,
+                    kind,
+                },
+        } = &instruction.kind
+        {
+            match kind {
+                ir::ExprKind::Call { target, args } => {
+                    for (param_idx, arg) in args.iter() {
+                        if self.global_ctx.kept_params[*target][param_idx] {
+                            self.count_reference(*arg);
+                        }
+                    }
+                }
+                ir::ExprKind::CallValue { target: _, args } => {
+                    for arg in args.values() {
+                        self.count_reference(*arg);
+                    }
+                }
+                _ => {}
+            }
+        }
         match instruction {
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Let {
+                        binding,
+                        ty,
+                        expr:
+                            ir::Expr {
+                                local_idx,
+                                span: expr_span,
+                                kind: ir::ExprKind::Match { source, arms },
+                            },
+                    },
+                span,
+            } => {
+                return Ok(ir::Instruction {
+                    kind: ir::InstructionKind::Let {
+                        binding: *binding,
+                        ty: *ty,
+                        expr: ir::Expr {
+                            local_idx: *local_idx,
+                            span: *expr_span,
+                            kind: ir::ExprKind::Match {
+                                source: *source,
+                                arms: self.collect_yielding_arms(*local_idx, arms)?,
+                            },
+                        },
+                    },
+                    span: *span,
+                });
+            }
             ir::Instruction {
                 kind:
                     ir::InstructionKind::Let {
@@ -127,28 +365,55 @@ impl<'gc> LocalOwnRcCtx<'gc> {
                             ir::Expr {
                                 local_idx,
                                 span: _,
-                                kind: ir::ExprKind::Record { ty, fields },
+                                kind: ir::ExprKind::Record { ty: _, fields } | ir::ExprKind::Tuple(fields),
                             },
                     },
                 span: _,
             } => {
-                self.retain_malloc_map(*local_idx, *ty);
+                let ty = self.local_ty(*local_idx);
+                self.insert_malloc_map(*local_idx, ty);
                 let fields_by_ty_sess = self
                     .global_ctx
                     .ty_sess
-                    .ty_kind(*ty)
-                    .as_struct()
+                    .ty_kind(ty)
+                    .record_fields()
                     .unwrap()
-                    .fields
                     .clone();
                 for (field_idx, local_idx) in fields.iter() {
-                    if self
+                    if self.is_counted_field(*fields_by_ty_sess.get(field_idx).unwrap()) {
+                        self.retain_field(*local_idx, *fields_by_ty_sess.get(field_idx).unwrap())?;
+                    }
+                }
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Let {
+                        binding: _,
+                        ty: _,
+                        expr:
+                            ir::Expr {
+                                local_idx,
+                                span: _,
+                                kind:
+                                    ir::ExprKind::Variant {
+                                        ty,
+                                        discriminant,
+                                        fields,
+                                    },
+                            },
+                    },
+                span: _,
+            } => {
+                self.insert_malloc_map(*local_idx, *ty);
+                for (field_idx, local_idx) in fields.iter() {
+                    let field_ty = self
                         .global_ctx
                         .ty_sess
-                        .ty_kind(*fields_by_ty_sess.get(field_idx).unwrap())
-                        .is_struct()
-                    {
-                        self.retain_malloc_map(*local_idx, *fields_by_ty_sess.get(field_idx).unwrap());
+                        .ty_kind(*ty)
+                        .variant_field_ty(*discriminant, field_idx)
+                        .unwrap();
+                    if self.is_counted_field(field_ty) {
+                        self.retain_field(*local_idx, field_ty)?;
                     }
                 }
             }
@@ -156,65 +421,558 @@ impl<'gc> LocalOwnRcCtx<'gc> {
                 kind:
                     ir::InstructionKind::Let {
                         binding: _,
-                        ty: Some(ty),
+                        ty: _,
+                        expr:
+                            ir::Expr {
+                                local_idx,
+                                span: _,
+                                kind: ir::ExprKind::Call { target, args: _ },
+                            },
+                    },
+                span: _,
+            } => {
+                if let Some(ty) = self.global_ctx.return_ty(*target) {
+                    if self.is_counted(ty) || self.global_ctx.ty_sess.ty_kind(ty).is_string() {
+                        self.insert_returned(*local_idx, ty);
+                    }
+                }
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Let {
+                        binding: _,
+                        ty: _,
+                        expr:
+                            ir::Expr {
+                                local_idx,
+                                span: _,
+                                kind: ir::ExprKind::CallValue { target: _, args: _ },
+                            },
+                    },
+                span: _,
+            } => {
+                let ty = self.local_ty(*local_idx);
+                if self.is_counted(ty) || self.global_ctx.ty_sess.ty_kind(ty).is_string() {
+                    self.insert_returned(*local_idx, ty);
+                }
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Let {
+                        binding: _,
+                        ty: _,
+                        expr:
+                            ir::Expr {
+                                local_idx,
+                                span: _,
+                                kind: ir::ExprKind::Closure { ty, captures, .. },
+                            },
+                    },
+                span: _,
+            } => {
+                self.insert_malloc_map(*local_idx, *ty);
+                // NOTE the environment keeps a reference to what it captured, which its drop routine releases
+                for capture in captures.iter() {
+                    let capture_ty = self.local_ty(*capture);
+                    if self.holds_counted(*capture, capture_ty) {
+                        self.retain_field(*capture, capture_ty)?;
+                    }
+                }
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Let {
+                        binding,
+                        ty,
                         expr:
                             ir::Expr {
                                 local_idx: _,
                                 span: _,
-                                kind: ir::ExprKind::Var(local_idx_in_var, _),
+                                kind: ir::ExprKind::Var(local_idx_in_var, field_idxes),
+                            },
+                    },
+                span: _,
+            } if field_idxes.is_empty()
+                // NOTE a read of a mutable binding nothing expects a type of is a snapshot of it, while a binding
+                // of another local takes a reference whether or not it is annotated
+                && (ty.is_some() || self.resolve_slot(*local_idx_in_var).is_none())
+                && self.holds_counted(*local_idx_in_var, self.local_ty(*binding)) =>
+            {
+                if self.is_borrowed(*local_idx_in_var) {
+                    self.borrowed.insert(*binding);
+                } else {
+                    self.retain_malloc_map(*local_idx_in_var, *binding, self.local_ty(*binding))?;
+                }
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Let {
+                        binding: _,
+                        ty: _,
+                        expr:
+                            ir::Expr {
+                                local_idx,
+                                span: _,
+                                kind: ir::ExprKind::StringLiteral(_),
                             },
                     },
                 span: _,
             } => {
-                if self.global_ctx.ty_sess.ty_kind(*ty).is_struct() {
-                    self.retain_malloc_map(*local_idx_in_var, *ty);
+                self.string_literals.insert(*local_idx);
+            }
+            ir::Instruction {
+                kind: ir::InstructionKind::LetMut { binding, ty: _, init },
+                span: _,
+            } => {
+                let ty = self.local_ty(*binding);
+                // NOTE a newly allocated value is moved into the slot, a string literal is copied onto the heap for
+                // it, and anything else stays borrowed
+                if self.is_string_literal(*init) {
+                    self.insert_malloc_map(*binding, ty);
+                } else if self.holds_counted(*init, ty) {
+                    let mut malloc_map = self.malloc_map.borrow_mut();
+                    if let Some(entry) = malloc_map.remove(init) {
+                        malloc_map.insert(*binding, entry);
+                    }
+                }
+                self.slots.insert(*binding);
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Assign {
+                        binding,
+                        fields,
+                        index: None,
+                        ty: _,
+                        value,
+                    },
+                span: _,
+            } if fields.is_empty() => {
+                let ty = self.local_ty(*binding);
+                if self.is_string_literal(*value) {
+                    self.insert_malloc_map(*value, ty);
+                    self.overwrite_slot(*binding, *value);
+                } else if self.holds_counted(*value, ty) {
+                    self.overwrite_slot(*binding, *value);
+                }
+            }
+            ir::Instruction {
+                kind:
+                    ir::InstructionKind::Assign {
+                        binding,
+                        fields,
+                        index: None,
+                        ty: Some(ty),
+                        value,
+                    },
+                span: _,
+            } if !fields.is_empty() && self.is_counted_field(*ty) => {
+                // NOTE the record retains the stored value and lets go of the one it replaces
+                self.retain_field(*value, *ty)?;
+                self.release_field(*binding, fields, *ty);
+            }
+            _ => {}
+        }
+        Ok(instruction.clone())
+    }
+
+    /// Whether values of the type are boxed with a reference count. Arrays are kept by value and need none.
+    fn is_counted(&self, ty: ty::Ty) -> bool {
+        let ty_kind = self.global_ctx.ty_sess.ty_kind(ty);
+        ty_kind.is_record() || ty_kind.is_enum() || ty_kind.is_fn()
+    }
+
+    /// Whether the local holds a value with a reference count. A string only has one once it was returned from a
+    /// function or given by the caller, as a literal stays on the stack.
+    fn holds_counted(&self, local_idx: LocalIdx, ty: ty::Ty) -> bool {
+        self.is_counted(ty)
+            || (self.global_ctx.ty_sess.ty_kind(ty).is_string()
+                && (self.is_owned(local_idx) || self.is_borrowed(local_idx)))
+    }
+
+    fn local_ty(&self, local_idx: LocalIdx) -> ty::Ty {
+        self.global_ctx.ty_env.borrow()[self.def.def_idx][local_idx]
+    }
+
+    fn new_local(&self, ty: ty::Ty, span: alc_diagnostic::Span) -> LocalIdx {
+        let local_idx = self.def.local_idxr.next().with_span(span);
+        self.global_ctx.ty_env.borrow_mut()[self.def.def_idx].push(ty);
+        local_idx
+    }
+
+    /// Whether a record or an enum keeps a reference to the values of the type in its fields, which its drop glue
+    /// releases. A string in a field is always on the heap, as only a pointer to its characters is stored.
+    fn is_counted_field(&self, ty: ty::Ty) -> bool {
+        self.is_counted(ty) || self.global_ctx.ty_sess.ty_kind(ty).is_string()
+    }
+
+    fn is_owned(&self, local_idx: LocalIdx) -> bool {
+        self.owner(self.resolve(local_idx)).is_some()
+    }
+
+    fn owner(&self, local_idx: LocalIdx) -> Option<&LocalOwnRcCtx<'gc>> {
+        if self.malloc_map.borrow().contains_key(&local_idx) {
+            Some(self)
+        } else {
+            self.parent.and_then(|parent| parent.owner(local_idx))
+        }
+    }
+
+    /// Collects the arms of a match expression. The value of the match is owned by this scope when the arms yield
+    /// a value with a reference count.
+    fn collect_yielding_arms(&mut self, local_idx: LocalIdx, arms: &[ir::Arm]) -> Result<Vec<ir::Arm>> {
+        let mut yielded: Option<(ty::Ty, RefCount)> = None;
+        let mut unowned_yields = vec![];
+        let mut collected_arms = vec![];
+        for arm in arms {
+            let mut ctx = self.arm_ctx(arm, Scope::YieldingArm);
+            let target = ctx.collect_block(&arm.target)?;
+            if let Some((ty, count)) = ctx.yielded.get() {
+                yielded = Some((ty, yielded.map_or(count, |(_, known)| known.max(count))));
+            }
+            unowned_yields.extend(ctx.unowned_yields.take());
+            collected_arms.push(ir::Arm {
+                span: arm.span,
+                pattern: arm.pattern.clone(),
+                target,
+            });
+        }
+        if let Some((ty, count)) = yielded {
+            if let Some((_, _, unowned)) = unowned_yields.first() {
+                self.forbid_increment(*unowned, unowned.span(), "yielding the value here")?;
+            }
+            for arm in collected_arms.iter_mut() {
+                Self::retain_yields(&mut arm.target, &unowned_yields, ty);
+            }
+            let count = if unowned_yields.is_empty() {
+                count
+            } else {
+                count.max(2)
+            };
+            self.malloc_map.borrow_mut().insert(local_idx, (ty, count));
+        }
+        Ok(collected_arms)
+    }
+
+    /// Retains the values that a block and the blocks it branches to yield without owning them, before the values
+    /// of the scopes they leave are released.
+    fn retain_yields(block: &mut ir::Block, unowned_yields: &[(ir::BlockIdx, usize, LocalIdx)], ty: ty::Ty) {
+        if let ir::Terminator::Yield(local_idx) = block.terminator {
+            for (block_idx, idx, _) in unowned_yields {
+                if *block_idx == block.block_idx {
+                    block.instructions.insert(
+                        *idx,
+                        ir::Instruction {
+                            kind: ir::InstructionKind::IncrementRc(local_idx, ty),
+                            span: local_idx.span(),
+                        },
+                    );
                 }
             }
+        }
+        match &mut block.terminator {
+            ir::Terminator::Match { source: _, arms } => {
+                for arm in arms.iter_mut() {
+                    Self::retain_yields(&mut arm.target, unowned_yields, ty);
+                }
+            }
+            ir::Terminator::Decision { tree, arms } => {
+                Self::retain_yields(tree, unowned_yields, ty);
+                for arm in arms.iter_mut() {
+                    Self::retain_yields(&mut arm.body, unowned_yields, ty);
+                }
+            }
+            ir::Terminator::Loop { body, next } => {
+                Self::retain_yields(body, unowned_yields, ty);
+                Self::retain_yields(next, unowned_yields, ty);
+            }
             _ => {}
         }
-        instruction.clone()
     }
 
-    fn collect_arm(&mut self, arm: &ir::Arm) -> ir::Arm {
-        ir::Arm {
+    /// Passes the value yielded by an arm of a match expression on to the match. A value owned by the scopes being
+    /// left is moved out of them, and any other value is retained so that the match owns its value on every path.
+    fn yield_value(&mut self, local_idx: LocalIdx, block_idx: ir::BlockIdx) -> Result<()> {
+        let yielded = self.resolve(local_idx);
+        let mut moved = None;
+        let mut owned = None;
+        let mut scope = Some(&*self);
+        let mut inside = true;
+        while let Some(ctx) = scope {
+            if let Some((ty, count)) = ctx.malloc_map.borrow_mut().get_mut(&yielded) {
+                if inside {
+                    moved = Some(yielded);
+                } else {
+                    *count += 1;
+                }
+                owned = Some((*ty, *count, inside));
+                break;
+            }
+            inside &= ctx.scope != Scope::YieldingArm;
+            scope = ctx.parent;
+        }
+        let arm = self.yielding_arm();
+        match owned {
+            Some((ty, count, inside)) => {
+                arm.note_yield(ty, count);
+                if !inside {
+                    self.forbid_increment(local_idx, local_idx.span(), "yielding the value here")?;
+                    self.instructions.push(ir::Instruction {
+                        kind: ir::InstructionKind::IncrementRc(local_idx, ty),
+                        span: local_idx.span(),
+                    });
+                }
+            }
+            None => arm
+                .unowned_yields
+                .borrow_mut()
+                .push((block_idx, self.instructions.len(), local_idx)),
+        }
+        self.release_scopes(Scope::YieldingArm, moved);
+        Ok(())
+    }
+
+    fn note_yield(&self, ty: ty::Ty, count: RefCount) {
+        let count = self.yielded.get().map_or(count, |(_, known)| known.max(count));
+        self.yielded.set(Some((ty, count)));
+    }
+
+    fn yielding_arm(&self) -> &LocalOwnRcCtx<'gc> {
+        if self.scope == Scope::YieldingArm {
+            self
+        } else {
+            self.parent.unwrap().yielding_arm()
+        }
+    }
+
+    fn collect_arm(&mut self, arm: &ir::Arm) -> Result<ir::Arm> {
+        let mut ctx = self.arm_ctx(arm, Scope::Arm);
+        Ok(ir::Arm {
             span: arm.span,
             pattern: arm.pattern.clone(),
-            target: LocalOwnRcCtx::new(self.global_ctx, Some(self)).collect_block(&arm.target),
+            target: ctx.collect_block(&arm.target)?,
+        })
+    }
+
+    fn collect_arm_body(&mut self, arm: &ir::ArmBody) -> Result<ir::ArmBody> {
+        let mut ctx = LocalOwnRcCtx::new(self.global_ctx, self.def, Some(self), Scope::Arm);
+        ctx.borrowed.extend(arm.params.iter().copied());
+        Ok(ir::ArmBody {
+            params: arm.params.clone(),
+            body: ctx.collect_block(&arm.body)?,
+        })
+    }
+
+    fn arm_ctx(&self, arm: &ir::Arm, scope: Scope) -> LocalOwnRcCtx {
+        let mut ctx = LocalOwnRcCtx::new(self.global_ctx, self.def, Some(self), scope);
+        // NOTE what a pattern binds is borrowed from the matched value
+        match &arm.pattern {
+            ir::PatternKind::Ident(binding) => {
+                ctx.borrowed.insert(*binding);
+            }
+            ir::PatternKind::Variant { fields, .. } | ir::PatternKind::Record { fields, .. } => {
+                ctx.borrowed.extend(fields.values().copied());
+            }
+            _ => {}
         }
+        ctx
     }
 
-    fn retain_malloc_map(&mut self, local_idx: LocalIdx, ty: ty::Ty) {
-        if let Some((_, count)) = self.malloc_map.get_mut(&local_idx) {
-            *count += 1;
-            self.instructions.push(ir::Instruction {
-                kind: ir::InstructionKind::IncrementRc(local_idx, ty),
-                span: local_idx.span(),
-            });
-            return;
+    fn slot_owner(&self, binding: LocalIdx) -> Option<&LocalOwnRcCtx<'gc>> {
+        if self.slots.contains(&binding) {
+            Some(self)
+        } else {
+            self.parent.and_then(|parent| parent.slot_owner(binding))
         }
+    }
 
-        self.malloc_map.insert(local_idx, (ty, 1));
+    /// Releases the value held by a mutable binding before it is overwritten, and moves ownership of a newly
+    /// allocated value into it. The binding may belong to an enclosing scope, such as outside of a loop.
+    fn overwrite_slot(&mut self, binding: LocalIdx, value: LocalIdx) {
+        let moved = self.malloc_map.borrow_mut().remove(&value);
+        let owner = match self.slot_owner(binding) {
+            Some(owner) => owner,
+            None => return,
+        };
+        let released = owner.malloc_map.borrow_mut().remove(&binding);
+        if let Some(entry) = moved {
+            owner.malloc_map.borrow_mut().insert(binding, entry);
+        }
+        if let Some((ty, count)) = released {
+            self.instructions
+                .push(Self::release_instruction(binding, ty, count));
+        }
     }
 
-    fn release_malloc_map(&mut self) {
-        let mut new_malloc_map = HashMap::new();
-        for (malloc_idx, (ty, count)) in self.malloc_map.iter_mut() {
-            *count -= 1;
-            if count <= &mut 0 {
-                self.instructions.push(ir::Instruction {
-                    kind: ir::InstructionKind::Free(*malloc_idx, *ty),
-                    span: malloc_idx.span(),
-                });
-                continue;
-            } else {
-                self.instructions.push(ir::Instruction {
-                    kind: ir::InstructionKind::DecrementRc(*malloc_idx, *ty),
-                    span: malloc_idx.span(),
-                });
+    fn resolve_slot(&self, local_idx: LocalIdx) -> Option<LocalIdx> {
+        if let Some(slot) = self.snapshots.get(&local_idx) {
+            Some(*slot)
+        } else if self.slots.contains(&local_idx) {
+            Some(local_idx)
+        } else {
+            self.parent.and_then(|parent| parent.resolve_slot(local_idx))
+        }
+    }
+
+    fn resolve(&self, local_idx: LocalIdx) -> LocalIdx {
+        self.resolve_slot(local_idx).unwrap_or(local_idx)
+    }
+
+    fn is_string_literal(&self, local_idx: LocalIdx) -> bool {
+        self.string_literals.contains(&local_idx)
+            || self
+                .parent
+                .is_some_and(|parent| parent.is_string_literal(local_idx))
+    }
+
+    fn is_borrowed(&self, local_idx: LocalIdx) -> bool {
+        self.borrowed.contains(&local_idx) || self.parent.is_some_and(|parent| parent.is_borrowed(local_idx))
+    }
+
+    fn insert_malloc_map(&mut self, local_idx: LocalIdx, ty: ty::Ty) {
+        self.malloc_map.borrow_mut().insert(local_idx, (ty, 1));
+    }
+
+    /// Registers a value returned from a function. The caller owns a reference to it, which may not be the only one
+    /// as a function can return what it was given.
+    fn insert_returned(&mut self, local_idx: LocalIdx, ty: ty::Ty) {
+        self.malloc_map.borrow_mut().insert(local_idx, (ty, 2));
+    }
+
+    fn retain_malloc_map(&mut self, local_idx: LocalIdx, binding: LocalIdx, ty: ty::Ty) -> Result<()> {
+        self.forbid_increment(local_idx, binding.span(), "this binding")?;
+        let count = self.count_reference(local_idx);
+        self.instructions.push(ir::Instruction {
+            kind: ir::InstructionKind::IncrementRc(local_idx, ty),
+            span: binding.span(),
+        });
+        self.snapshots.remove(&binding);
+        self.malloc_map.borrow_mut().insert(binding, (ty, count));
+        Ok(())
+    }
+
+    /// Rejects taking another reference to a value at `span` under `--ownership=strict`.
+    fn forbid_increment(&self, local_idx: LocalIdx, span: Span, site: &str) -> Result<()> {
+        if self.global_ctx.command_options.ownership != Ownership::Strict {
+            return Ok(());
+        }
+        let resolved = self.resolve(local_idx);
+        let owner = self.owner(resolved);
+        let owner_span = owner
+            .and_then(|owner| {
+                owner
+                    .malloc_map
+                    .borrow()
+                    .get_key_value(&resolved)
+                    .map(|(owner, _)| owner.span())
+            })
+            .filter(|owner_span| *owner_span != span);
+        let mut notes = vec![String::from(
+            "implicit reference count increments are rejected under '--ownership=strict'",
+        )];
+        if owner.is_none() {
+            notes.push(String::from(
+                "the value is borrowed from the caller or from the value holding it",
+            ));
+        }
+        Err(Box::from(
+            Diagnostic::new_error(
+                "attempt to take another reference to an owned value",
+                Label::new(
+                    self.global_ctx.file_id,
+                    span,
+                    format!("{} would increment the reference count", site),
+                ),
+            )
+            .with_secondary_labels(
+                owner_span.map(|owner_span| {
+                    Label::new(self.global_ctx.file_id, owner_span, "the value is owned here")
+                }),
+            )
+            .with_notes(notes),
+        ))
+    }
+
+    fn retain_field(&mut self, local_idx: LocalIdx, ty: ty::Ty) -> Result<()> {
+        self.forbid_increment(local_idx, local_idx.span(), "storing the value here")?;
+        self.count_reference(local_idx);
+        self.instructions.push(ir::Instruction {
+            kind: ir::InstructionKind::IncrementRc(local_idx, ty),
+            span: local_idx.span(),
+        });
+        Ok(())
+    }
+
+    /// Counts a new reference to a value in the scope owning it, returning the number of references known to it.
+    fn count_reference(&self, local_idx: LocalIdx) -> RefCount {
+        let local_idx = self.resolve(local_idx);
+        match self.owner(local_idx) {
+            Some(owner) => {
+                let mut malloc_map = owner.malloc_map.borrow_mut();
+                let (_, count) = malloc_map.get_mut(&local_idx).unwrap();
+                *count += 1;
+                *count
             }
+            // NOTE a value that isn't owned here is referenced by its owner as well
+            None => 2,
+        }
+    }
+
+    fn release_field(&mut self, binding: LocalIdx, fields: &[ty::FieldIdx], ty: ty::Ty) {
+        let replaced = self.new_local(ty, binding.span());
+        self.instructions.push(ir::Instruction {
+            kind: ir::InstructionKind::Let {
+                binding: replaced,
+                ty: Some(ty),
+                expr: ir::Expr {
+                    local_idx: replaced,
+                    span: binding.span(),
+                    kind: ir::ExprKind::Var(binding, fields.to_vec()),
+                },
+            },
+            span: binding.span(),
+        });
+        self.instructions.push(ir::Instruction {
+            kind: ir::InstructionKind::DecrementRc(replaced, ty),
+            span: binding.span(),
+        });
+    }
 
-            new_malloc_map.insert(*malloc_idx, (*ty, *count));
+    /// Releases what the scopes being left own, from this one out to the closest `last` one, except for `moved`
+    /// whose reference is passed on. The enclosing scopes still own their values on the paths that stay in them, so
+    /// only this one forgets what it released.
+    fn release_scopes(&mut self, last: Scope, moved: Option<LocalIdx>) {
+        if let Some(moved) = moved {
+            self.malloc_map.borrow_mut().remove(&moved);
+        }
+        self.release_malloc_map();
+        let mut scope = if self.scope == last { None } else { self.parent };
+        while let Some(ctx) = scope {
+            for (local_idx, (ty, count)) in ctx.malloc_map.borrow().iter() {
+                if Some(*local_idx) != moved {
+                    self.instructions
+                        .push(Self::release_instruction(*local_idx, *ty, *count));
+                }
+            }
+            scope = if ctx.scope == last { None } else { ctx.parent };
+        }
+    }
+
+    fn release_instruction(local_idx: LocalIdx, ty: ty::Ty, count: RefCount) -> ir::Instruction {
+        ir::Instruction {
+            kind: if count <= 1 {
+                ir::InstructionKind::Free(local_idx, ty)
+            } else {
+                ir::InstructionKind::DecrementRc(local_idx, ty)
+            },
+            span: local_idx.span(),
+        }
+    }
+
+    fn release_malloc_map(&mut self) {
+        for (malloc_idx, (ty, count)) in self.malloc_map.take() {
+            self.instructions
+                .push(Self::release_instruction(malloc_idx, ty, count));
         }
-        self.malloc_map = new_malloc_map;
     }
 }
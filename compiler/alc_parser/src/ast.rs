@@ -2,15 +2,47 @@ use alc_diagnostic::Spanned;
 
 pub type Ident = String;
 
-#[derive(Clone, Debug)]
+/// The name that stands for the implementing type in the signatures of a trait.
+pub const SELF_TY: &str = "Self";
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Ty {
     I8,
     I16,
     I32,
     I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Bool,
     String,
     Array(Box<Ty>, i32),
-    TyName(Ident),
+    Tuple(Vec<Ty>),
+    /// The type of function values, as in `func(i32, string) bool`.
+    Fn(Vec<Ty>, Box<Ty>),
+    /// A named type, along with its type arguments when it is generic.
+    TyName(Ident, Vec<Ty>),
+}
+
+#[derive(Clone, Debug)]
+pub struct NumberLiteral {
+    pub value: u64,
+    pub suffix: Option<Ty>,
+}
+
+impl From<u64> for NumberLiteral {
+    fn from(value: u64) -> NumberLiteral {
+        NumberLiteral { value, suffix: None }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FloatLiteral {
+    pub value: f64,
+    pub suffix: Option<Ty>,
 }
 
 #[derive(Clone, Debug)]
@@ -19,6 +51,14 @@ pub struct Binding {
     pub ty: Spanned<Ty>,
 }
 
+/// The fields of an enum variant, as declared, instantiated or matched on.
+#[derive(Clone, Debug)]
+pub enum Fields<T> {
+    Unit,
+    Tuple(Vec<Spanned<T>>),
+    Named(Vec<(Spanned<Ident>, Spanned<T>)>),
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum UnopKind {
     Not,
@@ -41,14 +81,24 @@ pub enum BinopKind {
     Xor,
     LShift,
     RShift,
+    LogicalAnd,
+    LogicalOr,
 }
 
 #[derive(Clone, Debug)]
 pub enum Expr {
-    NumberLiteral(i64),
+    NumberLiteral(NumberLiteral),
+    FloatLiteral(FloatLiteral),
+    BoolLiteral(bool),
     ArrayLiteral(Vec<Spanned<Expr>>),
     StringLiteral(String),
+    Tuple(Vec<Spanned<Expr>>),
+    /// A binding followed by the fields read from it, where the fields of a tuple are named by their position.
     Var(Vec<Spanned<Ident>>),
+    Index {
+        target: Spanned<Box<Expr>>,
+        index: Spanned<Box<Expr>>,
+    },
     Unop {
         kind: Spanned<UnopKind>,
         operand: Spanned<Box<Expr>>,
@@ -58,17 +108,30 @@ pub enum Expr {
         left: Spanned<Box<Expr>>,
         right: Spanned<Box<Expr>>,
     },
+    /// Calls the function declared as `target`, or the function value bound to it.
     Call {
         target: Spanned<Ident>,
+        ty_args: Vec<Spanned<Ty>>,
+        args: Vec<Spanned<Expr>>,
+    },
+    Closure(Box<Closure>),
+    /// An `if` or a `match` used as an expression, whose value is the one its branches end with.
+    Branch(Box<Term>),
+    /// Calls a method of the struct or enum that `receiver` evaluates to.
+    MethodCall {
+        receiver: Spanned<Box<Expr>>,
+        method: Spanned<Ident>,
         args: Vec<Spanned<Expr>>,
     },
     Variant {
         enum_name: Spanned<Ident>,
+        ty_args: Vec<Spanned<Ty>>,
         discriminant: Spanned<Ident>,
-        body: Spanned<Box<Expr>>,
+        fields: Fields<Expr>,
     },
     Record {
         struct_name: Spanned<Ident>,
+        ty_args: Vec<Spanned<Ty>>,
         fields: Vec<(Spanned<Ident>, Spanned<Expr>)>,
     },
     Socket {
@@ -119,38 +182,70 @@ pub enum Expr {
         send_flags: Spanned<Box<Expr>>,
         format_string: Spanned<Box<Expr>>,
         http_header: Spanned<Box<Expr>>,
-        call_handler: Spanned<Box<Expr>>,
+        /// The function called for every request, which gives the body of the response.
+        handler: Spanned<Box<Expr>>,
     },
 }
 
+/// A function written where a value is expected, which can use the bindings in scope where it is written. Its
+/// name, if it is given one, is only used to name the function it is compiled to.
+#[derive(Clone, Debug)]
+pub struct Closure {
+    pub name: Option<Spanned<Ident>>,
+    pub params: Vec<Spanned<Binding>>,
+    pub return_ty: Spanned<Ty>,
+    pub body: Spanned<Term>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Pattern {
-    NumberLiteral(i64),
+    NumberLiteral(NumberLiteral),
+    BoolLiteral(bool),
     ArrayLiteral(Vec<Spanned<Expr>>),
     StringLiteral(String),
+    Wildcard,
     Ident(Ident),
+    Tuple(Vec<Spanned<Pattern>>),
     Variant {
         enum_name: Spanned<Ident>,
+        ty_args: Vec<Spanned<Ty>>,
         discriminant: Spanned<Ident>,
-        bound: Spanned<Ident>,
+        fields: Fields<Pattern>,
     },
     Record {
         struct_name: Spanned<Ident>,
-        fields: Vec<(Spanned<Ident>, Spanned<Ident>)>,
+        ty_args: Vec<Spanned<Ty>>,
+        fields: Vec<(Spanned<Ident>, Spanned<Pattern>)>,
     },
+    Or(Vec<Spanned<Pattern>>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Arm {
+    pub pattern: Spanned<Pattern>,
+    pub guard: Option<Spanned<Expr>>,
+    pub body: Box<Spanned<Term>>,
 }
 
 #[derive(Clone, Debug)]
 pub enum Term {
     Let {
         binder: Spanned<Ident>,
+        mutable: bool,
         annotation: Option<Spanned<Ty>>,
         expr: Spanned<Expr>,
         body: Box<Spanned<Term>>,
     },
+    /// Takes apart the value of `expr`, as in `let (a, b) = pair`. The pattern and the rest of the term form the
+    /// only arm of a match on it.
+    LetPattern {
+        annotation: Option<Spanned<Ty>>,
+        expr: Spanned<Expr>,
+        arm: Box<Arm>,
+    },
     Match {
         source: Spanned<Expr>,
-        arms: Vec<(Spanned<Pattern>, Box<Spanned<Term>>)>,
+        arms: Vec<Arm>,
     },
     If {
         source: Spanned<Expr>,
@@ -161,34 +256,108 @@ pub enum Term {
         expr: Spanned<Expr>,
         body: Box<Spanned<Term>>,
     },
+    Assign {
+        target: Spanned<Vec<Spanned<Ident>>>,
+        index: Option<Spanned<Box<Expr>>>,
+        expr: Spanned<Expr>,
+        body: Box<Spanned<Term>>,
+    },
+    While {
+        source: Spanned<Expr>,
+        block: Box<Spanned<Term>>,
+        body: Box<Spanned<Term>>,
+    },
+    Loop {
+        block: Box<Spanned<Term>>,
+        body: Box<Spanned<Term>>,
+    },
+    Break,
+    Continue,
+    /// The end of a block that gives no value, as after the assignment in `{ i = i + 1 }`. A loop body starts its
+    /// next iteration there, and a branch of an `if` or `match` statement goes on to what follows the statement.
+    End,
+    /// Evaluates `expr` for its effects, as in `close(fd)`, and discards its value.
+    Expr {
+        expr: Spanned<Expr>,
+        body: Box<Spanned<Term>>,
+    },
+    /// Returns the value of `expr` from the function, as in `return 0`.
     Return(Expr),
+    /// The value of the term, which the function returns unless the term is a branch of an `if` or a `match`
+    /// expression, which takes the value instead.
+    Value(Expr),
+}
+
+/// The traits a type parameter is bound to, as in `T: Hash + Show`.
+#[derive(Clone, Debug)]
+pub struct Bound {
+    pub ty_param: Spanned<Ident>,
+    pub traits: Vec<Spanned<Ident>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct FnDecl {
     pub name: Spanned<Ident>,
+    pub ty_params: Vec<Spanned<Ident>>,
+    pub bounds: Vec<Bound>,
     pub params: Vec<Spanned<Binding>>,
     pub return_ty: Spanned<Ty>,
     pub body: Spanned<Term>,
 }
 
+#[derive(Clone, Debug)]
+pub struct Variant {
+    pub name: Spanned<Ident>,
+    pub fields: Fields<Ty>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Enum {
     pub name: Spanned<Ident>,
-    pub variants: Vec<Spanned<Binding>>,
+    pub ty_params: Vec<Spanned<Ident>>,
+    pub variants: Vec<Spanned<Variant>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Struct {
     pub name: Spanned<Ident>,
+    pub ty_params: Vec<Spanned<Ident>>,
     pub fields: Vec<Spanned<Binding>>,
 }
 
+/// The signature of a method that every implementation of a trait provides. The receiver and any other use of
+/// `Self` stand for the implementing type.
+#[derive(Clone, Debug)]
+pub struct MethodSig {
+    pub name: Spanned<Ident>,
+    pub params: Vec<Spanned<Binding>>,
+    pub return_ty: Spanned<Ty>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Trait {
+    pub name: Spanned<Ident>,
+    pub methods: Vec<Spanned<MethodSig>>,
+}
+
+/// The methods of a struct or an enum. Each method is a function that takes the receiver, `self`, as its first
+/// parameter and has the type parameters of the block before its own. An `impl Trait for Type` block provides
+/// the methods of the trait.
+#[derive(Clone, Debug)]
+pub struct Impl {
+    pub trait_name: Option<Spanned<Ident>>,
+    pub ty_name: Spanned<Ident>,
+    pub ty_params: Vec<Spanned<Ident>>,
+    pub methods: Vec<Spanned<FnDecl>>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Item {
     Fn(Box<FnDecl>),
     Enum(Enum),
     Struct(Struct),
+    Impl(Impl),
+    Trait(Trait),
 }
 
 #[derive(Debug)]
@@ -1,27 +1,34 @@
 use crate::token::{Kind, Token};
 use alc_diagnostic::{Diagnostic, FileId, Files, Label, Result, Span, Spanned};
-use std::{iter::FusedIterator, str::Chars};
+use std::{collections::VecDeque, iter::FusedIterator, str::Chars};
 
 #[derive(Clone, Debug)]
 pub struct Lexer<'a> {
     file_id: FileId,
     initial_len: usize,
     chars: Chars<'a>,
+    pending: VecDeque<Result<Spanned<Token>>>,
 }
 
 impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Spanned<Token>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.pending.pop_front() {
+            return Some(item);
+        }
         let lo = self.index();
         let token = match self.chars.next()? {
             c if c.is_whitespace() => {
                 self.skip_whitespace();
                 return self.next();
             }
-            c if c.is_ascii_digit() => Token::new(Kind::NumberLiteral, &self.next_literal(c)),
+            c if c.is_ascii_digit() => self.next_literal(c),
             c if c.is_alphabetic() || c == '_' => self.next_ident_or_keyword(c),
-            '"' => self.next_string_literal()?,
+            '"' => match self.next_string_literal(lo) {
+                Ok(token) => token,
+                Err(diagnostic) => return Some(Err(diagnostic)),
+            },
             '!' => match self.nth_char(0) {
                 '=' => {
                     self.chars.next()?;
@@ -38,14 +45,28 @@ impl<'a> Iterator for Lexer<'a> {
                     return self.next();
                 }
                 '*' => {
-                    self.chars.next()?;
-                    while self.chars.next()? != '*' || self.chars.next()? != '/' {}
+                    self.chars.next();
+                    if let Err(diagnostic) = self.skip_block_comment(lo) {
+                        return Some(Err(diagnostic));
+                    }
                     return self.next();
                 }
                 _ => Kind::Div.into(),
             },
-            '&' => Kind::And.into(),
-            '|' => Kind::Or.into(),
+            '&' => match self.nth_char(0) {
+                '&' => {
+                    self.chars.next()?;
+                    Kind::AndAnd.into()
+                }
+                _ => Kind::And.into(),
+            },
+            '|' => match self.nth_char(0) {
+                '|' => {
+                    self.chars.next()?;
+                    Kind::OrOr.into()
+                }
+                _ => Kind::Or.into(),
+            },
             '^' => Kind::Xor.into(),
             '(' => Kind::LParen.into(),
             ')' => Kind::RParen.into(),
@@ -108,7 +129,8 @@ impl<'a> Iterator for Lexer<'a> {
             }
         };
         let hi = self.index();
-        Some(Ok(Span::new(lo, hi).span(token)))
+        self.pending.push_back(Ok(Span::new(lo, hi).span(token)));
+        self.pending.pop_front()
     }
 }
 
@@ -120,6 +142,7 @@ impl<'a> Lexer<'a> {
             file_id,
             initial_len: sess.source(file_id).as_bytes().len(),
             chars: sess.source(file_id).chars(),
+            pending: VecDeque::new(),
         }
     }
 
@@ -144,37 +167,181 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn next_literal(&mut self, first: char) -> String {
+    fn next_literal(&mut self, first: char) -> Token {
         let mut data = String::new();
         data.push(first);
+        let is_decimal = !(first == '0' && matches!(self.nth_char(0), 'x' | 'o' | 'b'));
+        let mut kind = Kind::NumberLiteral;
+        let mut has_exponent = false;
         loop {
             match self.nth_char(0) {
-                c if c.is_ascii_digit() => {
+                '.' if is_decimal && kind == Kind::NumberLiteral && self.nth_char(1).is_ascii_digit() => {
+                    kind = Kind::FloatLiteral;
+                    data.push('.');
+                    self.chars.next();
+                }
+                'e' | 'E'
+                    if is_decimal
+                        && !has_exponent
+                        && (self.nth_char(1).is_ascii_digit()
+                            || (matches!(self.nth_char(1), '+' | '-')
+                                && self.nth_char(2).is_ascii_digit())) =>
+                {
+                    kind = Kind::FloatLiteral;
+                    has_exponent = true;
+                    data.push(self.chars.next().unwrap());
+                    if matches!(self.nth_char(0), '+' | '-') {
+                        data.push(self.chars.next().unwrap());
+                    }
+                }
+                // NOTE radix prefixes, separators and suffixes are validated by the parser
+                c if c.is_ascii_alphanumeric() || c == '_' => {
                     data.push(c);
                     self.chars.next();
                 }
-                _ => break data,
+                _ => break Token::new(kind, &data),
+            }
+        }
+    }
+
+    fn skip_block_comment(&mut self, lo: u32) -> Result<()> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.chars.next() {
+                Some('*') if self.nth_char(0) == '/' => {
+                    self.chars.next();
+                    depth -= 1;
+                }
+                Some('/') if self.nth_char(0) == '*' => {
+                    self.chars.next();
+                    depth += 1;
+                }
+                Some(_) => {}
+                None => {
+                    return Err(Box::from(Diagnostic::new_error(
+                        "unterminated block comment",
+                        Label::new(self.file_id, lo..lo + 2, "block comment starts here"),
+                    )))
+                }
             }
         }
+        Ok(())
     }
 
-    pub fn next_string_literal(&mut self) -> Option<Token> {
+    /// Invalid escapes are queued as diagnostics ahead of the literal so that parsing can go on.
+    pub fn next_string_literal(&mut self, lo: u32) -> Result<Token> {
         let mut data = String::new();
         loop {
-            match self.chars.next()? {
-                '"' => break Some(Token::new(Kind::StringLiteral, &data)),
-                '\\' => match self.nth_char(0) {
-                    'n' => {
-                        data.push('\n');
-                        self.chars.next();
-                    }
-                    c => data.push(c),
+            let escape_lo = self.index();
+            match self.chars.next() {
+                Some('"') => break,
+                Some('\\') => match self.next_escape(escape_lo) {
+                    Ok(Some(c)) => data.push(c),
+                    Ok(None) => continue,
+                    Err(diagnostic) => self.pending.push_back(Err(diagnostic)),
                 },
-                c => {
-                    data.push(c);
+                Some(c) => data.push(c),
+                None => {
+                    return Err(Box::from(Diagnostic::new_error(
+                        "unterminated string literal",
+                        Label::new(self.file_id, lo..lo + 1, "string literal starts here"),
+                    )))
                 }
             }
         }
+        Ok(Token::new(Kind::StringLiteral, &data))
+    }
+
+    /// Reads the escape sequence following a backslash. `Ok(None)` means the input ended.
+    fn next_escape(&mut self, lo: u32) -> Result<Option<char>> {
+        let c = match self.chars.next() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        Ok(Some(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            'x' => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match self.nth_char(0).to_digit(16) {
+                        Some(digit) => {
+                            value = value * 16 + digit;
+                            self.chars.next();
+                        }
+                        None => {
+                            return Err(self.escape_diagnostic(
+                                lo,
+                                "numeric character escape is too short",
+                                "'\\x' must be followed by two hexadecimal digits",
+                            ))
+                        }
+                    }
+                }
+                if value > 0x7f {
+                    return Err(self.escape_diagnostic(
+                        lo,
+                        "out of range hex escape",
+                        "must be a character in the range [\\x00-\\x7f]",
+                    ));
+                }
+                char::from(value as u8)
+            }
+            'u' => {
+                if self.nth_char(0) != '{' {
+                    return Err(self.escape_diagnostic(
+                        lo,
+                        "incorrect unicode escape sequence",
+                        "format of unicode escape sequences is '\\u{...}'",
+                    ));
+                }
+                self.chars.next();
+                let mut value: u32 = 0;
+                let mut digits = 0;
+                while let Some(digit) = self.nth_char(0).to_digit(16) {
+                    value = value.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                    self.chars.next();
+                }
+                if self.nth_char(0) != '}' || digits == 0 || digits > 6 {
+                    return Err(self.escape_diagnostic(
+                        lo,
+                        "incorrect unicode escape sequence",
+                        "format of unicode escape sequences is '\\u{...}' with 1 to 6 hexadecimal digits",
+                    ));
+                }
+                self.chars.next();
+                match char::from_u32(value) {
+                    Some(c) => c,
+                    None => {
+                        return Err(self.escape_diagnostic(
+                            lo,
+                            "invalid unicode character escape",
+                            format!("'{:x}' is not a valid unicode scalar value", value),
+                        ))
+                    }
+                }
+            }
+            c => {
+                return Err(self.escape_diagnostic(
+                    lo,
+                    "unknown character escape",
+                    format!("'\\{}' is not a valid escape", c),
+                ))
+            }
+        }))
+    }
+
+    fn escape_diagnostic(&self, lo: u32, message: &str, label: impl Into<String>) -> Box<Diagnostic> {
+        Box::from(Diagnostic::new_error(
+            message,
+            Label::new(self.file_id, lo..self.index(), label),
+        ))
     }
 
     pub fn next_ident_or_keyword(&mut self, first: char) -> Token {
@@ -191,16 +358,34 @@ impl<'a> Lexer<'a> {
         }
         match data.as_str() {
             "let" => Kind::Let.into(),
+            "mut" => Kind::Mut.into(),
             "match" => Kind::Match.into(),
             "if" => Kind::If.into(),
             "else" => Kind::Else.into(),
+            "while" => Kind::While.into(),
+            "loop" => Kind::Loop.into(),
+            "break" => Kind::Break.into(),
+            "continue" => Kind::Continue.into(),
+            "return" => Kind::Return.into(),
             "func" => Kind::Func.into(),
             "struct" => Kind::Struct.into(),
             "enum" => Kind::Enum.into(),
+            "impl" => Kind::Impl.into(),
+            "trait" => Kind::Trait.into(),
+            "for" => Kind::For.into(),
+            "true" => Kind::True.into(),
+            "false" => Kind::False.into(),
             "i8" => Kind::I8Ty.into(),
             "i16" => Kind::I16Ty.into(),
             "i32" => Kind::I32Ty.into(),
             "i64" => Kind::I64Ty.into(),
+            "u8" => Kind::U8Ty.into(),
+            "u16" => Kind::U16Ty.into(),
+            "u32" => Kind::U32Ty.into(),
+            "u64" => Kind::U64Ty.into(),
+            "f32" => Kind::F32Ty.into(),
+            "f64" => Kind::F64Ty.into(),
+            "bool" => Kind::BoolTy.into(),
             "string" => Kind::StringTy.into(),
             "env" if self.nth_char(0) == '!' => {
                 self.chars.next();
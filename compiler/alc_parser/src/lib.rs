@@ -5,14 +5,16 @@ mod token;
 
 use crate::parser::Parser;
 use alc_command_option::CommandOptions;
-use alc_diagnostic::{FileId, Files, Result, Span, Spanned};
+use alc_diagnostic::{FileId, Files, MultiResult, Span, Spanned};
 use log::debug;
 
-pub fn parse(command_options: &CommandOptions, files: &Files, file_id: FileId) -> Result<ast::Ast> {
-    let parser = Parser::new(command_options, files, file_id)?;
+pub fn parse(command_options: &CommandOptions, files: &Files, file_id: FileId) -> MultiResult<ast::Ast> {
+    let mut parser = Parser::new(command_options, files, file_id).map_err(|diagnostic| vec![*diagnostic])?;
     let mut items = reserved_items();
-    for item in parser {
-        items.push(item?);
+    items.extend(parser.by_ref());
+    let diagnostics = parser.into_diagnostics();
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
     }
     let ast = ast::Ast { items };
     debug!("{:#?}", ast);
@@ -23,30 +25,32 @@ fn reserved_items() -> Vec<Spanned<ast::Item>> {
     vec![
         spanned(ast::Item::Struct(ast::Struct {
             name: spanned(String::from("SockAddrIn")),
+            ty_params: vec![],
             fields: vec![
                 spanned(ast::Binding {
                     binder: spanned(String::from("family")),
-                    ty: spanned(ast::Ty::I16),
+                    ty: spanned(ast::Ty::U16),
                 }),
                 spanned(ast::Binding {
                     binder: spanned(String::from("port")),
-                    ty: spanned(ast::Ty::I16),
+                    ty: spanned(ast::Ty::U16),
                 }),
                 spanned(ast::Binding {
                     binder: spanned(String::from("addr")),
-                    ty: spanned(ast::Ty::TyName(String::from("InAddr"))),
+                    ty: spanned(ast::Ty::TyName(String::from("InAddr"), vec![])),
                 }),
                 spanned(ast::Binding {
                     binder: spanned(String::from("buf")),
-                    ty: spanned(ast::Ty::Array(Box::new(ast::Ty::I8), 8)),
+                    ty: spanned(ast::Ty::Array(Box::new(ast::Ty::U8), 8)),
                 }),
             ],
         })),
         spanned(ast::Item::Struct(ast::Struct {
             name: spanned(String::from("InAddr")),
+            ty_params: vec![],
             fields: vec![spanned(ast::Binding {
                 binder: spanned(String::from("s_addr")),
-                ty: spanned(ast::Ty::I32),
+                ty: spanned(ast::Ty::U32),
             })],
         })),
     ]
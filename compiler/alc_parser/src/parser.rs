@@ -14,6 +14,12 @@ pub enum Restriction {
     NoStructLiteral,
 }
 
+/// What a parenthesised list holds: a single element, which the parentheses only group, or a tuple.
+enum Parenthesised<T> {
+    Group(T),
+    Tuple(Vec<T>),
+}
+
 #[derive(Clone, Debug)]
 pub struct Parser<'a> {
     file_id: FileId,
@@ -22,24 +28,26 @@ pub struct Parser<'a> {
     tokens: Lexer<'a>,
     #[allow(unused)]
     command_options: &'a CommandOptions,
-    child_items: Vec<Spanned<ast::Item>>,
-    child_item_count: usize,
+    depth: usize,
+    diagnostics: Vec<Diagnostic>,
+    truncated: bool,
 }
 
 impl<'a> Iterator for Parser<'a> {
-    type Item = Result<Spanned<ast::Item>>;
+    type Item = Spanned<ast::Item>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current.is_none() {
-            // TODO: リファクタ
-            if let Some(child_item) = self.child_items.get(self.child_item_count) {
-                self.child_item_count += 1;
-                return Some(Ok(child_item.clone()));
+        while self.current.is_some() {
+            self.depth = 0;
+            match self.next_item() {
+                Ok(item) => return Some(item),
+                Err(diagnostic) => {
+                    self.report(*diagnostic);
+                    self.recover(0, &[]);
+                }
             }
-            None
-        } else {
-            Some(self.next_item())
         }
+        None
     }
 }
 
@@ -117,27 +125,94 @@ impl<'a> Parser<'a> {
         (LAngle, Less, 10),
         (RAngle, Greater, 10),
         (Leq, Leq, 10),
-        (Geq, Geq, 10)
+        (Geq, Geq, 10),
+        (AndAnd, LogicalAnd, 5),
+        (OrOr, LogicalOr, 4)
     ];
 
     pub fn new(command_options: &'a CommandOptions, files: &'a Files, file_id: FileId) -> Result<Parser<'a>> {
-        let mut lexer = Lexer::new(files, file_id);
+        let lexer = Lexer::new(files, file_id);
         debug!("{:#?}", lexer);
-        if let Some(first) = lexer.next() {
-            Ok(Parser {
-                file_id,
-                current: Some(first?),
-                last_span: Span::dummy(),
-                tokens: lexer,
-                command_options,
-                child_items: Vec::new(),
-                child_item_count: 0,
-            })
-        } else {
-            Err(Box::from(Diagnostic::new_bug(
+        let mut parser = Parser {
+            file_id,
+            current: None,
+            last_span: Span::dummy(),
+            tokens: lexer,
+            command_options,
+            depth: 0,
+            diagnostics: Vec::new(),
+            truncated: false,
+        };
+        parser.current = parser.next_token();
+        if parser.current.is_none() && parser.diagnostics.is_empty() {
+            return Err(Box::from(Diagnostic::new_bug(
                 "cannot construct parser from empty token stream",
                 Label::new(file_id, Span::dummy(), "this file appears to be empty"),
-            )))
+            )));
+        }
+        Ok(parser)
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        debug!("recovering from {:?}", diagnostic);
+        // NOTE errors at the end of a token stream cut short by the lexer only restate its diagnostic
+        if self.current.is_none() && self.truncated {
+            return;
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn next_token(&mut self) -> Option<Spanned<Token>> {
+        let mut truncated = false;
+        loop {
+            match self.tokens.next() {
+                Some(Ok(token)) => return Some(token),
+                Some(Err(diagnostic)) => {
+                    self.diagnostics.push(*diagnostic);
+                    truncated = true;
+                }
+                None => {
+                    self.truncated = truncated;
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn bump(&mut self) -> Option<Spanned<Token>> {
+        let mut token = self.next_token();
+        if let Some(ref current) = self.current {
+            match current.kind() {
+                Kind::LParen | Kind::LCurl | Kind::LSquare => self.depth += 1,
+                Kind::RParen | Kind::RCurl | Kind::RSquare => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+            self.last_span = current.span();
+        }
+        mem::swap(&mut self.current, &mut token);
+        token
+    }
+
+    #[inline]
+    fn next_is_item(&self) -> bool {
+        self.next_is(Kind::Func)
+            || self.next_is(Kind::Struct)
+            || self.next_is(Kind::Enum)
+            || self.next_is(Kind::Impl)
+            || self.next_is(Kind::Trait)
+    }
+
+    /// Skips tokens until one of `sync` is found at nesting `depth` or the next item begins.
+    fn recover(&mut self, depth: usize, sync: &[Kind]) {
+        while let Some(kind) = self.current.as_ref().map(|current| current.kind()) {
+            if self.depth < depth || (self.depth == depth && sync.contains(&kind)) || self.next_is_item() {
+                break;
+            }
+            self.bump();
         }
     }
 
@@ -161,12 +236,7 @@ impl<'a> Parser<'a> {
 
     fn eat(&mut self, kind: Kind) -> Result<Spanned<Token>> {
         match &self.current {
-            Some(ref current) if current.is(kind) => {
-                let mut token = self.tokens.next().map_or(Ok(None), |token| token.map(Some))?;
-                self.last_span = current.span();
-                mem::swap(&mut self.current, &mut token);
-                Ok(token.unwrap())
-            }
+            Some(ref current) if current.is(kind) => Ok(self.bump().unwrap()),
             Some(ref current) => Err(Box::from(Diagnostic::new_error(
                 "token type mismatch",
                 Label::new(
@@ -198,9 +268,19 @@ impl<'a> Parser<'a> {
         elem: impl Fn(&mut Parser) -> Result<T>,
     ) -> Result<Spanned<Vec<T>>> {
         let span = self.eat(head)?.span();
+        let depth = self.depth;
         let mut elems = vec![];
         while !self.next_is(tail) {
-            elems.push(elem(self)?);
+            match elem(self) {
+                Ok(parsed) => elems.push(parsed),
+                Err(diagnostic) => {
+                    self.recover(depth, &[Kind::Comma, tail]);
+                    if self.depth != depth || !(self.next_is(Kind::Comma) || self.next_is(tail)) {
+                        return Err(diagnostic);
+                    }
+                    self.report(*diagnostic);
+                }
+            }
             if self.next_is(Kind::Comma) {
                 self.eat(Kind::Comma)?;
             } else {
@@ -211,6 +291,58 @@ impl<'a> Parser<'a> {
         Ok(span.span(elems))
     }
 
+    /// Parses what follows the name of a variant: nothing for a unit variant, a parenthesised list for a tuple
+    /// variant, or a braced list of `name: elem` for a variant with named fields.
+    fn next_variant_fields<T>(
+        &mut self,
+        span: Span,
+        res: Restriction,
+        elem: impl Fn(&mut Parser) -> Result<Spanned<T>>,
+    ) -> Result<Spanned<ast::Fields<T>>> {
+        if self.next_is(Kind::LParen) {
+            let fields = self.next_comma_group(Kind::LParen, Kind::RParen, &elem)?;
+            Ok(span
+                .merge(fields.span())
+                .span(ast::Fields::Tuple(fields.into_raw())))
+        } else if self.next_is(Kind::LCurl) && res != Restriction::NoStructLiteral {
+            let fields = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| {
+                let field_name = this.next_ident()?;
+                this.eat(Kind::Colon)?;
+                Ok((field_name, elem(this)?))
+            })?;
+            Ok(span
+                .merge(fields.span())
+                .span(ast::Fields::Named(fields.into_raw())))
+        } else {
+            Ok(span.span(ast::Fields::Unit))
+        }
+    }
+
+    /// Parses a parenthesised list, which is a tuple unless it holds a single element without a trailing comma.
+    fn next_parenthesised<T>(
+        &mut self,
+        elem: impl Fn(&mut Parser) -> Result<T>,
+    ) -> Result<Spanned<Parenthesised<T>>> {
+        let span = self.eat(Kind::LParen)?.span();
+        let mut elems = vec![];
+        let mut trailing_comma = false;
+        while !self.next_is(Kind::RParen) {
+            elems.push(elem(self)?);
+            trailing_comma = self.next_is(Kind::Comma);
+            if trailing_comma {
+                self.eat(Kind::Comma)?;
+            } else {
+                break;
+            }
+        }
+        let span = span.merge(self.eat(Kind::RParen)?.span());
+        Ok(span.span(if elems.len() == 1 && !trailing_comma {
+            Parenthesised::Group(elems.pop().unwrap())
+        } else {
+            Parenthesised::Tuple(elems)
+        }))
+    }
+
     fn next_array_elements(&mut self) -> Result<Spanned<Vec<Spanned<ast::Expr>>>> {
         let span = self.eat(Kind::LSquare)?.span();
         let mut elems = vec![];
@@ -223,8 +355,8 @@ impl<'a> Parser<'a> {
         elems.push(first_val.clone());
         if self.next_is(Kind::Semi) {
             self.eat(Kind::Semi)?;
-            let count = self.next_number_literal()?;
-            for _ in 1..count.into_raw() {
+            let count = self.next_array_len()?;
+            for _ in 1..count {
                 elems.push(first_val.clone());
             }
             return Ok(span.merge(self.eat(Kind::RSquare)?.span()).span(elems));
@@ -247,22 +379,188 @@ impl<'a> Parser<'a> {
         Ok(token.span().span(token.value().unwrap().to_string()))
     }
 
-    fn unpack_number_literal(&self, data: &str, span: Span) -> Result<i64> {
-        data.parse::<i64>().map_err(|err| {
-            Box::from(Diagnostic::new_bug(
-                "failed to parse u64 literal",
-                Label::new(self.file_id, span, err.to_string()),
-            ))
-        })
+    /// Parses the field after a `.`, which is either a name or the position of a field of a tuple. The lexer reads
+    /// the positions in `pair.0.1` as the float literal `0.1`, which is split up into both of them again.
+    fn next_fields(&mut self) -> Result<Vec<Spanned<ast::Ident>>> {
+        let kind = if self.next_is(Kind::NumberLiteral) {
+            Kind::NumberLiteral
+        } else if self.next_is(Kind::FloatLiteral) {
+            Kind::FloatLiteral
+        } else {
+            return Ok(vec![self.next_ident()?]);
+        };
+        let token = self.eat(kind)?;
+        let (data, span) = (token.value().unwrap(), token.span());
+        let is_position =
+            |position: &str| !position.is_empty() && position.bytes().all(|b| b.is_ascii_digit());
+        match data.split_once('.') {
+            None if is_position(data) => Ok(vec![span.span(data.to_owned())]),
+            Some((outer, inner)) if is_position(outer) && is_position(inner) => {
+                let split = span.start().0 + outer.len() as u32;
+                Ok(vec![
+                    Span::new(span.start(), split).span(outer.to_owned()),
+                    Span::new(split + 1, span.end()).span(inner.to_owned()),
+                ])
+            }
+            _ => Err(Box::from(Diagnostic::new_error(
+                "invalid field",
+                Label::new(
+                    self.file_id,
+                    span,
+                    "expected the name of a field or the position of a field of a tuple",
+                ),
+            ))),
+        }
+    }
+
+    fn number_literal_diagnostic(&self, message: &str, span: Span, label: String) -> Box<Diagnostic> {
+        Box::from(Diagnostic::new_error(
+            message,
+            Label::new(self.file_id, span, label),
+        ))
     }
 
-    fn next_number_literal(&mut self) -> Result<Spanned<i64>> {
+    fn unpack_number_literal(&self, data: &str, span: Span) -> Result<ast::NumberLiteral> {
+        let (radix, digits) = match data.get(..2) {
+            Some("0x") => (16, &data[2..]),
+            Some("0o") => (8, &data[2..]),
+            Some("0b") => (2, &data[2..]),
+            _ => (10, data),
+        };
+        let end = digits
+            .find(|c: char| !(c.is_digit(radix.max(10)) || c == '_'))
+            .unwrap_or(digits.len());
+        let suffix = match &digits[end..] {
+            "" => None,
+            "i8" => Some(ast::Ty::I8),
+            "i16" => Some(ast::Ty::I16),
+            "i32" => Some(ast::Ty::I32),
+            "i64" => Some(ast::Ty::I64),
+            "u8" => Some(ast::Ty::U8),
+            "u16" => Some(ast::Ty::U16),
+            "u32" => Some(ast::Ty::U32),
+            "u64" => Some(ast::Ty::U64),
+            "f32" => Some(ast::Ty::F32),
+            "f64" => Some(ast::Ty::F64),
+            suffix => {
+                return Err(self.number_literal_diagnostic(
+                    "invalid suffix for number literal",
+                    span,
+                    format!("invalid suffix '{}'", suffix),
+                ))
+            }
+        };
+        let mut value: u64 = 0;
+        let mut digit_count = 0;
+        for c in digits[..end].chars().filter(|c| *c != '_') {
+            let digit = c.to_digit(radix).ok_or_else(|| {
+                self.number_literal_diagnostic(
+                    "invalid digit in number literal",
+                    span,
+                    format!("invalid digit '{}' for a base {} literal", c, radix),
+                )
+            })?;
+            value = value
+                .checked_mul(radix as u64)
+                .and_then(|value| value.checked_add(digit as u64))
+                .ok_or_else(|| {
+                    self.number_literal_diagnostic(
+                        "number literal is too large",
+                        span,
+                        "value exceeds the range of any integer type".to_owned(),
+                    )
+                })?;
+            digit_count += 1;
+        }
+        if digit_count == 0 {
+            return Err(self.number_literal_diagnostic(
+                "no valid digits found for number",
+                span,
+                "expected at least one digit".to_owned(),
+            ));
+        }
+        Ok(ast::NumberLiteral { value, suffix })
+    }
+
+    fn unpack_float_literal(&self, data: &str, span: Span) -> Result<ast::FloatLiteral> {
+        let (digits, suffix) = match data
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '_' | 'e' | 'E' | '+' | '-')))
+        {
+            Some(index) => (&data[..index], &data[index..]),
+            None => (data, ""),
+        };
+        let suffix = match suffix {
+            "" => None,
+            "f32" => Some(ast::Ty::F32),
+            "f64" => Some(ast::Ty::F64),
+            suffix => {
+                return Err(self.number_literal_diagnostic(
+                    "invalid suffix for float literal",
+                    span,
+                    format!("invalid suffix '{}'", suffix),
+                ))
+            }
+        };
+        let value = digits.replace('_', "").parse::<f64>().map_err(|_| {
+            self.number_literal_diagnostic(
+                "invalid float literal",
+                span,
+                "expected digits, a decimal point and an optional exponent".to_owned(),
+            )
+        })?;
+        if value.is_infinite() {
+            return Err(self.number_literal_diagnostic(
+                "number literal is too large",
+                span,
+                "value exceeds the range of any float type".to_owned(),
+            ));
+        }
+        Ok(ast::FloatLiteral { value, suffix })
+    }
+
+    fn next_float_literal(&mut self) -> Result<Spanned<ast::FloatLiteral>> {
+        let token = self.eat(Kind::FloatLiteral)?;
+        Ok(token
+            .span()
+            .span(self.unpack_float_literal(token.value().unwrap(), token.span())?))
+    }
+
+    fn next_number_literal(&mut self) -> Result<Spanned<ast::NumberLiteral>> {
         let token = self.eat(Kind::NumberLiteral)?;
         Ok(token
             .span()
             .span(self.unpack_number_literal(token.value().unwrap(), token.span())?))
     }
 
+    fn next_array_len(&mut self) -> Result<i32> {
+        let len = self.next_number_literal()?;
+        i32::try_from(len.value).map_err(|_| {
+            Box::from(
+                Diagnostic::new_error(
+                    "array length out of range",
+                    Label::new(
+                        self.file_id,
+                        len.span(),
+                        format!("'{}' does not fit into the length of an array", len.value),
+                    ),
+                )
+                .with_notes(vec![format!("the largest array length is {}", i32::MAX)]),
+            )
+        })
+    }
+
+    fn next_is_bool_literal(&self) -> bool {
+        self.next_is(Kind::True) || self.next_is(Kind::False)
+    }
+
+    fn next_bool_literal(&mut self) -> Result<Spanned<bool>> {
+        if self.next_is(Kind::True) {
+            Ok(self.eat(Kind::True)?.span().span(true))
+        } else {
+            Ok(self.eat(Kind::False)?.span().span(false))
+        }
+    }
+
     fn next_string_literal(&mut self) -> Result<Spanned<String>> {
         let token = self.eat(Kind::StringLiteral)?;
         Ok(token.span().span(token.value().unwrap().to_string()))
@@ -277,19 +575,131 @@ impl<'a> Parser<'a> {
             Ok(self.eat(Kind::I32Ty)?.span().span(ast::Ty::I32))
         } else if self.next_is(Kind::I64Ty) {
             Ok(self.eat(Kind::I64Ty)?.span().span(ast::Ty::I64))
+        } else if self.next_is(Kind::U8Ty) {
+            Ok(self.eat(Kind::U8Ty)?.span().span(ast::Ty::U8))
+        } else if self.next_is(Kind::U16Ty) {
+            Ok(self.eat(Kind::U16Ty)?.span().span(ast::Ty::U16))
+        } else if self.next_is(Kind::U32Ty) {
+            Ok(self.eat(Kind::U32Ty)?.span().span(ast::Ty::U32))
+        } else if self.next_is(Kind::U64Ty) {
+            Ok(self.eat(Kind::U64Ty)?.span().span(ast::Ty::U64))
+        } else if self.next_is(Kind::F32Ty) {
+            Ok(self.eat(Kind::F32Ty)?.span().span(ast::Ty::F32))
+        } else if self.next_is(Kind::F64Ty) {
+            Ok(self.eat(Kind::F64Ty)?.span().span(ast::Ty::F64))
+        } else if self.next_is(Kind::BoolTy) {
+            Ok(self.eat(Kind::BoolTy)?.span().span(ast::Ty::Bool))
         } else if self.next_is(Kind::StringTy) {
             Ok(self.eat(Kind::StringTy)?.span().span(ast::Ty::String))
+        } else if self.next_is(Kind::LParen) {
+            let tys = self.next_parenthesised(|this| this.next_ty())?;
+            let span = tys.span();
+            Ok(match tys.into_raw() {
+                Parenthesised::Group(ty) => ty,
+                Parenthesised::Tuple(tys) => {
+                    span.span(ast::Ty::Tuple(tys.into_iter().map(Spanned::into_raw).collect()))
+                }
+            })
+        } else if self.next_is(Kind::Func) {
+            let span = self.eat(Kind::Func)?.span();
+            let param_tys = self.next_comma_group(Kind::LParen, Kind::RParen, |this| this.next_ty())?;
+            let return_ty = self.next_ty()?;
+            Ok(span.merge(return_ty.span()).span(ast::Ty::Fn(
+                param_tys.into_raw().into_iter().map(Spanned::into_raw).collect(),
+                Box::new(return_ty.into_raw()),
+            )))
         } else if self.next_is(Kind::LSquare) {
             let span = self.eat(Kind::LSquare)?.span();
             let ty = self.next_ty()?;
             self.eat(Kind::Semi)?;
-            let size = self.next_number_literal()?;
+            let size = self.next_array_len()?;
             let span = span.merge(self.eat(Kind::RSquare)?.span());
-            Ok(span.span(ast::Ty::Array(Box::from(ty.into_raw()), size.into_raw() as i32)))
+            Ok(span.span(ast::Ty::Array(Box::from(ty.into_raw()), size)))
         } else {
             let ident = self.next_ident()?;
-            Ok(ident.span().span(ast::Ty::TyName(ident.into_raw())))
+            let ty_args = self.next_ty_args()?;
+            Ok(ident.span().merge(ty_args.span()).span(ast::Ty::TyName(
+                ident.into_raw(),
+                ty_args.into_raw().into_iter().map(Spanned::into_raw).collect(),
+            )))
+        }
+    }
+
+    /// Parses the type arguments following a generic name, as in `Result<i32, string>`, if there are any.
+    fn next_ty_args(&mut self) -> Result<Spanned<Vec<Spanned<ast::Ty>>>> {
+        if !self.next_is(Kind::LAngle) {
+            return Ok(self.last_span.clip().span(vec![]));
+        }
+        let span = self.eat(Kind::LAngle)?.span();
+        let mut ty_args = vec![self.next_ty()?];
+        while self.next_is(Kind::Comma) {
+            self.eat(Kind::Comma)?;
+            ty_args.push(self.next_ty()?);
+        }
+        let span = span.merge(self.eat_closing_angle()?);
+        Ok(span.span(ty_args))
+    }
+
+    /// Eats the `>` closing a list of type arguments, splitting it off a `>>` that closes nested lists.
+    fn eat_closing_angle(&mut self) -> Result<Span> {
+        if self.next_is(Kind::RShift) {
+            let span = self.current.as_ref().unwrap().span();
+            let (first, second) = (
+                Span::new(span.start(), span.start().0 + 1),
+                Span::new(span.start().0 + 1, span.end()),
+            );
+            self.current = Some(second.span(Token::from(Kind::RAngle)));
+            self.last_span = first;
+            Ok(first)
+        } else {
+            Ok(self.eat(Kind::RAngle)?.span())
+        }
+    }
+
+    /// Parses the type parameters of a generic item, as in `enum Option<T>`, if there are any.
+    fn next_ty_params(&mut self) -> Result<Vec<Spanned<ast::Ident>>> {
+        if self.next_is(Kind::LAngle) {
+            Ok(self
+                .next_comma_group(Kind::LAngle, Kind::RAngle, |this| this.next_ident())?
+                .into_raw())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Parses the type parameters of a generic function along with the traits they are bound to, as in
+    /// `func hash_all<T: Hash + Eq>`.
+    fn next_bounded_ty_params(&mut self) -> Result<(Vec<Spanned<ast::Ident>>, Vec<ast::Bound>)> {
+        if !self.next_is(Kind::LAngle) {
+            return Ok((vec![], vec![]));
+        }
+        let params = self
+            .next_comma_group(Kind::LAngle, Kind::RAngle, |this| {
+                let ty_param = this.next_ident()?;
+                let mut traits = vec![];
+                if this.next_is(Kind::Colon) {
+                    this.eat(Kind::Colon)?;
+                    traits.push(this.next_ident()?);
+                    while this.next_is(Kind::Plus) {
+                        this.eat(Kind::Plus)?;
+                        traits.push(this.next_ident()?);
+                    }
+                }
+                Ok((ty_param, traits))
+            })?
+            .into_raw();
+        let mut ty_params = vec![];
+        let mut bounds = vec![];
+        for (ty_param, traits) in params {
+            if !traits.is_empty() {
+                bounds.push(ast::Bound {
+                    ty_param: ty_param.clone(),
+                    traits,
+                });
+            }
+            ty_params.push(ty_param);
         }
+        Ok((ty_params, bounds))
     }
 
     fn next_ident_expr(&mut self, res: Restriction) -> Result<Spanned<ast::Expr>> {
@@ -299,46 +709,116 @@ impl<'a> Parser<'a> {
             let span = ident.span().merge(args.span());
             Ok(span.span(ast::Expr::Call {
                 target: ident,
+                ty_args: vec![],
                 args: args.into_raw(),
             }))
         } else if self.next_is(Kind::Separator) {
             self.eat(Kind::Separator)?;
+            let ty_args = if self.next_is(Kind::LAngle) {
+                let ty_args = self.next_ty_args()?.into_raw();
+                if self.next_is(Kind::LParen) {
+                    let args = self.next_comma_group(Kind::LParen, Kind::RParen, |this| this.next_expr())?;
+                    let span = ident.span().merge(args.span());
+                    return Ok(span.span(ast::Expr::Call {
+                        target: ident,
+                        ty_args,
+                        args: args.into_raw(),
+                    }));
+                } else if self.next_is(Kind::LCurl) && res != Restriction::NoStructLiteral {
+                    return self.next_record_expr(ident, ty_args);
+                }
+                self.eat(Kind::Separator)?;
+                ty_args
+            } else {
+                vec![]
+            };
             let discriminant = self.next_ident()?;
-            self.eat(Kind::LParen)?;
-            let body = self.next_expr()?;
-            let span = ident.span().merge(self.eat(Kind::RParen)?.span());
-            Ok(span.span(ast::Expr::Variant {
+            let fields = self.next_variant_fields(ident.span().merge(discriminant.span()), res, |this| {
+                this.next_expr()
+            })?;
+            Ok(fields.span().span(ast::Expr::Variant {
                 enum_name: ident,
+                ty_args,
                 discriminant,
-                body: body.boxed(),
-            }))
-        } else if self.next_is(Kind::LCurl) && res != Restriction::NoStructLiteral {
-            let fields = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| {
-                let field_name = this.next_ident()?;
-                this.eat(Kind::Colon)?;
-                Ok((field_name, this.next_expr()?))
-            })?;
-            let span = ident.span().merge(fields.span());
-            Ok(span.span(ast::Expr::Record {
-                struct_name: ident,
                 fields: fields.into_raw(),
             }))
+        } else if self.next_is(Kind::LCurl) && res != Restriction::NoStructLiteral {
+            self.next_record_expr(ident, vec![])
         } else if self.next_is(Kind::Dot) {
             let mut vars = vec![ident];
             while self.next_is(Kind::Dot) {
                 self.eat(Kind::Dot)?;
-                let field = self.next_ident()?;
-                vars.push(field);
+                vars.extend(self.next_fields()?);
+                if self.next_is(Kind::LParen) {
+                    let method = vars.pop().unwrap();
+                    let span = vars.first().unwrap().span().merge(vars.last().unwrap().span());
+                    let receiver = self.next_method_call(span.span(ast::Expr::Var(vars)), method)?;
+                    return self.next_index_expr(receiver);
+                }
             }
             let span = vars.first().unwrap().span().merge(vars.last().unwrap().span());
-            Ok(span.span(ast::Expr::Var(vars)))
+            self.next_index_expr(span.span(ast::Expr::Var(vars)))
         } else {
-            Ok(ident
+            let var = ident
                 .span()
-                .span(ast::Expr::Var(vec![ident.span().span(ident.into_raw())])))
+                .span(ast::Expr::Var(vec![ident.span().span(ident.into_raw())]));
+            self.next_index_expr(var)
+        }
+    }
+
+    /// Parses the arguments of a call to `method` on `receiver`, and any calls chained onto its result.
+    fn next_method_call(
+        &mut self,
+        mut receiver: Spanned<ast::Expr>,
+        mut method: Spanned<ast::Ident>,
+    ) -> Result<Spanned<ast::Expr>> {
+        loop {
+            let args = self.next_comma_group(Kind::LParen, Kind::RParen, |this| this.next_expr())?;
+            let span = receiver.span().merge(args.span());
+            receiver = span.span(ast::Expr::MethodCall {
+                receiver: receiver.boxed(),
+                method,
+                args: args.into_raw(),
+            });
+            if !self.next_is(Kind::Dot) {
+                return Ok(receiver);
+            }
+            self.eat(Kind::Dot)?;
+            method = self.next_ident()?;
         }
     }
 
+    fn next_record_expr(
+        &mut self,
+        struct_name: Spanned<ast::Ident>,
+        ty_args: Vec<Spanned<ast::Ty>>,
+    ) -> Result<Spanned<ast::Expr>> {
+        let fields = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| {
+            let field_name = this.next_ident()?;
+            this.eat(Kind::Colon)?;
+            Ok((field_name, this.next_expr()?))
+        })?;
+        let span = struct_name.span().merge(fields.span());
+        Ok(span.span(ast::Expr::Record {
+            struct_name,
+            ty_args,
+            fields: fields.into_raw(),
+        }))
+    }
+
+    fn next_index_expr(&mut self, mut target: Spanned<ast::Expr>) -> Result<Spanned<ast::Expr>> {
+        while self.next_is(Kind::LSquare) {
+            self.eat(Kind::LSquare)?;
+            let index = self.next_expr()?;
+            let span = target.span().merge(self.eat(Kind::RSquare)?.span());
+            target = span.span(ast::Expr::Index {
+                target: target.boxed(),
+                index: index.boxed(),
+            });
+        }
+        Ok(target)
+    }
+
     fn next_primary(&mut self, res: Restriction) -> Result<Spanned<ast::Expr>> {
         if self.next_is(Kind::Env) {
             let span = self.eat(Kind::Env)?.span();
@@ -359,17 +839,32 @@ impl<'a> Parser<'a> {
         } else if self.next_is(Kind::NumberLiteral) {
             let literal = self.next_number_literal()?;
             Ok(literal.span().span(ast::Expr::NumberLiteral(literal.into_raw())))
+        } else if self.next_is(Kind::FloatLiteral) {
+            let literal = self.next_float_literal()?;
+            Ok(literal.span().span(ast::Expr::FloatLiteral(literal.into_raw())))
+        } else if self.next_is_bool_literal() {
+            let literal = self.next_bool_literal()?;
+            Ok(literal.span().span(ast::Expr::BoolLiteral(literal.into_raw())))
         } else if self.next_is(Kind::StringLiteral) {
             let literal = self.next_string_literal()?;
             Ok(literal.span().span(ast::Expr::StringLiteral(literal.into_raw())))
         } else if self.next_is(Kind::LParen) {
-            self.eat(Kind::LParen)?;
-            let expr = self.next_expr()?;
-            self.eat(Kind::RParen)?;
-            Ok(expr)
+            let exprs = self.next_parenthesised(|this| this.next_expr())?;
+            let span = exprs.span();
+            Ok(match exprs.into_raw() {
+                Parenthesised::Group(expr) => expr,
+                Parenthesised::Tuple(exprs) => span.span(ast::Expr::Tuple(exprs)),
+            })
         } else if self.next_is(Kind::LSquare) {
             let elements = self.next_array_elements()?;
             Ok(elements.span().span(ast::Expr::ArrayLiteral(elements.into_raw())))
+        } else if self.next_is(Kind::If) || self.next_is(Kind::Match) {
+            let term = if self.next_is(Kind::If) {
+                self.next_if_term()?
+            } else {
+                self.next_match_term()?
+            };
+            Ok(term.span().span(ast::Expr::Branch(Box::new(term.into_raw()))))
         } else if self.next_is(Kind::Socket) {
             let span = self.eat(Kind::Socket)?.span();
             self.eat(Kind::LParen)?;
@@ -467,43 +962,28 @@ impl<'a> Parser<'a> {
             self.eat(Kind::LParen)?;
             let port = self.next_expr()?;
             self.eat(Kind::Comma)?;
-            let handler = self.next_fn_item()?;
+            let handler = self.next_expr()?;
             self.eat(Kind::RParen)?;
-            let call_handler = if let ast::Item::Fn(fn_decl) = handler.clone().into_raw() {
-                span.span(ast::Expr::Call {
-                    target: fn_decl.name,
-                    args: vec![],
-                })
-            } else {
-                Err(Box::from(Diagnostic::new_error(
-                    "token type mismatch",
-                    Label::new(
-                        self.file_id,
-                        span,
-                        // TODO: リファクタ
-                        "TODO".to_string(),
-                    ),
-                )))?
-            };
-            self.child_items.push(handler);
-            let domain = span.span(ast::Expr::NumberLiteral(2));
-            let ty = span.span(ast::Expr::NumberLiteral(1));
-            let protocol = span.span(ast::Expr::NumberLiteral(0));
+            let domain = span.span(ast::Expr::NumberLiteral(2.into()));
+            let ty = span.span(ast::Expr::NumberLiteral(1.into()));
+            let protocol = span.span(ast::Expr::NumberLiteral(0.into()));
             let address = span.span(ast::Expr::Record {
                 struct_name: span.span(String::from("SockAddrIn")),
+                ty_args: vec![],
                 fields: vec![
                     (
                         span.span(String::from("family")),
-                        span.span(ast::Expr::NumberLiteral(2)),
+                        span.span(ast::Expr::NumberLiteral(2.into())),
                     ),
                     (span.span(String::from("port")), port),
                     (
                         span.span(String::from("addr")),
                         span.span(ast::Expr::Record {
                             struct_name: span.span(String::from("InAddr")),
+                            ty_args: vec![],
                             fields: vec![(
                                 span.span(String::from("s_addr")),
-                                span.span(ast::Expr::NumberLiteral(0)),
+                                span.span(ast::Expr::NumberLiteral(0.into())),
                             )],
                         }),
                     ),
@@ -511,31 +991,31 @@ impl<'a> Parser<'a> {
                         span.span(String::from("buf")),
                         span.span(ast::Expr::ArrayLiteral(vec![
                             span.span(ast::Expr::NumberLiteral(
-                                0
+                                0.into()
                             ));
                             8
                         ])),
                     ),
                 ],
             });
-            let address_length = span.span(ast::Expr::NumberLiteral(16));
-            let backlog = span.span(ast::Expr::NumberLiteral(50));
+            let address_length = span.span(ast::Expr::NumberLiteral(16.into()));
+            let backlog = span.span(ast::Expr::NumberLiteral(50.into()));
             let recv_buffer = span.span(ast::Expr::ArrayLiteral(vec![
                 span.span(ast::Expr::NumberLiteral(
-                    0
+                    0.into()
                 ));
                 1024
             ]));
-            let recv_buffer_length = span.span(ast::Expr::NumberLiteral(1024));
-            let recv_flags = span.span(ast::Expr::NumberLiteral(0));
+            let recv_buffer_length = span.span(ast::Expr::NumberLiteral(1024.into()));
+            let recv_flags = span.span(ast::Expr::NumberLiteral(0.into()));
             let send_buffer = span.span(ast::Expr::ArrayLiteral(vec![
                 span.span(ast::Expr::NumberLiteral(
-                    0
+                    0.into()
                 ));
                 1024
             ]));
-            let send_buffer_length = span.span(ast::Expr::NumberLiteral(1024));
-            let send_flags = span.span(ast::Expr::NumberLiteral(0));
+            let send_buffer_length = span.span(ast::Expr::NumberLiteral(1024.into()));
+            let send_flags = span.span(ast::Expr::NumberLiteral(0.into()));
             let format_string = span.span(ast::Expr::StringLiteral(String::from("%s%s")));
             let http_header = span.span(ast::Expr::StringLiteral(String::from(
                 "HTTP/1.0 200 OK\nContent-Type: text/html\n\n",
@@ -556,8 +1036,10 @@ impl<'a> Parser<'a> {
                 send_flags: send_flags.boxed(),
                 format_string: format_string.boxed(),
                 http_header: http_header.boxed(),
-                call_handler: call_handler.boxed(),
+                handler: handler.boxed(),
             }))
+        } else if self.next_is(Kind::Func) {
+            self.next_closure()
         } else if self.next_is(Kind::Ident) {
             self.next_ident_expr(res)
         } else if self.next_is_unop() {
@@ -570,7 +1052,7 @@ impl<'a> Parser<'a> {
             }))
         } else {
             Err(Box::from(self.multi_expectation_diagnostic(vec![
-                Kind::U64Literal,
+                Kind::NumberLiteral,
                 Kind::Ident,
                 Kind::LParen,
             ])))
@@ -616,54 +1098,128 @@ impl<'a> Parser<'a> {
     }
 
     fn next_pattern(&mut self) -> Result<Spanned<ast::Pattern>> {
+        let pattern = self.next_primary_pattern()?;
+        if !self.next_is(Kind::Or) {
+            return Ok(pattern);
+        }
+        let mut span = pattern.span();
+        let mut alternatives = vec![pattern];
+        while self.next_is(Kind::Or) {
+            self.eat(Kind::Or)?;
+            let alternative = self.next_primary_pattern()?;
+            span = span.merge(alternative.span());
+            alternatives.push(alternative);
+        }
+        Ok(span.span(ast::Pattern::Or(alternatives)))
+    }
+
+    fn next_primary_pattern(&mut self) -> Result<Spanned<ast::Pattern>> {
         if self.next_is(Kind::NumberLiteral) {
             let literal = self.next_number_literal()?;
             Ok(literal
                 .span()
                 .span(ast::Pattern::NumberLiteral(literal.into_raw())))
+        } else if self.next_is_bool_literal() {
+            let literal = self.next_bool_literal()?;
+            Ok(literal.span().span(ast::Pattern::BoolLiteral(literal.into_raw())))
         } else if self.next_is(Kind::StringLiteral) {
             let literal = self.next_string_literal()?;
             Ok(literal
                 .span()
                 .span(ast::Pattern::StringLiteral(literal.into_raw())))
+        } else if self.next_is(Kind::LSquare) {
+            let elements = self.next_array_elements()?;
+            Ok(elements
+                .span()
+                .span(ast::Pattern::ArrayLiteral(elements.into_raw())))
+        } else if self.next_is(Kind::LParen) {
+            let patterns = self.next_parenthesised(|this| this.next_pattern())?;
+            let span = patterns.span();
+            Ok(match patterns.into_raw() {
+                Parenthesised::Group(pattern) => pattern,
+                Parenthesised::Tuple(patterns) => span.span(ast::Pattern::Tuple(patterns)),
+            })
         } else if self.next_is(Kind::Ident) {
             let ident = self.next_ident()?;
             if self.next_is(Kind::Separator) {
                 self.eat(Kind::Separator)?;
+                let ty_args = if self.next_is(Kind::LAngle) {
+                    let ty_args = self.next_ty_args()?.into_raw();
+                    if self.next_is(Kind::LCurl) {
+                        return self.next_record_pattern(ident, ty_args);
+                    }
+                    self.eat(Kind::Separator)?;
+                    ty_args
+                } else {
+                    vec![]
+                };
                 let discriminant = self.next_ident()?;
-                self.eat(Kind::LParen)?;
-                let bound = self.next_ident()?;
-                let span = ident.span().merge(self.eat(Kind::RParen)?.span());
-                Ok(span.span(ast::Pattern::Variant {
+                let fields = self.next_variant_fields(
+                    ident.span().merge(discriminant.span()),
+                    Restriction::None,
+                    |this| this.next_pattern(),
+                )?;
+                Ok(fields.span().span(ast::Pattern::Variant {
                     enum_name: ident,
+                    ty_args,
                     discriminant,
-                    bound,
-                }))
-            } else if self.next_is(Kind::LCurl) {
-                let fields = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| {
-                    let fieldname = this.next_ident()?;
-                    this.eat(Kind::Colon)?;
-                    Ok((fieldname, this.next_ident()?))
-                })?;
-                let span = ident.span().merge(fields.span());
-                Ok(span.span(ast::Pattern::Record {
-                    struct_name: ident,
                     fields: fields.into_raw(),
                 }))
+            } else if self.next_is(Kind::LCurl) {
+                self.next_record_pattern(ident, vec![])
+            } else if *ident == "_" {
+                Ok(ident.span().span(ast::Pattern::Wildcard))
             } else {
                 Ok(ident.span().span(ast::Pattern::Ident(ident.into_raw())))
             }
         } else {
             Err(Box::from(
-                self.multi_expectation_diagnostic(vec![Kind::U64Literal, Kind::Ident])
+                self.multi_expectation_diagnostic(vec![Kind::NumberLiteral, Kind::Ident, Kind::LParen])
                     .with_notes(vec!["this is in order to form a valid pattern".to_owned()]),
             ))
         }
     }
 
+    fn next_record_pattern(
+        &mut self,
+        struct_name: Spanned<ast::Ident>,
+        ty_args: Vec<Spanned<ast::Ty>>,
+    ) -> Result<Spanned<ast::Pattern>> {
+        let fields = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| {
+            let fieldname = this.next_ident()?;
+            this.eat(Kind::Colon)?;
+            Ok((fieldname, this.next_pattern()?))
+        })?;
+        let span = struct_name.span().merge(fields.span());
+        Ok(span.span(ast::Pattern::Record {
+            struct_name,
+            ty_args,
+            fields: fields.into_raw(),
+        }))
+    }
+
     fn next_term(&mut self) -> Result<Spanned<ast::Term>> {
         if self.next_is(Kind::Let) {
             let span = self.eat(Kind::Let)?.span();
+            let mutable = self.next_is(Kind::Mut);
+            if mutable {
+                self.eat(Kind::Mut)?;
+            } else if self.next_is(Kind::LParen) {
+                let pattern = self.next_primary_pattern()?;
+                let annotation = self.next_ty_annotation()?;
+                self.eat(Kind::Eq)?;
+                let expr = self.next_expr()?;
+                let body = self.next_term()?;
+                return Ok(span.merge(body.span()).span(ast::Term::LetPattern {
+                    annotation,
+                    expr,
+                    arm: Box::new(ast::Arm {
+                        pattern,
+                        guard: None,
+                        body: Box::new(body),
+                    }),
+                }));
+            }
             let binder = self.next_ident()?;
             let annotation = self.next_ty_annotation()?;
             self.eat(Kind::Eq)?;
@@ -671,6 +1227,7 @@ impl<'a> Parser<'a> {
             let body = self.next_term()?;
             Ok(span.merge(body.span()).span(ast::Term::Let {
                 binder,
+                mutable,
                 annotation,
                 expr,
                 body: Box::new(body),
@@ -685,15 +1242,117 @@ impl<'a> Parser<'a> {
                 expr,
                 body: Box::new(body),
             }))
-        } else if self.next_is(Kind::Match) {
-            self.next_match_term()
-        } else if self.next_is(Kind::If) {
-            self.next_if_term()
+        } else if self.next_is(Kind::While) {
+            let span = self.eat(Kind::While)?.span();
+            let source = self.next_expr_res(Restriction::NoStructLiteral)?;
+            let block = self.next_block()?;
+            let body = self.next_term()?;
+            Ok(span.merge(body.span()).span(ast::Term::While {
+                source,
+                block: Box::new(block),
+                body: Box::new(body),
+            }))
+        } else if self.next_is(Kind::Loop) {
+            let span = self.eat(Kind::Loop)?.span();
+            let block = self.next_block()?;
+            let body = self.next_term()?;
+            Ok(span.merge(body.span()).span(ast::Term::Loop {
+                block: Box::new(block),
+                body: Box::new(body),
+            }))
+        } else if self.next_is(Kind::Break) || self.next_is(Kind::Continue) {
+            self.next_jump_term()
+        } else if self.next_is(Kind::Return) {
+            self.next_return_term()
+        } else if self.next_is(Kind::Match) || self.next_is(Kind::If) {
+            let term = if self.next_is(Kind::Match) {
+                self.next_match_term()?
+            } else {
+                self.next_if_term()?
+            };
+            if self.next_is_term_end() {
+                return Ok(term);
+            }
+            // NOTE followed by more of the body, it is an expression whose value is discarded
+            let expr = term.span().span(ast::Expr::Branch(Box::new(term.into_raw())));
+            self.next_expr_term(expr)
         } else if self.next_is(Kind::LCurl) {
             self.next_block()
+        } else if self.next_is(Kind::RCurl) {
+            let span = self.current.as_ref().unwrap().span();
+            Ok(span.span(ast::Term::End))
         } else {
             let expr = self.next_expr()?;
-            Ok(expr.span().span(ast::Term::Return(expr.into_raw())))
+            if self.next_is(Kind::Eq) {
+                return self.next_assign_term(expr);
+            }
+            if self.next_is_term_end() {
+                return Ok(expr.span().span(ast::Term::Value(expr.into_raw())));
+            }
+            self.next_expr_term(expr)
+        }
+    }
+
+    /// Whether the term ends here, at the end of the block it is in.
+    #[inline]
+    fn next_is_term_end(&self) -> bool {
+        self.current.is_none() || self.next_is(Kind::RCurl)
+    }
+
+    fn next_expr_term(&mut self, expr: Spanned<ast::Expr>) -> Result<Spanned<ast::Term>> {
+        let body = self.next_term()?;
+        Ok(expr.span().merge(body.span()).span(ast::Term::Expr {
+            expr,
+            body: Box::new(body),
+        }))
+    }
+
+    fn next_return_term(&mut self) -> Result<Spanned<ast::Term>> {
+        let span = self.eat(Kind::Return)?.span();
+        let expr = self.next_expr()?;
+        Ok(span.merge(expr.span()).span(ast::Term::Return(expr.into_raw())))
+    }
+
+    fn next_assign_term(&mut self, target: Spanned<ast::Expr>) -> Result<Spanned<ast::Term>> {
+        let span = target.span();
+        let (target, index) = match target.into_raw() {
+            ast::Expr::Var(stream) => (span.span(stream), None),
+            ast::Expr::Index { target, index } => match (target.span(), *target.into_raw()) {
+                (target_span, ast::Expr::Var(stream)) => (target_span.span(stream), Some(index)),
+                _ => {
+                    return Err(Box::from(Diagnostic::new_error(
+                        "invalid left-hand side of assignment",
+                        Label::new(
+                            self.file_id,
+                            span,
+                            "only one level of indexing can be assigned to",
+                        ),
+                    )))
+                }
+            },
+            _ => {
+                return Err(Box::from(Diagnostic::new_error(
+                    "invalid left-hand side of assignment",
+                    Label::new(self.file_id, span, "cannot assign to this expression"),
+                )))
+            }
+        };
+        self.eat(Kind::Eq)?;
+        let expr = self.next_expr()?;
+        let body = self.next_term()?;
+        Ok(span.merge(body.span()).span(ast::Term::Assign {
+            target,
+            index,
+            expr,
+            body: Box::new(body),
+        }))
+    }
+
+    fn next_jump_term(&mut self) -> Result<Spanned<ast::Term>> {
+        if self.next_is(Kind::Break) {
+            Ok(self.eat(Kind::Break)?.span().span(ast::Term::Break))
+        } else {
+            Ok(self.eat(Kind::Continue)?.span().span(ast::Term::Continue))
         }
     }
 
@@ -713,24 +1372,59 @@ impl<'a> Parser<'a> {
             let term = self.next_if_term()?;
             self.eat(Kind::Comma)?;
             Ok(term)
+        } else if self.next_is(Kind::Break) || self.next_is(Kind::Continue) {
+            let term = self.next_jump_term()?;
+            self.eat(Kind::Comma)?;
+            Ok(term)
+        } else if self.next_is(Kind::Return) {
+            let term = self.next_return_term()?;
+            self.eat(Kind::Comma)?;
+            Ok(term)
         } else if self.next_is(Kind::LCurl) {
             self.next_block()
         } else {
             let expr = self.next_expr()?;
             self.eat(Kind::Comma)?;
-            Ok(expr.span().span(ast::Term::Return(expr.into_raw())))
+            Ok(expr.span().span(ast::Term::Value(expr.into_raw())))
         }
     }
 
+    fn next_match_arm(&mut self) -> Result<ast::Arm> {
+        let pattern = self.next_pattern()?;
+        let guard = if self.next_is(Kind::If) {
+            self.eat(Kind::If)?;
+            Some(self.next_expr_res(Restriction::NoStructLiteral)?)
+        } else {
+            None
+        };
+        self.eat(Kind::MatchArrow)?;
+        Ok(ast::Arm {
+            pattern,
+            guard,
+            body: Box::new(self.next_match_arm_body()?),
+        })
+    }
+
     fn next_match_term(&mut self) -> Result<Spanned<ast::Term>> {
         let span = self.eat(Kind::Match)?.span();
         let source = self.next_expr_res(Restriction::NoStructLiteral)?;
         self.eat(Kind::LCurl)?;
+        let depth = self.depth;
         let mut arms = vec![];
         while !self.next_is(Kind::RCurl) {
-            let pattern = self.next_pattern()?;
-            self.eat(Kind::MatchArrow)?;
-            arms.push((pattern, Box::new(self.next_match_arm_body()?)));
+            match self.next_match_arm() {
+                Ok(arm) => arms.push(arm),
+                Err(diagnostic) => {
+                    self.recover(depth, &[Kind::Comma, Kind::RCurl]);
+                    if self.depth != depth || !(self.next_is(Kind::Comma) || self.next_is(Kind::RCurl)) {
+                        return Err(diagnostic);
+                    }
+                    self.report(*diagnostic);
+                    if self.next_is(Kind::Comma) {
+                        self.eat(Kind::Comma)?;
+                    }
+                }
+            }
         }
         let span = span.merge(self.eat(Kind::RCurl)?.span());
         Ok(span.span(ast::Term::Match { source, arms }))
@@ -773,6 +1467,7 @@ impl<'a> Parser<'a> {
     fn next_fn_item(&mut self) -> Result<Spanned<ast::Item>> {
         let span = self.eat(Kind::Func)?.span();
         let name = self.next_ident()?;
+        let (ty_params, bounds) = self.next_bounded_ty_params()?;
         let params = self
             .next_comma_group(Kind::LParen, Kind::RParen, |this| this.next_binding())?
             .into_raw();
@@ -780,6 +1475,29 @@ impl<'a> Parser<'a> {
         let body = self.next_term()?;
         let span = span.merge(body.span());
         Ok(span.span(ast::Item::Fn(Box::new(ast::FnDecl {
+            name,
+            ty_params,
+            bounds,
+            params,
+            return_ty,
+            body,
+        }))))
+    }
+
+    fn next_closure(&mut self) -> Result<Spanned<ast::Expr>> {
+        let span = self.eat(Kind::Func)?.span();
+        let name = if self.next_is(Kind::Ident) {
+            Some(self.next_ident()?)
+        } else {
+            None
+        };
+        let params = self
+            .next_comma_group(Kind::LParen, Kind::RParen, |this| this.next_binding())?
+            .into_raw();
+        let return_ty = self.next_ty()?;
+        let body = self.next_term()?;
+        let span = span.merge(body.span());
+        Ok(span.span(ast::Expr::Closure(Box::new(ast::Closure {
             name,
             params,
             return_ty,
@@ -790,19 +1508,19 @@ impl<'a> Parser<'a> {
     fn next_enum_item(&mut self) -> Result<Spanned<ast::Item>> {
         let span = self.eat(Kind::Enum)?.span();
         let name = self.next_ident()?;
+        let ty_params = self.next_ty_params()?;
         let variants = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| {
-            let discriminant = this.next_ident()?;
-            this.eat(Kind::LParen)?;
-            let ty = this.next_ty()?;
-            let span = discriminant.span().merge(this.eat(Kind::RParen)?.span());
-            Ok(span.span(ast::Binding {
-                binder: discriminant,
-                ty,
+            let name = this.next_ident()?;
+            let fields = this.next_variant_fields(name.span(), Restriction::None, |this| this.next_ty())?;
+            Ok(fields.span().span(ast::Variant {
+                name,
+                fields: fields.into_raw(),
             }))
         })?;
         let span = span.merge(variants.span());
         Ok(span.span(ast::Item::Enum(ast::Enum {
             name,
+            ty_params,
             variants: variants.into_raw(),
         })))
     }
@@ -810,14 +1528,113 @@ impl<'a> Parser<'a> {
     fn next_struct_item(&mut self) -> Result<Spanned<ast::Item>> {
         let span = self.eat(Kind::Struct)?.span();
         let name = self.next_ident()?;
+        let ty_params = self.next_ty_params()?;
         let fields = self.next_comma_group(Kind::LCurl, Kind::RCurl, |this| this.next_binding())?;
         let span = span.merge(fields.span());
         Ok(span.span(ast::Item::Struct(ast::Struct {
             name,
+            ty_params,
             fields: fields.into_raw(),
         })))
     }
 
+    /// Parses an impl block, which is either `impl Type { .. }` or `impl Trait for Type { .. }`.
+    fn next_impl_item(&mut self) -> Result<Spanned<ast::Item>> {
+        let span = self.eat(Kind::Impl)?.span();
+        let mut ty_name = self.next_ident()?;
+        let trait_name = if self.next_is(Kind::For) {
+            self.eat(Kind::For)?;
+            Some(std::mem::replace(&mut ty_name, self.next_ident()?))
+        } else {
+            None
+        };
+        let ty_params = self.next_ty_params()?;
+        let self_ty = ty_name.span().span(ast::Ty::TyName(
+            ty_name.to_string(),
+            ty_params
+                .iter()
+                .map(|ty_param| ast::Ty::TyName(ty_param.to_string(), vec![]))
+                .collect(),
+        ));
+        self.eat(Kind::LCurl)?;
+        let mut methods = vec![];
+        while !self.next_is(Kind::RCurl) {
+            methods.push(self.next_method(&ty_params, &self_ty)?);
+        }
+        let span = span.merge(self.eat(Kind::RCurl)?.span());
+        Ok(span.span(ast::Item::Impl(ast::Impl {
+            trait_name,
+            ty_name,
+            ty_params,
+            methods,
+        })))
+    }
+
+    /// Parses a method in an impl block, where the receiver `self` is written without a type.
+    fn next_method(
+        &mut self,
+        impl_ty_params: &[Spanned<ast::Ident>],
+        self_ty: &Spanned<ast::Ty>,
+    ) -> Result<Spanned<ast::FnDecl>> {
+        let span = self.eat(Kind::Func)?.span();
+        let name = self.next_ident()?;
+        let (own_ty_params, bounds) = self.next_bounded_ty_params()?;
+        let mut ty_params = impl_ty_params.to_vec();
+        ty_params.extend(own_ty_params);
+        let params = self.next_method_params(self_ty)?;
+        let return_ty = self.next_ty()?;
+        let body = self.next_term()?;
+        let span = span.merge(body.span());
+        Ok(span.span(ast::FnDecl {
+            name,
+            ty_params,
+            bounds,
+            params,
+            return_ty,
+            body,
+        }))
+    }
+
+    /// Parses the parameters of a method, where a bare `self` has the type `self_ty`.
+    fn next_method_params(&mut self, self_ty: &Spanned<ast::Ty>) -> Result<Vec<Spanned<ast::Binding>>> {
+        Ok(self
+            .next_comma_group(Kind::LParen, Kind::RParen, |this| {
+                let binder = this.next_ident()?;
+                if *binder == "self" && !this.next_is(Kind::Colon) {
+                    let ty = binder.span().span((**self_ty).clone());
+                    return Ok(binder.span().span(ast::Binding { binder, ty }));
+                }
+                this.eat(Kind::Colon)?;
+                let ty = this.next_ty()?;
+                Ok(binder.span().merge(ty.span()).span(ast::Binding { binder, ty }))
+            })?
+            .into_raw())
+    }
+
+    fn next_trait_item(&mut self) -> Result<Spanned<ast::Item>> {
+        let span = self.eat(Kind::Trait)?.span();
+        let name = self.next_ident()?;
+        let self_ty = name
+            .span()
+            .span(ast::Ty::TyName(String::from(ast::SELF_TY), vec![]));
+        self.eat(Kind::LCurl)?;
+        let mut methods = vec![];
+        while !self.next_is(Kind::RCurl) {
+            let span = self.eat(Kind::Func)?.span();
+            let name = self.next_ident()?;
+            let params = self.next_method_params(&self_ty)?;
+            let return_ty = self.next_ty()?;
+            let span = span.merge(return_ty.span());
+            methods.push(span.span(ast::MethodSig {
+                name,
+                params,
+                return_ty,
+            }));
+        }
+        let span = span.merge(self.eat(Kind::RCurl)?.span());
+        Ok(span.span(ast::Item::Trait(ast::Trait { name, methods })))
+    }
+
     fn next_item(&mut self) -> Result<Spanned<ast::Item>> {
         if self.next_is(Kind::Func) {
             self.next_fn_item()
@@ -825,11 +1642,17 @@ impl<'a> Parser<'a> {
             self.next_struct_item()
         } else if self.next_is(Kind::Enum) {
             self.next_enum_item()
+        } else if self.next_is(Kind::Impl) {
+            self.next_impl_item()
+        } else if self.next_is(Kind::Trait) {
+            self.next_trait_item()
         } else {
             Err(Box::from(self.multi_expectation_diagnostic(vec![
                 Kind::Func,
                 Kind::Struct,
                 Kind::Enum,
+                Kind::Impl,
+                Kind::Trait,
             ])))
         }
     }
@@ -12,20 +12,40 @@ pub enum Kind {
     And,
     Or,
     Xor,
+    AndAnd,
+    OrOr,
     LShift,
     RShift,
     Ident,
     Let,
+    Mut,
     Match,
     If,
     Else,
+    While,
+    Loop,
+    Break,
+    Continue,
+    Return,
     Func,
     Struct,
     Enum,
+    Impl,
+    Trait,
+    For,
+    True,
+    False,
     I8Ty,
     I16Ty,
     I32Ty,
     I64Ty,
+    U8Ty,
+    U16Ty,
+    U32Ty,
+    U64Ty,
+    F32Ty,
+    F64Ty,
+    BoolTy,
     StringTy,
     Eq,
     MatchArrow,
@@ -42,7 +62,7 @@ pub enum Kind {
     LSquare,
     RSquare,
     NumberLiteral,
-    U64Literal,
+    FloatLiteral,
     StringLiteral,
     Env,
     Dot,
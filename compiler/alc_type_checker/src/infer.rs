@@ -0,0 +1,350 @@
+//! Type variables for the locals of a function, solved by unification while its body is checked.
+//!
+//! A variable is bound to a type once a use of its value determines one. Integer and float literals without a
+//! suffix or an expected type start out only knowing that they are integers or floats, so they can take their
+//! type from a later use, and fall back to `i32` and `f64` when nothing determines it. Each variable remembers
+//! where its type came from, so that a mismatch can point at both of the uses that disagree.
+
+use alc_ast_lowering::{idx::Idx, ty};
+use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct TyVar(usize);
+
+#[derive(Debug, Copy, Clone)]
+enum Bound {
+    Unknown,
+    Integer,
+    Float,
+    Ty(ty::Ty),
+    /// An array whose element type is still being inferred.
+    Array(TyVar, i32),
+    /// A tuple whose element types are still being inferred, given by its index into the elements of tuples.
+    Tuple(usize),
+}
+
+impl Bound {
+    /// How much of the type is known, which decides whose origin is kept when two variables are unified.
+    fn rank(&self) -> usize {
+        match self {
+            Bound::Unknown => 0,
+            Bound::Integer | Bound::Float => 1,
+            Bound::Array(..) | Bound::Tuple(_) => 2,
+            Bound::Ty(_) => 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    parent: Option<TyVar>,
+    bound: Bound,
+    origin: Option<Span>,
+}
+
+/// A class of types that an operation admits, which is checked once every variable is resolved.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Class {
+    Integer,
+    IntegerOrBool,
+    IntegerOrFloat,
+    IntegerFloatOrBool,
+    StringOrFloat,
+    /// Anything that lives on the heap.
+    Boxed,
+}
+
+impl Class {
+    pub(crate) fn admits(&self, ty_kind: &ty::TyKind) -> bool {
+        match self {
+            Class::Integer => ty_kind.is_integer(),
+            Class::IntegerOrBool => ty_kind.is_integer() || ty_kind.is_bool(),
+            Class::IntegerOrFloat => ty_kind.is_integer() || ty_kind.is_float(),
+            Class::IntegerFloatOrBool => ty_kind.is_integer() || ty_kind.is_float() || ty_kind.is_bool(),
+            Class::StringOrFloat => ty_kind.is_string() || ty_kind.is_float(),
+            Class::Boxed => !ty_kind.is_integer() && !ty_kind.is_float() && !ty_kind.is_bool(),
+        }
+    }
+}
+
+pub(crate) struct Unifier<'a> {
+    file_id: FileId,
+    ty_sess: &'a ty::TySess,
+    nodes: Vec<Node>,
+    tuples: Vec<Vec<TyVar>>,
+}
+
+impl<'a> Unifier<'a> {
+    pub(crate) fn new(file_id: FileId, ty_sess: &'a ty::TySess) -> Unifier<'a> {
+        Unifier {
+            file_id,
+            ty_sess,
+            nodes: vec![],
+            tuples: vec![],
+        }
+    }
+
+    fn push(&mut self, bound: Bound, origin: Option<Span>) -> TyVar {
+        self.nodes.push(Node {
+            parent: None,
+            bound,
+            origin,
+        });
+        TyVar(self.nodes.len() - 1)
+    }
+
+    /// A variable about which nothing is known yet.
+    pub(crate) fn fresh(&mut self) -> TyVar {
+        self.push(Bound::Unknown, None)
+    }
+
+    /// The variable of an integer literal written at `span`.
+    pub(crate) fn integer(&mut self, span: Span) -> TyVar {
+        self.push(Bound::Integer, Some(span))
+    }
+
+    /// The variable of a float literal written at `span`.
+    pub(crate) fn float(&mut self, span: Span) -> TyVar {
+        self.push(Bound::Float, Some(span))
+    }
+
+    /// The variable of an array literal written at `span`, whose elements have the type of `element`.
+    pub(crate) fn array(&mut self, element: TyVar, size: i32, span: Span) -> TyVar {
+        self.push(Bound::Array(element, size), Some(span))
+    }
+
+    /// The variable of a tuple written at `span`, whose elements have the types of `elements`.
+    pub(crate) fn tuple(&mut self, elements: Vec<TyVar>, span: Span) -> TyVar {
+        self.tuples.push(elements);
+        self.push(Bound::Tuple(self.tuples.len() - 1), Some(span))
+    }
+
+    /// A variable bound to `ty`, which is expected because of what is written at `span`.
+    pub(crate) fn known(&mut self, ty: ty::Ty, span: Span) -> TyVar {
+        self.push(Bound::Ty(ty), Some(span))
+    }
+
+    fn root(&self, var: TyVar) -> TyVar {
+        let mut var = var;
+        while let Some(parent) = self.nodes[var.0].parent {
+            var = parent;
+        }
+        var
+    }
+
+    fn bound(&self, var: TyVar) -> Bound {
+        self.nodes[self.root(var).0].bound
+    }
+
+    #[inline]
+    pub(crate) fn origin(&self, var: TyVar) -> Option<Span> {
+        self.nodes[self.root(var).0].origin
+    }
+
+    /// The type of `var`, if it is already fully known.
+    pub(crate) fn probe(&self, var: TyVar) -> Option<ty::Ty> {
+        match self.bound(var) {
+            Bound::Ty(ty) => Some(ty),
+            Bound::Array(element, size) => Some(self.ty_sess.make_array(self.probe(element)?, size)),
+            Bound::Tuple(tuple) => Some(
+                self.ty_sess.make_tuple(
+                    self.tuples[tuple]
+                        .iter()
+                        .map(|element| self.probe(*element))
+                        .collect::<Option<_>>()?,
+                ),
+            ),
+            Bound::Unknown | Bound::Integer | Bound::Float => None,
+        }
+    }
+
+    /// Where the type of `var` is determined by something other than a literal.
+    pub(crate) fn expected_at(&self, var: TyVar) -> Option<Span> {
+        match self.bound(var) {
+            Bound::Ty(_) | Bound::Array(..) | Bound::Tuple(_) => self.origin(var),
+            Bound::Unknown | Bound::Integer | Bound::Float => None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_unknown(&self, var: TyVar) -> bool {
+        matches!(self.bound(var), Bound::Unknown)
+    }
+
+    /// The variable of the elements of `var`, if it is known to be an array.
+    pub(crate) fn element(&mut self, var: TyVar) -> Option<TyVar> {
+        match self.bound(var) {
+            Bound::Array(element, _) => Some(element),
+            Bound::Ty(ty) => {
+                let element_ty = self.ty_sess.ty_kind(ty).as_array()?.element_ty;
+                let origin = self.origin(var);
+                Some(self.push(Bound::Ty(element_ty), origin))
+            }
+            Bound::Unknown | Bound::Integer | Bound::Float | Bound::Tuple(_) => None,
+        }
+    }
+
+    /// The variable of the field at `field_idx` of `var`, if it is known to be a record with such a field.
+    pub(crate) fn field(&mut self, var: TyVar, field_idx: ty::FieldIdx) -> Option<TyVar> {
+        match self.bound(var) {
+            Bound::Tuple(tuple) => self.tuples[tuple].get(field_idx.index()).copied(),
+            Bound::Ty(ty) => {
+                let field_ty = self.ty_sess.ty_kind(ty).field_ty(field_idx)?;
+                let origin = self.origin(var);
+                Some(self.push(Bound::Ty(field_ty), origin))
+            }
+            Bound::Unknown | Bound::Integer | Bound::Float | Bound::Array(..) => None,
+        }
+    }
+
+    /// Makes `actual` and `expected` the same type, reporting `label` when they can't be.
+    pub(crate) fn unify(&mut self, actual: TyVar, expected: TyVar, label: Label) -> Result<()> {
+        let (actual_root, expected_root) = (self.root(actual), self.root(expected));
+        if actual_root == expected_root {
+            return Ok(());
+        }
+        let (actual_bound, expected_bound) = (self.bound(actual_root), self.bound(expected_root));
+        let bound = match (actual_bound, expected_bound) {
+            (Bound::Unknown, bound) | (bound, Bound::Unknown) => bound,
+            (Bound::Integer, Bound::Integer) => Bound::Integer,
+            // NOTE an integer literal can stand for a float, as it can where a float is expected while lowering
+            (Bound::Float, Bound::Float)
+            | (Bound::Integer, Bound::Float)
+            | (Bound::Float, Bound::Integer) => Bound::Float,
+            (Bound::Integer, Bound::Ty(ty)) | (Bound::Ty(ty), Bound::Integer)
+                if self.ty_sess.ty_kind(ty).is_integer() || self.ty_sess.ty_kind(ty).is_float() =>
+            {
+                Bound::Ty(ty)
+            }
+            (Bound::Float, Bound::Ty(ty)) | (Bound::Ty(ty), Bound::Float)
+                if self.ty_sess.ty_kind(ty).is_float() =>
+            {
+                Bound::Ty(ty)
+            }
+            (Bound::Ty(left), Bound::Ty(right)) if left == right => Bound::Ty(left),
+            (Bound::Array(element, size), Bound::Ty(ty)) | (Bound::Ty(ty), Bound::Array(element, size))
+                if self.ty_sess.ty_kind(ty).as_array().map(|array| array.size) == Some(size) =>
+            {
+                let element_ty = match actual_bound {
+                    Bound::Ty(_) => self.element(actual_root),
+                    _ => self.element(expected_root),
+                };
+                let element_ty = element_ty.unwrap();
+                self.unify(element, element_ty, label)?;
+                Bound::Ty(ty)
+            }
+            (Bound::Array(left, left_size), Bound::Array(right, right_size)) if left_size == right_size => {
+                self.unify(left, right, label)?;
+                Bound::Array(right, right_size)
+            }
+            (Bound::Tuple(tuple), Bound::Ty(ty)) | (Bound::Ty(ty), Bound::Tuple(tuple))
+                if self
+                    .ty_sess
+                    .ty_kind(ty)
+                    .as_tuple()
+                    .map(|tuple| tuple.fields.len())
+                    == Some(self.tuples[tuple].len()) =>
+            {
+                let ty_root = match actual_bound {
+                    Bound::Ty(_) => actual_root,
+                    _ => expected_root,
+                };
+                for (position, element) in self.tuples[tuple].clone().into_iter().enumerate() {
+                    let field = self.field(ty_root, ty::FieldIdx::new(position)).unwrap();
+                    self.unify(element, field, label.clone())?;
+                }
+                Bound::Ty(ty)
+            }
+            (Bound::Tuple(left), Bound::Tuple(right))
+                if self.tuples[left].len() == self.tuples[right].len() =>
+            {
+                for (left, right) in self.tuples[left]
+                    .clone()
+                    .into_iter()
+                    .zip(self.tuples[right].clone())
+                {
+                    self.unify(left, right, label.clone())?;
+                }
+                Bound::Tuple(right)
+            }
+            _ => return Err(self.mismatch(actual_root, expected_root, label)),
+        };
+        let origin = if actual_bound.rank() > expected_bound.rank() {
+            self.nodes[actual_root.0].origin
+        } else {
+            self.nodes[expected_root.0].origin
+        };
+        self.nodes[actual_root.0].parent = Some(expected_root);
+        let root = &mut self.nodes[expected_root.0];
+        root.bound = bound;
+        root.origin = origin.or(root.origin);
+        Ok(())
+    }
+
+    fn mismatch(&self, actual: TyVar, expected: TyVar, label: Label) -> Box<Diagnostic> {
+        let secondary_labels = [expected, actual]
+            .iter()
+            .filter_map(|var| {
+                self.origin(*var)
+                    .filter(|origin| **origin != label.span)
+                    .map(|origin| Label::new(self.file_id, origin, self.explain(*var)))
+            })
+            .collect::<Vec<_>>();
+        Box::from(
+            Diagnostic::new_error("type mismatch", label)
+                .with_secondary_labels(secondary_labels)
+                .with_notes(vec![format!(
+                    "expected '{}', found '{}'",
+                    self.describe(expected),
+                    self.describe(actual)
+                )]),
+        )
+    }
+
+    /// Why `var` has the type it has, to be shown at its origin.
+    fn explain(&self, var: TyVar) -> String {
+        match self.bound(var) {
+            Bound::Integer => String::from("this is an integer literal"),
+            Bound::Float => String::from("this is a float literal"),
+            _ => format!("'{}' is expected here", self.describe(var)),
+        }
+    }
+
+    /// The type of `var` as far as it is known.
+    pub(crate) fn describe(&self, var: TyVar) -> String {
+        match self.bound(var) {
+            Bound::Unknown => String::from("_"),
+            Bound::Integer => String::from("{integer}"),
+            Bound::Float => String::from("{float}"),
+            Bound::Ty(ty) => self.ty_sess.ty_name(ty),
+            Bound::Array(element, size) => format!("[{}; {}]", self.describe(element), size),
+            Bound::Tuple(tuple) => format!(
+                "({})",
+                self.tuples[tuple]
+                    .iter()
+                    .map(|element| self.describe(*element))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// The type of `var` once nothing more can be learnt about it, with literals taking their default types.
+    pub(crate) fn resolve(&self, var: TyVar) -> Option<ty::Ty> {
+        match self.bound(var) {
+            Bound::Unknown => None,
+            Bound::Integer => Some(self.ty_sess.make_i32()),
+            Bound::Float => Some(self.ty_sess.make_f64()),
+            Bound::Ty(ty) => Some(ty),
+            Bound::Array(element, size) => Some(self.ty_sess.make_array(self.resolve(element)?, size)),
+            Bound::Tuple(tuple) => Some(
+                self.ty_sess.make_tuple(
+                    self.tuples[tuple]
+                        .iter()
+                        .map(|element| self.resolve(*element))
+                        .collect::<Option<_>>()?,
+                ),
+            ),
+        }
+    }
+}
@@ -1,3 +1,10 @@
+mod infer;
+mod usefulness;
+
+use crate::{
+    infer::{Class, TyVar, Unifier},
+    usefulness::MatchChecker,
+};
 use alc_ast_lowering::{
     idx_vec::{IdxVec, IntoIdxVec},
     ir,
@@ -5,7 +12,10 @@ use alc_ast_lowering::{
 };
 use alc_command_option::CommandOptions;
 use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
-use std::{collections::HashMap, ops::Deref};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+};
 
 pub fn check(
     command_options: &CommandOptions,
@@ -43,8 +53,12 @@ impl<'tcx> TyCtx<'tcx> {
             global_ctx.bind(def_idx, def.ty, def.span)?;
         }
         let mut env = IdxVec::new();
-        for def in ir.defs.values() {
-            env.push(LocalTyCtx::check_def(&global_ctx, def)?);
+        // NOTE a closure is defined after the function it is written in, which gives the types of its captures
+        let mut capture_tys = HashMap::new();
+        for (def_idx, def) in ir.defs.iter() {
+            let (tys, closures) = LocalTyCtx::check_def(&global_ctx, def, capture_tys.remove(&def_idx))?;
+            capture_tys.extend(closures);
+            env.push(tys);
         }
         Ok(env)
     }
@@ -78,14 +92,37 @@ impl<'tcx> TyCtx<'tcx> {
     }
 }
 
+/// A literal whose type is inferred, kept so that its value can be checked against that type.
+enum Literal {
+    Number(u64),
+    Float(f64),
+}
+
 struct LocalTyCtx<'tcx> {
     global_ctx: &'tcx TyCtx<'tcx>,
     prototype: ty::Prototype,
-    tys: HashMap<ir::LocalIdx, (Span, ty::Ty)>,
+    unifier: Unifier<'tcx>,
+    tys: HashMap<ir::LocalIdx, (Span, TyVar)>,
+    yield_tys: Vec<Option<TyVar>>,
+    /// The params of the arm bodies that the leaves of a decision jump to.
+    arm_params: HashMap<ir::BlockIdx, Vec<ir::LocalIdx>>,
+    mutables: HashSet<ir::LocalIdx>,
+    /// Uses that only admit a class of types, which are checked once the types of all locals are known.
+    checks: Vec<(TyVar, Class, Span, &'static str)>,
+    literals: Vec<(TyVar, Literal, Span)>,
+    /// The closures made in the function, with the values they capture.
+    closures: Vec<(ir::DefIdx, Vec<ir::LocalIdx>)>,
 }
 
+/// The types of the values captured by each closure made in a function.
+type CaptureTys = Vec<(ir::DefIdx, Vec<ty::Ty>)>;
+
 impl<'tcx> LocalTyCtx<'tcx> {
-    fn check_def(global_ctx: &'tcx TyCtx<'tcx>, def: &ir::Def) -> Result<IdxVec<ir::LocalIdx, ty::Ty>> {
+    fn check_def(
+        global_ctx: &'tcx TyCtx<'tcx>,
+        def: &ir::Def,
+        capture_tys: Option<Vec<ty::Ty>>,
+    ) -> Result<(IdxVec<ir::LocalIdx, ty::Ty>, CaptureTys)> {
         let prototype = global_ctx
             .ty_sess
             .ty_kind(def.ty)
@@ -104,24 +141,158 @@ impl<'tcx> LocalTyCtx<'tcx> {
         let mut local_ctx = LocalTyCtx {
             global_ctx,
             prototype,
+            unifier: Unifier::new(global_ctx.file_id, global_ctx.ty_sess),
             tys: HashMap::new(),
+            yield_tys: vec![],
+            arm_params: HashMap::new(),
+            mutables: HashSet::new(),
+            checks: vec![],
+            literals: vec![],
+            closures: vec![],
         };
-        local_ctx.check_entry(&def.entry)?;
-        local_ctx
-            .tys
-            .into_iter()
-            .map(|(idx, (_, ty))| (idx, ty))
-            .collect::<HashMap<_, _>>()
-            .into_idx_vec()
-            .ok_or_else(|| {
-                Box::from(Diagnostic::new_bug(
-                    "failed to collect type environment for function",
-                    Label::new(global_ctx.file_id, def.span, "not all local indices were typed"),
-                ))
+        local_ctx.check_entry(&def.entry, capture_tys)?;
+        let tys = local_ctx.resolve()?;
+        local_ctx.check_deferred()?;
+        for written_match in def.matches.iter() {
+            let source_ty = tys.get(&written_match.source).copied().ok_or_else(|| {
+                Diagnostic::new_bug(
+                    "failed to read type of match",
+                    Label::new(
+                        global_ctx.file_id,
+                        written_match.source.span(),
+                        "match source was not typed",
+                    ),
+                )
+            })?;
+            MatchChecker::new(global_ctx.file_id, global_ctx.ty_sess).check(
+                written_match.source,
+                source_ty,
+                &written_match.arms,
+            )?;
+        }
+        let closures = local_ctx
+            .closures
+            .iter()
+            .map(|(def_idx, captures)| {
+                let capture_tys = captures.iter().map(|capture| tys[capture]).collect();
+                (*def_idx, capture_tys)
             })
+            .collect();
+        let tys = tys.into_idx_vec().ok_or_else(|| {
+            Box::from(Diagnostic::new_bug(
+                "failed to collect type environment for function",
+                Label::new(global_ctx.file_id, def.span, "not all local indices were typed"),
+            ))
+        })?;
+        Ok((tys, closures))
+    }
+
+    /// Gives every local the type inferred for it, in the order in which they are written.
+    fn resolve(&self) -> Result<HashMap<ir::LocalIdx, ty::Ty>> {
+        let mut locals = self.tys.iter().collect::<Vec<_>>();
+        locals.sort_by_key(|(_, (span, _))| span.start());
+        let mut tys = HashMap::new();
+        for (local_idx, (span, var)) in locals {
+            let ty = self.unifier.resolve(*var).ok_or_else(|| {
+                Diagnostic::new_error(
+                    "type annotations needed",
+                    Label::new(
+                        self.file_id,
+                        *span,
+                        "the type of this value could not be inferred",
+                    ),
+                )
+                .with_notes(vec![String::from(
+                    "give the binding a type, as in 'let name: i32 = ..'",
+                )])
+            })?;
+            tys.insert(*local_idx, ty);
+        }
+        Ok(tys)
+    }
+
+    /// Checks the uses that only admit some types and the literals whose types were inferred.
+    fn check_deferred(&self) -> Result<()> {
+        for (var, class, span, message) in self.checks.iter() {
+            let admitted = self
+                .unifier
+                .resolve(*var)
+                .is_some_and(|ty| class.admits(&self.ty_sess.ty_kind(ty)));
+            if !admitted {
+                return Err(Box::from(Diagnostic::new_error(
+                    "type mismatch",
+                    Label::new(self.file_id, *span, *message),
+                )));
+            }
+        }
+        for (var, literal, span) in self.literals.iter() {
+            if let Some(ty) = self.unifier.resolve(*var) {
+                self.check_literal(ty, literal, *span, self.unifier.expected_at(*var))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the value of `literal` fits into the type that was inferred for it.
+    fn check_literal(
+        &self,
+        ty: ty::Ty,
+        literal: &Literal,
+        span: Span,
+        expected_at: Option<Span>,
+    ) -> Result<()> {
+        let ty_kind = self.ty_sess.ty_kind(ty);
+        let range = match (literal, &*ty_kind) {
+            (Literal::Number(value), _) => {
+                macro_rules! range {
+                    ( $int:ty ) => {
+                        match <$int>::try_from(*value) {
+                            Ok(_) => None,
+                            Err(_) => Some((
+                                value.to_string(),
+                                format!("{}..={}", <$int>::MIN, <$int>::MAX),
+                            )),
+                        }
+                    };
+                }
+                match &*ty_kind {
+                    ty::TyKind::I8 => range!(i8),
+                    ty::TyKind::I16 => range!(i16),
+                    ty::TyKind::I32 => range!(i32),
+                    ty::TyKind::I64 => range!(i64),
+                    ty::TyKind::U8 => range!(u8),
+                    ty::TyKind::U16 => range!(u16),
+                    ty::TyKind::U32 => range!(u32),
+                    _ => None,
+                }
+            }
+            (Literal::Float(value), ty::TyKind::F32) if (*value as f32).is_infinite() => {
+                Some((format!("{:e}", value), format!("{:e}..={:e}", f32::MIN, f32::MAX)))
+            }
+            (Literal::Float(_), _) => None,
+        };
+        let (value, range) = match range {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        let name = self.ty_sess.ty_name(ty);
+        Err(Box::from(
+            Diagnostic::new_error(
+                format!("literal out of range for '{}'", name),
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!("'{}' does not fit into the type '{}'", value, name),
+                ),
+            )
+            .with_secondary_labels(expected_at.map(|expected_at| {
+                Label::new(self.file_id, expected_at, format!("'{}' is expected here", name))
+            }))
+            .with_notes(vec![format!("the range of '{}' is {}", name, range)]),
+        ))
     }
 
-    fn check_entry(&mut self, entry: &ir::Entry) -> Result<()> {
+    fn check_entry(&mut self, entry: &ir::Entry, capture_tys: Option<Vec<ty::Ty>>) -> Result<()> {
         for (param_idx, binding) in entry.param_bindings.iter() {
             let param_ty = self.prototype.params.get(param_idx).copied().ok_or_else(|| {
                 Diagnostic::new_bug(
@@ -129,7 +300,13 @@ impl<'tcx> LocalTyCtx<'tcx> {
                     Label::new(self.file_id, binding.span(), "parameter type could not be read"),
                 )
             })?;
-            self.bind(*binding, param_ty)?;
+            let var = self.unifier.known(param_ty, binding.span());
+            self.bind(*binding, var)?;
+        }
+        let capture_bindings = entry.capture_bindings.iter().flatten();
+        for (binding, capture_ty) in capture_bindings.zip(capture_tys.into_iter().flatten()) {
+            let var = self.unifier.known(capture_ty, binding.span());
+            self.bind(*binding, var)?;
         }
         self.check_block(&entry.body)
     }
@@ -141,30 +318,30 @@ impl<'tcx> LocalTyCtx<'tcx> {
         self.check_terminator(&block.terminator)
     }
 
-    fn bind(&mut self, idx: ir::LocalIdx, ty: ty::Ty) -> Result<()> {
-        if let Some((other_span, other_ty)) = self.tys.insert(idx, (idx.span(), ty)) {
+    fn bind(&mut self, idx: ir::LocalIdx, var: TyVar) -> Result<()> {
+        if let Some((other_span, other_var)) = self.tys.insert(idx, (idx.span(), var)) {
             return Err(Box::from(
                 Diagnostic::new_error(
                     "could not infer single type for value",
                     Label::new(
                         self.file_id,
                         idx.span(),
-                        format!("attempted to rebind as {:?}", ty),
+                        format!("attempted to rebind as {}", self.unifier.describe(var)),
                     ),
                 )
                 .with_secondary_labels(vec![Label::new(
                     self.file_id,
                     other_span,
-                    format!("previously bound as {:?}", other_ty),
+                    format!("previously bound as {}", self.unifier.describe(other_var)),
                 )]),
             ));
         }
         Ok(())
     }
 
-    fn lookup(&self, idx: ir::LocalIdx) -> Result<ty::Ty> {
-        if let Some((_, ty)) = self.tys.get(&idx) {
-            Ok(*ty)
+    fn lookup(&self, idx: ir::LocalIdx) -> Result<TyVar> {
+        if let Some((_, var)) = self.tys.get(&idx) {
+            Ok(*var)
         } else {
             Err(Box::from(Diagnostic::new_error(
                 "reference to unbound variable",
@@ -177,140 +354,312 @@ impl<'tcx> LocalTyCtx<'tcx> {
         }
     }
 
-    fn check_expr_kind(&mut self, expr_kind: &ir::ExprKind, span: Span) -> Result<ty::Ty> {
+    /// The type of `local_idx` as far as it is known, which has to include the fields read from it.
+    fn lookup_place(&mut self, local_idx: ir::LocalIdx, field_idxes: &[ty::FieldIdx]) -> Result<TyVar> {
+        let var = self.lookup(local_idx)?;
+        if field_idxes.is_empty() {
+            return Ok(var);
+        }
+        let mut var = var;
+        for field_idx in field_idxes.iter() {
+            var = self.unifier.field(var, *field_idx).ok_or_else(|| {
+                Diagnostic::new_error(
+                    "type annotations needed",
+                    Label::new(
+                        self.file_id,
+                        local_idx.span(),
+                        "the type of this value must be known to access its fields",
+                    ),
+                )
+            })?;
+        }
+        Ok(var)
+    }
+
+    /// Checks that `array` can be indexed by `index`, giving the type of its elements.
+    fn check_index(&mut self, array: TyVar, array_span: Span, index: ir::LocalIdx) -> Result<TyVar> {
+        let element = match self.unifier.element(array) {
+            Some(element) => element,
+            None if self.unifier.is_unknown(array) => {
+                return Err(Box::from(Diagnostic::new_error(
+                    "type annotations needed",
+                    Label::new(
+                        self.file_id,
+                        array_span,
+                        "the type of this value must be known to index it",
+                    ),
+                )))
+            }
+            None => {
+                return Err(Box::from(Diagnostic::new_error(
+                    "type mismatch",
+                    Label::new(self.file_id, array_span, "only arrays can be indexed"),
+                )))
+            }
+        };
+        let index_var = self.lookup(index)?;
+        self.checks.push((
+            index_var,
+            Class::Integer,
+            index.span(),
+            "array index must have an integer type",
+        ));
+        Ok(element)
+    }
+
+    /// Makes the type of `actual` the one expected by `expected`, reporting a mismatch at `span`.
+    fn expect(&mut self, actual: TyVar, expected: TyVar, span: Span, message: &str) -> Result<()> {
+        let label = Label::new(self.file_id, span, message);
+        self.unifier.unify(actual, expected, label)
+    }
+
+    /// Checks the arguments of a call to a function of type `fn_ty`, giving the type of its result.
+    fn check_call(
+        &mut self,
+        fn_ty: ty::Ty,
+        args: &IdxVec<ty::ParamIdx, ir::LocalIdx>,
+        span: Span,
+    ) -> Result<TyVar> {
+        let param_count = self.ty_sess.ty_kind(fn_ty).param_count().ok_or_else(|| {
+            Diagnostic::new_bug(
+                "failed to read fn type",
+                Label::new(self.file_id, span, "could not get parameter count for function"),
+            )
+        })?;
+        if args.len() != param_count {
+            return Err(Box::from(Diagnostic::new_error(
+                "argument count mismatch",
+                Label::new(
+                    self.file_id,
+                    span,
+                    format!(
+                        "function expected {} parameters but {} were found",
+                        param_count,
+                        args.len()
+                    ),
+                ),
+            )));
+        }
+        for (param_idx, local_idx) in args.iter() {
+            let arg_var = self.lookup(*local_idx)?;
+            let param_ty = self.ty_sess.ty_kind(fn_ty).param_ty(param_idx).ok_or_else(|| {
+                Diagnostic::new_bug(
+                    "failed to read fn type",
+                    Label::new(self.file_id, local_idx.span(), "parameter type could not be read"),
+                )
+            })?;
+            let param_var = self.unifier.known(param_ty, local_idx.span());
+            self.expect(
+                arg_var,
+                param_var,
+                local_idx.span(),
+                "argument types and parameter types do not match",
+            )?;
+        }
+        let return_ty = self.ty_sess.ty_kind(fn_ty).return_ty().ok_or_else(|| {
+            Diagnostic::new_bug(
+                "failed to read fn type",
+                Label::new(self.file_id, span, "return type could not be read"),
+            )
+        })?;
+        Ok(self.unifier.known(return_ty, span))
+    }
+
+    fn check_expr_kind(&mut self, expr_kind: &ir::ExprKind, span: Span) -> Result<TyVar> {
+        let known = |local_ctx: &mut Self, ty| Ok(local_ctx.unifier.known(ty, span));
         match expr_kind {
-            ir::ExprKind::I8Literal(_) => Ok(self.ty_sess.make_i8()),
-            ir::ExprKind::I16Literal(_) => Ok(self.ty_sess.make_i16()),
-            ir::ExprKind::I32Literal(_) => Ok(self.ty_sess.make_i32()),
-            ir::ExprKind::I64Literal(_) => Ok(self.ty_sess.make_i64()),
+            ir::ExprKind::I8Literal(_) => known(self, self.ty_sess.make_i8()),
+            ir::ExprKind::I16Literal(_) => known(self, self.ty_sess.make_i16()),
+            ir::ExprKind::I32Literal(_) => known(self, self.ty_sess.make_i32()),
+            ir::ExprKind::I64Literal(_) => known(self, self.ty_sess.make_i64()),
+            ir::ExprKind::U8Literal(_) => known(self, self.ty_sess.make_u8()),
+            ir::ExprKind::U16Literal(_) => known(self, self.ty_sess.make_u16()),
+            ir::ExprKind::U32Literal(_) => known(self, self.ty_sess.make_u32()),
+            ir::ExprKind::U64Literal(_) => known(self, self.ty_sess.make_u64()),
+            ir::ExprKind::F32Literal(_) => known(self, self.ty_sess.make_f32()),
+            ir::ExprKind::F64Literal(_) => known(self, self.ty_sess.make_f64()),
+            ir::ExprKind::NumberLiteral(value) => {
+                let var = self.unifier.integer(span);
+                self.literals.push((var, Literal::Number(*value), span));
+                Ok(var)
+            }
+            ir::ExprKind::FloatLiteral(value) => {
+                let var = self.unifier.float(span);
+                self.literals.push((var, Literal::Float(*value), span));
+                Ok(var)
+            }
+            ir::ExprKind::BoolLiteral(_) => known(self, self.ty_sess.make_bool()),
             ir::ExprKind::ArrayLiteral { element_ty, elements } => {
-                Ok(self.ty_sess.make_array(*element_ty, elements.len() as i32))
+                let element = match element_ty {
+                    Some(element_ty) => self.unifier.known(*element_ty, span),
+                    None => self.unifier.fresh(),
+                };
+                // NOTE the elements are not locals of their own, so they are all checked at the span of the array
+                for element_kind in elements.iter() {
+                    let element_var = self.check_expr_kind(element_kind, span)?;
+                    self.expect(
+                        element_var,
+                        element,
+                        span,
+                        "array elements must have the same type",
+                    )?;
+                }
+                self.checks.push((
+                    element,
+                    Class::IntegerFloatOrBool,
+                    span,
+                    "array literals can only hold integers, floats and bools",
+                ));
+                Ok(self.unifier.array(element, elements.len() as i32, span))
+            }
+            ir::ExprKind::StringLiteral(_) => known(self, self.ty_sess.make_string()),
+            ir::ExprKind::Var(local_idx, field_idxes) => self.lookup_place(*local_idx, field_idxes),
+            ir::ExprKind::Index {
+                target,
+                fields,
+                index,
+            } => {
+                let target_var = self.lookup_place(*target, fields)?;
+                self.check_index(target_var, target.span(), *index)
             }
-            ir::ExprKind::StringLiteral(_) => Ok(self.ty_sess.make_string()),
-            ir::ExprKind::Var(local_idx, _) => self.lookup(*local_idx),
             ir::ExprKind::Unop { operand, .. } => {
-                // NOTE this is a bit of a hack, but at present the only unary operator only takes u64 types
-                if self.lookup(*operand)? != self.ty_sess.make_i32() {
-                    Err(Box::from(Diagnostic::new_error(
-                        "type mismatch",
-                        Label::new(
-                            self.file_id,
-                            span,
-                            "argument to unary operator must have type i32",
-                        ),
-                    )))
+                // NOTE at present the only unary operator is not, which is bitwise on integers and logical on bools
+                let operand_var = self.lookup(*operand)?;
+                self.checks.push((
+                    operand_var,
+                    Class::IntegerOrBool,
+                    span,
+                    "argument to unary operator must have an integer or bool type",
+                ));
+                Ok(operand_var)
+            }
+            ir::ExprKind::Binop { kind, left, right } => {
+                let left_var = self.lookup(*left)?;
+                let right_var = self.lookup(*right)?;
+                let takes_bool = matches!(
+                    kind,
+                    ir::BinopKind::Eq
+                        | ir::BinopKind::Neq
+                        | ir::BinopKind::And
+                        | ir::BinopKind::Or
+                        | ir::BinopKind::Xor
+                );
+                // NOTE bitwise and shift operators have no meaning for floats
+                let takes_float = matches!(
+                    kind,
+                    ir::BinopKind::Plus | ir::BinopKind::Minus | ir::BinopKind::Mul | ir::BinopKind::Div
+                ) || kind.is_comparison();
+                self.expect(
+                    right_var,
+                    left_var,
+                    right.span(),
+                    "arguments to binary operator must have the same type",
+                )
+                .map_err(|diagnostic| {
+                    Box::from(diagnostic.with_secondary_labels(vec![Label::new(
+                        self.file_id,
+                        left.span(),
+                        "type of the other argument is given here",
+                    )]))
+                })?;
+                let (class, message) = match (takes_bool, takes_float) {
+                    (true, true) => (
+                        Class::IntegerFloatOrBool,
+                        "arguments to binary operator must have an integer, float or bool type",
+                    ),
+                    (true, false) => (
+                        Class::IntegerOrBool,
+                        "arguments to binary operator must have an integer or bool type",
+                    ),
+                    (false, true) => (
+                        Class::IntegerOrFloat,
+                        "arguments to binary operator must have an integer or float type",
+                    ),
+                    (false, false) => (
+                        Class::Integer,
+                        "arguments to binary operator must have an integer type",
+                    ),
+                };
+                self.checks.push((left_var, class, left.span(), message));
+                if kind.is_comparison() {
+                    known(self, self.ty_sess.make_bool())
                 } else {
-                    Ok(self.ty_sess.make_i32())
+                    Ok(left_var)
                 }
             }
-            ir::ExprKind::Binop { left, right, .. } => {
-                // NOTE same hack works here because at present all binary operators only take u64 types
-                if self.lookup(*left)? != self.ty_sess.make_i32() {
-                    Err(Box::from(Diagnostic::new_error(
-                        "type mismatch",
+            ir::ExprKind::Call { target, args } => {
+                let fn_ty = self.global_ctx.lookup(*target, span)?;
+                self.check_call(fn_ty, args, span)
+            }
+            ir::ExprKind::CallValue { target, args } => {
+                let target_var = self.lookup(*target)?;
+                match self.unifier.probe(target_var) {
+                    Some(fn_ty) if self.ty_sess.ty_kind(fn_ty).is_fn() => self.check_call(fn_ty, args, span),
+                    None if self.unifier.is_unknown(target_var) => Err(Box::from(Diagnostic::new_error(
+                        "type annotations needed",
                         Label::new(
                             self.file_id,
-                            left.span(),
-                            "arguments to binary operator must have type i32",
+                            target.span(),
+                            "the type of this value must be known to call it",
                         ),
-                    )))
-                } else if self.lookup(*right)? != self.ty_sess.make_i32() {
-                    Err(Box::from(Diagnostic::new_error(
+                    ))),
+                    _ => Err(Box::from(Diagnostic::new_error(
                         "type mismatch",
                         Label::new(
                             self.file_id,
-                            right.span(),
-                            "arguments to binary operator must have type i32",
-                        ),
-                    )))
-                } else {
-                    Ok(self.ty_sess.make_i32())
-                }
-            }
-            ir::ExprKind::Call { target, args } => {
-                let fn_ty = self.global_ctx.lookup(*target, span)?;
-                let param_count = self.ty_sess.ty_kind(fn_ty).param_count().ok_or_else(|| {
-                    Diagnostic::new_bug(
-                        "failed to read fn type",
-                        Label::new(self.file_id, span, "could not get parameter count for function"),
-                    )
-                })?;
-                if args.len() != param_count {
-                    return Err(Box::from(Diagnostic::new_error(
-                        "argument count mismatch",
-                        Label::new(
-                            self.file_id,
-                            span,
+                            target.span(),
                             format!(
-                                "function expected {} parameters but {} were found",
-                                param_count,
-                                args.len()
+                                "only functions can be called, but this has the type '{}'",
+                                self.unifier.describe(target_var)
                             ),
                         ),
-                    )));
+                    ))),
                 }
-                for (param_idx, local_idx) in args.iter() {
-                    let arg_ty = self.lookup(*local_idx)?;
-                    let param_ty = self.ty_sess.ty_kind(fn_ty).param_ty(param_idx).ok_or_else(|| {
-                        Diagnostic::new_bug(
-                            "failed to read fn type",
-                            Label::new(self.file_id, local_idx.span(), "parameter type could not be read"),
-                        )
-                    })?;
-                    if arg_ty != param_ty {
-                        return Err(Box::from(Diagnostic::new_error(
-                            "type mismatch",
-                            Label::new(
-                                self.file_id,
-                                local_idx.span(),
-                                "argument types and parameter types do not match",
-                            ),
-                        )));
-                    }
+            }
+            ir::ExprKind::Closure { ty, target, captures } => {
+                for capture in captures.iter() {
+                    self.lookup(*capture)?;
                 }
-                self.ty_sess.ty_kind(fn_ty).return_ty().ok_or_else(|| {
-                    Box::from(Diagnostic::new_bug(
-                        "failed to read fn type",
-                        Label::new(self.file_id, span, "return type could not be read"),
-                    ))
-                })
+                self.closures.push((*target, captures.clone()));
+                known(self, *ty)
             }
             ir::ExprKind::Variant {
                 ty,
                 discriminant,
-                body,
+                fields,
             } => {
-                let body_ty = self.lookup(*body)?;
-                let variant_ty = self
-                    .ty_sess
-                    .ty_kind(*ty)
-                    .variant_ty(*discriminant)
-                    .ok_or_else(|| {
-                        Diagnostic::new_bug(
-                            "failed to read variant of enum",
-                            Label::new(
-                                self.file_id,
-                                span,
-                                format!("the type of {:?} could not be read from the enum", discriminant),
-                            ),
-                        )
-                    })?;
-                if body_ty != variant_ty {
-                    Err(Box::from(Diagnostic::new_error(
-                        "type mismatch",
-                        Label::new(
-                            self.file_id,
-                            body.span(),
-                            "enum variant cannot be instantiated using the given body",
-                        ),
-                    )))
-                } else {
-                    Ok(*ty)
+                for (field_idx, local_idx) in fields.iter() {
+                    let body_var = self.lookup(*local_idx)?;
+                    let field_ty = self
+                        .ty_sess
+                        .ty_kind(*ty)
+                        .variant_field_ty(*discriminant, field_idx)
+                        .ok_or_else(|| {
+                            Diagnostic::new_bug(
+                                "failed to read variant of enum",
+                                Label::new(
+                                    self.file_id,
+                                    span,
+                                    format!(
+                                        "the type of {:?} of {:?} could not be read from the enum",
+                                        field_idx, discriminant
+                                    ),
+                                ),
+                            )
+                        })?;
+                    let field_var = self.unifier.known(field_ty, local_idx.span());
+                    self.expect(
+                        body_var,
+                        field_var,
+                        local_idx.span(),
+                        "enum variant cannot be instantiated using the given body",
+                    )?;
                 }
+                known(self, *ty)
             }
             ir::ExprKind::Record { ty, fields } => {
                 for (field_idx, local_idx) in fields.iter() {
-                    let body_ty = self.lookup(*local_idx)?;
+                    let body_var = self.lookup(*local_idx)?;
                     let field_ty = self.ty_sess.ty_kind(*ty).field_ty(field_idx).ok_or_else(|| {
                         Diagnostic::new_bug(
                             "failed to read field of struct",
@@ -321,74 +670,116 @@ impl<'tcx> LocalTyCtx<'tcx> {
                             ),
                         )
                     })?;
-                    if body_ty != field_ty {
-                        return Err(Box::from(Diagnostic::new_error(
-                            "type mismatch",
-                            Label::new(
-                                self.file_id,
-                                local_idx.span(),
-                                "struct field cannot be instantiated using the given body",
-                            ),
-                        )));
-                    }
+                    let field_var = self.unifier.known(field_ty, local_idx.span());
+                    self.expect(
+                        body_var,
+                        field_var,
+                        local_idx.span(),
+                        "struct field cannot be instantiated using the given body",
+                    )?;
                 }
-                Ok(*ty)
-            }
-            ir::ExprKind::Socket { .. } => Ok(self.ty_sess.make_i32()),
-            ir::ExprKind::Bind { .. } => Ok(self.ty_sess.make_i32()),
-            ir::ExprKind::Listen { .. } => Ok(self.ty_sess.make_i32()),
-            ir::ExprKind::Accept { .. } => Ok(self.ty_sess.make_i32()),
-            ir::ExprKind::Recv { .. } => Ok(self.ty_sess.make_i64()),
-            ir::ExprKind::Send { .. } => Ok(self.ty_sess.make_i64()),
-            ir::ExprKind::Close { .. } => Ok(self.ty_sess.make_i32()),
-            ir::ExprKind::ListenAndServe { .. } => Ok(self.ty_sess.make_i32()),
+                known(self, *ty)
+            }
+            ir::ExprKind::Tuple(fields) => {
+                let elements = fields
+                    .values()
+                    .map(|local_idx| self.lookup(*local_idx))
+                    .collect::<Result<_>>()?;
+                Ok(self.unifier.tuple(elements, span))
+            }
+            ir::ExprKind::Match { source, arms } => {
+                self.yield_tys.push(None);
+                self.check_arms(*source, arms)?;
+                // NOTE when every branch returns or leaves a loop, only an annotation can give the value a type
+                Ok(self
+                    .yield_tys
+                    .pop()
+                    .flatten()
+                    .unwrap_or_else(|| self.unifier.fresh()))
+            }
+            ir::ExprKind::Socket { .. } => known(self, self.ty_sess.make_i32()),
+            ir::ExprKind::Bind { .. } => known(self, self.ty_sess.make_i32()),
+            ir::ExprKind::Listen { .. } => known(self, self.ty_sess.make_i32()),
+            ir::ExprKind::Accept { .. } => known(self, self.ty_sess.make_i32()),
+            ir::ExprKind::Recv { .. } => known(self, self.ty_sess.make_i64()),
+            ir::ExprKind::Send { .. } => known(self, self.ty_sess.make_i64()),
+            ir::ExprKind::Close { .. } => known(self, self.ty_sess.make_i32()),
+            ir::ExprKind::ListenAndServe { handler, .. } => {
+                let handler_var = self.lookup(*handler)?;
+                let handler_ty = self.ty_sess.make_fn(self.ty_sess.make_string(), IdxVec::new());
+                let handler_ty_var = self.unifier.known(handler_ty, handler.span());
+                self.expect(
+                    handler_var,
+                    handler_ty_var,
+                    handler.span(),
+                    "the handler of a server must be a function without parameters that returns a string",
+                )?;
+                known(self, self.ty_sess.make_i32())
+            }
         }
     }
 
-    fn check_expr(&mut self, expr: &ir::Expr) -> Result<ty::Ty> {
+    fn check_expr(&mut self, expr: &ir::Expr) -> Result<TyVar> {
         self.check_expr_kind(&expr.kind, expr.span)
     }
 
-    fn check_pattern(
-        &mut self,
-        source_ty: ty::Ty,
-        pattern_kind: &ir::PatternKind,
-        span: Span,
-    ) -> Result<ty::Ty> {
+    fn check_pattern(&mut self, source: TyVar, pattern_kind: &ir::PatternKind, span: Span) -> Result<TyVar> {
+        let known = |local_ctx: &mut Self, ty| Ok(local_ctx.unifier.known(ty, span));
         match pattern_kind {
-            ir::PatternKind::I8Literal(_) => Ok(self.ty_sess.make_i8()),
-            ir::PatternKind::I16Literal(_) => Ok(self.ty_sess.make_i16()),
-            ir::PatternKind::I32Literal(_) => Ok(self.ty_sess.make_i32()),
-            ir::PatternKind::I64Literal(_) => Ok(self.ty_sess.make_i64()),
+            ir::PatternKind::I8Literal(_) => known(self, self.ty_sess.make_i8()),
+            ir::PatternKind::I16Literal(_) => known(self, self.ty_sess.make_i16()),
+            ir::PatternKind::I32Literal(_) => known(self, self.ty_sess.make_i32()),
+            ir::PatternKind::I64Literal(_) => known(self, self.ty_sess.make_i64()),
+            ir::PatternKind::U8Literal(_) => known(self, self.ty_sess.make_u8()),
+            ir::PatternKind::U16Literal(_) => known(self, self.ty_sess.make_u16()),
+            ir::PatternKind::U32Literal(_) => known(self, self.ty_sess.make_u32()),
+            ir::PatternKind::U64Literal(_) => known(self, self.ty_sess.make_u64()),
+            ir::PatternKind::BoolLiteral(_) => known(self, self.ty_sess.make_bool()),
             ir::PatternKind::ArrayLiteral { element_ty, elements } => {
-                Ok(self.ty_sess.make_array(*element_ty, elements.len() as i32))
+                known(self, self.ty_sess.make_array(*element_ty, elements.len() as i32))
             }
-            ir::PatternKind::StringLiteral(_) => Ok(self.ty_sess.make_string()),
+            ir::PatternKind::StringLiteral(_) => known(self, self.ty_sess.make_string()),
             ir::PatternKind::Ident(binding) => {
-                self.bind(*binding, source_ty)?;
-                Ok(source_ty)
+                self.bind(*binding, source)?;
+                Ok(source)
             }
             ir::PatternKind::Variant {
                 ty,
                 discriminant,
-                binding,
+                fields,
             } => {
-                let variant_ty = self
-                    .ty_sess
-                    .ty_kind(*ty)
-                    .variant_ty(*discriminant)
-                    .ok_or_else(|| {
-                        Diagnostic::new_bug(
-                            "failed to read variant of enum",
-                            Label::new(
-                                self.file_id,
-                                span,
-                                format!("the type of {:?} could not be read from the enum", discriminant),
-                            ),
-                        )
-                    })?;
-                self.bind(*binding, variant_ty)?;
-                Ok(*ty)
+                for (field_idx, binding) in fields.iter() {
+                    let field_ty = self
+                        .ty_sess
+                        .ty_kind(*ty)
+                        .variant_field_ty(*discriminant, field_idx)
+                        .ok_or_else(|| {
+                            Diagnostic::new_bug(
+                                "failed to read variant of enum",
+                                Label::new(
+                                    self.file_id,
+                                    binding.span(),
+                                    format!(
+                                        "the type of {:?} of {:?} could not be read from the enum",
+                                        field_idx, discriminant
+                                    ),
+                                ),
+                            )
+                        })?;
+                    let field_var = self.unifier.known(field_ty, binding.span());
+                    self.bind(*binding, field_var)?;
+                }
+                known(self, *ty)
+            }
+            // NOTE the element types a tuple pattern was lowered with may be guesses, so they are inferred instead
+            ir::PatternKind::Record { ty, fields } if self.ty_sess.ty_kind(*ty).is_tuple() => {
+                let mut elements = Vec::with_capacity(fields.len());
+                for binding in fields.values() {
+                    let element = self.unifier.fresh();
+                    self.bind(*binding, element)?;
+                    elements.push(element);
+                }
+                Ok(self.unifier.tuple(elements, span))
             }
             ir::PatternKind::Record { ty, fields } => {
                 for (field_idx, binding) in fields.iter() {
@@ -402,9 +793,10 @@ impl<'tcx> LocalTyCtx<'tcx> {
                             ),
                         )
                     })?;
-                    self.bind(*binding, field_ty)?;
+                    let field_var = self.unifier.known(field_ty, binding.span());
+                    self.bind(*binding, field_var)?;
                 }
-                Ok(*ty)
+                known(self, *ty)
             }
         }
     }
@@ -412,51 +804,93 @@ impl<'tcx> LocalTyCtx<'tcx> {
     fn check_instruction(&mut self, instruction: &ir::InstructionKind, span: Span) -> Result<()> {
         match instruction {
             ir::InstructionKind::Let { binding, ty, expr } => {
-                let bound_ty = self.check_expr(expr)?;
-                match ty {
-                    Some(ty) if *ty != bound_ty => {
-                        return Err(Box::from(Diagnostic::new_error(
-                            "type mismatch",
-                            Label::new(
-                                self.file_id,
-                                span,
-                                "declared type for let binding does not match type of bound expression",
-                            ),
-                        )))
-                    }
-                    _ => {
-                        self.bind(*binding, bound_ty)?;
-                    }
+                let bound_var = self.check_expr(expr)?;
+                if let Some(ty) = ty {
+                    let declared_var = self.unifier.known(*ty, span);
+                    self.expect(
+                        bound_var,
+                        declared_var,
+                        span,
+                        "declared type for let binding does not match type of bound expression",
+                    )?;
+                }
+                self.bind(*binding, bound_var)?;
+            }
+            ir::InstructionKind::LetMut { binding, ty, init } => {
+                let init_var = self.lookup(*init)?;
+                if let Some(ty) = ty {
+                    let declared_var = self.unifier.known(*ty, span);
+                    self.expect(
+                        init_var,
+                        declared_var,
+                        span,
+                        "declared type for let binding does not match type of bound expression",
+                    )?;
+                }
+                self.bind(*binding, init_var)?;
+                self.mutables.insert(*binding);
+            }
+            ir::InstructionKind::Assign {
+                binding,
+                fields,
+                index,
+                ty: _,
+                value,
+            } => {
+                if !self.mutables.contains(binding) {
+                    return Err(Box::from(
+                        Diagnostic::new_error(
+                            "cannot assign to an immutable binding",
+                            Label::new(self.file_id, binding.span(), "cannot assign to this binding"),
+                        )
+                        .with_notes(vec![String::from(
+                            "bindings are immutable unless they are declared with 'let mut'",
+                        )]),
+                    ));
                 }
+                let mut place_var = self.lookup_place(*binding, fields)?;
+                if let Some(index) = index {
+                    place_var = self.check_index(place_var, binding.span(), *index)?;
+                }
+                let value_var = self.lookup(*value)?;
+                self.expect(
+                    value_var,
+                    place_var,
+                    value.span(),
+                    "assigned value does not match the type of the binding",
+                )
+                .map_err(|diagnostic| {
+                    Box::from(diagnostic.with_secondary_labels(vec![Label::new(
+                        self.file_id,
+                        binding.span(),
+                        "type of the binding is given here",
+                    )]))
+                })?;
             }
-            ir::InstructionKind::Println { idx: _ } => {
-                // let expr_ty = self.check_expr(expr)?;
-                // if expr_ty != self.ty_sess.make_string() {
-                //     return Err(Diagnostic::new_error(
-                //         "type mismatch",
-                //         Label::new(
-                //             self.file_id,
-                //             expr.span(),
-                //             "println! can only be used with string literals",
-                //         ),
-                //     ));
-                // }
+            ir::InstructionKind::Println { idx } => {
+                let expr_var = self.lookup(*idx)?;
+                self.checks.push((
+                    expr_var,
+                    Class::StringOrFloat,
+                    idx.span(),
+                    "println can only be used with strings and floats",
+                ));
             }
             ir::InstructionKind::Mark(local_idx, ty)
             | ir::InstructionKind::Unmark(local_idx, ty)
             | ir::InstructionKind::Free(local_idx, ty) => {
-                // NOTE this would want extending to cover all primitive types given that any further types were added
-                if self.lookup(*local_idx)? == self.ty_sess.make_i64() {
-                    return Err(Box::from(Diagnostic::new_error(
-                        "type mismatch",
-                        Label::new(
-                            self.file_id,
-                            local_idx.span(),
-                            "cannot free/mark/unmark primitive types",
-                        ),
-                    )));
-                }
-                if self.lookup(*local_idx)? != *ty {
+                let local_var = self.lookup(*local_idx)?;
+                self.checks.push((
+                    local_var,
+                    Class::Boxed,
+                    local_idx.span(),
+                    "cannot free/mark/unmark primitive types",
+                ));
+                if self
+                    .unifier
+                    .probe(local_var)
+                    .is_some_and(|local_ty| local_ty != *ty)
+                {
                     return Err(Box::from(Diagnostic::new_bug(
                         "type mismatch",
                         Label::new(
@@ -476,39 +910,97 @@ impl<'tcx> LocalTyCtx<'tcx> {
     fn check_terminator(&mut self, terminator: &ir::Terminator) -> Result<()> {
         match terminator {
             ir::Terminator::Return(local_idx) => {
-                let body_ty = self.lookup(*local_idx)?;
-                if body_ty != self.prototype.return_ty {
-                    Err(Box::from(Diagnostic::new_error(
-                        "type mismatch",
-                        Label::new(
-                            self.file_id,
-                            local_idx.span(),
-                            "return type does not match type of returned expression",
-                        ),
-                    )))
-                } else {
-                    Ok(())
-                }
+                let body_var = self.lookup(*local_idx)?;
+                let return_var = self.unifier.known(self.prototype.return_ty, local_idx.span());
+                self.expect(
+                    body_var,
+                    return_var,
+                    local_idx.span(),
+                    "return type does not match type of returned expression",
+                )
             }
-            ir::Terminator::Match { source, arms } => {
-                let source_ty = self.lookup(*source)?;
+            ir::Terminator::Match { source, arms } => self.check_arms(*source, arms),
+            ir::Terminator::Decision { tree, arms } => {
                 for arm in arms.iter() {
-                    let pattern_ty = self.check_pattern(source_ty, &arm.pattern, arm.span)?;
-                    if pattern_ty != source_ty {
-                        return Err(Box::from(Diagnostic::new_error(
-                            "type mismatch",
-                            Label::new(
-                                self.file_id,
-                                source.span(),
-                                "match arm contains pattern with type incompatible with that of the match source",
-                            ),
-                        )));
+                    for param in arm.params.iter() {
+                        let var = self.unifier.fresh();
+                        self.bind(*param, var)?;
                     }
-                    self.check_block(&arm.target)?;
+                    self.arm_params.insert(arm.body.block_idx, arm.params.clone());
+                }
+                self.check_block(tree)?;
+                for arm in arms.iter() {
+                    self.check_block(&arm.body)?;
                 }
                 Ok(())
             }
+            ir::Terminator::Jump { target, args } => {
+                let params = self.arm_params[target].clone();
+                for (arg, param) in args.iter().zip(params) {
+                    let arg_var = self.lookup(*arg)?;
+                    let param_var = self.lookup(param)?;
+                    self.expect(
+                        arg_var,
+                        param_var,
+                        arg.span(),
+                        "value is bound with a type incompatible with that of the other alternatives",
+                    )?;
+                }
+                Ok(())
+            }
+            ir::Terminator::Yield(local_idx) => {
+                let body_var = self.lookup(*local_idx)?;
+                match self.yield_tys.last().copied() {
+                    Some(Some(yield_var)) => self.expect(
+                        body_var,
+                        yield_var,
+                        local_idx.span(),
+                        "value has a type incompatible with that of the other match arms",
+                    ),
+                    Some(None) => {
+                        *self.yield_tys.last_mut().unwrap() = Some(body_var);
+                        Ok(())
+                    }
+                    None => Err(Box::from(Diagnostic::new_bug(
+                        "yield outside of match expression",
+                        Label::new(self.file_id, local_idx.span(), "value is yielded here"),
+                    ))),
+                }
+            }
+            ir::Terminator::Loop { body, next } => {
+                self.check_block(body)?;
+                self.check_block(next)
+            }
+            ir::Terminator::Break | ir::Terminator::Continue => Ok(()),
+        }
+    }
+
+    fn check_arms(&mut self, source: ir::LocalIdx, arms: &[ir::Arm]) -> Result<()> {
+        let source_var = self.lookup(source)?;
+        if !arms.is_empty()
+            && arms
+                .iter()
+                .all(|arm| matches!(arm.pattern, ir::PatternKind::BoolLiteral(_)))
+        {
+            let bool_var = self.unifier.known(self.ty_sess.make_bool(), source.span());
+            self.expect(
+                source_var,
+                bool_var,
+                source.span(),
+                "expected a condition of type bool",
+            )?;
+        }
+        for arm in arms.iter() {
+            let pattern_var = self.check_pattern(source_var, &arm.pattern, source.span())?;
+            self.expect(
+                pattern_var,
+                source_var,
+                source.span(),
+                "match arm contains pattern with type incompatible with that of the match source",
+            )?;
+            self.check_block(&arm.target)?;
         }
+        Ok(())
     }
 }
 
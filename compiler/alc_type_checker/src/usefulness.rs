@@ -0,0 +1,420 @@
+//! Exhaustiveness and reachability of match arms, following the usefulness algorithm of
+//! "Warnings for pattern matching" (Maranget, 2007).
+//!
+//! A pattern is useful with respect to the arms before it when some value is matched by it but by none of them.
+//! An arm whose pattern is not useful can never be reached, and a match is exhaustive when a wildcard placed
+//! after all of its arms would not be useful.
+
+use alc_ast_lowering::{ir, ty};
+use alc_diagnostic::{Diagnostic, FileId, Label, Result, Span};
+
+/// The most missing patterns listed when a match is not exhaustive.
+const MAX_WITNESSES: usize = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Ctor {
+    Bool(bool),
+    Variant(ty::VariantIdx),
+    Record,
+    /// A number, string or array literal. These types have too many values to list, so only a wildcard covers
+    /// them.
+    Literal(String),
+}
+
+#[derive(Clone, Debug)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+    Or(Vec<Pat>),
+}
+
+pub(crate) struct MatchChecker<'a> {
+    file_id: FileId,
+    ty_sess: &'a ty::TySess,
+}
+
+impl<'a> MatchChecker<'a> {
+    pub(crate) fn new(file_id: FileId, ty_sess: &'a ty::TySess) -> MatchChecker<'a> {
+        MatchChecker { file_id, ty_sess }
+    }
+
+    /// Reports the first arm that can never be reached, or else the values of `source_ty` that no arm covers.
+    /// An arm with a guard may not match, so it covers no values for the arms after it.
+    pub(crate) fn check(
+        &self,
+        source: ir::LocalIdx,
+        source_ty: ty::Ty,
+        arms: &[ir::WrittenArm],
+    ) -> Result<()> {
+        let mut matrix = Vec::with_capacity(arms.len());
+        for arm in arms.iter() {
+            let row = vec![self.lower_pattern(&arm.pattern, source_ty, arm.span)?];
+            if self.is_useful(&matrix, &row, &[source_ty]).is_none() {
+                return Err(Box::from(
+                    Diagnostic::new_error(
+                        "unreachable pattern",
+                        Label::new(self.file_id, arm.span, "this arm is never reached"),
+                    )
+                    .with_notes(vec![String::from(
+                        "every value it matches is already matched by an arm above it",
+                    )]),
+                ));
+            }
+            if !arm.guarded {
+                matrix.push(row);
+            }
+        }
+        let mut witnesses = vec![];
+        while witnesses.len() <= MAX_WITNESSES {
+            match self.is_useful(&matrix, &[Pat::Wild], &[source_ty]) {
+                Some(witness) => {
+                    witnesses.push(self.display(&witness[0], source_ty));
+                    matrix.push(witness);
+                }
+                None => break,
+            }
+        }
+        if witnesses.is_empty() {
+            return Ok(());
+        }
+        Err(Box::from(
+            Diagnostic::new_error(
+                "non-exhaustive patterns",
+                Label::new(self.file_id, source.span(), self.describe_witnesses(witnesses)),
+            )
+            .with_notes(vec![String::from(
+                "add arms for the missing patterns, or an arm that binds any remaining value",
+            )]),
+        ))
+    }
+
+    fn describe_witnesses(&self, mut witnesses: Vec<String>) -> String {
+        let more = witnesses.len() > MAX_WITNESSES;
+        witnesses.truncate(MAX_WITNESSES);
+        let mut quoted = witnesses
+            .iter()
+            .map(|witness| format!("'{}'", witness))
+            .collect::<Vec<_>>();
+        if more {
+            format!("patterns {} and more not covered", quoted.join(", "))
+        } else if quoted.len() == 1 {
+            format!("pattern {} not covered", quoted[0])
+        } else {
+            let last = quoted.pop().unwrap();
+            format!("patterns {} and {} not covered", quoted.join(", "), last)
+        }
+    }
+
+    /// Checks that the pattern matches values of type `ty`, which the arms of a match that are never reached have
+    /// not been checked for.
+    fn lower_pattern(&self, pattern: &ir::WrittenPattern, ty: ty::Ty, span: Span) -> Result<Pat> {
+        let (pattern_ty, pat) = match pattern {
+            ir::WrittenPattern::Wild => return Ok(Pat::Wild),
+            ir::WrittenPattern::Literal(kind) => self.lower_literal(kind),
+            ir::WrittenPattern::Variant {
+                ty: variant_ty,
+                discriminant,
+                fields,
+            } => {
+                let mut lowered_fields = Vec::with_capacity(fields.len());
+                for (field_idx, field) in fields.iter() {
+                    let field_ty = self
+                        .ty_sess
+                        .ty_kind(*variant_ty)
+                        .variant_field_ty(*discriminant, field_idx);
+                    lowered_fields.push(match field_ty {
+                        Some(field_ty) => self.lower_pattern(field, field_ty, span)?,
+                        None => Pat::Wild,
+                    });
+                }
+                (
+                    *variant_ty,
+                    Pat::Ctor(Ctor::Variant(*discriminant), lowered_fields),
+                )
+            }
+            ir::WrittenPattern::Record {
+                ty: record_ty,
+                fields,
+            } => {
+                // NOTE the element types a tuple pattern was lowered with may be guesses, so it takes those matched
+                let record_ty =
+                    if self.ty_sess.ty_kind(*record_ty).is_tuple() && self.ty_sess.ty_kind(ty).is_tuple() {
+                        &ty
+                    } else {
+                        record_ty
+                    };
+                let mut lowered_fields = Vec::with_capacity(fields.len());
+                for (field_idx, field) in fields.iter() {
+                    let field_ty = self.ty_sess.ty_kind(*record_ty).field_ty(field_idx);
+                    lowered_fields.push(match field_ty {
+                        Some(field_ty) => self.lower_pattern(field, field_ty, span)?,
+                        None => Pat::Wild,
+                    });
+                }
+                (*record_ty, Pat::Ctor(Ctor::Record, lowered_fields))
+            }
+            ir::WrittenPattern::Or(alternatives) => {
+                return Ok(Pat::Or(
+                    alternatives
+                        .iter()
+                        .map(|alternative| self.lower_pattern(alternative, ty, span))
+                        .collect::<Result<Vec<_>>>()?,
+                ))
+            }
+        };
+        if pattern_ty != ty {
+            return Err(Box::from(Diagnostic::new_error(
+                "type mismatch",
+                Label::new(
+                    self.file_id,
+                    span,
+                    "match arm contains pattern with type incompatible with that of the match source",
+                ),
+            )));
+        }
+        Ok(pat)
+    }
+
+    /// The type of a literal pattern and its constructor.
+    fn lower_literal(&self, kind: &ir::PatternKind) -> (ty::Ty, Pat) {
+        let literal = |value: String| Pat::Ctor(Ctor::Literal(value), vec![]);
+        match kind {
+            ir::PatternKind::I8Literal(value) => (self.ty_sess.make_i8(), literal(value.to_string())),
+            ir::PatternKind::I16Literal(value) => (self.ty_sess.make_i16(), literal(value.to_string())),
+            ir::PatternKind::I32Literal(value) => (self.ty_sess.make_i32(), literal(value.to_string())),
+            ir::PatternKind::I64Literal(value) => (self.ty_sess.make_i64(), literal(value.to_string())),
+            ir::PatternKind::U8Literal(value) => (self.ty_sess.make_u8(), literal(value.to_string())),
+            ir::PatternKind::U16Literal(value) => (self.ty_sess.make_u16(), literal(value.to_string())),
+            ir::PatternKind::U32Literal(value) => (self.ty_sess.make_u32(), literal(value.to_string())),
+            ir::PatternKind::U64Literal(value) => (self.ty_sess.make_u64(), literal(value.to_string())),
+            ir::PatternKind::BoolLiteral(value) => {
+                (self.ty_sess.make_bool(), Pat::Ctor(Ctor::Bool(*value), vec![]))
+            }
+            ir::PatternKind::ArrayLiteral { element_ty, elements } => (
+                self.ty_sess.make_array(*element_ty, elements.len() as i32),
+                literal(format!("{:?}", elements)),
+            ),
+            ir::PatternKind::StringLiteral(value) => {
+                (self.ty_sess.make_string(), literal(format!("{:?}", value)))
+            }
+            ir::PatternKind::Ident(_) | ir::PatternKind::Variant { .. } | ir::PatternKind::Record { .. } => {
+                unreachable!("written patterns only hold literals")
+            }
+        }
+    }
+
+    /// Every constructor of the type, or `None` when there are too many to list.
+    fn all_ctors(&self, ty: ty::Ty) -> Option<Vec<Ctor>> {
+        match &*self.ty_sess.ty_kind(ty) {
+            ty::TyKind::Bool => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+            ty::TyKind::Enum(desc) => Some(desc.variants.keys().map(Ctor::Variant).collect()),
+            ty::TyKind::Struct(_) | ty::TyKind::Tuple(_) => Some(vec![Ctor::Record]),
+            _ => None,
+        }
+    }
+
+    /// The types of the values held by a constructor.
+    fn sub_tys(&self, ctor: &Ctor, ty: ty::Ty) -> Vec<ty::Ty> {
+        let ty_kind = self.ty_sess.ty_kind(ty);
+        match ctor {
+            Ctor::Variant(discriminant) => ty_kind
+                .variant(*discriminant)
+                .map(|variant| variant.fields.values().copied().collect())
+                .unwrap_or_default(),
+            Ctor::Record => ty_kind
+                .record_fields()
+                .map(|fields| fields.values().copied().collect())
+                .unwrap_or_default(),
+            Ctor::Bool(_) | Ctor::Literal(_) => vec![],
+        }
+    }
+
+    /// The rest of a row that matches a value built with `ctor`, with the values it holds in front.
+    fn specialize(&self, row: &[Pat], ctor: &Ctor, arity: usize) -> Option<Vec<Pat>> {
+        let (head, rest) = row.split_first().unwrap();
+        let mut specialized = match head {
+            Pat::Wild => vec![Pat::Wild; arity],
+            Pat::Ctor(head_ctor, args) if head_ctor == ctor => args.clone(),
+            Pat::Ctor(..) | Pat::Or(_) => return None,
+        };
+        specialized.extend_from_slice(rest);
+        Some(specialized)
+    }
+
+    /// Splits a row starting with an or-pattern into one row for each alternative.
+    fn expand(&self, row: &[Pat]) -> Vec<Vec<Pat>> {
+        match row.split_first() {
+            Some((Pat::Or(alternatives), rest)) => alternatives
+                .iter()
+                .flat_map(|alternative| self.expand(&[vec![alternative.clone()], rest.to_vec()].concat()))
+                .collect(),
+            _ => vec![row.to_vec()],
+        }
+    }
+
+    /// Gives a row of patterns matching a value that `row` matches but no row of `matrix` does, if there is one.
+    fn is_useful(&self, matrix: &[Vec<Pat>], row: &[Pat], tys: &[ty::Ty]) -> Option<Vec<Pat>> {
+        if let Some(Pat::Or(_)) = row.first() {
+            return self
+                .expand(row)
+                .iter()
+                .find_map(|alternative| self.is_useful(matrix, alternative, tys));
+        }
+        let matrix = &matrix.iter().flat_map(|row| self.expand(row)).collect::<Vec<_>>();
+        let (head, rest) = match row.split_first() {
+            Some(split) => split,
+            None if matrix.is_empty() => return Some(vec![]),
+            None => return None,
+        };
+        let (ty, rest_tys) = (tys[0], &tys[1..]);
+        match head {
+            Pat::Ctor(ctor, _) => self.is_useful_for_ctor(matrix, row, ctor, ty, rest_tys),
+            Pat::Or(_) => unreachable!("or-patterns are expanded first"),
+            Pat::Wild => {
+                let used = matrix
+                    .iter()
+                    .filter_map(|row| match &row[0] {
+                        Pat::Ctor(ctor, _) => Some(ctor),
+                        Pat::Wild | Pat::Or(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                let all_ctors = self.all_ctors(ty);
+                match all_ctors {
+                    Some(all_ctors) if all_ctors.iter().all(|ctor| used.contains(&ctor)) => all_ctors
+                        .iter()
+                        .find_map(|ctor| self.is_useful_for_ctor(matrix, row, ctor, ty, rest_tys)),
+                    all_ctors => {
+                        // NOTE values built with a constructor no row starts with are only matched by wildcards
+                        let default = matrix
+                            .iter()
+                            .filter(|row| matches!(row[0], Pat::Wild))
+                            .map(|row| row[1..].to_vec())
+                            .collect::<Vec<_>>();
+                        let witness = self.is_useful(&default, rest, rest_tys)?;
+                        let missing = all_ctors
+                            .filter(|_| !used.is_empty())
+                            .and_then(|all_ctors| all_ctors.into_iter().find(|ctor| !used.contains(&ctor)));
+                        let head = match missing {
+                            Some(ctor) => {
+                                let arity = self.sub_tys(&ctor, ty).len();
+                                Pat::Ctor(ctor, vec![Pat::Wild; arity])
+                            }
+                            None => Pat::Wild,
+                        };
+                        Some([vec![head], witness].concat())
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_useful_for_ctor(
+        &self,
+        matrix: &[Vec<Pat>],
+        row: &[Pat],
+        ctor: &Ctor,
+        ty: ty::Ty,
+        rest_tys: &[ty::Ty],
+    ) -> Option<Vec<Pat>> {
+        let sub_tys = self.sub_tys(ctor, ty);
+        let arity = sub_tys.len();
+        let specialized = matrix
+            .iter()
+            .filter_map(|row| self.specialize(row, ctor, arity))
+            .collect::<Vec<_>>();
+        let mut witness = self.is_useful(
+            &specialized,
+            &self.specialize(row, ctor, arity)?,
+            &[sub_tys, rest_tys.to_vec()].concat(),
+        )?;
+        let rest = witness.split_off(arity);
+        Some([vec![Pat::Ctor(ctor.clone(), witness)], rest].concat())
+    }
+
+    fn display(&self, pat: &Pat, ty: ty::Ty) -> String {
+        let ty_kind = self.ty_sess.ty_kind(ty);
+        match pat {
+            Pat::Wild => String::from("_"),
+            Pat::Ctor(Ctor::Bool(value), _) => value.to_string(),
+            Pat::Ctor(Ctor::Variant(discriminant), args) => {
+                let path = format!(
+                    "{}::{}",
+                    ty_kind.name().unwrap_or_default(),
+                    ty_kind.variant_name(*discriminant).unwrap_or_default()
+                );
+                match ty_kind.variant(*discriminant) {
+                    Some(variant) if variant.is_named() => {
+                        let fields = variant
+                            .fields
+                            .iter()
+                            .zip(args.iter())
+                            .map(|((field_idx, field_ty), arg)| {
+                                format!(
+                                    "{}: {}",
+                                    variant.field_names[field_idx],
+                                    self.display(arg, *field_ty)
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        format!("{} {{ {} }}", path, fields.join(", "))
+                    }
+                    Some(variant) if !variant.fields.is_empty() => {
+                        let fields = variant
+                            .fields
+                            .values()
+                            .zip(args.iter())
+                            .map(|(field_ty, arg)| self.display(arg, *field_ty))
+                            .collect::<Vec<_>>();
+                        format!("{}({})", path, fields.join(", "))
+                    }
+                    _ => path,
+                }
+            }
+            Pat::Ctor(Ctor::Record, args) if ty_kind.is_tuple() => {
+                let fields = ty_kind
+                    .record_fields()
+                    .map(|fields| {
+                        fields
+                            .values()
+                            .zip(args.iter())
+                            .map(|(field_ty, arg)| self.display(arg, *field_ty))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                match fields.len() {
+                    1 => format!("({},)", fields[0]),
+                    _ => format!("({})", fields.join(", ")),
+                }
+            }
+            Pat::Ctor(Ctor::Record, args) => {
+                let fields = ty_kind
+                    .as_struct()
+                    .map(|desc| {
+                        desc.fields
+                            .iter()
+                            .zip(args.iter())
+                            .map(|((field_idx, field_ty), arg)| {
+                                format!(
+                                    "{}: {}",
+                                    ty_kind.field_name(field_idx).unwrap_or_default(),
+                                    self.display(arg, *field_ty)
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                format!(
+                    "{} {{ {} }}",
+                    ty_kind.name().unwrap_or_default(),
+                    fields.join(", ")
+                )
+            }
+            Pat::Ctor(Ctor::Literal(literal), _) => literal.clone(),
+            Pat::Or(alternatives) => alternatives
+                .iter()
+                .map(|alternative| self.display(alternative, ty))
+                .collect::<Vec<_>>()
+                .join(" | "),
+        }
+    }
+}